@@ -0,0 +1,92 @@
+//! Compares sequential and rayon-powered construction of a million-node ring.
+//!
+//! Run with `cargo bench --features rayon --bench parallel_build`.
+
+use std::{
+    hint::black_box,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use deferred_cell::{DeferredError, DeferredSync, InitErrors, SetOnceSync, parallel};
+
+const NODES: usize = 1_000_000;
+const RUNS: u32 = 5;
+
+struct Record {
+    id: usize,
+    name: String,
+}
+
+struct Node {
+    name: String,
+    checksum: u64,
+    next: DeferredSync<Node>,
+    skip: DeferredSync<Node>,
+}
+
+fn make_node(record: &Record) -> Node {
+    // Stand-in for per-record parsing and validation work
+    let checksum = record
+        .name
+        .bytes()
+        .fold(record.id as u64, |acc, b| acc.rotate_left(5) ^ u64::from(b));
+    Node {
+        name: record.name.to_uppercase(),
+        checksum,
+        next: DeferredSync::default(),
+        skip: DeferredSync::default(),
+    }
+}
+
+fn wire(i: usize, node: &Node, nodes: &[Arc<Node>]) -> Result<(), DeferredError> {
+    SetOnceSync::from(&node.next).try_set(&nodes[(i + 1) % nodes.len()])?;
+    SetOnceSync::from(&node.skip).try_set(&nodes[(i + 1_000) % nodes.len()])
+}
+
+fn time(label: &str, mut f: impl FnMut() -> Vec<Arc<Node>>) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let nodes = f();
+        best = best.min(start.elapsed());
+        black_box(nodes[NODES - 1].next.get().checksum);
+        black_box(&nodes[0].skip.get().name);
+    }
+    println!("{label:<24} {best:?}");
+    best
+}
+
+fn main() -> Result<(), InitErrors> {
+    let records: Vec<Record> = (0..NODES)
+        .map(|id| Record {
+            id,
+            name: format!("node-{id}"),
+        })
+        .collect();
+
+    let sequential = time("sequential", || {
+        let nodes: Vec<_> = records.iter().map(|r| Arc::new(make_node(r))).collect();
+        parallel::wire_sequential(&nodes, wire).ok();
+        nodes
+    });
+    let parallel_build = time("par_build + sequential", || {
+        let nodes = parallel::par_build(&records, make_node);
+        parallel::wire_sequential(&nodes, wire).ok();
+        nodes
+    });
+    let parallel_sharded = time("par_build + sharded", || {
+        let nodes = parallel::par_build(&records, make_node);
+        parallel::par_wire_sharded(&nodes, wire).ok();
+        nodes
+    });
+
+    println!(
+        "speedup: {:.2}x (build), {:.2}x (build + wire)",
+        sequential.as_secs_f64() / parallel_build.as_secs_f64(),
+        sequential.as_secs_f64() / parallel_sharded.as_secs_f64(),
+    );
+
+    let nodes = parallel::par_build(&records, make_node);
+    parallel::par_wire_sharded(&nodes, wire)
+}
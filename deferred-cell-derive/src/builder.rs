@@ -0,0 +1,217 @@
+use proc_macro2::{Group, TokenStream, TokenTree};
+use quote::{ToTokens, format_ident, quote};
+use syn::{DeriveInput, Field, GenericParam, Ident};
+
+use crate::util::{CellKind, cell_type, named_fields};
+
+/// Replaces `Self` in a field type, since the type is reused inside the builder.
+fn replace_self(tokens: TokenStream, with: &TokenStream) -> TokenStream {
+    tokens
+        .into_iter()
+        .flat_map(|tree| match tree {
+            TokenTree::Ident(ident) if ident == "Self" => with.clone(),
+            TokenTree::Group(group) => {
+                let mut replaced =
+                    Group::new(group.delimiter(), replace_self(group.stream(), with));
+                replaced.set_span(group.span());
+                TokenTree::Group(replaced).into_token_stream()
+            }
+            other => other.into_token_stream(),
+        })
+        .collect()
+}
+
+fn is_optional(field: &Field) -> syn::Result<bool> {
+    let mut optional = false;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("builder")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("optional") {
+                optional = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `optional`"))
+            }
+        })?;
+    }
+    Ok(optional)
+}
+
+struct CellField<'a> {
+    ident: &'a Ident,
+    cell: TokenStream,
+    pointer: TokenStream,
+    param: Ident,
+    optional: bool,
+}
+
+pub fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let builder = format_ident!("{}Builder", name);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let self_ty = quote!(#name #ty_generics);
+    let struct_params: Vec<_> = input
+        .generics
+        .params
+        .iter()
+        .cloned()
+        .map(|mut param| {
+            match &mut param {
+                GenericParam::Type(t) => t.default = None,
+                GenericParam::Const(c) => c.default = None,
+                GenericParam::Lifetime(_) => {}
+            }
+            param
+        })
+        .collect();
+    let struct_args: Vec<_> = input
+        .generics
+        .params
+        .iter()
+        .map(|param| match param {
+            GenericParam::Type(t) => t.ident.to_token_stream(),
+            GenericParam::Const(c) => c.ident.to_token_stream(),
+            GenericParam::Lifetime(l) => l.lifetime.to_token_stream(),
+        })
+        .collect();
+
+    let mut payload = Vec::new();
+    let mut cells = Vec::new();
+    for field in named_fields(input)? {
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        let ty = replace_self(field.ty.to_token_stream(), &self_ty);
+        match cell_type(&field.ty) {
+            Some((kind, target)) => {
+                let target = replace_self(target.to_token_stream(), &self_ty);
+                let pointer = match kind {
+                    CellKind::Rc => quote!(::std::rc::Rc<#target>),
+                    CellKind::Sync => quote!(::std::sync::Arc<#target>),
+                };
+                cells.push(CellField {
+                    ident,
+                    cell: ty,
+                    pointer,
+                    param: format_ident!("__{}", ident.to_string().to_uppercase()),
+                    optional: is_optional(field)?,
+                });
+            }
+            None => payload.push((ident, ty)),
+        }
+    }
+
+    let params: Vec<_> = cells.iter().map(|c| &c.param).collect();
+    let payload_idents: Vec<_> = payload.iter().map(|(i, _)| *i).collect();
+    let payload_types: Vec<_> = payload.iter().map(|(_, t)| t).collect();
+    let cell_idents: Vec<_> = cells.iter().map(|c| c.ident).collect();
+    let missing: Vec<_> = cells
+        .iter()
+        .map(|_| quote!(::deferred_cell::builder::Missing))
+        .collect();
+    let builder_doc = format!(
+        "Builder for [`{name}`] that only offers `build()` once every required deferred field is set."
+    );
+
+    let setters = cells.iter().enumerate().map(|(k, field)| {
+        let ident = field.ident;
+        let cell = &field.cell;
+        let pointer = &field.pointer;
+        let others = params
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != k)
+            .map(|(_, p)| p);
+        let before: Vec<_> = params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                if i == k {
+                    quote!(::deferred_cell::builder::Missing)
+                } else {
+                    quote!(#p)
+                }
+            })
+            .collect();
+        let after: Vec<_> = params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                if i == k {
+                    quote!(::deferred_cell::builder::Provided<#cell>)
+                } else {
+                    quote!(#p)
+                }
+            })
+            .collect();
+        let rest = cell_idents.iter().filter(|i| **i != ident);
+        let doc = format!("Supplies the `{ident}` link.");
+        quote! {
+            impl<#(#struct_params,)* #(#others),*> #builder<#(#struct_args,)* #(#before),*> #where_clause {
+                #[doc = #doc]
+                #vis fn #ident(self, value: &#pointer) -> #builder<#(#struct_args,)* #(#after),*> {
+                    #builder {
+                        #(#payload_idents: self.#payload_idents,)*
+                        #(#rest: self.#rest,)*
+                        #ident: ::deferred_cell::builder::Provided(<#cell>::from(value)),
+                        __marker: ::core::marker::PhantomData,
+                    }
+                }
+            }
+        }
+    });
+
+    let build_params = cells.iter().filter(|c| c.optional).map(|c| &c.param);
+    let build_bounds = cells.iter().filter(|c| c.optional).map(|c| {
+        let param = &c.param;
+        let cell = &c.cell;
+        quote!(#param: ::deferred_cell::builder::Slot<#cell>)
+    });
+    let build_args = cells.iter().map(|c| {
+        if c.optional {
+            let param = &c.param;
+            quote!(#param)
+        } else {
+            let cell = &c.cell;
+            quote!(::deferred_cell::builder::Provided<#cell>)
+        }
+    });
+    let where_predicates = where_clause.map(|w| &w.predicates);
+
+    Ok(quote! {
+        #[doc = #builder_doc]
+        #vis struct #builder<#(#struct_params,)* #(#params),*> #where_clause {
+            #(#payload_idents: #payload_types,)*
+            #(#cell_idents: #params,)*
+            __marker: ::core::marker::PhantomData<fn() -> #self_ty>,
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Starts a builder from the node's non-deferred fields, in declaration order.
+            #[allow(clippy::too_many_arguments)]
+            #vis fn builder(#(#payload_idents: #payload_types),*) -> #builder<#(#struct_args,)* #(#missing),*> {
+                #builder {
+                    #(#payload_idents,)*
+                    #(#cell_idents: ::deferred_cell::builder::Missing,)*
+                    __marker: ::core::marker::PhantomData,
+                }
+            }
+        }
+
+        #(#setters)*
+
+        impl<#(#struct_params,)* #(#build_params),*> #builder<#(#struct_args,)* #(#build_args),*>
+        where
+            #(#build_bounds,)*
+            #where_predicates
+        {
+            /// Builds the node with every supplied link already set.
+            #vis fn build(self) -> #self_ty {
+                #name {
+                    #(#payload_idents: self.#payload_idents,)*
+                    #(#cell_idents: ::deferred_cell::builder::Slot::into_cell(self.#cell_idents),)*
+                }
+            }
+        }
+    })
+}
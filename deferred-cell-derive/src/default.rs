@@ -0,0 +1,45 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, Type};
+
+use crate::util::{cell_type, named_fields, vec_item};
+
+/// Returns `true` for `Deferred<T>`, `DeferredSync<T>`, and `Vec`s of them.
+fn is_cell_field(ty: &Type) -> bool {
+    cell_type(ty).is_some() || vec_item(ty).and_then(cell_type).is_some()
+}
+
+pub fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = named_fields(input)?;
+    let (cells, payload): (Vec<_>, Vec<_>) = fields.into_iter().partition(|f| is_cell_field(&f.ty));
+    let cell_idents = cells.iter().map(|f| &f.ident);
+    let payload_idents: Vec<_> = payload.iter().map(|f| &f.ident).collect();
+    let payload_types = payload.iter().map(|f| &f.ty);
+    let default_impl = payload.is_empty().then(|| {
+        quote! {
+            impl #impl_generics ::core::default::Default for #name #ty_generics #where_clause {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+        }
+    });
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Creates the node from its non-deferred fields, in declaration order, with
+            /// every deferred field unset.
+            #[allow(clippy::too_many_arguments)]
+            #[must_use]
+            #vis fn new(#(#payload_idents: #payload_types),*) -> Self {
+                Self {
+                    #(#payload_idents,)*
+                    #(#cell_idents: ::core::default::Default::default(),)*
+                }
+            }
+        }
+        #default_impl
+    })
+}
@@ -0,0 +1,29 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::DeriveInput;
+
+use crate::util::{cell_type, named_fields};
+
+pub fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let entries = named_fields(input)?
+        .into_iter()
+        .filter(|f| cell_type(&f.ty).is_some())
+        .filter_map(|f| f.ident.as_ref())
+        .map(|ident| {
+            let label = ident.to_string();
+            quote! { (#label, &self.#ident as &dyn ::deferred_cell::AnyDeferredCell) }
+        });
+    Ok(quote! {
+        impl #impl_generics ::deferred_cell::DeferredFields for #name #ty_generics #where_clause {
+            fn deferred_fields(
+                &self,
+            ) -> impl ::core::iter::Iterator<
+                Item = (&'static str, &dyn ::deferred_cell::AnyDeferredCell),
+            > {
+                [#(#entries),*].into_iter()
+            }
+        }
+    })
+}
@@ -0,0 +1,198 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, DeriveInput, Ident, Type};
+
+use crate::util::{cell_type, is_edge, named_fields, vec_item};
+
+/// How a field is written in a node record.
+enum Role {
+    /// A `Deferred<Self>` edge, written as the ID of its target.
+    Edge,
+    /// A `Vec<Deferred<Self>>` of edges, written as a list of IDs.
+    Edges,
+    /// Any other field, written as itself.
+    Payload,
+}
+
+struct Field<'a> {
+    ident: &'a Ident,
+    ty: &'a Type,
+    role: Role,
+    /// `#[serde(..)]` attributes, forwarded to the record.
+    serde: Vec<&'a Attribute>,
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<Vec<Field<'_>>> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "expected a struct without generic parameters",
+        ));
+    }
+    let name = &input.ident;
+    named_fields(input)?
+        .into_iter()
+        .filter_map(|field| field.ident.as_ref().map(|ident| (ident, field)))
+        .map(|(ident, field)| {
+            let ty = &field.ty;
+            let role = if is_edge(ty, name) {
+                Role::Edge
+            } else if vec_item(ty).is_some_and(|item| is_edge(item, name)) {
+                Role::Edges
+            } else if cell_type(ty)
+                .or_else(|| vec_item(ty).and_then(cell_type))
+                .is_some()
+            {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    "only `Deferred<Self>` and `Vec<Deferred<Self>>` cells can be serialized",
+                ));
+            } else {
+                Role::Payload
+            };
+            let serde = field
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("serde"))
+                .collect();
+            Ok(Field {
+                ident,
+                ty,
+                role,
+                serde,
+            })
+        })
+        .collect()
+}
+
+pub fn expand_serialize(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let record_name = name.to_string();
+    let fields = fields(input)?;
+    let record = fields.iter().map(|f| {
+        let Field {
+            ident, ty, serde, ..
+        } = f;
+        let ty = match f.role {
+            Role::Edge => quote!(::core::option::Option<usize>),
+            Role::Edges => quote!(::std::vec::Vec<::core::option::Option<usize>>),
+            Role::Payload => quote!(&'a #ty),
+        };
+        quote! { #(#serde)* #ident: #ty }
+    });
+    let values = fields.iter().map(|f| {
+        let ident = f.ident;
+        let label = format!("{name}.{ident}");
+        match f.role {
+            Role::Edge => quote! {
+                #ident: ids
+                    .id_of(&self.#ident)
+                    .map_err(|e| ::deferred_cell::__private::edge_error::<__S::Error>(e, #label))?
+            },
+            Role::Edges => quote! {
+                #ident: self
+                    .#ident
+                    .iter()
+                    .map(|cell| ids.id_of(cell))
+                    .collect::<::core::result::Result<_, _>>()
+                    .map_err(|e| ::deferred_cell::__private::edge_error::<__S::Error>(e, #label))?
+            },
+            Role::Payload => quote!(#ident: &self.#ident),
+        }
+    });
+    Ok(quote! {
+        impl ::deferred_cell::graph_serde::GraphSerialize for #name {
+            fn serialize_node<__S: ::deferred_cell::__private::serde::Serializer>(
+                &self,
+                ids: &::deferred_cell::graph_serde::NodeIds<Self>,
+                serializer: __S,
+            ) -> ::core::result::Result<__S::Ok, __S::Error> {
+                #[derive(::deferred_cell::__private::serde::Serialize)]
+                #[serde(crate = "::deferred_cell::__private::serde", rename = #record_name)]
+                struct Record<'a> {
+                    #(#record,)*
+                    #[serde(skip)]
+                    _node: ::core::marker::PhantomData<&'a ()>,
+                }
+                let record = Record {
+                    #(#values,)*
+                    _node: ::core::marker::PhantomData,
+                };
+                ::deferred_cell::__private::serde::Serialize::serialize(&record, serializer)
+            }
+        }
+    })
+}
+
+pub fn expand_deserialize(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let record_name = name.to_string();
+    let fields = fields(input)?;
+    let record = fields.iter().map(|f| {
+        let Field {
+            ident, ty, serde, ..
+        } = f;
+        let ty = match f.role {
+            Role::Edge => quote!(::core::option::Option<usize>),
+            Role::Edges => quote!(::std::vec::Vec<::core::option::Option<usize>>),
+            Role::Payload => quote!(#ty),
+        };
+        quote! { #(#serde)* #ident: #ty }
+    });
+    let ids = fields.iter().filter_map(|f| {
+        let ident = f.ident;
+        match f.role {
+            Role::Edge => Some(quote!(ids.push(record.#ident);)),
+            Role::Edges => Some(quote!(ids.extend(record.#ident.iter().copied());)),
+            Role::Payload => None,
+        }
+    });
+    let values = fields.iter().map(|f| {
+        let ident = f.ident;
+        match f.role {
+            Role::Edge => quote!(#ident: ::deferred_cell::Deferred::new()),
+            Role::Edges => quote! {
+                #ident: record.#ident.iter().map(|_| ::deferred_cell::Deferred::new()).collect()
+            },
+            Role::Payload => quote!(#ident: record.#ident),
+        }
+    });
+    let mut edges = quote!(::core::iter::empty());
+    for f in &fields {
+        let ident = f.ident;
+        match f.role {
+            Role::Edge => edges = quote!(#edges.chain(::core::iter::once(&self.#ident))),
+            Role::Edges => edges = quote!(#edges.chain(self.#ident.iter())),
+            Role::Payload => {}
+        }
+    }
+    Ok(quote! {
+        impl ::deferred_cell::graph_serde::GraphDeserialize for #name {
+            fn deserialize_node<'de, __D: ::deferred_cell::__private::serde::Deserializer<'de>>(
+                deserializer: __D,
+            ) -> ::core::result::Result<
+                (Self, ::std::vec::Vec<::core::option::Option<usize>>),
+                __D::Error,
+            > {
+                #[derive(::deferred_cell::__private::serde::Deserialize)]
+                #[serde(crate = "::deferred_cell::__private::serde", rename = #record_name)]
+                struct Record {
+                    #(#record,)*
+                }
+                let record =
+                    <Record as ::deferred_cell::__private::serde::Deserialize>::deserialize(
+                        deserializer,
+                    )?;
+                #[allow(unused_mut)]
+                let mut ids = ::std::vec::Vec::new();
+                #(#ids)*
+                ::core::result::Result::Ok((Self { #(#values,)* }, ids))
+            }
+            fn edges(
+                &self,
+            ) -> impl ::core::iter::Iterator<Item = &::deferred_cell::Deferred<Self>> {
+                #edges
+            }
+        }
+    })
+}
@@ -0,0 +1,119 @@
+//! Derive macros for [`deferred-cell`](https://docs.rs/deferred-cell).
+//!
+//! These are re-exported by `deferred-cell` when its `derive` feature is enabled;
+//! depend on that crate rather than on this one directly.
+#![deny(clippy::unwrap_used, clippy::expect_used)]
+#![warn(clippy::all, clippy::nursery)]
+
+use proc_macro::TokenStream;
+use syn::{DeriveInput, parse_macro_input};
+
+mod builder;
+mod default;
+mod fields;
+mod graph_serde;
+mod setters;
+mod util;
+mod visit;
+
+/// Implements `deferred_cell::DeferredFields` for a struct with named fields.
+///
+/// Every field whose type is `Deferred<T>` or `DeferredSync<T>` is listed, in
+/// declaration order.
+#[proc_macro_derive(DeferredFields)]
+pub fn derive_deferred_fields(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    fields::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `deferred_cell::HasNeighbors` for a struct with named fields.
+///
+/// Every `Deferred<Self>` field and every `Vec<Deferred<Self>>` field becomes an edge, in
+/// declaration order. Cells pointing at other types are ignored.
+#[proc_macro_derive(Visit)]
+pub fn derive_visit(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    visit::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `deferred_cell::graph_serde::GraphSerialize` for a struct with named fields.
+///
+/// Every `Deferred<Self>` field is written as the ID of its target, or `null` when unset,
+/// and every `Vec<Deferred<Self>>` field as a list of IDs. Other fields are written as
+/// ordinary serde fields, and `#[serde(..)]` attributes on them are honored, though
+/// serializing functions see them behind a reference. Cells pointing at other types are
+/// rejected. Requires the `serde` feature of `deferred-cell`.
+#[proc_macro_derive(GraphSerialize, attributes(serde))]
+pub fn derive_graph_serialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    graph_serde::expand_serialize(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `deferred_cell::graph_serde::GraphDeserialize` for a struct with named
+/// fields, reading the records written by `GraphSerialize`.
+///
+/// Deferred fields start unset and are wired once every node of the graph has been
+/// read. Requires the `serde` feature of `deferred-cell`.
+#[proc_macro_derive(GraphDeserialize, attributes(serde))]
+pub fn derive_graph_deserialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    graph_serde::expand_deserialize(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generates a typestate builder for a struct with deferred fields.
+///
+/// `Node::builder(..)` takes the non-deferred fields in declaration order and returns a
+/// `NodeBuilder` with one method per deferred field. `build()` only exists once every
+/// deferred field has been supplied, except fields marked `#[builder(optional)]`, which
+/// stay unset when skipped.
+#[proc_macro_derive(DeferredBuilder, attributes(builder))]
+pub fn derive_deferred_builder(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    builder::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generates a `new` constructor that leaves every deferred field unset.
+///
+/// `new` takes the remaining fields as arguments, in declaration order. `Deferred<T>` and
+/// `DeferredSync<T>` fields start unset and `Vec`s of them start empty. When the struct
+/// has no other fields, `Default` is implemented as well.
+#[proc_macro_derive(DeferredDefault)]
+pub fn derive_deferred_default(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    default::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generates typed accessors for every deferred field of a struct.
+///
+/// For each `Deferred<T>` field `foo` of `Node`, this adds `set_foo(&self, &Rc<T>)`, a
+/// chaining `with_foo(self, &Rc<T>) -> Result<Self, WithError<Self>>`, whose error hands
+/// the node back, and the getters
+/// `try_foo(&self) -> Result<Rc<T>, _>` and `foo(&self) -> Rc<T>`. Errors are wrapped
+/// with `Node.foo` as context. `DeferredSync<T>` fields get the same methods using `Arc<T>`.
+#[proc_macro_attribute]
+pub fn deferred(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "#[deferred] does not take arguments",
+        )
+        .into_compile_error()
+        .into();
+    }
+    let input = parse_macro_input!(input as DeriveInput);
+    setters::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,83 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::DeriveInput;
+
+use crate::util::{CellKind, cell_type, named_fields};
+
+pub fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let methods = named_fields(input)?.into_iter().filter_map(|field| {
+        let ident = field.ident.as_ref()?;
+        let (kind, target) = cell_type(&field.ty)?;
+        let setter = format_ident!("set_{}", ident);
+        let builder = format_ident!("with_{}", ident);
+        let try_getter = format_ident!("try_{}", ident);
+        let context = format!("{name}.{ident}");
+        let (pointer, set_once) = match kind {
+            CellKind::Rc => (quote!(::std::rc::Rc), quote!(::deferred_cell::SetOnce)),
+            CellKind::Sync => (
+                quote!(::std::sync::Arc),
+                quote!(::deferred_cell::SetOnceSync),
+            ),
+        };
+        let setter_doc = format!("Sets `{ident}` to a weak reference to `value`.");
+        let builder_doc = format!(
+            "Sets `{ident}` and returns `self`, for chaining. On failure, the error hands `self` back."
+        );
+        let try_getter_doc = format!("Returns the target of `{ident}`, with `{context}` as error context.");
+        let getter_doc = format!(
+            "Returns the target of `{ident}`.\n\n# Panics\nPanics, or calls the installed `failure` handler, if `{context}` is not set or its target was dropped."
+        );
+        Some(quote! {
+            #[doc = #try_getter_doc]
+            #[track_caller]
+            #vis fn #try_getter(
+                &self,
+            ) -> ::core::result::Result<#pointer<#target>, ::deferred_cell::DeferredError> {
+                let _cell = ::deferred_cell::__private::enter_cell(#context);
+                self.#ident.try_get().map_err(|e| e.context(#context))
+            }
+            #[doc = #getter_doc]
+            #[must_use]
+            #[track_caller]
+            #vis fn #ident(&self) -> #pointer<#target> {
+                match self.#try_getter() {
+                    ::core::result::Result::Ok(value) => value,
+                    ::core::result::Result::Err(error) => ::deferred_cell::failure::fail(&error),
+                }
+            }
+            #[doc = #setter_doc]
+            #[track_caller]
+            #vis fn #setter(
+                &self,
+                value: &#pointer<#target>,
+            ) -> ::core::result::Result<(), ::deferred_cell::DeferredError> {
+                let _cell = ::deferred_cell::__private::enter_cell(#context);
+                #set_once::from(&self.#ident)
+                    .try_set(value)
+                    .map_err(|e| e.context(#context))
+            }
+            #[doc = #builder_doc]
+            #[track_caller]
+            #vis fn #builder(
+                self,
+                value: &#pointer<#target>,
+            ) -> ::core::result::Result<Self, ::deferred_cell::WithError<Self>> {
+                match self.#setter(value) {
+                    ::core::result::Result::Ok(()) => ::core::result::Result::Ok(self),
+                    ::core::result::Result::Err(error) => {
+                        ::core::result::Result::Err(::deferred_cell::WithError { node: self, error })
+                    }
+                }
+            }
+        })
+    });
+    Ok(quote! {
+        #input
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#methods)*
+        }
+    })
+}
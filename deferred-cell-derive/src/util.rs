@@ -0,0 +1,77 @@
+use syn::{Data, DeriveInput, Field, Fields, GenericArgument, PathArguments, Type};
+
+/// The kinds of cell a field can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellKind {
+    Rc,
+    Sync,
+}
+
+/// Returns the named fields of a struct, or an error pointing at the input.
+pub fn named_fields(input: &DeriveInput) -> syn::Result<Vec<&Field>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields.named.iter().collect()),
+            _ => Err(syn::Error::new_spanned(
+                &input.ident,
+                "expected a struct with named fields",
+            )),
+        },
+        _ => Err(syn::Error::new_spanned(&input.ident, "expected a struct")),
+    }
+}
+
+/// Recognizes `Deferred<T>` and `DeferredSync<T>`, returning the cell kind and `T`.
+pub fn cell_type(ty: &Type) -> Option<(CellKind, &Type)> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let kind = match segment.ident.to_string().as_str() {
+        "Deferred" => CellKind::Rc,
+        "DeferredSync" => CellKind::Sync,
+        _ => return None,
+    };
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(target) => Some((kind, target)),
+        _ => None,
+    }
+}
+
+/// Returns `true` when `ty` names the deriving struct, either as `Self` or by name.
+fn is_self(ty: &Type, name: &syn::Ident) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|s| s.ident == "Self" || s.ident == *name),
+        _ => false,
+    }
+}
+
+/// Recognizes `Vec<T>` and returns `T`.
+pub fn vec_item(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Vec" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(item) => Some(item),
+        _ => None,
+    }
+}
+
+/// Returns `true` for `Deferred<Self>`, the cells that are graph edges.
+pub fn is_edge(ty: &Type, name: &syn::Ident) -> bool {
+    cell_type(ty).is_some_and(|(kind, target)| kind == CellKind::Rc && is_self(target, name))
+}
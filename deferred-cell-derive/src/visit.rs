@@ -0,0 +1,30 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::DeriveInput;
+
+use crate::util::{is_edge, named_fields, vec_item};
+
+pub fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut chain = quote!(::core::iter::empty());
+    for field in named_fields(input)? {
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        if is_edge(&field.ty, name) {
+            chain = quote!(#chain.chain(::core::iter::once(&self.#ident)));
+        } else if vec_item(&field.ty).is_some_and(|item| is_edge(item, name)) {
+            chain = quote!(#chain.chain(self.#ident.iter()));
+        }
+    }
+    Ok(quote! {
+        impl #impl_generics ::deferred_cell::HasNeighbors for #name #ty_generics #where_clause {
+            fn neighbors(
+                &self,
+            ) -> impl ::core::iter::Iterator<Item = &::deferred_cell::Deferred<Self>> {
+                #chain
+            }
+        }
+    })
+}
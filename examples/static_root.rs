@@ -0,0 +1,81 @@
+//! A process-wide graph root declared as a `static` and wired during startup.
+//!
+//! Worker threads may start before the graph is ready: they block in `wait()` until
+//! startup publishes the root, then read it with `get()`.
+//!
+//! Run with `cargo run --example static_root`.
+//!
+//! Needs `DeferredSync::new` to be `const`, which it is not with the `loom` feature.
+
+#[cfg(not(feature = "loom"))]
+mod app {
+    use std::{
+        sync::{Arc, OnceLock},
+        thread,
+    };
+
+    use deferred_cell::{DeferredError, DeferredSync, SetOnceSync};
+
+    struct Node {
+        name: &'static str,
+        next: DeferredSync<Self>,
+    }
+
+    /// Entry point into the graph, wired once at startup.
+    static ROOT: DeferredSync<Node> = DeferredSync::new();
+    /// Owns the nodes, since `ROOT` and the node cells only hold weak references.
+    static NODES: OnceLock<Vec<Arc<Node>>> = OnceLock::new();
+
+    pub(super) fn startup() -> Result<(), DeferredError> {
+        let nodes: Vec<_> = ["boot", "config", "serve"]
+            .into_iter()
+            .map(|name| {
+                Arc::new(Node {
+                    name,
+                    next: DeferredSync::new(),
+                })
+            })
+            .collect();
+        for (i, node) in nodes.iter().enumerate() {
+            SetOnceSync::from(&node.next).try_set(&nodes[(i + 1) % nodes.len()])?;
+        }
+        let nodes = NODES.get_or_init(|| nodes);
+        SetOnceSync::from(&ROOT).try_set(&nodes[0])
+    }
+
+    pub(super) fn main() -> Result<(), DeferredError> {
+        let workers: Vec<_> = (0..4)
+            .map(|id| {
+                thread::spawn(move || -> Result<String, DeferredError> {
+                    let root = ROOT.wait()?;
+                    Ok(format!(
+                        "worker {id}: {} -> {}",
+                        root.name,
+                        root.next.get().name
+                    ))
+                })
+            })
+            .collect();
+
+        startup()?;
+
+        for worker in workers {
+            match worker.join() {
+                Ok(line) => println!("{}", line?),
+                Err(_) => eprintln!("worker panicked"),
+            }
+        }
+        println!("main: root is {}", ROOT.get().name);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+fn main() -> Result<(), deferred_cell::DeferredError> {
+    app::main()
+}
+
+#[cfg(feature = "loom")]
+fn main() {
+    eprintln!("static_root cannot run with the `loom` feature");
+}
@@ -0,0 +1,278 @@
+//! Set-once cells holding `generational_arena` indices.
+//!
+//! A [`DeferredIndex`] is a write-once edge to a value stored in an `Arena`. Its
+//! generation check makes removals visible: once the target is removed, even if its slot
+//! is reused, [`DeferredIndex::try_get`] fails instead of returning the new occupant.
+//!
+//! An [`IndexResolver`] wires such cells from user keys, and [`stale`] finds cells left
+//! pointing at removed entries.
+//!
+//! ```
+//! use deferred_cell::arena::{DeferredIndex, IndexResolver};
+//! use generational_arena::Arena;
+//!
+//! struct Room {
+//!     name: &'static str,
+//!     exit: DeferredIndex,
+//! }
+//!
+//! let mut rooms = Arena::new();
+//! let mut resolver = IndexResolver::new();
+//! for name in ["hall", "cellar"] {
+//!     resolver.register(name, rooms.insert(Room { name, exit: DeferredIndex::new() })).unwrap();
+//! }
+//! resolver.edge("hall", |r: &Room| &r.exit, "cellar");
+//! resolver.edge("cellar", |r: &Room| &r.exit, "hall");
+//! let keys = resolver.wire(&rooms).unwrap();
+//!
+//! let exit = rooms[keys["hall"]].exit.try_get(&rooms).unwrap();
+//! assert_eq!(exit.name, "cellar");
+//! ```
+
+use std::{cell::OnceCell, collections::HashMap, fmt::Debug, hash::Hash};
+
+use generational_arena::{Arena, Index};
+
+use crate::{DeferredError, resolver::ResolveError};
+
+/// Registered indices, kept in registration order when [`IndexResolver::wire_indexed`]
+/// is available.
+#[cfg(feature = "indexmap")]
+type Indices<K> = indexmap::IndexMap<K, Index>;
+#[cfg(not(feature = "indexmap"))]
+type Indices<K> = HashMap<K, Index>;
+
+/// A write-once reference to a value stored in an `Arena`.
+#[derive(Debug, Clone, Default)]
+pub struct DeferredIndex(OnceCell<Index>);
+
+impl From<Index> for DeferredIndex {
+    fn from(index: Index) -> Self {
+        Self(OnceCell::from(index))
+    }
+}
+
+impl DeferredIndex {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(OnceCell::new())
+    }
+    /// Looks the target up in `arena`.
+    ///
+    /// Fails if the cell is unset or its target has been removed.
+    pub fn try_get<'a, T>(&self, arena: &'a Arena<T>) -> Result<&'a T, DeferredError> {
+        self.0
+            .get()
+            .and_then(|&index| arena.get(index))
+            .ok_or(DeferredError::NotInitializedError())
+    }
+    /// Returns the index the cell was set to, without checking it against any arena.
+    #[must_use]
+    pub fn index(&self) -> Option<Index> {
+        self.0.get().copied()
+    }
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.0.get().is_some()
+    }
+}
+
+/// A helper for setting a [`DeferredIndex`].
+#[derive(Debug, Clone)]
+pub struct SetOnceIndex<'a>(&'a DeferredIndex);
+
+impl<'a> SetOnceIndex<'a> {
+    pub const fn from(cell: &'a DeferredIndex) -> Self {
+        Self(cell)
+    }
+    pub fn try_set(&self, index: Index) -> Result<(), DeferredError> {
+        self.0
+            .0
+            .set(index)
+            .map_err(|_| DeferredError::DuplicateInitialization())
+    }
+    #[must_use]
+    pub fn can_set(&self) -> bool {
+        self.0.0.get().is_none()
+    }
+}
+
+/// Selects the cell of a node that an edge is stored in.
+pub type IndexSlot<T> = for<'n> fn(&'n T) -> &'n DeferredIndex;
+
+/// Returns the index of every node whose `slot` points at a removed entry.
+pub fn stale<T>(arena: &Arena<T>, slot: IndexSlot<T>) -> Vec<Index> {
+    arena
+        .iter()
+        .filter(|(_, node)| slot(node).index().is_some_and(|i| !arena.contains(i)))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+struct Edge<K, T> {
+    from: K,
+    slot: IndexSlot<T>,
+    to: K,
+}
+
+/// Maps keys to arena indices and wires edges between keys.
+pub struct IndexResolver<K, T> {
+    indices: Indices<K>,
+    edges: Vec<Edge<K, T>>,
+}
+
+impl<K, T> Default for IndexResolver<K, T> {
+    fn default() -> Self {
+        Self {
+            indices: Indices::default(),
+            edges: Vec::new(),
+        }
+    }
+}
+
+impl<K, T> IndexResolver<K, T>
+where
+    K: Eq + Hash + Clone + Debug,
+{
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers the node stored at `index` under `key`.
+    pub fn register(&mut self, key: K, index: Index) -> Result<(), ResolveError<K>> {
+        if self.indices.contains_key(&key) {
+            return Err(ResolveError::DuplicateKey(key));
+        }
+        self.indices.insert(key, index);
+        Ok(())
+    }
+    /// Queues `slot(from)` to be pointed at `to` by [`wire`](Self::wire).
+    pub fn edge(&mut self, from: K, slot: IndexSlot<T>, to: K) {
+        self.edges.push(Edge { from, slot, to });
+    }
+    /// Returns the index registered under `key`.
+    pub fn get(&self, key: &K) -> Option<Index> {
+        self.indices.get(key).copied()
+    }
+    /// Wires every queued edge against `arena` and returns the registered indices.
+    ///
+    /// Fails without setting any cell if an edge refers to an unregistered key, or to a
+    /// key whose entry has been removed from `arena`.
+    pub fn wire(self, arena: &Arena<T>) -> Result<HashMap<K, Index>, ResolveError<K>> {
+        self.wire_edges(arena)?;
+        Ok(self.indices.into_iter().collect())
+    }
+    /// Like [`wire`](Self::wire), but returns an `IndexMap` that iterates in registration
+    /// order. Requires the `indexmap` feature.
+    #[cfg(feature = "indexmap")]
+    pub fn wire_indexed(
+        self,
+        arena: &Arena<T>,
+    ) -> Result<indexmap::IndexMap<K, Index>, ResolveError<K>> {
+        self.wire_edges(arena)?;
+        Ok(self.indices)
+    }
+    fn wire_edges(&self, arena: &Arena<T>) -> Result<(), ResolveError<K>> {
+        let unresolved: Vec<_> = self
+            .edges
+            .iter()
+            .filter(|edge| {
+                !(self.indices.contains_key(&edge.from) && self.indices.contains_key(&edge.to))
+            })
+            .map(|edge| (edge.from.clone(), edge.to.clone()))
+            .collect();
+        if !unresolved.is_empty() {
+            return Err(ResolveError::Unresolved(unresolved));
+        }
+        if let Some((key, _)) = self.indices.iter().find(|&(_, &i)| !arena.contains(i)) {
+            return Err(ResolveError::Stale(key.clone()));
+        }
+        for edge in &self.edges {
+            let from = &arena[self.indices[&edge.from]];
+            SetOnceIndex::from((edge.slot)(from)).try_set(self.indices[&edge.to])?;
+        }
+        Ok(())
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Node {
+        value: u8,
+        next: DeferredIndex,
+    }
+
+    fn node(value: u8) -> Node {
+        Node {
+            value,
+            next: DeferredIndex::new(),
+        }
+    }
+
+    fn next(node: &Node) -> &DeferredIndex {
+        &node.next
+    }
+
+    #[test]
+    fn removed_targets_are_detected() {
+        let mut arena = Arena::new();
+        let mut resolver = IndexResolver::new();
+        for (key, value) in [("a", 1), ("b", 2)] {
+            resolver.register(key, arena.insert(node(value))).unwrap();
+        }
+        resolver.edge("a", next, "b");
+        resolver.edge("b", next, "a");
+        let keys = resolver.wire(&arena).unwrap();
+        let (a, b) = (keys["a"], keys["b"]);
+        assert_eq!(arena[a].next.try_get(&arena).unwrap().value, 2);
+        assert!(!SetOnceIndex::from(&arena[a].next).can_set());
+
+        arena.remove(b);
+        arena.insert(node(3));
+        assert!(arena[a].next.try_get(&arena).is_err());
+        assert_eq!(stale(&arena, next), [a]);
+    }
+    #[test]
+    fn wiring_checks_every_key() {
+        let mut arena = Arena::new();
+        let mut resolver = IndexResolver::new();
+        resolver.register("a", arena.insert(node(1))).unwrap();
+        assert!(matches!(
+            resolver.register("a", arena.insert(node(2))),
+            Err(ResolveError::DuplicateKey("a"))
+        ));
+        let removed = arena.insert(node(3));
+        resolver.register("gone", removed).unwrap();
+        arena.remove(removed);
+
+        resolver.edge("a", next, "gone");
+        assert!(matches!(
+            resolver.wire(&arena),
+            Err(ResolveError::Stale("gone"))
+        ));
+
+        let mut resolver = IndexResolver::<_, Node>::new();
+        resolver.edge("a", next, "missing");
+        assert!(matches!(
+            resolver.wire(&arena),
+            Err(ResolveError::Unresolved(edges)) if edges == [("a", "missing")]
+        ));
+    }
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn indexed_wiring_keeps_registration_order() {
+        let mut arena = Arena::new();
+        let mut resolver = IndexResolver::new();
+        for (key, value) in [("c", 1), ("a", 2), ("b", 3)] {
+            resolver.register(key, arena.insert(node(value))).unwrap();
+        }
+        resolver.edge("c", next, "a");
+        let keys = resolver.wire_indexed(&arena).unwrap();
+        assert_eq!(keys.keys().copied().collect::<Vec<_>>(), ["c", "a", "b"]);
+        assert_eq!(arena[keys["c"]].next.try_get(&arena).unwrap().value, 2);
+    }
+}
@@ -0,0 +1,225 @@
+//! Reports of cells left dangling when a graph is dropped.
+//!
+//! An [`AuditedGraph`] owns a graph's nodes and, when dropped, checks every
+//! [`DeferredFields`] cell for ones that were never wired or whose target died before
+//! the graph did. Findings go to a callback, or by default to the `tracing` and `log`
+//! diagnostics when those features are enabled.
+//!
+//! ```
+//! use deferred_cell::{AnyDeferredCell, Deferred, DeferredFields, SetOnce, audit::AuditedGraph};
+//! use std::{cell::RefCell, rc::Rc};
+//!
+//! struct Node {
+//!     next: Deferred<Node>,
+//! }
+//!
+//! impl DeferredFields for Node {
+//!     fn deferred_fields(&self) -> impl Iterator<Item = (&'static str, &dyn AnyDeferredCell)> {
+//!         [("next", &self.next as &dyn AnyDeferredCell)].into_iter()
+//!     }
+//! }
+//!
+//! let found = Rc::new(RefCell::new(Vec::new()));
+//! let sink = Rc::clone(&found);
+//! let a = Rc::new(Node { next: Deferred::new() });
+//! let b = Rc::new(Node { next: Deferred::new() });
+//! SetOnce::from(&a.next).try_set(&b).unwrap();
+//!
+//! drop(AuditedGraph::new(vec![a, b]).with_reporter(move |cells| {
+//!     sink.borrow_mut().extend(cells.iter().map(ToString::to_string));
+//! }));
+//! assert_eq!(*found.borrow(), ["node 1 `next` was never wired"]);
+//! ```
+
+use std::{fmt, mem, ops::Deref, rc::Rc};
+
+use crate::{DeferredFields, diagnostics};
+
+/// Why a cell was reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DanglingReason {
+    /// The cell was never set.
+    Unset,
+    /// The cell was set, but its target was dropped before the graph.
+    TargetDropped,
+}
+
+/// A cell found dangling by an audit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DanglingCell {
+    /// Index of the node holding the cell.
+    pub node: usize,
+    /// Name of the field holding the cell.
+    pub field: &'static str,
+    /// Name of the type the cell points to.
+    pub target_type: &'static str,
+    pub reason: DanglingReason,
+}
+
+impl fmt::Display for DanglingCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problem = match self.reason {
+            DanglingReason::Unset => "was never wired",
+            DanglingReason::TargetDropped => "outlived its target",
+        };
+        write!(f, "node {} `{}` {problem}", self.node, self.field)
+    }
+}
+
+type Reporter = Box<dyn FnMut(&[DanglingCell])>;
+
+/// Owns the nodes of a graph and reports dangling cells when dropped.
+pub struct AuditedGraph<T: DeferredFields> {
+    nodes: Vec<Rc<T>>,
+    reporter: Option<Reporter>,
+}
+
+impl<T: DeferredFields> AuditedGraph<T> {
+    /// Takes ownership of `nodes`, reporting through the diagnostics features on drop.
+    #[must_use]
+    pub fn new(nodes: Vec<Rc<T>>) -> Self {
+        Self {
+            nodes,
+            reporter: None,
+        }
+    }
+    /// Reports dangling cells to `reporter` instead. It is only called if any are found.
+    #[must_use]
+    pub fn with_reporter(mut self, reporter: impl FnMut(&[DanglingCell]) + 'static) -> Self {
+        self.reporter = Some(Box::new(reporter));
+        self
+    }
+    /// Adds a node to the audited graph.
+    pub fn push(&mut self, node: Rc<T>) {
+        self.nodes.push(node);
+    }
+    /// Returns every dangling cell, in node then field order.
+    #[must_use]
+    pub fn audit(&self) -> Vec<DanglingCell> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(node, value)| {
+                value.deferred_fields().filter_map(move |(field, cell)| {
+                    let reason = if !cell.is_ready() {
+                        DanglingReason::Unset
+                    } else if cell.is_dangling() {
+                        DanglingReason::TargetDropped
+                    } else {
+                        return None;
+                    };
+                    Some(DanglingCell {
+                        node,
+                        field,
+                        target_type: cell.target_type_name(),
+                        reason,
+                    })
+                })
+            })
+            .collect()
+    }
+    /// Returns the nodes without auditing them.
+    #[must_use]
+    pub fn into_nodes(mut self) -> Vec<Rc<T>> {
+        mem::take(&mut self.nodes)
+    }
+}
+
+impl<T: DeferredFields> Deref for AuditedGraph<T> {
+    type Target = [Rc<T>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.nodes
+    }
+}
+
+impl<T: DeferredFields> Drop for AuditedGraph<T> {
+    fn drop(&mut self) {
+        let dangling = self.audit();
+        if dangling.is_empty() {
+            return;
+        }
+        match &mut self.reporter {
+            Some(reporter) => reporter(&dangling),
+            None => dangling.iter().for_each(diagnostics::dangling_cell),
+        }
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{AnyDeferredCell, Deferred, SetOnce};
+
+    struct Node {
+        parent: Deferred<Self>,
+        label: Deferred<String>,
+    }
+
+    impl DeferredFields for Node {
+        fn deferred_fields(&self) -> impl Iterator<Item = (&'static str, &dyn AnyDeferredCell)> {
+            [
+                ("parent", &self.parent as &dyn AnyDeferredCell),
+                ("label", &self.label as &dyn AnyDeferredCell),
+            ]
+            .into_iter()
+        }
+    }
+
+    fn node() -> Rc<Node> {
+        Rc::new(Node {
+            parent: Deferred::new(),
+            label: Deferred::new(),
+        })
+    }
+
+    #[test]
+    fn reports_unset_and_outlived_cells() {
+        let found = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&found);
+        let (root, child) = (node(), node());
+        let label = Rc::new(String::from("child"));
+        SetOnce::from(&child.parent).try_set(&root).unwrap();
+        SetOnce::from(&child.label).try_set(&label).unwrap();
+        SetOnce::from(&root.label).try_set(&label).unwrap();
+        SetOnce::from(&root.parent).try_set(&root).unwrap();
+        let graph = AuditedGraph::new(vec![root, child])
+            .with_reporter(move |cells| sink.borrow_mut().extend_from_slice(cells));
+
+        assert!(graph.audit().is_empty());
+        drop(label);
+        drop(graph);
+
+        let found = found.borrow();
+        assert_eq!(
+            found.iter().map(|c| (c.node, c.field)).collect::<Vec<_>>(),
+            [(0, "label"), (1, "label")]
+        );
+        assert!(
+            found
+                .iter()
+                .all(|c| c.reason == DanglingReason::TargetDropped
+                    && c.target_type == "alloc::string::String")
+        );
+    }
+    #[test]
+    fn into_nodes_skips_the_audit() {
+        let called = Rc::new(RefCell::new(false));
+        let flag = Rc::clone(&called);
+        let graph =
+            AuditedGraph::new(vec![node()]).with_reporter(move |_| *flag.borrow_mut() = true);
+        assert_eq!(graph.audit().len(), 2);
+        assert_eq!(graph.into_nodes().len(), 1);
+        assert!(!*called.borrow());
+    }
+}
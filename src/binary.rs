@@ -0,0 +1,588 @@
+//! A compact binary format for wired graphs, with no dependencies.
+//!
+//! [`encode`] writes the node count, then for each node its number of edges, the length
+//! of its payload and the payload as written by [`BinaryPayload::encode`], and the
+//! index of each edge's target as the difference from the previous one. Numbers are
+//! LEB128 varints, and differences are zigzag encoded, so graphs whose edges mostly
+//! lead to nearby nodes take a byte or two per edge. A CRC-32 of everything before it ends the output, and [`decode`]
+//! checks it before reading anything, so corrupted snapshots fail with
+//! [`CodecError::Corrupted`] instead of building a wrong graph. Otherwise it rebuilds
+//! the nodes and wires them again.
+//!
+//! [`encode_to`] and [`decode_from`] do the same through `std::io` writers and readers,
+//! one node at a time, so large graphs need not be held as bytes in memory. They need
+//! the `std` feature; [`encode`] and [`decode`] only need `alloc`.
+//!
+//! [`write_varint`] and [`read_varint`] are public so payloads can use the same
+//! encoding for their own numbers. They, [`crc32`], and [`CodecError`] only need `core`,
+//! so firmware without an allocator can read and check snapshots too.
+//!
+//! ```
+//! use deferred_cell::{
+//!     Deferred, HasNeighbors, SetOnce,
+//!     binary::{self, BinaryPayload, CodecError},
+//! };
+//! use std::rc::Rc;
+//!
+//! struct Sensor {
+//!     id: u64,
+//!     peers: Vec<Deferred<Sensor>>,
+//! }
+//!
+//! impl HasNeighbors for Sensor {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         self.peers.iter()
+//!     }
+//! }
+//!
+//! impl BinaryPayload for Sensor {
+//!     fn encode(&self, out: &mut Vec<u8>) {
+//!         binary::write_varint(out, self.id);
+//!     }
+//!     fn decode(input: &mut &[u8], degree: usize) -> Result<Self, CodecError> {
+//!         let id = binary::read_varint(input)?;
+//!         Ok(Sensor { id, peers: (0..degree).map(|_| Deferred::new()).collect() })
+//!     }
+//! }
+//!
+//! let sensors: Vec<_> = [(300, 1), (7, 1)]
+//!     .map(|(id, degree)| {
+//!         Rc::new(Sensor { id, peers: (0..degree).map(|_| Deferred::new()).collect() })
+//!     })
+//!     .into();
+//! SetOnce::from(&sensors[0].peers[0]).try_set(&sensors[1]).unwrap();
+//! SetOnce::from(&sensors[1].peers[0]).try_set(&sensors[0]).unwrap();
+//!
+//! let bytes = binary::encode(&sensors).unwrap();
+//! assert_eq!(bytes.len(), 14);
+//! let decoded = binary::decode(&bytes, |s: &Sensor, k| &s.peers[k]).unwrap();
+//! assert_eq!(decoded[0].peers[0].get().id, 7);
+//! ```
+
+#[cfg(feature = "alloc")]
+use alloc::{rc::Rc, vec::Vec};
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+use thiserror::Error;
+
+#[cfg(feature = "alloc")]
+use crate::{Deferred, DeferredError, HasNeighbors, SetOnce, frozen::Frozen};
+
+/// A problem encoding or decoding a graph.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum CodecError {
+    #[error("Input ended in the middle of a value!")]
+    UnexpectedEnd,
+    #[error("Checksum {actual:#010x} does not match the stored {expected:#010x}!")]
+    Corrupted { expected: u32, actual: u32 },
+    #[error("Varint does not fit in 64 bits!")]
+    Overflow,
+    #[error("Node {node} has an edge to node {target}, which does not exist!")]
+    OutOfRange { node: usize, target: i128 },
+    /// A payload [`BinaryPayload::decode`] could not read, and why.
+    #[error("Invalid payload: {0}!")]
+    Payload(&'static str),
+    #[error("Node {node} left {len} bytes of its payload unread!")]
+    UnreadPayload { node: usize, len: usize },
+    /// A node claiming more edges than the input can hold, or than [`decode_from`]
+    /// accepts.
+    #[error("Node {node} claims {degree} edges, more than the input can hold!")]
+    TooManyEdges { node: usize, degree: usize },
+    #[cfg(feature = "alloc")]
+    #[error(transparent)]
+    Wiring(#[from] DeferredError),
+    #[cfg(feature = "std")]
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for CodecError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => Self::UnexpectedEnd,
+            _ => Self::Io(error),
+        }
+    }
+}
+
+/// A node type that can write its payload to bytes and read it back.
+///
+/// Only the payload is written; edges are handled by [`encode`] and [`decode`].
+#[cfg(feature = "alloc")]
+pub trait BinaryPayload: Sized {
+    /// Appends the node's payload to `out`.
+    fn encode(&self, out: &mut Vec<u8>);
+    /// Reads a payload from `input`, which holds exactly the bytes written by
+    /// [`encode`](Self::encode), and makes a node with `degree` unset cells.
+    fn decode(input: &mut &[u8], degree: usize) -> Result<Self, CodecError>;
+}
+
+/// Appends `value` to `out` as a LEB128 varint.
+pub fn write_varint(out: &mut impl Extend<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.extend([(value as u8) | 0x80]);
+        value >>= 7;
+    }
+    out.extend([value as u8]);
+}
+
+/// Reads a LEB128 varint from the front of `input`, advancing past it.
+pub fn read_varint(input: &mut &[u8]) -> Result<u64, CodecError> {
+    read_varint_with(|| {
+        let (&byte, rest) = input.split_first().ok_or(CodecError::UnexpectedEnd)?;
+        *input = rest;
+        Ok(byte)
+    })
+}
+
+fn read_varint_with(mut next: impl FnMut() -> Result<u8, CodecError>) -> Result<u64, CodecError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = next()?;
+        let bits = u64::from(byte & 0x7f);
+        if bits << shift >> shift != bits {
+            return Err(CodecError::Overflow);
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(CodecError::Overflow)
+}
+
+/// Returns the CRC-32 (IEEE) checksum of `bytes`.
+#[must_use]
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !crc32_update(!0, bytes)
+}
+
+fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// Where an encoded graph is written: a `Vec` for [`encode`], or a writer for
+/// [`encode_to`].
+#[cfg(feature = "alloc")]
+trait Sink {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), CodecError>;
+}
+
+#[cfg(feature = "alloc")]
+impl Sink for Vec<u8> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), CodecError> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+struct Writer<W>(W);
+
+#[cfg(feature = "std")]
+impl<W: Write> Sink for Writer<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), CodecError> {
+        Ok(self.0.write_all(bytes)?)
+    }
+}
+
+/// Where an encoded graph is read from: a slice for [`decode`], or a reader for
+/// [`decode_from`].
+#[cfg(feature = "alloc")]
+trait Source {
+    /// Reads exactly `len` bytes into `buf`, without allocating for more than the input
+    /// holds.
+    fn read(&mut self, len: usize, buf: &mut Vec<u8>) -> Result<(), CodecError>;
+    /// The most edges the next node can have. Each edge takes at least one byte.
+    fn max_degree(&self) -> usize;
+}
+
+#[cfg(feature = "alloc")]
+impl Source for &[u8] {
+    fn read(&mut self, len: usize, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        let (bytes, rest) = self
+            .split_at_checked(len)
+            .ok_or(CodecError::UnexpectedEnd)?;
+        buf.clear();
+        buf.extend_from_slice(bytes);
+        *self = rest;
+        Ok(())
+    }
+    fn max_degree(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(feature = "std")]
+struct Reader<R>(R);
+
+#[cfg(feature = "std")]
+impl<R: Read> Source for Reader<R> {
+    fn read(&mut self, len: usize, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        buf.clear();
+        (&mut self.0).take(len as u64).read_to_end(buf)?;
+        if buf.len() < len {
+            return Err(CodecError::UnexpectedEnd);
+        }
+        Ok(())
+    }
+    fn max_degree(&self) -> usize {
+        MAX_STREAMED_DEGREE
+    }
+}
+
+/// A [`Sink`] or [`Source`] that keeps a running checksum of the bytes passing through.
+#[cfg(feature = "alloc")]
+struct Checked<S> {
+    inner: S,
+    crc: u32,
+}
+
+#[cfg(feature = "alloc")]
+impl<S> Checked<S> {
+    const fn new(inner: S) -> Self {
+        Self { inner, crc: !0 }
+    }
+    const fn checksum(&self) -> u32 {
+        !self.crc
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Sink> Checked<S> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), CodecError> {
+        self.crc = crc32_update(self.crc, bytes);
+        self.inner.write(bytes)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Source> Checked<S> {
+    fn read(&mut self, len: usize, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        self.inner.read(len, buf)?;
+        self.crc = crc32_update(self.crc, buf);
+        Ok(())
+    }
+    fn varint(&mut self) -> Result<u64, CodecError> {
+        let mut byte = Vec::with_capacity(1);
+        read_varint_with(|| {
+            self.read(1, &mut byte)?;
+            Ok(byte[0])
+        })
+    }
+    fn len(&mut self) -> Result<usize, CodecError> {
+        usize::try_from(self.varint()?).map_err(|_| CodecError::Overflow)
+    }
+}
+
+/// Writes `nodes` and the [`HasNeighbors`] edges between them, followed by a checksum.
+///
+/// Fails if an edge is unset or leads outside `nodes`.
+#[cfg(feature = "alloc")]
+pub fn encode<T: HasNeighbors + BinaryPayload>(nodes: &[Rc<T>]) -> Result<Vec<u8>, CodecError> {
+    write_graph(nodes, Vec::new())
+}
+
+/// Like [`encode`], but writes to `out` one node at a time, and returns it.
+#[cfg(feature = "std")]
+pub fn encode_to<T, W>(nodes: &[Rc<T>], out: W) -> Result<W, CodecError>
+where
+    T: HasNeighbors + BinaryPayload,
+    W: Write,
+{
+    write_graph(nodes, Writer(out)).map(|Writer(out)| out)
+}
+
+#[cfg(feature = "alloc")]
+fn write_graph<T, S>(nodes: &[Rc<T>], out: S) -> Result<S, CodecError>
+where
+    T: HasNeighbors + BinaryPayload,
+    S: Sink,
+{
+    let graph = Frozen::new(nodes)?;
+    let mut out = Checked::new(out);
+    let (mut record, mut payload) = (Vec::new(), Vec::new());
+    write_varint(&mut record, nodes.len() as u64);
+    for node in graph.iter() {
+        payload.clear();
+        node.encode(&mut payload);
+        write_varint(&mut record, node.neighbors().count() as u64);
+        write_varint(&mut record, payload.len() as u64);
+        record.extend_from_slice(&payload);
+        let mut previous = node.index() as i64;
+        for target in node.neighbors() {
+            let delta = target.index() as i64 - previous;
+            write_varint(&mut record, ((delta << 1) ^ (delta >> 63)) as u64);
+            previous = target.index() as i64;
+        }
+        out.write(&record)?;
+        record.clear();
+    }
+    let checksum = out.checksum();
+    out.inner.write(&checksum.to_le_bytes())?;
+    Ok(out.inner)
+}
+
+/// Checks the checksum of nodes written by [`encode`], then reads them and wires the
+/// `k`-th edge of each through `slot(node, k)`.
+///
+/// A node claiming more edges than there are bytes left fails with
+/// [`CodecError::TooManyEdges`] before its payload is decoded.
+#[cfg(feature = "alloc")]
+pub fn decode<T: BinaryPayload>(
+    input: &[u8],
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, CodecError> {
+    let split = input
+        .len()
+        .checked_sub(4)
+        .ok_or(CodecError::UnexpectedEnd)?;
+    let (body, stored) = input.split_at(split);
+    let expected = u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]);
+    let actual = crc32(body);
+    if actual != expected {
+        return Err(CodecError::Corrupted { expected, actual });
+    }
+    read_graph(input, slot)
+}
+
+/// The most edges [`decode_from`] accepts on one node, since a reader cannot tell how
+/// many bytes are left.
+#[cfg(feature = "std")]
+pub const MAX_STREAMED_DEGREE: usize = 1 << 16;
+
+/// Like [`decode`], but reads from `input` one node at a time.
+///
+/// The checksum can only be compared at the end, before any edge is wired, so a
+/// corrupted stream may fail with another error first. Nodes with more than
+/// [`MAX_STREAMED_DEGREE`] edges fail with [`CodecError::TooManyEdges`].
+#[cfg(feature = "std")]
+pub fn decode_from<T: BinaryPayload>(
+    input: impl Read,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, CodecError> {
+    read_graph(Reader(input), slot)
+}
+
+#[cfg(feature = "alloc")]
+fn read_graph<T: BinaryPayload>(
+    input: impl Source,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, CodecError> {
+    let mut input = Checked::new(input);
+    let len = input.varint()?;
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut payload = Vec::new();
+    for index in 0..len {
+        let degree = input.len()?;
+        let size = input.len()?;
+        input.read(size, &mut payload)?;
+        if degree > input.inner.max_degree() {
+            return Err(CodecError::TooManyEdges {
+                node: nodes.len(),
+                degree,
+            });
+        }
+        let mut rest = payload.as_slice();
+        nodes.push(Rc::new(T::decode(&mut rest, degree)?));
+        if !rest.is_empty() {
+            return Err(CodecError::UnreadPayload {
+                node: nodes.len() - 1,
+                len: rest.len(),
+            });
+        }
+        let mut previous = i128::from(index);
+        for k in 0..degree {
+            let zigzag = input.varint()?;
+            let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+            let target = previous + i128::from(delta);
+            if !(0..i128::from(len)).contains(&target) {
+                return Err(CodecError::OutOfRange {
+                    node: nodes.len() - 1,
+                    target,
+                });
+            }
+            edges.push((nodes.len() - 1, k, target as usize));
+            previous = target;
+        }
+    }
+    let actual = input.checksum();
+    input.inner.read(4, &mut payload)?;
+    let expected = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+    if actual != expected {
+        return Err(CodecError::Corrupted { expected, actual });
+    }
+    for (from, k, to) in edges {
+        SetOnce::from(slot(&nodes[from], k)).try_set(&nodes[to])?;
+    }
+    Ok(nodes)
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Node {
+        name: String,
+        edges: Vec<Deferred<Self>>,
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.edges.iter()
+        }
+    }
+
+    impl BinaryPayload for Node {
+        fn encode(&self, out: &mut Vec<u8>) {
+            out.extend_from_slice(self.name.as_bytes());
+        }
+        fn decode(input: &mut &[u8], degree: usize) -> Result<Self, CodecError> {
+            let name = std::mem::take(input);
+            Ok(Self {
+                name: String::from_utf8(name.to_vec())
+                    .map_err(|_| CodecError::Payload("name is not UTF-8"))?,
+                edges: (0..degree).map(|_| Deferred::new()).collect(),
+            })
+        }
+    }
+
+    fn slot(node: &Node, k: usize) -> &Deferred<Node> {
+        &node.edges[k]
+    }
+
+    /// Encodes `a -> [c, a, c]`, `b`, and `c -> [b]`.
+    fn sample() -> Vec<u8> {
+        let edges = [vec![2, 0, 2], vec![], vec![1]];
+        let nodes: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .zip(&edges)
+            .map(|(name, targets)| {
+                Rc::new(Node {
+                    name: (*name).to_owned(),
+                    edges: targets.iter().map(|_| Deferred::new()).collect(),
+                })
+            })
+            .collect();
+        for (from, targets) in edges.iter().enumerate() {
+            for (k, &to) in targets.iter().enumerate() {
+                SetOnce::from(&nodes[from].edges[k])
+                    .try_set(&nodes[to])
+                    .unwrap();
+            }
+        }
+        let bytes = encode(&nodes).unwrap();
+        assert_eq!(encode_to(&nodes, Vec::new()).unwrap(), bytes);
+        bytes
+    }
+
+    /// Appends the checksum a valid encoding ends with.
+    fn sealed(mut bytes: Vec<u8>) -> Vec<u8> {
+        bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn graphs_round_trip() {
+        let decoded = decode(&sample(), slot).unwrap();
+        let targets: Vec<Vec<_>> = decoded
+            .iter()
+            .map(|n| n.edges.iter().map(|e| e.get().name.clone()).collect())
+            .collect();
+        assert_eq!(targets, [vec!["c", "a", "c"], vec![], vec!["b"]]);
+    }
+    #[test]
+    fn graphs_stream_from_readers() {
+        let bytes = sample();
+        let streamed = decode_from(bytes.as_slice(), slot).unwrap();
+        assert_eq!(streamed[0].edges[0].get().name, "c");
+        assert_eq!(streamed[2].edges[0].get().name, "b");
+    }
+    #[test]
+    fn truncated_input_is_rejected() {
+        let bytes = sample();
+        for end in 0..bytes.len() {
+            assert!(decode(&bytes[..end], slot).is_err());
+            assert!(decode_from(&bytes[..end], slot).is_err());
+        }
+    }
+    #[test]
+    fn checksum_mismatches_are_corruption() {
+        let mut flipped = sample();
+        flipped[3] ^= 0x10;
+        assert!(matches!(
+            decode(&flipped, slot),
+            Err(CodecError::Corrupted { .. })
+        ));
+        assert!(matches!(
+            decode_from(flipped.as_slice(), slot),
+            Err(CodecError::Corrupted { .. })
+        ));
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+    #[test]
+    fn overlong_varints_overflow() {
+        let mut overflow = vec![0xff; 10];
+        overflow.push(0x01);
+        assert!(matches!(
+            read_varint(&mut overflow.as_slice()),
+            Err(CodecError::Overflow)
+        ));
+    }
+    #[test]
+    fn edges_must_stay_in_the_graph() {
+        // One node with an edge to node 1.
+        let bad = sealed(vec![1, 1, 1, b'a', 2]);
+        assert!(matches!(
+            decode(&bad, slot),
+            Err(CodecError::OutOfRange { node: 0, target: 1 })
+        ));
+    }
+    #[test]
+    fn huge_degrees_are_rejected_before_decoding() {
+        // One node claiming u64::MAX edges, with a one-byte payload.
+        let mut huge = vec![1];
+        write_varint(&mut huge, u64::MAX);
+        huge.extend_from_slice(&[1, b'a']);
+        let huge = sealed(huge);
+        assert!(matches!(
+            decode(&huge, slot),
+            Err(CodecError::TooManyEdges { node: 0, .. })
+        ));
+        assert!(matches!(
+            decode_from(huge.as_slice(), slot),
+            Err(CodecError::TooManyEdges { node: 0, .. })
+        ));
+    }
+    #[test]
+    fn streamed_degrees_are_capped() {
+        let mut wide = vec![1];
+        write_varint(&mut wide, MAX_STREAMED_DEGREE as u64 + 1);
+        wide.extend_from_slice(&[1, b'a']);
+        wide.extend(std::iter::repeat_n(0, MAX_STREAMED_DEGREE + 1));
+        let wide = sealed(wide);
+        assert!(matches!(
+            decode_from(wide.as_slice(), slot),
+            Err(CodecError::TooManyEdges { node: 0, .. })
+        ));
+    }
+    #[test]
+    fn payload_errors_are_reported() {
+        let invalid = sealed(vec![1, 0, 1, 0xff]);
+        assert_eq!(
+            decode(&invalid, slot).unwrap_err().to_string(),
+            "Invalid payload: name is not UTF-8!"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Borrowing the [`RefCell`] behind a [`Deferred`] cell in one step.
+
+use std::{
+    cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut},
+    fmt,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+use crate::{Deferred, DeferredError};
+
+/// The error returned when borrowing the target of a [`Deferred`] cell fails.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum DeferredBorrowError {
+    #[error(transparent)]
+    Deferred(#[from] DeferredError),
+    #[error("Cannot borrow a value that is mutably borrowed!")]
+    Borrow(#[from] BorrowError),
+    #[error("Cannot mutably borrow a value that is already borrowed!")]
+    BorrowMut(#[from] BorrowMutError),
+}
+
+/// A shared borrow of a cell's target, which keeps the target alive until dropped.
+pub struct DeferredRef<'a, T> {
+    // Declared first so the borrow ends before `_target` is released.
+    guard: Ref<'a, T>,
+    _target: Rc<RefCell<T>>,
+}
+
+/// A mutable borrow of a cell's target, which keeps the target alive until dropped.
+pub struct DeferredRefMut<'a, T> {
+    // Declared first so the borrow ends before `_target` is released.
+    guard: RefMut<'a, T>,
+    _target: Rc<RefCell<T>>,
+}
+
+impl<T> Deref for DeferredRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> Deref for DeferredRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for DeferredRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DeferredRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DeferredRef").field(&*self.guard).finish()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DeferredRefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DeferredRefMut").field(&*self.guard).finish()
+    }
+}
+
+/// Upgrades a `Deferred<RefCell<T>>` and borrows its target in one step.
+///
+/// ```
+/// use deferred_cell::{Deferred, DeferredRefCellExt};
+/// use std::{cell::RefCell, rc::Rc};
+///
+/// let log = Rc::new(RefCell::new(Vec::new()));
+/// let cell = Deferred::from(&log);
+/// cell.borrow_mut_deferred().unwrap().push("started");
+/// assert_eq!(*cell.borrow_deferred().unwrap(), ["started"]);
+///
+/// let reading = cell.borrow_deferred().unwrap();
+/// assert!(cell.borrow_mut_deferred().is_err());
+/// drop((reading, log));
+/// assert!(cell.borrow_deferred().is_err());
+/// ```
+pub trait DeferredRefCellExt<T> {
+    /// Upgrades the cell and immutably borrows its target.
+    fn borrow_deferred(&self) -> Result<DeferredRef<'_, T>, DeferredBorrowError>;
+    /// Upgrades the cell and mutably borrows its target.
+    fn borrow_mut_deferred(&self) -> Result<DeferredRefMut<'_, T>, DeferredBorrowError>;
+}
+
+impl<T> DeferredRefCellExt<T> for Deferred<RefCell<T>> {
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    fn borrow_deferred(&self) -> Result<DeferredRef<'_, T>, DeferredBorrowError> {
+        let target = self.try_get()?;
+        // SAFETY: the `RefCell` lives in the allocation `target` keeps alive, which the
+        // guard owns and releases only after the borrow has ended.
+        let cell = unsafe { &*Rc::as_ptr(&target) };
+        Ok(DeferredRef {
+            guard: cell.try_borrow()?,
+            _target: target,
+        })
+    }
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    fn borrow_mut_deferred(&self) -> Result<DeferredRefMut<'_, T>, DeferredBorrowError> {
+        let target = self.try_get()?;
+        // SAFETY: as in `borrow_deferred`.
+        let cell = unsafe { &*Rc::as_ptr(&target) };
+        Ok(DeferredRefMut {
+            guard: cell.try_borrow_mut()?,
+            _target: target,
+        })
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Account {
+        balance: u32,
+        owner: Deferred<RefCell<Self>>,
+    }
+
+    #[test]
+    fn borrows_keep_targets_alive() {
+        let bank = Rc::new(RefCell::new(Account {
+            balance: 10,
+            owner: Deferred::new(),
+        }));
+        let client = Account {
+            balance: 0,
+            owner: Deferred::from(&bank),
+        };
+        let mut owner = client.owner.borrow_mut_deferred().unwrap();
+        drop(bank);
+        owner.balance += 5;
+        assert!(matches!(
+            client.owner.borrow_deferred(),
+            Err(DeferredBorrowError::Borrow(_))
+        ));
+        assert_eq!(owner.balance, 15);
+        assert!(matches!(
+            owner.owner.borrow_mut_deferred(),
+            Err(DeferredBorrowError::Deferred(_))
+        ));
+        drop(owner);
+        assert!(client.owner.borrow_deferred().is_err());
+    }
+}
@@ -0,0 +1,34 @@
+//! Typestate markers used by builders generated with `#[derive(DeferredBuilder)]`.
+//!
+//! A generated builder carries one type parameter per deferred field. It starts as
+//! [`Missing`] and becomes [`Provided`] once the field is supplied; `build()` is only
+//! implemented when every required field is [`Provided`], so forgetting to wire a field
+//! is a compile error rather than a runtime
+//! [`NotInitializedError`](crate::DeferredError::NotInitializedError).
+
+/// Marks a deferred field that has not been supplied yet.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Missing;
+
+/// Marks a deferred field that has been supplied, holding its already-set cell.
+#[derive(Debug, Clone)]
+pub struct Provided<C>(pub C);
+
+/// Converts a builder field state into the cell stored in the built node.
+pub trait Slot<C> {
+    fn into_cell(self) -> C;
+}
+
+impl<C: Default> Slot<C> for Missing {
+    #[inline]
+    fn into_cell(self) -> C {
+        C::default()
+    }
+}
+
+impl<C> Slot<C> for Provided<C> {
+    #[inline]
+    fn into_cell(self) -> C {
+        self.0
+    }
+}
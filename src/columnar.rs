@@ -0,0 +1,207 @@
+//! Structure-of-arrays graph storage.
+//!
+//! Instead of one `Rc` per node, a columnar graph keeps each payload field in its own
+//! [`Column`] and each kind of edge in a [`LinkColumn`] of set-once cells holding the
+//! [`NodeId`] of their target. Scanning one field across every node is then a walk
+//! over a contiguous slice rather than a chain of pointers, and following an edge is an
+//! index lookup. Link cells keep the same rules as [`Deferred`](crate::Deferred): each
+//! can be set once, and reading an unset one is an error.
+//!
+//! ```
+//! use deferred_cell::columnar::{Column, LinkColumn};
+//!
+//! struct Employees {
+//!     name: Column<&'static str>,
+//!     salary: Column<u32>,
+//!     manager: LinkColumn,
+//! }
+//!
+//! let mut staff = Employees { name: Column::new(), salary: Column::new(), manager: LinkColumn::new() };
+//! for (name, salary) in [("Ada", 300), ("Grace", 200), ("Alan", 100)] {
+//!     staff.name.push(name);
+//!     staff.salary.push(salary);
+//!     staff.manager.push_unset();
+//! }
+//! let [ada, grace, alan] = [0, 1, 2].map(|i| staff.name.id(i));
+//! staff.manager.try_set(grace, ada).unwrap();
+//! staff.manager.try_set(alan, grace).unwrap();
+//!
+//! assert_eq!(staff.salary.iter().sum::<u32>(), 600);
+//! assert_eq!(staff.name[staff.manager.try_get(alan).unwrap()], "Grace");
+//! assert!(staff.manager.try_get(ada).is_err());
+//! ```
+
+use std::{cell::OnceCell, ops::Index};
+
+use crate::DeferredError;
+
+/// The position of a node in a columnar graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    /// Returns the node's row in every column.
+    #[must_use]
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+    /// # Panics
+    /// Panics if `index` does not fit in a `u32`.
+    fn new(index: usize) -> Self {
+        #[allow(clippy::expect_used)]
+        Self(u32::try_from(index).expect("columnar graphs hold at most u32::MAX nodes"))
+    }
+}
+
+/// One payload field of every node, indexed by [`NodeId`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column<T>(Vec<T>);
+
+impl<T> Default for Column<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Column<T> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+    /// Appends a value and returns the id of its row.
+    pub fn push(&mut self, value: T) -> NodeId {
+        self.0.push(value);
+        NodeId::new(self.0.len() - 1)
+    }
+    /// Returns the id of row `index`.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    #[must_use]
+    pub fn id(&self, index: usize) -> NodeId {
+        assert!(index < self.0.len(), "row {index} out of bounds");
+        NodeId::new(index)
+    }
+    #[must_use]
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.0.get(id.index())
+    }
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.0.len()
+    }
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.0.iter()
+    }
+    /// Returns every value as a contiguous slice, in row order.
+    #[must_use]
+    pub fn as_slice(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<T> Index<NodeId> for Column<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId) -> &T {
+        &self.0[id.index()]
+    }
+}
+
+impl<T> FromIterator<T> for Column<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Column<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// One edge of every node, stored as set-once cells holding the target's [`NodeId`].
+///
+/// Methods taking a row panic if it is out of bounds, like slice indexing.
+#[derive(Debug, Clone, Default)]
+pub struct LinkColumn(Vec<OnceCell<NodeId>>);
+
+impl LinkColumn {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+    /// Creates a column of `len` unset cells.
+    #[must_use]
+    pub fn unset(len: usize) -> Self {
+        Self(vec![OnceCell::new(); len])
+    }
+    /// Appends an unset cell and returns the id of its row.
+    pub fn push_unset(&mut self) -> NodeId {
+        self.0.push(OnceCell::new());
+        NodeId::new(self.0.len() - 1)
+    }
+    /// Points the cell of `row` at `target`.
+    pub fn try_set(&self, row: NodeId, target: NodeId) -> Result<(), DeferredError> {
+        self.0[row.index()]
+            .set(target)
+            .map_err(|_| DeferredError::DuplicateInitialization())
+    }
+    /// Returns the target of the cell of `row`.
+    pub fn try_get(&self, row: NodeId) -> Result<NodeId, DeferredError> {
+        self.0[row.index()]
+            .get()
+            .copied()
+            .ok_or(DeferredError::NotInitializedError())
+    }
+    #[must_use]
+    pub fn is_ready(&self, row: NodeId) -> bool {
+        self.0[row.index()].get().is_some()
+    }
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.0.len()
+    }
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Returns each row's target, or `None` where the cell is unset.
+    pub fn iter(&self) -> impl Iterator<Item = Option<NodeId>> + '_ {
+        self.0.iter().map(|cell| cell.get().copied())
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links_are_set_once() {
+        let names: Column<_> = ["a", "b", "c"].into_iter().collect();
+        let next = LinkColumn::unset(names.len());
+        let [a, b, c] = [0, 1, 2].map(|i| names.id(i));
+
+        next.try_set(a, b).unwrap();
+        next.try_set(b, c).unwrap();
+        assert!(matches!(
+            next.try_set(a, c),
+            Err(DeferredError::DuplicateInitialization())
+        ));
+        assert!(matches!(
+            next.try_get(c),
+            Err(DeferredError::NotInitializedError())
+        ));
+        assert_eq!(names[next.try_get(next.try_get(a).unwrap()).unwrap()], "c");
+        assert_eq!(next.iter().collect::<Vec<_>>(), [Some(b), Some(c), None]);
+    }
+}
@@ -0,0 +1,256 @@
+//! Structural comparison of graphs.
+//!
+//! Two graphs are structurally equal when a walk from both roots in lockstep pairs up
+//! every reachable node one-to-one, paired nodes have equal keys, and their neighbor
+//! cells agree slot by slot. Node identity is ignored, so two separately built copies
+//! of a graph compare equal.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Debug, Display},
+    rc::Rc,
+};
+
+use crate::HasNeighbors;
+
+/// One difference found by [`diff`]. `path` lists the slots followed from the roots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch<K> {
+    /// Paired nodes have different keys.
+    Key { path: Vec<usize>, left: K, right: K },
+    /// Paired nodes have a different number of neighbor cells.
+    Degree {
+        path: Vec<usize>,
+        left: usize,
+        right: usize,
+    },
+    /// A neighbor cell is set on one side only.
+    Edge {
+        path: Vec<usize>,
+        slot: usize,
+        left_set: bool,
+        right_set: bool,
+    },
+    /// A neighbor cell leads to nodes that were already paired with other nodes.
+    Target { path: Vec<usize>, slot: usize },
+}
+
+fn fmt_path(f: &mut fmt::Formatter<'_>, path: &[usize]) -> fmt::Result {
+    write!(f, "root")?;
+    path.iter().try_for_each(|slot| write!(f, "/{slot}"))
+}
+
+impl<K: Debug> Display for Mismatch<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key { path, left, right } => {
+                write!(f, "node ")?;
+                fmt_path(f, path)?;
+                write!(f, ": {left:?} != {right:?}")
+            }
+            Self::Degree { path, left, right } => {
+                write!(f, "node ")?;
+                fmt_path(f, path)?;
+                write!(f, ": {left} edges != {right} edges")
+            }
+            Self::Edge {
+                path,
+                slot,
+                left_set,
+                right_set,
+            } => {
+                let state = |set: &bool| if *set { "set" } else { "unset" };
+                write!(f, "edge ")?;
+                fmt_path(f, path)?;
+                write!(f, " -> {slot}: {} != {}", state(left_set), state(right_set))
+            }
+            Self::Target { path, slot } => {
+                write!(f, "edge ")?;
+                fmt_path(f, path)?;
+                write!(f, " -> {slot}: leads to different nodes")
+            }
+        }
+    }
+}
+
+/// Compares the graphs reachable from `left` and `right`, pairing nodes by position.
+///
+/// Returns every mismatch found, in breadth-first order; an empty list means the graphs
+/// are structurally equal.
+pub fn diff<T, K>(left: &Rc<T>, right: &Rc<T>, key: impl Fn(&T) -> K) -> Vec<Mismatch<K>>
+where
+    T: HasNeighbors,
+    K: PartialEq,
+{
+    let mut mismatches = Vec::new();
+    let mut left_to_right = HashMap::from([(Rc::as_ptr(left), Rc::as_ptr(right))]);
+    let mut right_to_left = HashMap::from([(Rc::as_ptr(right), Rc::as_ptr(left))]);
+    let mut queue = VecDeque::from([(left.clone(), right.clone(), Vec::new())]);
+
+    while let Some((l, r, path)) = queue.pop_front() {
+        let (lk, rk) = (key(&l), key(&r));
+        if lk != rk {
+            mismatches.push(Mismatch::Key {
+                path: path.clone(),
+                left: lk,
+                right: rk,
+            });
+        }
+        let ln: Vec<_> = l.neighbors().map(|d| d.try_get().ok()).collect();
+        let rn: Vec<_> = r.neighbors().map(|d| d.try_get().ok()).collect();
+        if ln.len() != rn.len() {
+            mismatches.push(Mismatch::Degree {
+                path: path.clone(),
+                left: ln.len(),
+                right: rn.len(),
+            });
+        }
+        for (slot, (ln, rn)) in ln.into_iter().zip(rn).enumerate() {
+            let (ln, rn) = match (ln, rn) {
+                (Some(ln), Some(rn)) => (ln, rn),
+                (None, None) => continue,
+                (ln, rn) => {
+                    mismatches.push(Mismatch::Edge {
+                        path: path.clone(),
+                        slot,
+                        left_set: ln.is_some(),
+                        right_set: rn.is_some(),
+                    });
+                    continue;
+                }
+            };
+            let (lp, rp) = (Rc::as_ptr(&ln), Rc::as_ptr(&rn));
+            match (left_to_right.get(&lp), right_to_left.get(&rp)) {
+                (None, None) => {
+                    left_to_right.insert(lp, rp);
+                    right_to_left.insert(rp, lp);
+                    let mut next = path.clone();
+                    next.push(slot);
+                    queue.push_back((ln, rn, next));
+                }
+                (Some(&paired), _) if paired == rp => {}
+                _ => mismatches.push(Mismatch::Target {
+                    path: path.clone(),
+                    slot,
+                }),
+            }
+        }
+    }
+    mismatches
+}
+
+/// Returns `true` when the graphs reachable from `left` and `right` are structurally equal.
+pub fn graph_eq<T, K>(left: &Rc<T>, right: &Rc<T>, key: impl Fn(&T) -> K) -> bool
+where
+    T: HasNeighbors,
+    K: PartialEq,
+{
+    diff(left, right, key).is_empty()
+}
+
+/// Asserts that two graphs are structurally equal, printing every difference on failure.
+///
+/// Takes the two roots and a closure producing the comparable, `Debug` key of a node.
+/// See [`compare`](crate::compare) for what structural equality means.
+///
+/// ```should_panic
+/// use deferred_cell::{Deferred, HasNeighbors, SetOnce, assert_graph_eq};
+/// use std::rc::Rc;
+///
+/// struct Node {
+///     name: &'static str,
+///     next: Deferred<Node>,
+/// }
+///
+/// impl HasNeighbors for Node {
+///     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+///         std::iter::once(&self.next)
+///     }
+/// }
+///
+/// let pair = |a, b| {
+///     let first = Rc::new(Node { name: a, next: Deferred::new() });
+///     let second = Rc::new(Node { name: b, next: Deferred::new() });
+///     SetOnce::from(&first.next).try_set(&second).unwrap();
+///     (first, second)
+/// };
+/// let (left, _l) = pair("a", "b");
+/// let (right, _r) = pair("a", "c");
+///
+/// // Panics with: node root/0: "b" != "c"
+/// assert_graph_eq!(left, right, |n| n.name);
+/// ```
+#[macro_export]
+macro_rules! assert_graph_eq {
+    ($left:expr, $right:expr, $key:expr $(,)?) => {{
+        let mismatches = $crate::compare::diff(&$left, &$right, $key);
+        if !mismatches.is_empty() {
+            let report: ::std::vec::Vec<::std::string::String> =
+                mismatches.iter().map(|m| ::std::format!("  {m}")).collect();
+            ::core::panic!(
+                "assertion failed: graphs `{}` and `{}` differ:\n{}",
+                ::core::stringify!($left),
+                ::core::stringify!($right),
+                report.join("\n"),
+            );
+        }
+    }};
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Deferred, topology};
+
+    struct Node {
+        id: usize,
+        edges: Vec<Deferred<Self>>,
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.edges.iter()
+        }
+    }
+
+    fn node(slots: usize) -> impl Fn(usize) -> Node {
+        move |id| Node {
+            id,
+            edges: (0..slots).map(|_| Deferred::new()).collect(),
+        }
+    }
+
+    #[test]
+    fn separately_built_copies_are_equal() {
+        let a = topology::complete(4, node(3), |n, k| &n.edges[k]).unwrap();
+        let b = topology::complete(4, node(3), |n, k| &n.edges[k]).unwrap();
+        assert!(graph_eq(&a[0], &b[0], |n| n.id));
+        crate::assert_graph_eq!(a[1], b[1], |n| n.id);
+    }
+    #[test]
+    fn differences_are_reported() {
+        let ring = topology::ring(3, node(1), |n, k| &n.edges[k]).unwrap();
+        let longer = topology::ring(4, node(1), |n, k| &n.edges[k]).unwrap();
+        assert_eq!(
+            diff(&ring[0], &longer[0], |_| ()),
+            vec![Mismatch::Target {
+                path: vec![0, 0],
+                slot: 0
+            }]
+        );
+
+        let star = topology::star(3, node(2), |n, k| &n.edges[k]).unwrap();
+        let mismatches = diff(&ring[0], &star[0], |n| n.id);
+        let report: Vec<_> = mismatches.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            report,
+            vec![
+                "node root: 1 edges != 2 edges",
+                "node root/0: 1 edges != 2 edges",
+                "edge root/0 -> 0: leads to different nodes",
+            ]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! The [`binary`] format wrapped in zstd, enabled by the `compress`
+//! feature.
+//!
+//! Large graphs are mostly edge data, which repeats and compresses well. Like
+//! [`binary::encode_to`] and [`binary::decode_from`], [`encode_to`] and [`decode_from`]
+//! handle one node at a time, so a graph never has to be held in memory as bytes, either
+//! compressed or not.
+//!
+//! ```
+//! use deferred_cell::{
+//!     Deferred, HasNeighbors, SetOnce,
+//!     binary::{BinaryPayload, CodecError},
+//!     compress,
+//! };
+//! use std::rc::Rc;
+//!
+//! struct Cell {
+//!     next: Deferred<Cell>,
+//! }
+//!
+//! impl HasNeighbors for Cell {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         std::iter::once(&self.next)
+//!     }
+//! }
+//!
+//! impl BinaryPayload for Cell {
+//!     fn encode(&self, _: &mut Vec<u8>) {}
+//!     fn decode(_: &mut &[u8], _: usize) -> Result<Self, CodecError> {
+//!         Ok(Cell { next: Deferred::new() })
+//!     }
+//! }
+//!
+//! let cells: Vec<_> = (0..1000).map(|_| Rc::new(Cell { next: Deferred::new() })).collect();
+//! for (i, cell) in cells.iter().enumerate() {
+//!     SetOnce::from(&cell.next).try_set(&cells[(i + 1) % cells.len()]).unwrap();
+//! }
+//!
+//! let mut file = Vec::new();
+//! compress::encode_to(&cells, &mut file, compress::DEFAULT_LEVEL).unwrap();
+//! let decoded = compress::decode_from(file.as_slice(), |c: &Cell, _| &c.next).unwrap();
+//! assert_eq!(decoded.len(), 1000);
+//! ```
+
+use std::{
+    io::{Read, Write},
+    rc::Rc,
+};
+
+use crate::{
+    Deferred, HasNeighbors,
+    binary::{self, BinaryPayload, CodecError},
+};
+
+/// The zstd level used when no other is chosen.
+pub const DEFAULT_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+/// Writes `nodes` as [`binary::encode`] does, compressed at zstd `level`.
+pub fn encode<T: HasNeighbors + BinaryPayload>(
+    nodes: &[Rc<T>],
+    level: i32,
+) -> Result<Vec<u8>, CodecError> {
+    encode_to(nodes, Vec::new(), level)
+}
+
+/// Like [`encode`], but compresses into `out` one node at a time, and returns it.
+pub fn encode_to<T, W>(nodes: &[Rc<T>], out: W, level: i32) -> Result<W, CodecError>
+where
+    T: HasNeighbors + BinaryPayload,
+    W: Write,
+{
+    let encoder = zstd::Encoder::new(out, level)?;
+    Ok(binary::encode_to(nodes, encoder)?.finish()?)
+}
+
+/// Decompresses nodes written by [`encode`], and reads and wires them as
+/// [`binary::decode`] does.
+pub fn decode<T: BinaryPayload>(
+    input: &[u8],
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, CodecError> {
+    decode_from(input, slot)
+}
+
+/// Like [`decode`], but decompresses from `input` one node at a time.
+pub fn decode_from<T: BinaryPayload>(
+    input: impl Read,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, CodecError> {
+    binary::decode_from(zstd::Decoder::new(input)?, slot)
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SetOnce;
+
+    struct Node {
+        id: u64,
+        edges: [Deferred<Self>; 3],
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.edges.iter()
+        }
+    }
+
+    impl BinaryPayload for Node {
+        fn encode(&self, out: &mut Vec<u8>) {
+            binary::write_varint(out, self.id);
+        }
+        fn decode(input: &mut &[u8], _: usize) -> Result<Self, CodecError> {
+            Ok(Self {
+                id: binary::read_varint(input)?,
+                edges: Default::default(),
+            })
+        }
+    }
+
+    /// Returns 2000 nodes, each linked to the nodes 1, 7 and 500 places on.
+    fn ring() -> Vec<Rc<Node>> {
+        let nodes: Vec<_> = (0..2000)
+            .map(|id| {
+                Rc::new(Node {
+                    id,
+                    edges: Default::default(),
+                })
+            })
+            .collect();
+        for (i, node) in nodes.iter().enumerate() {
+            for (k, step) in [1, 7, 500].into_iter().enumerate() {
+                SetOnce::from(&node.edges[k])
+                    .try_set(&nodes[(i + step) % nodes.len()])
+                    .unwrap();
+            }
+        }
+        nodes
+    }
+
+    #[test]
+    fn repetitive_graphs_shrink_and_round_trip() {
+        let nodes = ring();
+        let plain = binary::encode(&nodes).unwrap();
+        let packed = encode(&nodes, DEFAULT_LEVEL).unwrap();
+        assert!(packed.len() * 2 < plain.len());
+
+        let decoded = decode(&packed, |n: &Node, k| &n.edges[k]).unwrap();
+        assert_eq!(decoded[1999].edges[0].get().id, 0);
+        assert_eq!(decoded[10].edges[2].get().id, 510);
+    }
+    #[test]
+    fn truncated_or_uncompressed_input_is_rejected() {
+        let nodes = ring();
+        let plain = binary::encode(&nodes).unwrap();
+        let packed = encode(&nodes, DEFAULT_LEVEL).unwrap();
+        assert!(decode(&packed[..packed.len() / 2], |n: &Node, k| &n.edges[k]).is_err());
+        assert!(decode(&plain, |n: &Node, k| &n.edges[k]).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! Loading named configuration entries into a wired graph.
+//!
+//! Configuration often describes an object graph as named entries, each of some kind,
+//! whose fields name other entries: a `server` entry with `upstream = "cache"`. A
+//! [`ConfigLoader`] declares each kind with a [`Kind`], saying how to make its node and
+//! which fields are links. Links may be required, optional, or fall back to a default
+//! entry when the field is left out.
+//!
+//! [`ConfigLoader::load`] checks every entry before building anything and reports
+//! every problem at once. Otherwise it makes the nodes, wires the links by name with a
+//! [`Resolver`], and returns a read-only [`ConfigGraph`].
+//!
+//! ```
+//! use deferred_cell::{Deferred, config_graph::{ConfigLoader, Entry, Kind}};
+//!
+//! struct Service {
+//!     name: String,
+//!     upstream: Deferred<Service>,
+//!     logger: Deferred<Service>,
+//! }
+//!
+//! let service = |name: &str, _: &Entry| Service {
+//!     name: name.to_owned(),
+//!     upstream: Deferred::new(),
+//!     logger: Deferred::new(),
+//! };
+//! let loader = ConfigLoader::new()
+//!     .kind(
+//!         "server",
+//!         Kind::new(service)
+//!             .link("upstream", |s| &s.upstream)
+//!             .link_or("logger", |s| &s.logger, "stdout"),
+//!     )
+//!     .kind("sink", Kind::new(service));
+//!
+//! let graph = loader
+//!     .load([
+//!         ("web", Entry::new("server").field("upstream", "api")),
+//!         ("api", Entry::new("server").field("upstream", "web").field("logger", "file")),
+//!         ("stdout", Entry::new("sink")),
+//!         ("file", Entry::new("sink")),
+//!     ])
+//!     .unwrap();
+//! assert_eq!(graph["web"].upstream.get().logger.get().name, "file");
+//! assert_eq!(graph["web"].logger.get().name, "stdout");
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Index,
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+use crate::resolver::{ResolveError, Resolver, Slot};
+
+/// A problem found by [`ConfigLoader::load`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    #[error("Entry {0:?} is defined more than once!")]
+    DuplicateName(String),
+    #[error("Entry {entry:?} has unknown kind {kind:?}!")]
+    UnknownKind { entry: String, kind: String },
+    #[error("Entry {entry:?} is missing the required link {field:?}!")]
+    MissingLink { entry: String, field: String },
+    #[error("Entry {entry:?} links {field:?} to unknown entry {target:?}!")]
+    UnknownTarget {
+        entry: String,
+        field: String,
+        target: String,
+    },
+    #[error(transparent)]
+    Wiring(#[from] ResolveError<String>),
+}
+
+/// One named entry of a configuration: its kind and its fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: String,
+    /// Field values, in field name order.
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Entry {
+    #[must_use]
+    pub fn new(kind: impl Into<String>) -> Self {
+        Self {
+            kind: kind.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+    /// Sets `field` to `value`, which names the target entry for link fields.
+    #[must_use]
+    pub fn field(mut self, field: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.insert(field.into(), value.into());
+        self
+    }
+    /// Returns the value of `field`.
+    #[must_use]
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields.get(field).map(String::as_str)
+    }
+}
+
+/// What a link field falls back to when an entry leaves it out.
+enum Fallback {
+    Required,
+    Unset,
+    Entry(Cow<'static, str>),
+}
+
+struct Link<T> {
+    field: Cow<'static, str>,
+    slot: Slot<T>,
+    fallback: Fallback,
+}
+
+type MakeNode<T> = Box<dyn Fn(&str, &Entry) -> T>;
+
+/// How to build the nodes of one kind of entry.
+pub struct Kind<T> {
+    make_node: MakeNode<T>,
+    links: Vec<Link<T>>,
+}
+
+impl<T> Kind<T> {
+    /// Declares a kind whose nodes are made by `make_node(name, entry)`.
+    #[must_use]
+    pub fn new(make_node: impl Fn(&str, &Entry) -> T + 'static) -> Self {
+        Self {
+            make_node: Box::new(make_node),
+            links: Vec::new(),
+        }
+    }
+    /// Treats `field` as a required link, stored in the cell selected by `slot`.
+    #[must_use]
+    pub fn link(self, field: impl Into<Cow<'static, str>>, slot: Slot<T>) -> Self {
+        self.with_link(field.into(), slot, Fallback::Required)
+    }
+    /// Treats `field` as a link whose cell is left unset when the field is missing.
+    #[must_use]
+    pub fn optional(self, field: impl Into<Cow<'static, str>>, slot: Slot<T>) -> Self {
+        self.with_link(field.into(), slot, Fallback::Unset)
+    }
+    /// Treats `field` as a link to the entry named `default` when the field is missing.
+    #[must_use]
+    pub fn link_or(
+        self,
+        field: impl Into<Cow<'static, str>>,
+        slot: Slot<T>,
+        default: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        self.with_link(field.into(), slot, Fallback::Entry(default.into()))
+    }
+    fn with_link(mut self, field: Cow<'static, str>, slot: Slot<T>, fallback: Fallback) -> Self {
+        self.links.push(Link {
+            field,
+            slot,
+            fallback,
+        });
+        self
+    }
+}
+
+/// Loads configuration entries of the kinds it was given.
+pub struct ConfigLoader<T> {
+    kinds: HashMap<String, Kind<T>>,
+}
+
+impl<T> Default for ConfigLoader<T> {
+    fn default() -> Self {
+        Self {
+            kinds: HashMap::new(),
+        }
+    }
+}
+
+impl<T> ConfigLoader<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Declares the entries of kind `name`, replacing any earlier declaration.
+    #[must_use]
+    pub fn kind(mut self, name: impl Into<String>, kind: Kind<T>) -> Self {
+        self.kinds.insert(name.into(), kind);
+        self
+    }
+    /// Builds and wires a node for every entry.
+    ///
+    /// Fails with every duplicate name, unknown kind, missing required link, and link
+    /// to an unknown entry, in entry order, without building any node.
+    pub fn load<N: Into<String>>(
+        &self,
+        entries: impl IntoIterator<Item = (N, Entry)>,
+    ) -> Result<ConfigGraph<T>, Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut names = HashSet::new();
+        let mut named = Vec::new();
+        for (name, entry) in entries {
+            let name = name.into();
+            if names.insert(name.clone()) {
+                named.push((name, entry));
+            } else {
+                errors.push(ConfigError::DuplicateName(name));
+            }
+        }
+
+        let mut edges = Vec::new();
+        for (name, entry) in &named {
+            let Some(kind) = self.kinds.get(&entry.kind) else {
+                errors.push(ConfigError::UnknownKind {
+                    entry: name.clone(),
+                    kind: entry.kind.clone(),
+                });
+                continue;
+            };
+            for link in &kind.links {
+                let target = match (entry.get(&link.field), &link.fallback) {
+                    (Some(target), _) => target,
+                    (None, Fallback::Entry(target)) => target,
+                    (None, Fallback::Unset) => continue,
+                    (None, Fallback::Required) => {
+                        errors.push(ConfigError::MissingLink {
+                            entry: name.clone(),
+                            field: link.field.to_string(),
+                        });
+                        continue;
+                    }
+                };
+                if names.contains(target) {
+                    edges.push((name.clone(), link.slot, target.to_owned()));
+                } else {
+                    errors.push(ConfigError::UnknownTarget {
+                        entry: name.clone(),
+                        field: link.field.to_string(),
+                        target: target.to_owned(),
+                    });
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let wire = || -> Result<_, ResolveError<String>> {
+            let mut resolver = Resolver::new();
+            for (from, slot, to) in edges {
+                resolver.edge(from, slot, to)?;
+            }
+            for (name, entry) in &named {
+                let make_node = &self.kinds[&entry.kind].make_node;
+                resolver.register(name.clone(), Rc::new(make_node(name, entry)))?;
+            }
+            resolver.finish_ordered()
+        };
+        let ordered = wire().map_err(|error| vec![error.into()])?;
+        let names = ordered.iter().map(|(name, _)| name.clone()).collect();
+        Ok(ConfigGraph {
+            nodes: ordered.into_iter().collect(),
+            names,
+        })
+    }
+}
+
+/// The wired nodes of a configuration, by entry name.
+#[derive(Debug)]
+pub struct ConfigGraph<T> {
+    nodes: HashMap<String, Rc<T>>,
+    /// Entry names, in the order the entries were given.
+    names: Vec<String>,
+}
+
+impl<T> ConfigGraph<T> {
+    /// Returns the node of the entry `name`.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Rc<T>> {
+        self.nodes.get(name)
+    }
+    /// Returns every entry name with its node, in the order the entries were given.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Rc<T>)> {
+        self.names
+            .iter()
+            .filter_map(|name| Some((name.as_str(), self.nodes.get(name)?)))
+    }
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl<T> Index<&str> for ConfigGraph<T> {
+    type Output = Rc<T>;
+
+    /// # Panics
+    /// Panics if there is no entry named `name`.
+    fn index(&self, name: &str) -> &Rc<T> {
+        &self.nodes[name]
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Deferred;
+
+    #[derive(Debug)]
+    struct Node {
+        port: Option<String>,
+        next: Deferred<Self>,
+        backup: Deferred<Self>,
+    }
+
+    fn loader() -> ConfigLoader<Node> {
+        let node = |_: &str, entry: &Entry| Node {
+            port: entry.get("port").map(str::to_owned),
+            next: Deferred::new(),
+            backup: Deferred::new(),
+        };
+        ConfigLoader::new()
+            .kind(
+                "stage",
+                Kind::new(node)
+                    .link_or("next", |n| &n.next, "end")
+                    .optional("backup", |n| &n.backup),
+            )
+            .kind("end", Kind::new(node).link("next", |n| &n.next))
+    }
+
+    #[test]
+    fn entries_wire_with_defaults() {
+        let graph = loader()
+            .load([
+                ("a", Entry::new("stage").field("backup", "b")),
+                (
+                    "b",
+                    Entry::new("stage").field("next", "a").field("port", "80"),
+                ),
+                ("end", Entry::new("end").field("next", "b")),
+            ])
+            .unwrap();
+        let names: Vec<_> = graph.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["a", "b", "end"]);
+        assert!(Rc::ptr_eq(&graph["a"].next.get(), &graph["end"]));
+        assert_eq!(graph["a"].backup.get().port.as_deref(), Some("80"));
+        assert!(!graph["b"].backup.is_ready());
+        assert_eq!(graph["end"].next.get().next.get().port, None);
+    }
+    #[test]
+    fn fields_iterate_in_name_order() {
+        let entry = Entry::new("stage").field("port", "80").field("next", "a");
+        let fields: Vec<_> = entry.fields.keys().map(String::as_str).collect();
+        assert_eq!(fields, ["next", "port"]);
+    }
+    #[test]
+    fn every_problem_is_reported() {
+        let errors: Vec<_> = loader()
+            .load([
+                ("a", Entry::new("stage").field("backup", "ghost")),
+                ("b", Entry::new("mystery")),
+                ("c", Entry::new("end")),
+                ("a", Entry::new("end")),
+            ])
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                r#"Entry "a" is defined more than once!"#,
+                r#"Entry "a" links "next" to unknown entry "end"!"#,
+                r#"Entry "a" links "backup" to unknown entry "ghost"!"#,
+                r#"Entry "b" has unknown kind "mystery"!"#,
+                r#"Entry "c" is missing the required link "next"!"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Wiring that keeps a graph acyclic, for dependency graphs such as spreadsheets.
+//!
+//! A formula engine must reject a reference that would make a cell depend on itself
+//! the moment it is entered, not in a later validation pass. [`DagGuard::try_set`] sets
+//! a cell only after checking that its owner cannot already be reached from the
+//! target through [`HasNeighbors`] edges. Otherwise the new edge would close a cycle,
+//! which is reported with the path it would form.
+//!
+//! ```
+//! use deferred_cell::{Deferred, HasNeighbors, dag::DagGuard};
+//! use std::rc::Rc;
+//!
+//! struct Cell {
+//!     name: &'static str,
+//!     inputs: [Deferred<Cell>; 2],
+//! }
+//!
+//! impl HasNeighbors for Cell {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         self.inputs.iter()
+//!     }
+//! }
+//!
+//! let cell = |name| Rc::new(Cell { name, inputs: [Deferred::new(), Deferred::new()] });
+//! let (a1, b1, c1) = (cell("A1"), cell("B1"), cell("C1"));
+//! let guard = DagGuard::new(|c: &Cell| c.name);
+//!
+//! guard.try_set(&a1, |c| &c.inputs[0], &b1).unwrap(); // A1 = B1 + ...
+//! guard.try_set(&b1, |c| &c.inputs[0], &c1).unwrap(); // B1 = C1 + ...
+//! let error = guard.try_set(&c1, |c| &c.inputs[0], &a1).unwrap_err();
+//! assert_eq!(error.to_string(), "Edge would close the cycle C1 -> A1 -> B1 -> C1!");
+//! assert!(!c1.inputs[0].is_ready());
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque, hash_map::Entry},
+    fmt::Display,
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+use crate::{Deferred, DeferredError, HasNeighbors, SetOnce};
+
+/// Errors returned by [`DagGuard::try_set`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum DagError {
+    /// The labels of the nodes on the cycle, starting and ending with the edge's owner.
+    #[error("Edge would close the cycle {}!", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error(transparent)]
+    Wiring(#[from] DeferredError),
+}
+
+/// Sets cells only when they keep the graph acyclic, naming nodes by a label in errors.
+pub struct DagGuard<F> {
+    label: F,
+}
+
+impl<F> DagGuard<F> {
+    #[must_use]
+    pub const fn new(label: F) -> Self {
+        Self { label }
+    }
+    /// Points `slot(from)` at `to`, unless `from` is reachable from `to`.
+    ///
+    /// The search only follows edges that are set, so the graph must be wired through
+    /// guards, or otherwise kept acyclic, for the guarantee to hold.
+    pub fn try_set<T, L>(
+        &self,
+        from: &Rc<T>,
+        slot: impl Fn(&T) -> &Deferred<T>,
+        to: &Rc<T>,
+    ) -> Result<(), DagError>
+    where
+        T: HasNeighbors,
+        F: Fn(&T) -> L,
+        L: Display,
+    {
+        if let Some(path) = path_between(to, from) {
+            let labels = std::iter::once(from)
+                .chain(&path)
+                .map(|node| (self.label)(node).to_string())
+                .collect();
+            return Err(DagError::Cycle(labels));
+        }
+        Ok(SetOnce::from(slot(from)).try_set(to)?)
+    }
+}
+
+/// Returns the nodes on a shortest path from `start` to `goal`, both included.
+fn path_between<T: HasNeighbors>(start: &Rc<T>, goal: &Rc<T>) -> Option<Vec<Rc<T>>> {
+    let mut parents: HashMap<*const T, Option<Rc<T>>> = HashMap::from([(Rc::as_ptr(start), None)]);
+    let mut queue = VecDeque::from([Rc::clone(start)]);
+    while let Some(node) = queue.pop_front() {
+        if Rc::ptr_eq(&node, goal) {
+            let mut path = vec![node];
+            while let Some(Some(parent)) = parents.get(&Rc::as_ptr(&path[path.len() - 1])) {
+                path.push(Rc::clone(parent));
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for next in node.neighbors().filter_map(|cell| cell.try_get().ok()) {
+            if let Entry::Vacant(entry) = parents.entry(Rc::as_ptr(&next)) {
+                entry.insert(Some(Rc::clone(&node)));
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Node {
+        id: u8,
+        inputs: Vec<Deferred<Self>>,
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.inputs.iter()
+        }
+    }
+
+    #[test]
+    fn edges_closing_cycles_are_rejected() {
+        let nodes: Vec<_> = (0..4)
+            .map(|id| {
+                Rc::new(Node {
+                    id,
+                    inputs: (0..2).map(|_| Deferred::new()).collect(),
+                })
+            })
+            .collect();
+        let guard = DagGuard::new(|n: &Node| n.id);
+        let set = |from: usize, k: usize, to: usize| {
+            guard.try_set(&nodes[from], |n| &n.inputs[k], &nodes[to])
+        };
+        set(0, 0, 1).unwrap();
+        set(0, 1, 2).unwrap();
+        set(1, 0, 3).unwrap();
+        set(2, 0, 3).unwrap();
+
+        let cycle = |result: Result<(), DagError>| match result {
+            Err(DagError::Cycle(path)) => path,
+            _ => panic!("expected a cycle"),
+        };
+        assert_eq!(cycle(set(3, 0, 0)), ["3", "0", "1", "3"]);
+        assert_eq!(cycle(set(3, 0, 3)), ["3", "3"]);
+        assert!(!nodes[3].inputs[0].is_ready());
+        assert!(matches!(set(1, 0, 2), Err(DagError::Wiring(_))));
+        set(1, 1, 2).unwrap();
+    }
+}
@@ -0,0 +1,402 @@
+//! Diagnostics emitted while wiring and reading cells.
+//!
+//! With the `tracing` feature enabled, sets, duplicate sets, and failed upgrades are
+//! reported as `tracing` events carrying the cell's address and target type. Setters
+//! and getters generated by `#[deferred]` run inside a `deferred_cell` span naming the
+//! field, such as `Node.parent`.
+//!
+//! With the `log` feature enabled, duplicate sets and failed upgrades are logged at
+//! warn level, and sets at trace level, under the `deferred_cell` target.
+//!
+//! With the `profiling` feature enabled, sets, gets, and failed upgrades are counted
+//! in [`profiling`](crate::profiling). With the `debug-registry` feature enabled, cell
+//! creation, sets, and drops keep the live counts in [`registry`](crate::registry)
+//! current. With the `fault-injection` feature enabled, reads may fail as configured
+//! in [`faults`](crate::faults). With the `journal` feature enabled, sets are appended
+//! to the active [`journal`](crate::journal) recording. Without any of these features
+//! every hook compiles to nothing.
+//!
+//! Hooks take the target's type name rather than a type parameter, so each is compiled
+//! once instead of once per target type.
+
+// Which hooks use their parameters, if any, depends on the enabled features.
+#![allow(unused_variables, clippy::missing_const_for_fn)]
+
+#[cfg(any(feature = "fault-injection", feature = "journal"))]
+use std::{cell::Cell, marker::PhantomData};
+
+use crate::DeferredError;
+#[cfg(feature = "std")]
+use crate::audit::DanglingCell;
+
+#[cfg(any(feature = "fault-injection", feature = "journal"))]
+std::thread_local! {
+    static CURRENT_CELL: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Reports a successful set of `cell` to `value`.
+#[cfg_attr(feature = "debug-location", track_caller)]
+#[inline]
+pub fn set(cell: *const (), value: *const (), target: &'static str) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(cell = ?cell, target, "deferred cell set");
+    #[cfg(feature = "log")]
+    log::trace!(target: "deferred_cell", "deferred cell {cell:?} of {target} set");
+    #[cfg(feature = "profiling")]
+    crate::profiling::record(target, |counters| &counters.sets);
+    #[cfg(feature = "journal")]
+    crate::journal::record(cell, value, target);
+}
+
+/// Reports a newly created cell, other than one made by the const `new`. Takes the
+/// cell's type name rather than the target's, so each cell kind is counted apart.
+#[inline]
+pub fn created(ready: bool, cell: &'static str) {
+    #[cfg(feature = "debug-registry")]
+    crate::registry::record(cell, |counts| {
+        counts.live += 1;
+        counts.unset += usize::from(!ready);
+    });
+}
+
+/// The registry's bookkeeping for one cell: the cell's type name once it is counted,
+/// and whether it has been set.
+///
+/// It does not mention the cell's target type, so its `Drop` impl leaves the drop check
+/// of the cell holding it unchanged.
+#[cfg(feature = "debug-registry")]
+#[derive(Debug)]
+pub struct Counted {
+    cell: std::sync::OnceLock<&'static str>,
+    ready: core::sync::atomic::AtomicBool,
+}
+
+#[cfg(feature = "debug-registry")]
+impl Counted {
+    /// Bookkeeping for a cell made by the const `new`, counted from its first set.
+    pub const fn uncounted() -> Self {
+        Self {
+            cell: std::sync::OnceLock::new(),
+            ready: core::sync::atomic::AtomicBool::new(false),
+        }
+    }
+    /// Bookkeeping for a cell already reported to [`created`].
+    pub fn new(ready: bool, cell: &'static str) -> Self {
+        Self {
+            cell: std::sync::OnceLock::from(cell),
+            ready: core::sync::atomic::AtomicBool::new(ready),
+        }
+    }
+    /// Reports the cell's set to the registry, counting the cell now if it was made by
+    /// `new`.
+    pub fn set(&self, cell: &'static str) {
+        let counted = self.cell.set(cell).is_err();
+        self.ready
+            .store(true, core::sync::atomic::Ordering::Relaxed);
+        crate::registry::record(cell, |counts| {
+            if counted {
+                counts.unset -= 1;
+            } else {
+                counts.live += 1;
+            }
+        });
+    }
+}
+
+#[cfg(feature = "debug-registry")]
+impl Drop for Counted {
+    fn drop(&mut self) {
+        if let Some(&cell) = self.cell.get() {
+            let ready = *self.ready.get_mut();
+            crate::registry::record(cell, |counts| {
+                counts.live -= 1;
+                counts.unset -= usize::from(!ready);
+            });
+        }
+    }
+}
+
+/// Reports a read, successful or not, and returns the injected failure, if any.
+#[inline]
+pub fn get(target: &'static str) -> Result<(), DeferredError> {
+    #[cfg(feature = "profiling")]
+    crate::profiling::record(target, |counters| &counters.gets);
+    #[cfg(feature = "fault-injection")]
+    crate::faults::check()?;
+    Ok(())
+}
+
+/// Reports a rejected second set and returns the matching error.
+#[cold]
+pub fn duplicate_set(cell: *const (), target: &'static str) -> DeferredError {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        cell = ?cell,
+        target,
+        "deferred cell set twice"
+    );
+    #[cfg(feature = "log")]
+    log::warn!(
+        target: "deferred_cell",
+        "deferred cell {cell:?} of {target} set twice"
+    );
+    DeferredError::DuplicateInitialization()
+}
+
+/// Reports a read of a set cell whose target was dropped and returns the matching error.
+#[cold]
+pub fn failed_upgrade(cell: *const (), target: &'static str) -> DeferredError {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        cell = ?cell,
+        target,
+        "deferred cell target was dropped"
+    );
+    #[cfg(feature = "log")]
+    log::warn!(
+        target: "deferred_cell",
+        "deferred cell {cell:?} of {target} read after its target was dropped"
+    );
+    #[cfg(feature = "profiling")]
+    crate::profiling::record(target, |counters| &counters.failed_upgrades);
+    DeferredError::TargetDropped()
+}
+
+/// Reports a cell found dangling by an [`AuditedGraph`](crate::audit::AuditedGraph).
+#[cfg(feature = "std")]
+#[cold]
+pub fn dangling_cell(cell: &DanglingCell) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        node = cell.node,
+        field = cell.field,
+        target = cell.target_type,
+        "deferred cell {cell}"
+    );
+    #[cfg(feature = "log")]
+    log::warn!(target: "deferred_cell", "deferred cell {cell}");
+}
+
+/// Marks the cell named by [`enter_cell`] as the one being accessed until dropped.
+#[cfg(feature = "std")]
+#[must_use]
+pub struct CellGuard {
+    #[cfg(feature = "tracing")]
+    _span: tracing::span::EnteredSpan,
+    #[cfg(any(feature = "fault-injection", feature = "journal"))]
+    _cell: CellScope,
+}
+
+/// Enters a span naming the cell about to be accessed. Used by generated code, which
+/// holds the guard instead of taking a closure so `#[track_caller]` keeps working.
+#[cfg(feature = "std")]
+#[inline]
+pub fn enter_cell(name: &'static str) -> CellGuard {
+    CellGuard {
+        #[cfg(feature = "tracing")]
+        _span: tracing::debug_span!("deferred_cell", cell = name).entered(),
+        #[cfg(any(feature = "fault-injection", feature = "journal"))]
+        _cell: CellScope::enter(name),
+    }
+}
+
+/// Returns the name of the cell entered on the current thread with [`enter_cell`], if
+/// any, for [`faults`](crate::faults) and [`journal`](crate::journal).
+#[cfg(any(feature = "fault-injection", feature = "journal"))]
+#[inline]
+pub fn current_cell() -> Option<&'static str> {
+    CURRENT_CELL.with(Cell::get)
+}
+
+/// Marks a named cell as the one being accessed on the current thread until dropped.
+/// Not `Send`, since dropping it on another thread would restore the wrong thread.
+#[cfg(any(feature = "fault-injection", feature = "journal"))]
+struct CellScope {
+    outer: Option<&'static str>,
+    _thread: PhantomData<*const ()>,
+}
+
+#[cfg(any(feature = "fault-injection", feature = "journal"))]
+impl CellScope {
+    fn enter(name: &'static str) -> Self {
+        let outer = CURRENT_CELL.with(|current| current.replace(Some(name)));
+        Self {
+            outer,
+            _thread: PhantomData,
+        }
+    }
+}
+
+#[cfg(any(feature = "fault-injection", feature = "journal"))]
+impl Drop for CellScope {
+    fn drop(&mut self) {
+        CURRENT_CELL.with(|current| current.set(self.outer));
+    }
+}
+
+/// Per-thread diagnostic state that worker threads spawned by the crate take over from
+/// the thread that spawned them, so their sets land in the same journal recording.
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+pub struct WorkerContext {
+    #[cfg(feature = "journal")]
+    journal: crate::journal::RecordingHandle,
+}
+
+#[cfg(feature = "std")]
+impl WorkerContext {
+    /// Captures the state of the current thread.
+    #[inline]
+    pub fn capture() -> Self {
+        Self {
+            #[cfg(feature = "journal")]
+            journal: crate::journal::RecordingHandle::current(),
+        }
+    }
+    /// Applies the captured state to the current thread until the guard is dropped.
+    #[inline]
+    pub fn enter(&self) -> WorkerGuard {
+        WorkerGuard {
+            #[cfg(feature = "journal")]
+            _journal: self.journal.attach(),
+        }
+    }
+}
+
+/// Undoes [`WorkerContext::enter`] when dropped.
+#[cfg(feature = "std")]
+#[must_use]
+pub struct WorkerGuard {
+    #[cfg(feature = "journal")]
+    _journal: crate::journal::Attached,
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use std::{
+        rc::Rc,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        Event, Metadata, Subscriber,
+        field::{Field, Visit},
+        span,
+    };
+
+    use crate::{Deferred, SetOnce};
+
+    /// Records `(level, message)` for every event.
+    #[derive(Default, Clone)]
+    struct Recorder(Arc<Mutex<Vec<(tracing::Level, String)>>>);
+
+    struct Message(String);
+
+    impl Visit for Message {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                self.0 = format!("{value:?}");
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut message = Message(String::new());
+            event.record(&mut message);
+            self.0
+                .lock()
+                .unwrap()
+                .push((*event.metadata().level(), message.0));
+        }
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    #[test]
+    fn wiring_mistakes_are_reported() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let cell = Deferred::new();
+            let target = Rc::new(1);
+            SetOnce::from(&cell).try_set(&target).unwrap();
+            SetOnce::from(&cell).try_set(&target).unwrap_err();
+            drop(target);
+            cell.try_get().unwrap_err();
+        });
+        let events = recorder.0.lock().unwrap().clone();
+        assert_eq!(
+            events,
+            vec![
+                (tracing::Level::TRACE, "deferred cell set".to_owned()),
+                (tracing::Level::WARN, "deferred cell set twice".to_owned()),
+                (
+                    tracing::Level::WARN,
+                    "deferred cell target was dropped".to_owned()
+                ),
+            ]
+        );
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(all(test, feature = "log"))]
+mod log_test {
+    use std::{rc::Rc, sync::Mutex};
+
+    use crate::{Deferred, SetOnce};
+
+    static RECORDS: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
+
+    struct Recorder;
+
+    impl log::Log for Recorder {
+        fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record<'_>) {
+            RECORDS
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+        fn flush(&self) {}
+    }
+
+    #[derive(Debug)]
+    struct Marker;
+
+    #[test]
+    fn wiring_mistakes_are_logged() {
+        log::set_logger(&Recorder).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+        let cell = Deferred::new();
+        let target = Rc::new(Marker);
+        SetOnce::from(&cell).try_set(&target).unwrap();
+        SetOnce::from(&cell).try_set(&target).unwrap_err();
+        drop(target);
+        cell.try_get().unwrap_err();
+
+        let records: Vec<_> = RECORDS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(level, message)| *level == log::Level::Warn && message.contains("Marker"))
+            .cloned()
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].1.ends_with("set twice"));
+        assert!(records[1].1.ends_with("read after its target was dropped"));
+    }
+}
@@ -0,0 +1,539 @@
+//! Dialogue and quest graphs authored in data files.
+//!
+//! Dialogue trees are really graphs: choices loop back to earlier lines, and authors
+//! refer to lines further down the file. A [`Dialogue`] loads [`Line`]s from
+//! [`Record`]s, CSV, or, with the `json` feature, JSON, and wires each [`Choice`] to the
+//! line it names through a [`Resolver`]. A choice naming no line is left unset rather
+//! than failing the load, so [`Dialogue::validate`] can report it alongside lines that
+//! cannot be reached, and [`Dialogue::to_dot`] draws the graph for review in Graphviz.
+//!
+//! In CSV, each row is a line's id and text followed by a label and target id for each
+//! choice. A header row starting with `id` is skipped, empty fields at the end of a row
+//! are ignored, and fields may be quoted, but not span several rows.
+//!
+//! JSON, with the `json` feature, is loaded with a `json::JsonLoader`, so each choice is
+//! a link such as `{"$ref": "shop"}`, and a link to a missing line fails the load.
+//!
+//! ```
+//! use deferred_cell::dialogue::Dialogue;
+//!
+//! let dialogue = Dialogue::from_csv(
+//!     r#"id,text,choice,next,choice,next
+//! start,"Hello, traveler.",Shop,shop,Leave,bye
+//! shop,What will it be?,Nothing,start
+//! bye,Farewell.
+//! "#,
+//! )
+//! .unwrap();
+//! let shop = dialogue.get("start").unwrap().choices[0].next().unwrap();
+//! assert_eq!(shop.text, "What will it be?");
+//! assert!(dialogue.validate("start").is_empty());
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+#[cfg(feature = "json")]
+use crate::json::{JsonLoader, LoadError};
+use crate::{
+    Deferred, HasNeighbors,
+    resolver::{ResolveError, Resolver},
+    visit,
+};
+
+/// Errors returned while loading a [`Dialogue`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum DialogueError {
+    #[error("Line id {0:?} is used more than once!")]
+    DuplicateId(String),
+    #[error("Invalid CSV on row {row}: {reason}!")]
+    Csv { row: usize, reason: &'static str },
+    #[cfg(feature = "json")]
+    #[error("Invalid dialogue JSON at {pointer}: {reason}!")]
+    Json {
+        pointer: String,
+        reason: &'static str,
+    },
+    #[cfg(feature = "json")]
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error(transparent)]
+    Resolve(#[from] ResolveError<String>),
+}
+
+/// A problem found by [`Dialogue::validate`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Issue {
+    #[error("Start line {0:?} does not exist!")]
+    MissingStart(String),
+    #[error("Choice {choice:?} of line {line:?} leads to missing line {target:?}!")]
+    Dangling {
+        line: String,
+        choice: String,
+        target: String,
+    },
+    #[error("Line {0:?} cannot be reached from the start!")]
+    Unreachable(String),
+}
+
+/// Serializes as a map of the issue's `kind`, its `message`, and the `line`, with the
+/// `choice` and `target` of a dangling choice.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Issue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            Self::MissingStart(line) | Self::Unreachable(line) => {
+                let kind = match self {
+                    Self::MissingStart(_) => "missing_start",
+                    _ => "unreachable",
+                };
+                map.serialize_entry("kind", kind)?;
+                map.serialize_entry("message", &self.to_string())?;
+                map.serialize_entry("line", line)?;
+            }
+            Self::Dangling {
+                line,
+                choice,
+                target,
+            } => {
+                map.serialize_entry("kind", "dangling")?;
+                map.serialize_entry("message", &self.to_string())?;
+                map.serialize_entry("line", line)?;
+                map.serialize_entry("choice", choice)?;
+                map.serialize_entry("target", target)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// A line of dialogue and the choices that follow it.
+#[derive(Debug)]
+pub struct Line {
+    pub id: String,
+    pub text: String,
+    pub choices: Vec<Choice>,
+}
+
+/// A choice leading from one line to another.
+#[derive(Debug)]
+pub struct Choice {
+    pub label: String,
+    /// The id of the line the choice leads to, as authored.
+    pub target: String,
+    next: Deferred<Line>,
+}
+
+impl Choice {
+    /// Returns the line the choice leads to, or `None` if no line has its target id.
+    #[must_use]
+    pub fn next(&self) -> Option<Rc<Line>> {
+        self.next.try_get().ok()
+    }
+}
+
+impl HasNeighbors for Line {
+    fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+        self.choices.iter().map(|choice| &choice.next)
+    }
+}
+
+/// A line as read from a data file, with each choice's label and target id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Record {
+    pub id: String,
+    pub text: String,
+    pub choices: Vec<(String, String)>,
+}
+
+/// The wired lines of a dialogue, in the order they were authored.
+#[derive(Debug)]
+pub struct Dialogue {
+    lines: Vec<Rc<Line>>,
+    index: HashMap<String, usize>,
+}
+
+impl Dialogue {
+    /// Creates a line per record and wires every choice whose target exists.
+    pub fn from_records(records: impl IntoIterator<Item = Record>) -> Result<Self, DialogueError> {
+        let mut resolver = Resolver::new();
+        let mut lines = Vec::new();
+        for record in records {
+            let line = Rc::new(Line {
+                id: record.id,
+                text: record.text,
+                choices: record
+                    .choices
+                    .into_iter()
+                    .map(|(label, target)| Choice {
+                        label,
+                        target,
+                        next: Deferred::new(),
+                    })
+                    .collect(),
+            });
+            resolver
+                .register(line.id.clone(), Rc::clone(&line))
+                .map_err(|error| match error {
+                    ResolveError::DuplicateKey(id) => DialogueError::DuplicateId(id),
+                    error => error.into(),
+                })?;
+            lines.push(line);
+        }
+        for line in &lines {
+            for (k, choice) in line.choices.iter().enumerate() {
+                let (from, to) = (line.id.clone(), choice.target.clone());
+                resolver.edge_at(from, |line, k| &line.choices[k].next, k, to)?;
+            }
+        }
+        // Edges still pending lead to missing lines, which `validate` reports.
+        Ok(Self::new(lines))
+    }
+    /// Loads rows of `id,text[,label,target]...`.
+    pub fn from_csv(source: &str) -> Result<Self, DialogueError> {
+        let mut records = Vec::new();
+        for (i, row) in source.lines().enumerate() {
+            let error = |reason| DialogueError::Csv { row: i + 1, reason };
+            if row.trim().is_empty() {
+                continue;
+            }
+            let fields = split_csv(row).ok_or_else(|| error("unterminated quote"))?;
+            if i == 0 && fields[0] == "id" {
+                continue;
+            }
+            let [id, text, choices @ ..] = &fields[..] else {
+                return Err(error("expected an id and a text"));
+            };
+            let padding = choices.iter().rev().take_while(|c| c.is_empty()).count();
+            let choices = &choices[..choices.len() - padding];
+            if choices.len() % 2 != 0 {
+                return Err(error("each choice needs a label and a target"));
+            }
+            records.push(Record {
+                id: id.clone(),
+                text: text.clone(),
+                choices: choices
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect(),
+            });
+        }
+        Self::from_records(records)
+    }
+    /// Loads an array of objects such as `{"id": "shop", "text": "...", "labels":
+    /// ["Leave"], "choices": [{"$ref": "bye"}]}`, where each choice links to a line and
+    /// is labelled by the entry of `labels` at the same position. Other fields of a line
+    /// must not hold objects or arrays.
+    #[cfg(feature = "json")]
+    pub fn from_json(doc: &serde_json::Value) -> Result<Self, DialogueError> {
+        check_json(doc)?;
+        let string = |value: &serde_json::Value| match value {
+            serde_json::Value::String(text) => text.clone(),
+            value => value.to_string(),
+        };
+        let graph = JsonLoader::new().load(
+            doc,
+            |line, _| {
+                let list = |field| line.get(field).and_then(serde_json::Value::as_array);
+                let targets = list("choices").into_iter().flatten();
+                Line {
+                    id: string(&line["id"]),
+                    text: string(&line["text"]),
+                    choices: list("labels")
+                        .into_iter()
+                        .flatten()
+                        .zip(targets)
+                        .map(|(label, link)| Choice {
+                            label: string(label),
+                            target: string(&link["$ref"]),
+                            next: Deferred::new(),
+                        })
+                        .collect(),
+                }
+            },
+            |line, k| &line.choices[k].next,
+        )?;
+        Ok(Self::new(graph.into_nodes()))
+    }
+    /// Indexes lines by id.
+    fn new(lines: Vec<Rc<Line>>) -> Self {
+        let index = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| (line.id.clone(), i))
+            .collect();
+        Self { lines, index }
+    }
+    /// Returns the line with the given id.
+    #[must_use]
+    pub fn get(&self, id: &str) -> Option<&Rc<Line>> {
+        self.index.get(id).map(|&i| &self.lines[i])
+    }
+    /// Returns every line, in the order they were authored.
+    #[must_use]
+    pub fn lines(&self) -> &[Rc<Line>] {
+        &self.lines
+    }
+    /// Returns every choice leading to a missing line, then every line that cannot be
+    /// reached from the line `start`.
+    #[must_use]
+    pub fn validate(&self, start: &str) -> Vec<Issue> {
+        let Some(start) = self.get(start) else {
+            return vec![Issue::MissingStart(start.to_owned())];
+        };
+        let mut issues: Vec<_> = self
+            .lines
+            .iter()
+            .flat_map(|line| {
+                line.choices
+                    .iter()
+                    .filter(|choice| !choice.next.is_ready())
+                    .map(|choice| Issue::Dangling {
+                        line: line.id.clone(),
+                        choice: choice.label.clone(),
+                        target: choice.target.clone(),
+                    })
+            })
+            .collect();
+        let reached: HashSet<_> = visit::bfs(start).map(|line| Rc::as_ptr(&line)).collect();
+        issues.extend(
+            self.lines
+                .iter()
+                .filter(|line| !reached.contains(&Rc::as_ptr(line)))
+                .map(|line| Issue::Unreachable(line.id.clone())),
+        );
+        issues
+    }
+    /// Renders the dialogue as a Graphviz digraph, labelling lines with their text and
+    /// edges with their choices. Dangling choices are left out.
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dialogue {\n");
+        for line in &self.lines {
+            let _ = writeln!(
+                dot,
+                "    {} [label={}];",
+                quote(&line.id),
+                quote(&line.text)
+            );
+            for (choice, next) in line.choices.iter().filter_map(|c| Some((c, c.next()?))) {
+                let _ = writeln!(
+                    dot,
+                    "    {} -> {} [label={}];",
+                    quote(&line.id),
+                    quote(&next.id),
+                    quote(&choice.label)
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Checks that `doc` is an array of lines with string ids and texts, one string label
+/// per choice, and a link for each choice.
+#[cfg(feature = "json")]
+fn check_json(doc: &serde_json::Value) -> Result<(), DialogueError> {
+    let error = |pointer: String, reason| DialogueError::Json { pointer, reason };
+    let lines = doc
+        .as_array()
+        .ok_or_else(|| error(String::new(), "expected an array"))?;
+    for (i, line) in lines.iter().enumerate() {
+        let line = line
+            .as_object()
+            .ok_or_else(|| error(format!("/{i}"), "expected an object"))?;
+        for field in ["id", "text"] {
+            if !line.get(field).is_some_and(serde_json::Value::is_string) {
+                return Err(error(format!("/{i}/{field}"), "expected a string"));
+            }
+        }
+        let list = |field| match line.get(field) {
+            None => Ok(&[][..]),
+            Some(serde_json::Value::Array(items)) => Ok(&items[..]),
+            Some(_) => Err(error(format!("/{i}/{field}"), "expected an array")),
+        };
+        let (labels, choices) = (list("labels")?, list("choices")?);
+        if let Some(k) = labels.iter().position(|label| !label.is_string()) {
+            return Err(error(format!("/{i}/labels/{k}"), "expected a string"));
+        }
+        let is_link = |choice: &serde_json::Value| {
+            let link = choice.as_object();
+            link.is_some_and(|link| link.len() == 1 && link.contains_key("$ref"))
+        };
+        if let Some(k) = choices.iter().position(|choice| !is_link(choice)) {
+            return Err(error(format!("/{i}/choices/{k}"), "expected a link"));
+        }
+        if labels.len() != choices.len() {
+            return Err(error(format!("/{i}/labels"), "expected a label per choice"));
+        }
+        let nested = line.iter().find(|&(field, value)| {
+            !matches!(field.as_str(), "labels" | "choices")
+                && (value.is_object() || value.is_array())
+        });
+        if let Some((field, _)) = nested {
+            return Err(error(
+                format!("/{i}/{field}"),
+                "expected a string, number or boolean",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Quotes a string for DOT.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Splits a CSV row into its fields, or returns `None` if a quote is not closed.
+fn split_csv(row: &str) -> Option<Vec<String>> {
+    let mut fields = vec![String::new()];
+    let mut chars = row.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut()?;
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => field.push(c),
+        }
+    }
+    (!quoted).then_some(fields)
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv_dialogues_validate_and_render() {
+        let source = "start,\"Say \"\"hi\"\"\",Again,start,Go,end,Shop,shop\n\nend,Bye\nlost,Nobody comes here,Back,start\n";
+        let dialogue = Dialogue::from_csv(source).unwrap();
+        let start = dialogue.get("start").unwrap();
+        assert_eq!(start.text, "Say \"hi\"");
+        assert!(Rc::ptr_eq(&start.choices[0].next().unwrap(), start));
+        assert!(start.choices[2].next().is_none());
+
+        let issues: Vec<_> = dialogue
+            .validate("start")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                r#"Choice "Shop" of line "start" leads to missing line "shop"!"#,
+                r#"Line "lost" cannot be reached from the start!"#,
+            ]
+        );
+        assert_eq!(
+            dialogue.validate("nowhere"),
+            [Issue::MissingStart("nowhere".into())]
+        );
+        assert_eq!(
+            dialogue.to_dot(),
+            r#"digraph dialogue {
+    "start" [label="Say \"hi\""];
+    "start" -> "start" [label="Again"];
+    "start" -> "end" [label="Go"];
+    "end" [label="Bye"];
+    "lost" [label="Nobody comes here"];
+    "lost" -> "start" [label="Back"];
+}
+"#
+        );
+
+        assert_eq!(
+            Dialogue::from_csv("a,b\na,\"c").unwrap_err().to_string(),
+            "Invalid CSV on row 2: unterminated quote!"
+        );
+        assert_eq!(
+            Dialogue::from_csv("a,b,c").unwrap_err().to_string(),
+            "Invalid CSV on row 1: each choice needs a label and a target!"
+        );
+        assert!(matches!(
+            Dialogue::from_csv("a,b\na,c"),
+            Err(DialogueError::DuplicateId(id)) if id == "a"
+        ));
+    }
+    #[test]
+    fn csv_padding_is_ignored() {
+        let dialogue = Dialogue::from_csv("hi,Hello.,Bye,bye,,\nbye,Farewell.,,,,\n").unwrap();
+        assert_eq!(dialogue.get("hi").unwrap().choices.len(), 1);
+        assert!(dialogue.get("bye").unwrap().choices.is_empty());
+        assert!(dialogue.validate("hi").is_empty());
+        assert_eq!(
+            Dialogue::from_csv("a,b,c,,").unwrap_err().to_string(),
+            "Invalid CSV on row 1: each choice needs a label and a target!"
+        );
+    }
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_dialogues_load() {
+        let doc = serde_json::json!([
+            {"id": "a", "text": "A", "labels": ["on", "stay"], "choices": [{"$ref": "b"}, {"$ref": "#/0"}]},
+            {"id": "b", "text": "B", "speaker": "Bo"},
+        ]);
+        let dialogue = Dialogue::from_json(&doc).unwrap();
+        let a = &dialogue.lines()[0];
+        assert_eq!(a.choices[0].next().unwrap().text, "B");
+        assert!(Rc::ptr_eq(&a.choices[1].next().unwrap(), a));
+        assert_eq!(dialogue.get("b").unwrap().text, "B");
+        assert!(dialogue.to_dot().contains(r#""a" -> "a" [label="stay"];"#));
+    }
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_dialogue_errors_are_reported() {
+        let message = |doc| Dialogue::from_json(&doc).unwrap_err().to_string();
+        assert_eq!(
+            message(
+                serde_json::json!([{"id": "a", "text": "A", "labels": [1], "choices": [{"$ref": "a"}]}])
+            ),
+            "Invalid dialogue JSON at /0/labels/0: expected a string!"
+        );
+        assert_eq!(
+            message(
+                serde_json::json!([{"id": "a", "text": "A", "labels": ["on"], "choices": ["a"]}])
+            ),
+            "Invalid dialogue JSON at /0/choices/0: expected a link!"
+        );
+        assert_eq!(
+            message(serde_json::json!([{"id": "a", "text": "A", "labels": ["on"]}])),
+            "Invalid dialogue JSON at /0/labels: expected a label per choice!"
+        );
+        assert_eq!(
+            message(serde_json::json!([{"id": "a", "text": "A", "mood": {}}])),
+            "Invalid dialogue JSON at /0/mood: expected a string, number or boolean!"
+        );
+        assert!(matches!(
+            Dialogue::from_json(&serde_json::json!([
+                {"id": "a", "text": "A", "labels": ["on"], "choices": [{"$ref": "b"}]},
+            ])),
+            Err(DialogueError::Load(LoadError::Unresolved { target, .. })) if target == "b"
+        ));
+        assert!(matches!(
+            Dialogue::from_json(&serde_json::json!([
+                {"id": "a", "text": "A"},
+                {"id": "a", "text": "B"},
+            ])),
+            Err(DialogueError::Load(LoadError::DuplicateId { id, .. })) if id == "a"
+        ));
+    }
+}
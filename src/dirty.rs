@@ -0,0 +1,157 @@
+//! Dirty-flag propagation for incremental recomputation.
+//!
+//! When a node of a wired graph changes, every node computed from it, directly or
+//! not, must be recomputed, and each only after its own inputs. [`propagate`] follows a
+//! reverse-edge accessor from the changed node to find those dependents, without
+//! looping on cycles, and returns them in topological order. Dependents that sit on a
+//! cycle, or downstream of one, have no such order and are returned apart.
+//!
+//! ```
+//! use deferred_cell::{Deferred, dirty};
+//! use std::rc::Rc;
+//!
+//! struct Formula {
+//!     name: &'static str,
+//!     dependents: Vec<Deferred<Formula>>,
+//! }
+//!
+//! fn dependents(formula: &Formula) -> Vec<Rc<Formula>> {
+//!     formula.dependents.iter().filter_map(|d| d.try_get().ok()).collect()
+//! }
+//!
+//! // C1 = A1 + B1, and B1 = A1 * 2.
+//! let c1 = Rc::new(Formula { name: "C1", dependents: vec![] });
+//! let b1 = Rc::new(Formula { name: "B1", dependents: vec![Deferred::from(&c1)] });
+//! let a1 = Rc::new(Formula {
+//!     name: "A1",
+//!     dependents: vec![Deferred::from(&c1), Deferred::from(&b1)],
+//! });
+//!
+//! let dirty = dirty::propagate(&a1, dependents);
+//! let names: Vec<_> = dirty.order.iter().map(|f| f.name).collect();
+//! assert_eq!(names, ["B1", "C1"]);
+//! assert!(dirty.cyclic.is_empty());
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque, hash_map::Entry},
+    rc::Rc,
+};
+
+/// The dependents found by [`propagate`].
+#[derive(Debug)]
+pub struct Dirty<T> {
+    /// Every dependent that can be ordered, each after all the dependents it depends on.
+    pub order: Vec<Rc<T>>,
+    /// Every dependent on or downstream of a cycle, in the order they were found.
+    pub cyclic: Vec<Rc<T>>,
+}
+
+impl<T> Dirty<T> {
+    /// Returns the number of dependents found.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.order.len() + self.cyclic.len()
+    }
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.order.is_empty() && self.cyclic.is_empty()
+    }
+}
+
+/// Finds every transitive dependent of `changed`, where `dependents(node)` returns the
+/// nodes computed directly from `node`.
+///
+/// `changed` itself is not returned, and edges leading back to it are ignored, since it
+/// is the source of the change.
+pub fn propagate<T, I>(changed: &Rc<T>, mut dependents: impl FnMut(&T) -> I) -> Dirty<T>
+where
+    I: IntoIterator<Item = Rc<T>>,
+{
+    // Every node found, its dependents by index, and how many of its inputs are dirty.
+    let mut nodes = vec![Rc::clone(changed)];
+    let mut edges: Vec<Vec<usize>> = Vec::new();
+    let mut inputs = vec![0];
+    let mut index = HashMap::from([(Rc::as_ptr(changed), 0)]);
+    while edges.len() < nodes.len() {
+        let node = Rc::clone(&nodes[edges.len()]);
+        let mut targets = Vec::new();
+        for next in dependents(&node) {
+            let target = match index.entry(Rc::as_ptr(&next)) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    nodes.push(next);
+                    inputs.push(0);
+                    *entry.insert(nodes.len() - 1)
+                }
+            };
+            if target != 0 {
+                inputs[target] += 1;
+                targets.push(target);
+            }
+        }
+        edges.push(targets);
+    }
+
+    let mut order = Vec::new();
+    let mut ready = VecDeque::from([0]);
+    while let Some(i) = ready.pop_front() {
+        for &target in &edges[i] {
+            inputs[target] -= 1;
+            if inputs[target] == 0 {
+                order.push(Rc::clone(&nodes[target]));
+                ready.push_back(target);
+            }
+        }
+    }
+    let cyclic = (1..nodes.len())
+        .filter(|&i| inputs[i] > 0)
+        .map(|i| Rc::clone(&nodes[i]))
+        .collect();
+    Dirty { order, cyclic }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Deferred, HasNeighbors, SetOnce, visit::neighbors_of};
+
+    struct Node {
+        id: u8,
+        dependents: Vec<Deferred<Self>>,
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.dependents.iter()
+        }
+    }
+
+    #[test]
+    fn cycles_are_set_apart() {
+        let nodes: Vec<_> = (0..6)
+            .map(|id| {
+                Rc::new(Node {
+                    id,
+                    dependents: (0..2).map(|_| Deferred::new()).collect(),
+                })
+            })
+            .collect();
+        let edges = [(0, 0, 2), (0, 1, 1), (1, 0, 2), (2, 0, 0), (2, 1, 3)];
+        let edges = edges.into_iter().chain([(3, 0, 4), (4, 0, 3), (4, 1, 5)]);
+        for (from, k, to) in edges {
+            SetOnce::from(&nodes[from].dependents[k])
+                .try_set(&nodes[to])
+                .unwrap();
+        }
+        let ids = |nodes: &[Rc<Node>]| nodes.iter().map(|n| n.id).collect::<Vec<_>>();
+
+        let dirty = propagate(&nodes[0], neighbors_of);
+        assert_eq!(ids(&dirty.order), [1, 2]);
+        assert_eq!(ids(&dirty.cyclic), [3, 4, 5]);
+        assert_eq!(dirty.len(), 5);
+        assert!(propagate(&nodes[5], neighbors_of).is_empty());
+    }
+}
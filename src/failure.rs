@@ -0,0 +1,91 @@
+//! What `get()` does when a cell cannot be read.
+//!
+//! By default, [`Deferred::get`](crate::Deferred::get),
+//! [`DeferredSync::get`](crate::DeferredSync::get), and the getters generated by
+//! `#[deferred]` panic with the error. Installing a handler replaces that panic for the
+//! whole process, for example to log and abort, or to enter a firmware fault routine.
+//! Handlers never return, so `get()` never yields a value it could not read.
+//!
+//! The handler is kept in an atomic, so it works without `std` or `alloc` as well.
+//!
+//! ```no_run
+//! use deferred_cell::{Deferred, failure};
+//!
+//! failure::set_handler(|error| {
+//!     eprintln!("deferred-cell [{}]: {error}", error.code());
+//!     std::process::abort()
+//! });
+//!
+//! let cell: Deferred<u32> = Deferred::new();
+//! let _ = cell.get(); // aborts
+//! ```
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::DeferredError;
+
+/// A process-wide replacement for the panic raised by a failed `get()`.
+pub type FailureHandler = fn(&DeferredError) -> !;
+
+/// The installed handler, or null for the default panic.
+static HANDLER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Installs `handler`, replacing any previous one.
+pub fn set_handler(handler: FailureHandler) {
+    HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+/// Removes the installed handler, restoring the default panic, and returns it.
+pub fn take_handler() -> Option<FailureHandler> {
+    decode(HANDLER.swap(ptr::null_mut(), Ordering::AcqRel))
+}
+
+fn decode(handler: *mut ()) -> Option<FailureHandler> {
+    // SAFETY: the only non-null pointers stored in `HANDLER` are `FailureHandler`s.
+    (!handler.is_null())
+        .then(|| unsafe { core::mem::transmute::<*mut (), FailureHandler>(handler) })
+}
+
+/// A handler that aborts the process without unwinding.
+#[cfg(feature = "std")]
+pub fn abort(_: &DeferredError) -> ! {
+    std::process::abort()
+}
+
+/// Hands `error` to the installed handler, or panics with it. Custom accessors can call
+/// this to fail the same way `get()` does.
+#[cold]
+#[track_caller]
+pub fn fail(error: &DeferredError) -> ! {
+    let Some(handler) = decode(HANDLER.load(Ordering::Acquire)) else {
+        panic!("{error}")
+    };
+    handler(error)
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    use super::*;
+    use crate::Deferred;
+
+    #[test]
+    fn installed_handler_replaces_the_panic() {
+        fn handler(error: &DeferredError) -> ! {
+            panic!("handled {}", error.code())
+        }
+        let cell: Deferred<u8> = Deferred::new();
+        set_handler(handler);
+        let payload = catch_unwind(AssertUnwindSafe(|| cell.get())).unwrap_err();
+        assert!(take_handler().is_some());
+
+        assert_eq!(*payload.downcast::<String>().unwrap(), "handled DC0002");
+        assert!(take_handler().is_none());
+    }
+}
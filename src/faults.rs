@@ -0,0 +1,198 @@
+//! Failure injection for exercising error paths, enabled by the `fault-injection` feature.
+//!
+//! While a [`Faults`] configuration is installed, reads through `get`, `try_get`, and
+//! `wait` on the current thread fail with the chosen [`Fault`] when they target a
+//! chosen cell or lose a seeded coin flip. Cells are named by the accessors generated
+//! with `#[deferred]`, such as `Node.parent`.
+//!
+//! ```
+//! use deferred_cell::{Deferred, DeferredError, faults::{Fault, Faults}};
+//! use std::rc::Rc;
+//!
+//! let target = Rc::new(1);
+//! let cell = Deferred::from(&target);
+//! {
+//!     let _faults = Faults::new().probability(1.0, 7, Fault::TargetDropped).install();
+//!     let error = cell.try_get().unwrap_err();
+//!     assert!(matches!(error.root_cause(), DeferredError::TargetDropped()));
+//! }
+//! assert_eq!(*cell.get(), 1);
+//! ```
+
+use std::{cell::RefCell, marker::PhantomData};
+
+use crate::DeferredError;
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Faults>> = const { RefCell::new(None) };
+}
+
+/// The error an injected failure reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Fault {
+    /// Fails with [`NotInitializedError`](DeferredError::NotInitializedError), as if the
+    /// cell had never been set.
+    #[default]
+    NotInitialized,
+    /// Fails with [`TargetDropped`](DeferredError::TargetDropped), as if the target had
+    /// been dropped.
+    TargetDropped,
+}
+
+impl Fault {
+    const fn error(self) -> DeferredError {
+        match self {
+            Self::NotInitialized => DeferredError::NotInitializedError(),
+            Self::TargetDropped => DeferredError::TargetDropped(),
+        }
+    }
+}
+
+/// Which reads to fail, and how.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    cells: Vec<(&'static str, Fault)>,
+    probability: f64,
+    fault: Fault,
+    state: u64,
+}
+
+impl Faults {
+    /// Creates a configuration that fails nothing.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Fails every read of the cell named `name`, such as `Node.parent`, with `fault`.
+    #[must_use]
+    pub fn cell(mut self, name: &'static str, fault: Fault) -> Self {
+        self.cells.push((name, fault));
+        self
+    }
+    /// Fails each read with `fault` with the given probability, drawn from a generator
+    /// seeded with `seed`.
+    #[must_use]
+    pub const fn probability(mut self, probability: f64, seed: u64, fault: Fault) -> Self {
+        self.probability = probability;
+        self.fault = fault;
+        self.state = seed | 1;
+        self
+    }
+    /// Activates the configuration on the current thread until the guard is dropped.
+    #[must_use]
+    pub fn install(self) -> FaultGuard {
+        let previous = ACTIVE.with(|active| active.replace(Some(self)));
+        FaultGuard {
+            previous,
+            _thread: PhantomData,
+        }
+    }
+    fn fault(&mut self, cell: Option<&'static str>) -> Option<Fault> {
+        if let Some(&(_, fault)) = cell.and_then(|name| self.cells.iter().find(|(n, _)| *n == name))
+        {
+            return Some(fault);
+        }
+        if self.probability <= 0.0 {
+            return None;
+        }
+        // xorshift64
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        let sample = (self.state >> 11) as f64 / (1u64 << 53) as f64;
+        (sample < self.probability).then_some(self.fault)
+    }
+}
+
+/// Restores the previously installed configuration, if any, when dropped.
+///
+/// Not `Send`: the configuration belongs to the thread that installed it.
+///
+/// ```compile_fail
+/// fn send<T: Send>(_: T) {}
+/// send(deferred_cell::faults::Faults::new().install());
+/// ```
+#[derive(Debug)]
+pub struct FaultGuard {
+    previous: Option<Faults>,
+    _thread: PhantomData<*const ()>,
+}
+
+impl Drop for FaultGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ACTIVE.with(|active| active.replace(previous));
+    }
+}
+
+/// Returns the error to inject into the current read, if any.
+pub(crate) fn check() -> Result<(), DeferredError> {
+    let cell = crate::diagnostics::current_cell();
+    let fault = ACTIVE.with(|active| active.borrow_mut().as_mut().and_then(|f| f.fault(cell)));
+    fault.map_or(Ok(()), |fault| Err(fault.error()))
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{Deferred, diagnostics};
+
+    #[test]
+    fn named_cells_fail_only_inside_their_accessor() {
+        let target = Rc::new(1);
+        let parent = Deferred::from(&target);
+        let _faults = Faults::new()
+            .cell("Node.parent", Fault::NotInitialized)
+            .cell("Node.child", Fault::TargetDropped)
+            .install();
+
+        let named = {
+            let _cell = diagnostics::enter_cell("Node.parent");
+            parent.try_get()
+        };
+        let dropped = {
+            let _cell = diagnostics::enter_cell("Node.child");
+            parent.try_get()
+        };
+        let other = {
+            let _cell = diagnostics::enter_cell("Node.sibling");
+            parent.try_get()
+        };
+
+        assert!(matches!(
+            named.unwrap_err().root_cause(),
+            DeferredError::NotInitializedError()
+        ));
+        assert!(matches!(
+            dropped.unwrap_err().root_cause(),
+            DeferredError::TargetDropped()
+        ));
+        assert_eq!(*other.unwrap(), 1);
+        assert_eq!(*parent.try_get().unwrap(), 1);
+    }
+    #[test]
+    fn probability_is_seeded_and_guards_nest() {
+        let target = Rc::new(1);
+        let cell = Deferred::from(&target);
+        let failures = |seed| {
+            let _faults = Faults::new()
+                .probability(0.25, seed, Fault::default())
+                .install();
+            (0..1000).filter(|_| cell.try_get().is_err()).count()
+        };
+
+        let first = failures(42);
+        assert_eq!(first, failures(42));
+        assert!((150..350).contains(&first));
+
+        let _outer = Faults::new()
+            .probability(1.0, 1, Fault::default())
+            .install();
+        drop(Faults::new().install());
+        assert!(cell.try_get().is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Reflection over the deferred cells of a struct.
+
+use std::any::type_name;
+
+use thiserror::Error;
+
+use crate::{Deferred, DeferredSync};
+
+/// Type-erased view of a deferred cell.
+pub trait AnyDeferredCell {
+    /// Returns `true` once the cell has been set.
+    fn is_ready(&self) -> bool;
+    /// Returns the name of the type the cell points to.
+    fn target_type_name(&self) -> &'static str;
+    /// Returns `true` if the cell was set but its target has since been dropped.
+    fn is_dangling(&self) -> bool;
+}
+
+impl<T> AnyDeferredCell for Deferred<T> {
+    #[inline]
+    fn is_ready(&self) -> bool {
+        Self::is_ready(self)
+    }
+    fn target_type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+    fn is_dangling(&self) -> bool {
+        Self::is_dangling(self)
+    }
+}
+
+impl<T> AnyDeferredCell for DeferredSync<T> {
+    #[inline]
+    fn is_ready(&self) -> bool {
+        Self::is_ready(self)
+    }
+    fn target_type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+    fn is_dangling(&self) -> bool {
+        Self::is_dangling(self)
+    }
+}
+
+/// Enumerates the deferred cells of a struct by field name.
+///
+/// Usually implemented with `#[derive(DeferredFields)]` (requires the `derive` feature),
+/// which lists every field of type `Deferred<T>` or `DeferredSync<T>`.
+///
+/// ```
+/// use deferred_cell::{AnyDeferredCell, Deferred, DeferredFields};
+///
+/// struct Node {
+///     parent: Deferred<Node>,
+///     sibling: Deferred<Node>,
+/// }
+///
+/// impl DeferredFields for Node {
+///     fn deferred_fields(&self) -> impl Iterator<Item = (&'static str, &dyn AnyDeferredCell)> {
+///         [
+///             ("parent", &self.parent as &dyn AnyDeferredCell),
+///             ("sibling", &self.sibling as &dyn AnyDeferredCell),
+///         ]
+///         .into_iter()
+///     }
+/// }
+///
+/// let node = Node { parent: Deferred::new(), sibling: Deferred::new() };
+/// let unset: Vec<_> = node
+///     .deferred_fields()
+///     .filter(|(_, cell)| !cell.is_ready())
+///     .map(|(name, _)| name)
+///     .collect();
+/// assert_eq!(unset, ["parent", "sibling"]);
+/// ```
+pub trait DeferredFields {
+    /// Returns each deferred field's name together with the cell itself.
+    fn deferred_fields(&self) -> impl Iterator<Item = (&'static str, &dyn AnyDeferredCell)>;
+
+    /// Checks that every deferred field has been set, reporting all unset fields at once.
+    ///
+    /// ```
+    /// # use deferred_cell::{AnyDeferredCell, Deferred, DeferredFields};
+    /// # struct Node { parent: Deferred<Node>, left_sibling: Deferred<Node> }
+    /// # impl DeferredFields for Node {
+    /// #     fn deferred_fields(&self) -> impl Iterator<Item = (&'static str, &dyn AnyDeferredCell)> {
+    /// #         [
+    /// #             ("parent", &self.parent as &dyn AnyDeferredCell),
+    /// #             ("left_sibling", &self.left_sibling as &dyn AnyDeferredCell),
+    /// #         ]
+    /// #         .into_iter()
+    /// #     }
+    /// # }
+    /// let node = Node { parent: Deferred::new(), left_sibling: Deferred::new() };
+    /// let error = node.validate().unwrap_err();
+    /// assert_eq!(error.to_string(), "Node: parent, left_sibling not initialized");
+    /// ```
+    fn validate(&self) -> Result<(), UnsetCells> {
+        let fields: Vec<_> = self
+            .deferred_fields()
+            .filter(|(_, cell)| !cell.is_ready())
+            .map(|(name, _)| name)
+            .collect();
+        if fields.is_empty() {
+            return Ok(());
+        }
+        let full = type_name::<Self>();
+        let path = full.split('<').next().unwrap_or(full);
+        Err(UnsetCells {
+            node: path.rsplit("::").next().unwrap_or(path),
+            fields,
+        })
+    }
+}
+
+/// The deferred fields of a node that were never set, returned by
+/// [`DeferredFields::validate`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{node}: {} not initialized", fields.join(", "))]
+pub struct UnsetCells {
+    /// Name of the node's type, without its module path or generic arguments.
+    pub node: &'static str,
+    /// Names of the unset fields, in declaration order.
+    pub fields: Vec<&'static str>,
+}
+
+impl UnsetCells {
+    /// Returns this error's stable code, `DC0004`. See [`DeferredError::code`](crate::DeferredError::code).
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        "DC0004"
+    }
+}
+
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for UnsetCells {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(self.code()))
+    }
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(
+            "wire every listed field with SetOnce::try_set before using the node",
+        ))
+    }
+}
+
+/// Serializes as a map of the stable `code`, the `message`, and the `node` and `fields`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for UnsetCells {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+        map.serialize_entry("node", self.node)?;
+        map.serialize_entry("fields", &self.fields)?;
+        map.end()
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+// loom's primitives panic outside `loom::model`.
+#[cfg(all(test, not(feature = "loom")))]
+mod test {
+    use std::{rc::Rc, sync::Arc};
+
+    use super::*;
+    use crate::SetOnce;
+
+    struct Pair<T> {
+        first: Deferred<T>,
+        second: DeferredSync<T>,
+    }
+
+    impl<T> DeferredFields for Pair<T> {
+        fn deferred_fields(&self) -> impl Iterator<Item = (&'static str, &dyn AnyDeferredCell)> {
+            [
+                ("first", &self.first as &dyn AnyDeferredCell),
+                ("second", &self.second as &dyn AnyDeferredCell),
+            ]
+            .into_iter()
+        }
+    }
+
+    #[test]
+    fn validate_lists_only_unset_fields() {
+        let shared = Arc::new(1);
+        let pair = Pair {
+            first: Deferred::new(),
+            second: DeferredSync::from(&shared),
+        };
+        assert_eq!(
+            pair.validate(),
+            Err(UnsetCells {
+                node: "Pair",
+                fields: vec!["first"]
+            })
+        );
+        assert_eq!(pair.validate().unwrap_err().code(), "DC0004");
+
+        let local = Rc::new(2);
+        SetOnce::from(&pair.first).try_set(&local).unwrap();
+        assert!(pair.validate().is_ok());
+    }
+}
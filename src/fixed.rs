@@ -0,0 +1,219 @@
+//! Graphs in fixed-capacity storage, for targets without an allocator.
+//!
+//! A [`FixedGraph`] keeps up to `N` nodes inline, like a `heapless::Vec`, so it can live
+//! on the stack or in any other storage the caller provides, and it never allocates.
+//! Cells are [`IndexCell`]s: an optional two-byte [`NodeIndex`], four bytes in all in
+//! place of a weak pointer, set once through [`FixedGraph::link`] and read back through
+//! [`FixedGraph::target`], with the same [`DeferredError`]s as
+//! [`Deferred`](crate::Deferred) cells. The capacity is checked at compile time to fit a
+//! [`NodeIndex`].
+//!
+//! The module only uses `core`, so it is available with the `std` and `alloc` features
+//! both turned off.
+//!
+//! ```
+//! use deferred_cell::fixed::{FixedGraph, IndexCell};
+//!
+//! struct Led {
+//!     pin: u8,
+//!     next: IndexCell,
+//! }
+//!
+//! let mut ring: FixedGraph<Led, 4> = FixedGraph::new();
+//! let pins = [2, 3, 5].map(|pin| ring.push(Led { pin, next: IndexCell::new() }).ok().unwrap());
+//! for (i, &pin) in pins.iter().enumerate() {
+//!     ring.link(pin, |led| &led.next, pins[(i + 1) % pins.len()]).unwrap();
+//! }
+//! assert_eq!(ring.target(&ring[pins[2]].next).unwrap().pin, 2);
+//! assert!(ring.link(pins[0], |led| &led.next, pins[1]).is_err());
+//! ```
+
+use core::{cell::Cell, fmt, ops::Index};
+
+use crate::DeferredError;
+
+/// The position of a node in a [`FixedGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeIndex(u16);
+
+impl NodeIndex {
+    #[must_use]
+    pub const fn get(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for NodeIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// A set-once cell holding the [`NodeIndex`] of its target.
+#[derive(Debug, Default)]
+pub struct IndexCell {
+    target: Cell<Option<NodeIndex>>,
+}
+
+impl IndexCell {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            target: Cell::new(None),
+        }
+    }
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.target.get().is_some()
+    }
+    /// Returns the index of the target, or an error if the cell is not set.
+    pub fn try_get(&self) -> Result<NodeIndex, DeferredError> {
+        self.target
+            .get()
+            .ok_or(DeferredError::NotInitializedError())
+    }
+    fn try_set(&self, target: NodeIndex) -> Result<(), DeferredError> {
+        if self.is_ready() {
+            return Err(DeferredError::DuplicateInitialization());
+        }
+        self.target.set(Some(target));
+        Ok(())
+    }
+}
+
+/// Up to `N` nodes stored inline, wired through [`IndexCell`]s.
+pub struct FixedGraph<T, const N: usize> {
+    nodes: [Option<T>; N],
+    len: u16,
+}
+
+impl<T, const N: usize> Default for FixedGraph<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> FixedGraph<T, N> {
+    const FITS: () = assert!(N <= u16::MAX as usize, "capacity must fit a NodeIndex");
+
+    #[must_use]
+    pub const fn new() -> Self {
+        let () = Self::FITS;
+        Self {
+            nodes: [const { None }; N],
+            len: 0,
+        }
+    }
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len as usize
+    }
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Adds `node` and returns its index, or hands it back if the graph is full.
+    pub fn push(&mut self, node: T) -> Result<NodeIndex, T> {
+        let Some(free) = self.nodes.get_mut(self.len as usize) else {
+            return Err(node);
+        };
+        *free = Some(node);
+        self.len += 1;
+        Ok(NodeIndex(self.len - 1))
+    }
+    /// Returns the node at `index`.
+    #[must_use]
+    pub fn get(&self, index: NodeIndex) -> Option<&T> {
+        self.nodes.get(index.get())?.as_ref()
+    }
+    /// Returns every node, in the order they were pushed.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeIndex, &T)> {
+        self.nodes
+            .iter()
+            .map_while(Option::as_ref)
+            .enumerate()
+            .map(|(index, node)| (NodeIndex(index as u16), node))
+    }
+    /// Sets the cell `slot` selects in the node at `from` to the node at `to`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `from` or `to` is not in this graph.
+    pub fn link(
+        &self,
+        from: NodeIndex,
+        slot: impl FnOnce(&T) -> &IndexCell,
+        to: NodeIndex,
+    ) -> Result<(), DeferredError> {
+        assert!(self.get(to).is_some(), "{to} is not in this graph");
+        slot(&self[from]).try_set(to)
+    }
+    /// Returns the node `cell` is set to, or an error if it is not set.
+    pub fn target(&self, cell: &IndexCell) -> Result<&T, DeferredError> {
+        let index = cell.try_get()?;
+        self.get(index).ok_or(DeferredError::NotInitializedError())
+    }
+}
+
+impl<T, const N: usize> Index<NodeIndex> for FixedGraph<T, N> {
+    type Output = T;
+
+    fn index(&self, index: NodeIndex) -> &T {
+        self.get(index)
+            .unwrap_or_else(|| panic!("{index} is not in this graph"))
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for FixedGraph<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Node {
+        value: u8,
+        parent: IndexCell,
+    }
+
+    fn node(value: u8) -> Node {
+        Node {
+            value,
+            parent: IndexCell::new(),
+        }
+    }
+
+    #[test]
+    fn cells_are_set_once_within_capacity() {
+        let mut graph: FixedGraph<Node, 2> = FixedGraph::new();
+        let root = graph.push(node(1)).unwrap();
+        let leaf = graph.push(node(2)).unwrap();
+        assert_eq!(graph.push(node(3)).unwrap_err().value, 3);
+        assert_eq!(graph.len(), graph.capacity());
+
+        assert!(matches!(
+            graph.target(&graph[leaf].parent),
+            Err(DeferredError::NotInitializedError())
+        ));
+        graph.link(leaf, |n| &n.parent, root).unwrap();
+        graph.link(root, |n| &n.parent, root).unwrap();
+        assert!(matches!(
+            graph.link(leaf, |n| &n.parent, leaf),
+            Err(DeferredError::DuplicateInitialization())
+        ));
+        assert_eq!(graph.target(&graph[leaf].parent).unwrap().value, 1);
+        assert_eq!(graph[leaf].parent.try_get().unwrap(), root);
+        assert!(graph.iter().map(|(_, n)| n.value).eq([1, 2]));
+        assert_eq!(core::mem::size_of::<IndexCell>(), 4);
+    }
+}
@@ -0,0 +1,222 @@
+//! Graphs frozen for the rest of the process.
+//!
+//! A graph built once at startup and never torn down gains nothing from weak
+//! references. [`leak_frozen`] resolves every [`HasNeighbors`] edge of the nodes to the
+//! index of its target, checking that each is set and points at another of the nodes,
+//! then leaks them. Edges are then followed through [`FrozenRef`]s, which hand out
+//! plain `&'static T` references with no upgrades or reference counting.
+//!
+//! Leaking is rarely wanted in tests, so [`Frozen::new`] offers the same checks and
+//! accessors over a borrowed slice of nodes instead.
+//!
+//! ```
+//! use deferred_cell::{Deferred, HasNeighbors, SetOnce, frozen};
+//! use std::rc::Rc;
+//!
+//! struct Node {
+//!     name: &'static str,
+//!     next: Deferred<Node>,
+//! }
+//!
+//! impl HasNeighbors for Node {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         std::iter::once(&self.next)
+//!     }
+//! }
+//!
+//! let nodes: Vec<_> = ["North", "South"]
+//!     .map(|name| Rc::new(Node { name, next: Deferred::new() }))
+//!     .into();
+//! SetOnce::from(&nodes[0].next).try_set(&nodes[1]).unwrap();
+//! SetOnce::from(&nodes[1].next).try_set(&nodes[0]).unwrap();
+//!
+//! let graph = frozen::leak_frozen(nodes).unwrap();
+//! let north = graph.get(0).unwrap();
+//! let south: &'static Node = north.neighbors().next().unwrap().get();
+//! assert_eq!(south.name, "South");
+//! ```
+
+use alloc::{boxed::Box, collections::BTreeMap, format, rc::Rc, vec::Vec};
+use core::ops::Deref;
+
+use crate::{DeferredError, HasNeighbors};
+
+/// Nodes whose edges have been resolved to one another.
+#[derive(Debug)]
+pub struct Frozen<'g, T> {
+    nodes: &'g [Rc<T>],
+    // Node `i`'s neighbors are `targets[offsets[i]..offsets[i + 1]]`.
+    offsets: Vec<usize>,
+    targets: Vec<usize>,
+}
+
+/// Checks and leaks `nodes`, so their edges can be followed as `&'static T`.
+///
+/// Nothing is leaked if the check fails.
+pub fn leak_frozen<T: HasNeighbors>(
+    nodes: Vec<Rc<T>>,
+) -> Result<&'static Frozen<'static, T>, DeferredError> {
+    let Frozen {
+        offsets, targets, ..
+    } = Frozen::new(&nodes)?;
+    Ok(Box::leak(Box::new(Frozen {
+        nodes: Vec::leak(nodes),
+        offsets,
+        targets,
+    })))
+}
+
+impl<'g, T: HasNeighbors> Frozen<'g, T> {
+    /// Resolves every edge of `nodes`, checking that each is set and points at one of
+    /// `nodes`.
+    pub fn new(nodes: &'g [Rc<T>]) -> Result<Self, DeferredError> {
+        let indices: BTreeMap<_, _> = nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (Rc::as_ptr(node), index))
+            .collect();
+        let mut offsets = Vec::with_capacity(nodes.len() + 1);
+        let mut targets = Vec::new();
+        offsets.push(0);
+        for (index, node) in nodes.iter().enumerate() {
+            for (k, cell) in node.neighbors().enumerate() {
+                let target = cell
+                    .try_get()
+                    .map_err(|e| e.context(format!("node {index} neighbor {k}")))?;
+                let Some(&target) = indices.get(&Rc::as_ptr(&target)) else {
+                    return Err(
+                        DeferredError::OutsideGraph().context(format!("node {index} neighbor {k}"))
+                    );
+                };
+                targets.push(target);
+            }
+            offsets.push(targets.len());
+        }
+        Ok(Self {
+            nodes,
+            offsets,
+            targets,
+        })
+    }
+}
+
+impl<T> Frozen<'_, T> {
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<FrozenRef<'_, T>> {
+        (index < self.nodes.len()).then_some(FrozenRef { graph: self, index })
+    }
+    pub fn iter(&self) -> impl Iterator<Item = FrozenRef<'_, T>> {
+        (0..self.nodes.len()).map(|index| FrozenRef { graph: self, index })
+    }
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// A node of a [`Frozen`] graph.
+#[derive(Debug)]
+pub struct FrozenRef<'a, T> {
+    graph: &'a Frozen<'a, T>,
+    index: usize,
+}
+
+impl<T> Clone for FrozenRef<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FrozenRef<'_, T> {}
+
+impl<'a, T> FrozenRef<'a, T> {
+    #[must_use]
+    pub fn get(self) -> &'a T {
+        &self.graph.nodes[self.index]
+    }
+    /// Returns the node's position in the graph.
+    #[must_use]
+    pub const fn index(self) -> usize {
+        self.index
+    }
+    /// Follows every edge of the node, in [`HasNeighbors`] order.
+    pub fn neighbors(self) -> impl Iterator<Item = Self> {
+        let graph = self.graph;
+        graph.targets[graph.offsets[self.index]..graph.offsets[self.index + 1]]
+            .iter()
+            .map(move |&index| Self { graph, index })
+    }
+}
+
+impl<T> Deref for FrozenRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.get()
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::{Deferred, SetOnce};
+
+    #[derive(Debug)]
+    struct Node {
+        value: u8,
+        next: Deferred<Self>,
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            std::iter::once(&self.next)
+        }
+    }
+
+    fn node(value: u8) -> Rc<Node> {
+        Rc::new(Node {
+            value,
+            next: Deferred::new(),
+        })
+    }
+
+    #[test]
+    fn borrowed_graphs_follow_edges_without_counting() {
+        let nodes = vec![node(1), node(2)];
+        SetOnce::from(&nodes[0].next).try_set(&nodes[1]).unwrap();
+        assert!(Frozen::new(&nodes).is_err());
+        SetOnce::from(&nodes[1].next).try_set(&nodes[0]).unwrap();
+
+        let graph = Frozen::new(&nodes).unwrap();
+        let values: Vec<_> = graph
+            .get(0)
+            .unwrap()
+            .neighbors()
+            .flat_map(FrozenRef::neighbors)
+            .map(|n| n.value)
+            .collect();
+        assert_eq!(values, [1]);
+        assert_eq!(
+            graph.iter().map(FrozenRef::index).collect::<Vec<_>>(),
+            [0, 1]
+        );
+        assert_eq!(Rc::strong_count(&nodes[1]), 1);
+    }
+    #[test]
+    fn edges_must_stay_inside_the_graph() {
+        let (inside, outside) = (node(1), node(2));
+        SetOnce::from(&inside.next).try_set(&outside).unwrap();
+        let error = leak_frozen(vec![inside]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "node 0 neighbor 0: Cell points outside the graph!"
+        );
+        assert_eq!(error.code(), "DC0010");
+    }
+}
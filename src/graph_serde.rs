@@ -0,0 +1,285 @@
+//! Serializing whole graphs with serde, enabled by the `serde` feature.
+//!
+//! A graph is written as a sequence of node records, in the order of the node slice.
+//! Every `Deferred<Self>` edge is replaced by the stable ID of its target, which is the
+//! target's position in that sequence, or `null` for an unset cell. Reading the
+//! sequence back creates every node first and then wires the edges, so cycles round
+//! trip like any other edge.
+//!
+//! Node types implement [`GraphSerialize`] and [`GraphDeserialize`], usually through
+//! the derives of the same names, and are passed to serde wrapped in
+//! [`SerializeGraph`] and [`DeserializeGraph`].
+//!
+//! ```
+//! # #[cfg(all(feature = "derive", feature = "json"))] {
+//! use deferred_cell::{
+//!     Deferred, GraphDeserialize, GraphSerialize, SetOnce,
+//!     graph_serde::{DeserializeGraph, SerializeGraph},
+//! };
+//! use std::rc::Rc;
+//!
+//! #[derive(GraphSerialize, GraphDeserialize)]
+//! struct Node {
+//!     name: String,
+//!     next: Deferred<Node>,
+//! }
+//!
+//! let nodes: Vec<_> = ["a", "b"]
+//!     .map(|name| Rc::new(Node { name: name.into(), next: Deferred::new() }))
+//!     .into();
+//! SetOnce::from(&nodes[0].next).try_set(&nodes[1]).unwrap();
+//! SetOnce::from(&nodes[1].next).try_set(&nodes[0]).unwrap();
+//!
+//! let json = serde_json::to_string(&SerializeGraph(&nodes)).unwrap();
+//! assert_eq!(json, r#"[{"name":"a","next":1},{"name":"b","next":0}]"#);
+//!
+//! let DeserializeGraph(copy) = serde_json::from_str::<DeserializeGraph<Node>>(&json).unwrap();
+//! assert_eq!(copy[0].next.get().next.get().name, "a");
+//! # }
+//! ```
+
+use std::{collections::HashMap, rc::Rc};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::{Deferred, DeferredError, SetOnce};
+
+/// A node that can be written as part of a graph.
+pub trait GraphSerialize: Sized {
+    /// Writes the node, replacing each edge with the ID `ids` assigns to its target.
+    fn serialize_node<S: Serializer>(
+        &self,
+        ids: &NodeIds<Self>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>;
+}
+
+/// A node that can be read as part of a graph.
+pub trait GraphDeserialize: Sized {
+    /// Reads a node with every edge unset, along with the IDs its edges point at, in
+    /// the order of [`edges`](Self::edges).
+    fn deserialize_node<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<(Self, Vec<Option<usize>>), D::Error>;
+    /// Returns the cells the IDs read by [`deserialize_node`](Self::deserialize_node)
+    /// are wired into.
+    fn edges(&self) -> impl Iterator<Item = &Deferred<Self>>;
+}
+
+/// The stable IDs of the nodes being serialized: each node's position in the slice.
+#[derive(Debug)]
+pub struct NodeIds<T> {
+    ids: HashMap<*const T, usize>,
+}
+
+impl<T> NodeIds<T> {
+    fn new(nodes: &[Rc<T>]) -> Self {
+        Self {
+            ids: nodes
+                .iter()
+                .enumerate()
+                .map(|(index, node)| (Rc::as_ptr(node), index))
+                .collect(),
+        }
+    }
+    /// Returns the ID of the node `cell` points at, or `None` if the cell is unset.
+    ///
+    /// Fails if the target was dropped or is not one of the nodes being serialized.
+    pub fn id_of(&self, cell: &Deferred<T>) -> Result<Option<usize>, DeferredError> {
+        if !cell.is_ready() {
+            return Ok(None);
+        }
+        let target = cell.try_get()?;
+        self.ids
+            .get(&Rc::as_ptr(&target))
+            .copied()
+            .map(Some)
+            .ok_or(DeferredError::OutsideGraph())
+    }
+}
+
+/// Serializes a graph as the sequence of its nodes.
+#[derive(Debug)]
+pub struct SerializeGraph<'a, T>(pub &'a [Rc<T>]);
+
+impl<T: GraphSerialize> Serialize for SerializeGraph<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct Node<'a, T> {
+            node: &'a T,
+            ids: &'a NodeIds<T>,
+        }
+        impl<T: GraphSerialize> Serialize for Node<'_, T> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.node.serialize_node(self.ids, serializer)
+            }
+        }
+
+        let ids = NodeIds::new(self.0);
+        serializer.collect_seq(self.0.iter().map(|node| Node {
+            node: &**node,
+            ids: &ids,
+        }))
+    }
+}
+
+/// A graph deserialized from the sequence of its nodes, with every edge wired.
+///
+/// Fails if a node reads a different number of IDs than it has
+/// [`edges`](GraphDeserialize::edges), or an ID is not the position of a node.
+#[derive(Debug)]
+pub struct DeserializeGraph<T>(pub Vec<Rc<T>>);
+
+impl<'de, T: GraphDeserialize> Deserialize<'de> for DeserializeGraph<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Node<T>(T, Vec<Option<usize>>);
+        impl<'de, T: GraphDeserialize> Deserialize<'de> for Node<T> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                T::deserialize_node(deserializer).map(|(node, ids)| Self(node, ids))
+            }
+        }
+
+        let records = Vec::<Node<T>>::deserialize(deserializer)?;
+        let mut ids = Vec::with_capacity(records.len());
+        let nodes: Vec<_> = records
+            .into_iter()
+            .map(|Node(node, edges)| {
+                ids.push(edges);
+                Rc::new(node)
+            })
+            .collect();
+        for (index, (node, edges)) in nodes.iter().zip(&ids).enumerate() {
+            let cells: Vec<_> = node.edges().collect();
+            if cells.len() != edges.len() {
+                return Err(de::Error::custom(format!(
+                    "node {index} has {} edges but {} IDs were read!",
+                    cells.len(),
+                    edges.len()
+                )));
+            }
+            for (k, (cell, id)) in cells.into_iter().zip(edges).enumerate() {
+                let Some(id) = *id else { continue };
+                nodes
+                    .get(id)
+                    .ok_or(DeferredError::OutsideGraph())
+                    .and_then(|target| SetOnce::from(cell).try_set(target))
+                    .map_err(|e| de::Error::custom(e.context(format!("node {index} edge {k}"))))?;
+            }
+        }
+        Ok(Self(nodes))
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+
+    /// A node written as its name and the IDs of its edges.
+    #[derive(Debug)]
+    struct Node {
+        name: String,
+        next: Deferred<Self>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Record {
+        name: String,
+        edges: Vec<Option<usize>>,
+    }
+
+    impl GraphSerialize for Node {
+        fn serialize_node<S: Serializer>(
+            &self,
+            ids: &NodeIds<Self>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            let next = ids.id_of(&self.next).map_err(serde::ser::Error::custom)?;
+            Record {
+                name: self.name.clone(),
+                edges: vec![next],
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl GraphDeserialize for Node {
+        fn deserialize_node<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<(Self, Vec<Option<usize>>), D::Error> {
+            let Record { name, edges } = Record::deserialize(deserializer)?;
+            let node = Self {
+                name,
+                next: Deferred::new(),
+            };
+            Ok((node, edges))
+        }
+        fn edges(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            std::iter::once(&self.next)
+        }
+    }
+
+    fn node(name: &str) -> Rc<Node> {
+        Rc::new(Node {
+            name: name.into(),
+            next: Deferred::new(),
+        })
+    }
+
+    fn read(json: &str) -> Result<Vec<Rc<Node>>, serde_json::Error> {
+        serde_json::from_str::<DeserializeGraph<Node>>(json).map(|DeserializeGraph(nodes)| nodes)
+    }
+
+    #[test]
+    fn cycles_round_trip() {
+        let nodes = vec![node("a"), node("b")];
+        SetOnce::from(&nodes[0].next).try_set(&nodes[1]).unwrap();
+        SetOnce::from(&nodes[1].next).try_set(&nodes[0]).unwrap();
+
+        let json = serde_json::to_string(&SerializeGraph(&nodes)).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"name":"a","edges":[1]},{"name":"b","edges":[0]}]"#
+        );
+        let copy = read(&json).unwrap();
+        assert_eq!(copy[0].next.get().name, "b");
+        assert!(Rc::ptr_eq(&copy[0].next.get().next.get(), &copy[0]));
+    }
+    #[test]
+    fn unset_cells_round_trip_as_null() {
+        let nodes = vec![node("a")];
+        let json = serde_json::to_string(&SerializeGraph(&nodes)).unwrap();
+        assert_eq!(json, r#"[{"name":"a","edges":[null]}]"#);
+        assert!(!read(&json).unwrap()[0].next.is_ready());
+    }
+    #[test]
+    fn targets_outside_the_graph_fail_to_serialize() {
+        let outside = node("outside");
+        let nodes = vec![node("a")];
+        SetOnce::from(&nodes[0].next).try_set(&outside).unwrap();
+        assert!(serde_json::to_string(&SerializeGraph(&nodes)).is_err());
+    }
+    #[test]
+    fn unknown_ids_fail_to_deserialize() {
+        let error = read(r#"[{"name":"a","edges":[3]}]"#).unwrap_err();
+        assert!(error.to_string().contains("node 0 edge 0"));
+    }
+    #[test]
+    fn too_many_ids_fail_to_deserialize() {
+        let error = read(r#"[{"name":"a","edges":[0,0]}]"#).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("node 0 has 1 edges but 2 IDs were read!")
+        );
+    }
+    #[test]
+    fn too_few_ids_fail_to_deserialize() {
+        let error = read(r#"[{"name":"a","edges":[]}]"#).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("node 0 has 1 edges but 0 IDs were read!")
+        );
+    }
+}
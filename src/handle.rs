@@ -0,0 +1,336 @@
+//! Integer handles to nodes and cells, for holding a graph across an FFI boundary.
+//!
+//! JavaScript cannot hold an `Rc`, and handing it raw pointers would let it read freed
+//! nodes. A [`HandleTable`] owns the nodes instead and gives out [`NodeHandle`]s, which
+//! are plain integers paired with a generation so a handle to a removed node is
+//! rejected rather than resolving to whatever took its slot. A [`CellHandle`] names one
+//! [`HasNeighbors`] cell of a node by position, so edges can be queried and wired from
+//! the other side as well.
+//!
+//! With the `wasm` feature, both handle types are `wasm_bindgen` classes. Keep the table
+//! in a `thread_local!` and export functions taking and returning handles. Passing a
+//! handle to an exported function by value consumes the JavaScript object, so take
+//! `&NodeHandle` or use [`NodeHandle::to_bits`] where JavaScript keeps using it:
+//!
+//! ```
+//! use deferred_cell::{Deferred, HasNeighbors, handle::{CellHandle, HandleTable, NodeHandle}};
+//! use std::{cell::RefCell, rc::Rc};
+//!
+//! struct Room {
+//!     exits: [Deferred<Room>; 2],
+//! }
+//!
+//! impl HasNeighbors for Room {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         self.exits.iter()
+//!     }
+//! }
+//!
+//! thread_local! {
+//!     static ROOMS: RefCell<HandleTable<Room>> = RefCell::new(HandleTable::new());
+//! }
+//!
+//! // #[wasm_bindgen]
+//! pub fn add_room() -> NodeHandle {
+//!     let room = Rc::new(Room { exits: [Deferred::new(), Deferred::new()] });
+//!     ROOMS.with_borrow_mut(|rooms| rooms.insert(room))
+//! }
+//!
+//! // #[wasm_bindgen]
+//! pub fn connect(from: &NodeHandle, exit: u32, to: &NodeHandle) -> Result<(), String> {
+//!     ROOMS
+//!         .with_borrow(|rooms| rooms.try_set(CellHandle::new(*from, exit), *to))
+//!         .map_err(|e| e.to_string())
+//! }
+//!
+//! let (hall, cellar) = (add_room(), add_room());
+//! connect(&hall, 0, &cellar).unwrap();
+//! let exit = ROOMS.with_borrow(|rooms| rooms.try_get(CellHandle::new(hall, 0)));
+//! assert_eq!(exit.unwrap(), cellar);
+//! ```
+
+use std::{collections::HashMap, fmt, rc::Rc};
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{DeferredError, HasNeighbors, SetOnce};
+
+/// An opaque reference to a node stored in a [`HandleTable`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeHandle {
+    index: u32,
+    generation: u32,
+}
+
+// wasm_bindgen cannot export const fns
+#[allow(clippy::missing_const_for_fn)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl NodeHandle {
+    /// Packs the handle into one integer, for storing it where a class cannot go.
+    #[must_use]
+    pub fn to_bits(&self) -> u64 {
+        ((self.generation as u64) << 32) | self.index as u64
+    }
+    /// Unpacks a handle packed by [`to_bits`](Self::to_bits).
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+impl fmt::Display for NodeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}v{}", self.index, self.generation)
+    }
+}
+
+/// The [`HasNeighbors`] cell at position `index` of a node.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CellHandle {
+    node: NodeHandle,
+    index: u32,
+}
+
+impl CellHandle {
+    #[must_use]
+    pub const fn new(node: NodeHandle, index: u32) -> Self {
+        Self { node, index }
+    }
+}
+
+#[allow(clippy::missing_const_for_fn)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CellHandle {
+    #[must_use]
+    pub fn node(&self) -> NodeHandle {
+        self.node
+    }
+    #[must_use]
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+}
+
+impl fmt::Display for CellHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} neighbor {}", self.node, self.index)
+    }
+}
+
+struct Slot<T> {
+    generation: u32,
+    node: Option<Rc<T>>,
+}
+
+/// Owns a graph's nodes and hands out handles to them.
+pub struct HandleTable<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    handles: HashMap<*const T, NodeHandle>,
+}
+
+impl<T> Default for HandleTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> HandleTable<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            handles: HashMap::new(),
+        }
+    }
+    /// Stores `node` and returns its handle. Inserting a node twice returns the handle
+    /// it already has.
+    ///
+    /// # Panics
+    /// Panics if the table would hold more than `u32::MAX` slots.
+    pub fn insert(&mut self, node: Rc<T>) -> NodeHandle {
+        if let Some(&handle) = self.handles.get(&Rc::as_ptr(&node)) {
+            return handle;
+        }
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(Slot {
+                generation: 0,
+                node: None,
+            });
+            #[allow(clippy::expect_used)]
+            u32::try_from(self.slots.len() - 1).expect("handle tables hold at most u32::MAX nodes")
+        });
+        let slot = &mut self.slots[index as usize];
+        let handle = NodeHandle {
+            index,
+            generation: slot.generation,
+        };
+        self.handles.insert(Rc::as_ptr(&node), handle);
+        slot.node = Some(node);
+        handle
+    }
+    /// Returns the node `handle` refers to.
+    ///
+    /// Fails with [`OutsideGraph`](DeferredError::OutsideGraph) if the handle is stale
+    /// or was never given out by this table.
+    pub fn get(&self, handle: NodeHandle) -> Result<&Rc<T>, DeferredError> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.node.as_ref())
+            .ok_or_else(|| {
+                DeferredError::OutsideGraph().context(format!("node {handle} is not in the table"))
+            })
+    }
+    /// Returns the handle of `node`, if it is stored in the table.
+    pub fn handle_of(&self, node: &Rc<T>) -> Option<NodeHandle> {
+        self.handles.get(&Rc::as_ptr(node)).copied()
+    }
+    /// Removes the node `handle` refers to, invalidating every copy of the handle.
+    pub fn remove(&mut self, handle: NodeHandle) -> Option<Rc<T>> {
+        self.get(handle).ok()?;
+        let slot = &mut self.slots[handle.index as usize];
+        let node = slot.node.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        self.handles.remove(&Rc::as_ptr(&node));
+        Some(node)
+    }
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+}
+
+impl<T: HasNeighbors> HandleTable<T> {
+    /// Points `cell` at the node `target` refers to.
+    pub fn try_set(&self, cell: CellHandle, target: NodeHandle) -> Result<(), DeferredError> {
+        let target = self.get(target)?;
+        self.with_cell(cell, |c| SetOnce::from(c).try_set(target))
+    }
+    /// Returns the handle of the node `cell` points at.
+    ///
+    /// Fails if the cell is unset, or its target is no longer in the table.
+    pub fn try_get(&self, cell: CellHandle) -> Result<NodeHandle, DeferredError> {
+        self.with_cell(cell, |c| {
+            let target = c.try_get()?;
+            self.handle_of(&target)
+                .ok_or_else(DeferredError::OutsideGraph)
+        })
+    }
+    /// Returns whether `cell` has been set.
+    pub fn is_ready(&self, cell: CellHandle) -> Result<bool, DeferredError> {
+        self.with_cell(cell, |c| Ok(c.is_ready()))
+    }
+    pub(crate) fn with_cell<R>(
+        &self,
+        cell: CellHandle,
+        f: impl FnOnce(&crate::Deferred<T>) -> Result<R, DeferredError>,
+    ) -> Result<R, DeferredError> {
+        let node = self.get(cell.node)?;
+        let Some(c) = node.neighbors().nth(cell.index as usize) else {
+            return Err(DeferredError::OutsideGraph().context(format!("{cell} does not exist")));
+        };
+        f(c).map_err(|e| e.context(cell.to_string()))
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Deferred;
+
+    struct Node {
+        next: Deferred<Self>,
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            std::iter::once(&self.next)
+        }
+    }
+
+    fn node() -> Rc<Node> {
+        Rc::new(Node {
+            next: Deferred::new(),
+        })
+    }
+
+    #[test]
+    fn cells_are_set_once_through_handles() {
+        let mut table = HandleTable::new();
+        let (a, b) = (table.insert(node()), table.insert(node()));
+        let next = CellHandle::new(a, 0);
+        assert!(!table.is_ready(next).unwrap());
+        table.try_set(next, b).unwrap();
+        assert_eq!(table.try_get(next).unwrap(), b);
+        assert!(table.try_set(next, a).is_err());
+        assert!(table.try_get(CellHandle::new(a, 1)).is_err());
+    }
+    #[test]
+    fn removed_nodes_invalidate_their_handles() {
+        let mut table = HandleTable::new();
+        let (a, b) = (table.insert(node()), table.insert(node()));
+        let next = CellHandle::new(a, 0);
+        table.try_set(next, b).unwrap();
+
+        let removed = table.remove(b).unwrap();
+        let c = table.insert(node());
+        assert_ne!(b, c);
+        let stale = table.get(b).err().unwrap();
+        assert_eq!(
+            stale.to_string(),
+            "node #1v0 is not in the table: Cell points outside the graph!"
+        );
+        assert_eq!(
+            table.try_get(next).unwrap_err().to_string(),
+            "#0v0 neighbor 0: Cell points outside the graph!"
+        );
+        drop(removed);
+        assert!(matches!(
+            table.try_get(next).unwrap_err().root_cause(),
+            DeferredError::TargetDropped()
+        ));
+    }
+    #[test]
+    fn reinserted_nodes_get_new_handles() {
+        let mut table = HandleTable::new();
+        let (a, b) = (table.insert(node()), table.insert(node()));
+        let next = CellHandle::new(a, 0);
+        table.try_set(next, b).unwrap();
+
+        let removed = table.remove(b).unwrap();
+        table.insert(node());
+        let moved = table.insert(removed);
+        assert_eq!(
+            moved,
+            NodeHandle {
+                index: 2,
+                generation: 0
+            }
+        );
+        assert_eq!(table.try_get(next).unwrap(), moved);
+    }
+    #[test]
+    fn handles_round_trip_through_bits() {
+        let mut table = HandleTable::new();
+        let a = table.insert(node());
+        table.remove(a).unwrap();
+        let b = table.insert(node());
+        assert_eq!(NodeHandle::from_bits(b.to_bits()), b);
+    }
+}
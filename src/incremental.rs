@@ -0,0 +1,180 @@
+//! Building graphs from nodes and edges that arrive over time.
+//!
+//! When a graph arrives as a stream, such as messages from the network, edges often
+//! refer to nodes that have not arrived yet. An [`IncrementalBuilder`] wires each edge
+//! as soon as both of its nodes are known, like a [`Resolver`], and keeps the others
+//! pending. [`pending`](IncrementalBuilder::pending) shows those forward references at
+//! any point, an optional limit bounds how many may pile up, and
+//! [`flush`](IncrementalBuilder::flush) hands over the nodes that have no pending edges
+//! left, so they can be used before the stream ends.
+//!
+//! ```
+//! use deferred_cell::{Deferred, incremental::IncrementalBuilder};
+//! use std::rc::Rc;
+//!
+//! struct Message {
+//!     text: &'static str,
+//!     reply_to: Deferred<Message>,
+//! }
+//!
+//! let message = |text| Rc::new(Message { text, reply_to: Deferred::new() });
+//! let mut builder = IncrementalBuilder::<u32, Message>::with_limit(100);
+//! builder.node(2, message("Me too")).unwrap();
+//! builder.edge(2, |m| &m.reply_to, 1).unwrap();
+//! builder.node(3, message("Hello")).unwrap();
+//! assert_eq!(builder.pending().collect::<Vec<_>>(), [(&2, &1)]);
+//!
+//! let ready: Vec<_> = builder.flush().into_iter().map(|(key, _)| key).collect();
+//! assert_eq!(ready, [3]);
+//!
+//! builder.node(1, message("I like graphs")).unwrap();
+//! let ready = builder.flush();
+//! assert_eq!(ready[0].1.reply_to.get().text, "I like graphs");
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    hash::Hash,
+    rc::Rc,
+};
+
+use crate::resolver::{ResolveError, Resolver, Slot};
+
+/// Wires nodes and edges as they arrive, tracking the edges still waiting for a node.
+pub struct IncrementalBuilder<K, T> {
+    resolver: Resolver<K, T>,
+    /// Keys registered since the last flush that still had pending edges then.
+    unflushed: Vec<K>,
+    limit: Option<usize>,
+}
+
+impl<K, T> Default for IncrementalBuilder<K, T> {
+    fn default() -> Self {
+        Self {
+            resolver: Resolver::default(),
+            unflushed: Vec::new(),
+            limit: None,
+        }
+    }
+}
+
+impl<K, T> IncrementalBuilder<K, T>
+where
+    K: Eq + Hash + Clone + Debug,
+{
+    /// Creates a builder that lets any number of edges wait for their nodes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Creates a builder that refuses edges once `limit` are waiting for their nodes.
+    #[must_use]
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..Self::default()
+        }
+    }
+    /// Registers `node` under `key` and wires every pending edge it completes.
+    pub fn node(&mut self, key: K, node: Rc<T>) -> Result<(), ResolveError<K>> {
+        self.resolver.register(key.clone(), node)?;
+        self.unflushed.push(key);
+        Ok(())
+    }
+    /// Wires `slot(from)` to `to` if both nodes have arrived, or queues the edge.
+    ///
+    /// Fails without queueing the edge if the limit of pending edges has been reached.
+    pub fn edge(&mut self, from: K, slot: Slot<T>, to: K) -> Result<(), ResolveError<K>> {
+        let waits = self.resolver.get(&from).is_none() || self.resolver.get(&to).is_none();
+        if let Some(limit) = self
+            .limit
+            .filter(|&limit| waits && self.pending_len() >= limit)
+        {
+            return Err(ResolveError::PendingLimit(limit));
+        }
+        self.resolver.edge(from, slot, to)
+    }
+    /// Returns the node registered under `key`.
+    pub fn get(&self, key: &K) -> Option<&Rc<T>> {
+        self.resolver.get(key)
+    }
+    /// Returns the `(from, to)` keys of every edge still waiting for a node, in the
+    /// order the edges were declared.
+    pub fn pending(&self) -> impl Iterator<Item = (&K, &K)> {
+        self.resolver.pending()
+    }
+    /// Returns the number of edges still waiting for a node.
+    pub fn pending_len(&self) -> usize {
+        self.resolver.pending_len()
+    }
+    /// Returns the nodes registered since the last flush that no pending edge leads
+    /// from or to, in the order they arrived.
+    ///
+    /// Nodes with pending edges are returned by a later flush, once those are wired.
+    /// Flushed nodes stay registered, so later edges may still refer to them.
+    pub fn flush(&mut self) -> Vec<(K, Rc<T>)> {
+        let waiting: HashSet<_> = self
+            .resolver
+            .pending()
+            .flat_map(|(from, to)| [from.clone(), to.clone()])
+            .collect();
+        let (held, ready): (Vec<_>, Vec<_>) = std::mem::take(&mut self.unflushed)
+            .into_iter()
+            .partition(|key| waiting.contains(key));
+        self.unflushed = held;
+        ready
+            .into_iter()
+            .filter_map(|key| {
+                let node = Rc::clone(self.resolver.get(&key)?);
+                Some((key, node))
+            })
+            .collect()
+    }
+    /// Returns every registered node, or every edge that is still pending.
+    pub fn finish(self) -> Result<HashMap<K, Rc<T>>, ResolveError<K>> {
+        self.resolver.finish()
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Deferred;
+
+    struct Node {
+        links: [Deferred<Self>; 2],
+    }
+
+    fn node() -> Rc<Node> {
+        Rc::new(Node {
+            links: [Deferred::new(), Deferred::new()],
+        })
+    }
+
+    #[test]
+    fn pending_edges_are_bounded_and_flushed_once_wired() {
+        let mut builder = IncrementalBuilder::<_, Node>::with_limit(2);
+        builder.edge("a", |n| &n.links[0], "b").unwrap();
+        builder.edge("b", |n| &n.links[0], "c").unwrap();
+        assert!(matches!(
+            builder.edge("c", |n| &n.links[0], "a"),
+            Err(ResolveError::PendingLimit(2))
+        ));
+
+        builder.node("a", node()).unwrap();
+        builder.node("b", node()).unwrap();
+        builder.edge("a", |n| &n.links[1], "b").unwrap();
+        assert_eq!(builder.pending_len(), 1);
+        assert!(builder.flush().iter().map(|(k, _)| *k).eq(["a"]));
+
+        builder.node("c", node()).unwrap();
+        builder.edge("c", |n| &n.links[0], "a").unwrap();
+        assert!(builder.flush().iter().map(|(k, _)| *k).eq(["b", "c"]));
+        assert!(builder.flush().is_empty());
+        let nodes = builder.finish().unwrap();
+        assert!(Rc::ptr_eq(&nodes["c"].links[0].get(), &nodes["a"]));
+    }
+}
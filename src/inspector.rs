@@ -0,0 +1,207 @@
+//! An egui widget for looking at a graph while it runs.
+//!
+//! [`GraphSnapshot::capture`] walks every node reachable from a set of roots, recording
+//! each node's label, its set edges, and the [`HasNeighbors`] cells that are still
+//! unset. Adding the snapshot to a `Ui` draws the nodes on a circle with an arrow per
+//! edge, and marks nodes with unset cells in the error color, listing those cells on
+//! hover.
+//!
+//! ```
+//! use deferred_cell::{Deferred, HasNeighbors, SetOnce, inspector::GraphSnapshot};
+//! use std::rc::Rc;
+//!
+//! struct Node {
+//!     name: &'static str,
+//!     next: Deferred<Node>,
+//! }
+//!
+//! impl HasNeighbors for Node {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         std::iter::once(&self.next)
+//!     }
+//! }
+//!
+//! let (a, b) = (
+//!     Rc::new(Node { name: "a", next: Deferred::new() }),
+//!     Rc::new(Node { name: "b", next: Deferred::new() }),
+//! );
+//! SetOnce::from(&a.next).try_set(&b).unwrap();
+//!
+//! let snapshot = GraphSnapshot::capture([&a], |n| n.name);
+//! assert_eq!(snapshot.unset(), [(1, 0)]);
+//! # fn show(ui: &mut egui::Ui, snapshot: GraphSnapshot) {
+//! ui.add(snapshot);
+//! # }
+//! ```
+
+use std::{collections::HashMap, f32::consts::TAU, fmt::Display, rc::Rc};
+
+use egui::{Align2, FontId, Pos2, Response, Sense, Stroke, Ui, Vec2, Widget};
+
+use crate::{HasNeighbors, visit};
+
+const NODE_RADIUS: f32 = 6.0;
+
+/// A snapshot of a graph's nodes and cells, ready to be drawn.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphSnapshot {
+    labels: Vec<String>,
+    edges: Vec<(usize, usize)>,
+    unset: Vec<(usize, usize)>,
+}
+
+impl GraphSnapshot {
+    /// Records every node reachable from `roots`, naming each with `label`.
+    pub fn capture<'a, T, L>(
+        roots: impl IntoIterator<Item = &'a Rc<T>>,
+        label: impl Fn(&T) -> L,
+    ) -> Self
+    where
+        T: HasNeighbors + 'a,
+        L: Display,
+    {
+        let mut nodes = Vec::new();
+        let mut index = HashMap::new();
+        for root in roots {
+            for node in visit::bfs(root) {
+                index.entry(Rc::as_ptr(&node)).or_insert_with(|| {
+                    nodes.push(node);
+                    nodes.len() - 1
+                });
+            }
+        }
+        let mut snapshot = Self::default();
+        for (from, node) in nodes.iter().enumerate() {
+            snapshot.labels.push(label(node).to_string());
+            for (k, cell) in node.neighbors().enumerate() {
+                match cell.try_get() {
+                    Ok(target) => snapshot.edges.push((from, index[&Rc::as_ptr(&target)])),
+                    Err(_) => snapshot.unset.push((from, k)),
+                }
+            }
+        }
+        snapshot
+    }
+    /// Returns each node's label, by node index.
+    #[must_use]
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+    /// Returns the `(from, to)` node indices of every set edge.
+    #[must_use]
+    pub fn edges(&self) -> &[(usize, usize)] {
+        &self.edges
+    }
+    /// Returns the node index and [`HasNeighbors`] position of every unset cell.
+    #[must_use]
+    pub fn unset(&self) -> &[(usize, usize)] {
+        &self.unset
+    }
+}
+
+impl Widget for GraphSnapshot {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let width = ui.available_width();
+        let (response, painter) = ui.allocate_painter(Vec2::splat(width), Sense::hover());
+        let rect = response.rect;
+        let radius = rect.width() * 0.4;
+        #[allow(clippy::cast_precision_loss)]
+        let positions: Vec<Pos2> = (0..self.labels.len())
+            .map(|i| {
+                let angle = TAU * i as f32 / self.labels.len() as f32;
+                rect.center() + radius * Vec2::angled(angle)
+            })
+            .collect();
+
+        let visuals = ui.visuals();
+        let edge = Stroke::new(1.0, visuals.weak_text_color());
+        for &(from, to) in &self.edges {
+            if from == to {
+                let above = positions[from] - Vec2::new(0.0, NODE_RADIUS * 2.0);
+                painter.circle_stroke(above, NODE_RADIUS, edge);
+                continue;
+            }
+            let span = positions[to] - positions[from];
+            let gap = span.normalized() * NODE_RADIUS;
+            painter.arrow(positions[from] + gap, span - 2.0 * gap, edge);
+        }
+        let font = FontId::proportional(12.0);
+        let mut hovered = Vec::new();
+        for (i, (label, &at)) in self.labels.iter().zip(&positions).enumerate() {
+            let unset: Vec<_> = self.unset.iter().filter(|(n, _)| *n == i).collect();
+            let color = if unset.is_empty() {
+                visuals.text_color()
+            } else {
+                visuals.error_fg_color
+            };
+            painter.circle_filled(at, NODE_RADIUS, color);
+            painter.text(
+                at + Vec2::new(0.0, NODE_RADIUS * 2.0),
+                Align2::CENTER_TOP,
+                label,
+                font.clone(),
+                color,
+            );
+            let near = response
+                .hover_pos()
+                .is_some_and(|pointer| pointer.distance(at) <= NODE_RADIUS * 2.0);
+            if near {
+                hovered.extend(
+                    unset
+                        .iter()
+                        .map(|(_, k)| format!("{label}: neighbor {k} not initialized")),
+                );
+            }
+        }
+        if hovered.is_empty() {
+            response
+        } else {
+            response.on_hover_text(hovered.join("\n"))
+        }
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Deferred, SetOnce};
+
+    struct Node {
+        name: &'static str,
+        links: [Deferred<Self>; 2],
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.links.iter()
+        }
+    }
+
+    fn node(name: &'static str) -> Rc<Node> {
+        Rc::new(Node {
+            name,
+            links: [Deferred::new(), Deferred::new()],
+        })
+    }
+
+    #[test]
+    fn captures_nodes_from_every_root_once() {
+        let (a, b, c) = (node("a"), node("b"), node("c"));
+        SetOnce::from(&a.links[0]).try_set(&b).unwrap();
+        SetOnce::from(&b.links[0]).try_set(&a).unwrap();
+        SetOnce::from(&b.links[1]).try_set(&b).unwrap();
+        SetOnce::from(&c.links[1]).try_set(&a).unwrap();
+
+        let snapshot = GraphSnapshot::capture([&a, &c, &b], |n| n.name);
+        assert_eq!(snapshot.labels(), ["a", "b", "c"]);
+        assert_eq!(snapshot.edges(), [(0, 1), (1, 0), (1, 1), (2, 0)]);
+        assert_eq!(snapshot.unset(), [(0, 1), (2, 0)]);
+
+        let ctx = egui::Context::default();
+        let _ = ctx.run(egui::RawInput::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| ui.add(snapshot.clone()));
+        });
+    }
+}
@@ -0,0 +1,169 @@
+//! Hash-consing nodes as they are built.
+//!
+//! Trees with repeated substructure, such as expression trees in a compiler, waste
+//! memory on identical copies. An [`Interner`] builds nodes bottom-up and returns the
+//! existing node whenever one with an equal payload and the same children was already
+//! built, turning the tree into a compact DAG. Children are interned first, so
+//! comparing them by identity compares their whole structure.
+//!
+//! Edges are weak, so the interner owns every node it builds. Keep it, or the nodes
+//! from [`Interner::into_nodes`], for as long as the graph is used.
+//!
+//! ```
+//! use deferred_cell::{Deferred, intern::Interner};
+//! use std::rc::Rc;
+//!
+//! struct Expr {
+//!     op: &'static str,
+//!     args: Vec<Deferred<Expr>>,
+//! }
+//!
+//! fn expr(op: &&'static str, arity: usize) -> Expr {
+//!     Expr { op: *op, args: (0..arity).map(|_| Deferred::new()).collect() }
+//! }
+//!
+//! fn arg(expr: &Expr, k: usize) -> &Deferred<Expr> {
+//!     &expr.args[k]
+//! }
+//!
+//! // (x + 1) * (x + 1)
+//! let mut interner = Interner::new();
+//! let mut sum = || -> Rc<Expr> {
+//!     let x = interner.intern("x", &[], expr, arg).unwrap();
+//!     let one = interner.intern("1", &[], expr, arg).unwrap();
+//!     interner.intern("+", &[x, one], expr, arg).unwrap()
+//! };
+//! let (left, right) = (sum(), sum());
+//! let product = interner.intern("*", &[left, right], expr, arg).unwrap();
+//!
+//! assert!(Rc::ptr_eq(&product.args[0].get(), &product.args[1].get()));
+//! assert_eq!((interner.len(), interner.reused()), (4, 3));
+//! ```
+
+use std::{collections::HashMap, hash::Hash, rc::Rc};
+
+use crate::{Deferred, DeferredError, SetOnce};
+
+/// Builds nodes of type `T` with payloads of type `P`, sharing identical ones.
+#[derive(Debug)]
+pub struct Interner<P, T> {
+    /// The index in `nodes` of the node with each payload and children.
+    index: HashMap<(P, Vec<*const T>), usize>,
+    nodes: Vec<Rc<T>>,
+    reused: usize,
+}
+
+impl<P, T> Default for Interner<P, T> {
+    fn default() -> Self {
+        Self {
+            index: HashMap::new(),
+            nodes: Vec::new(),
+            reused: 0,
+        }
+    }
+}
+
+impl<P: Eq + Hash, T> Interner<P, T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the node with `payload` whose edges lead to `children`, in order.
+    ///
+    /// If there is none yet, it is made with `make_node(&payload, children.len())` and
+    /// its `k`-th child is wired through `slot(node, k)`.
+    pub fn intern(
+        &mut self,
+        payload: P,
+        children: &[Rc<T>],
+        make_node: impl FnOnce(&P, usize) -> T,
+        slot: impl Fn(&T, usize) -> &Deferred<T>,
+    ) -> Result<Rc<T>, DeferredError> {
+        let key = (payload, children.iter().map(Rc::as_ptr).collect());
+        if let Some(&index) = self.index.get(&key) {
+            self.reused += 1;
+            return Ok(Rc::clone(&self.nodes[index]));
+        }
+        let node = Rc::new(make_node(&key.0, children.len()));
+        for (k, child) in children.iter().enumerate() {
+            SetOnce::from(slot(&node, k)).try_set(child)?;
+        }
+        self.index.insert(key, self.nodes.len());
+        self.nodes.push(Rc::clone(&node));
+        Ok(node)
+    }
+}
+
+impl<P, T> Interner<P, T> {
+    /// Returns the number of distinct nodes built.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    /// Returns how many times an existing node was returned instead of a new one.
+    #[must_use]
+    pub const fn reused(&self) -> usize {
+        self.reused
+    }
+    /// Returns every node built, in the order they were built, to keep them alive
+    /// after the interner is gone.
+    #[must_use]
+    pub fn into_nodes(self) -> Vec<Rc<T>> {
+        self.nodes
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Node {
+        value: u8,
+        children: Vec<Deferred<Self>>,
+    }
+
+    fn make(value: &u8, degree: usize) -> Node {
+        Node {
+            value: *value,
+            children: (0..degree).map(|_| Deferred::new()).collect(),
+        }
+    }
+
+    fn slot(node: &Node, k: usize) -> &Deferred<Node> {
+        &node.children[k]
+    }
+
+    #[test]
+    fn identical_subtrees_are_shared() {
+        let mut interner = Interner::new();
+        let (a, b) = (
+            interner.intern(1, &[], make, slot).unwrap(),
+            interner.intern(2, &[], make, slot).unwrap(),
+        );
+        let ab = interner
+            .intern(0, &[Rc::clone(&a), Rc::clone(&b)], make, slot)
+            .unwrap();
+        let ba = interner
+            .intern(0, &[Rc::clone(&b), Rc::clone(&a)], make, slot)
+            .unwrap();
+        let again = interner.intern(0, &[Rc::clone(&a), b], make, slot).unwrap();
+        assert!(Rc::ptr_eq(&ab, &again));
+        assert!(!Rc::ptr_eq(&ab, &ba));
+        assert_eq!(interner.intern(1, &[], make, slot).unwrap().value, 1);
+        assert_eq!((interner.len(), interner.reused()), (4, 2));
+
+        drop(a);
+        let nodes = interner.into_nodes();
+        assert_eq!(
+            nodes.iter().map(|n| n.value).collect::<Vec<_>>(),
+            [1, 2, 0, 0]
+        );
+        assert_eq!(ba.children[1].get().value, 1);
+    }
+}
@@ -0,0 +1,321 @@
+//! A record of every wiring step, enabled by the `journal` feature.
+//!
+//! While a [`Recording`] is active, every successful set of a
+//! [`Deferred`](crate::Deferred) or [`DeferredSync`](crate::DeferredSync) cell on the
+//! current thread is appended to it, with the cell's name when set through a
+//! `#[deferred]` accessor, the target's address and type, and when it happened. With
+//! the `debug-location` feature, each entry also records the line that made the set.
+//! [`finish`](Recording::finish) hands the entries over as a [`Journal`], so a graph
+//! that ends up mis-wired can be replayed step by step.
+//!
+//! The worker threads of [`wire_scoped`](crate::wire_scoped) and, with the `rayon`
+//! feature, `parallel::par_wire_sharded` record into the recording active on the thread
+//! that called them. Other threads join a recording through its
+//! [`handle`](Recording::handle).
+//!
+//! A recording belongs to the threads it is active on, not to a graph: nodes carry no
+//! journal, so every set made while it is active is recorded, whichever graph the cell
+//! belongs to. Start a recording around the code that wires one graph to journal that
+//! graph alone.
+//!
+//! ```
+//! use deferred_cell::{Deferred, SetOnce, journal::Recording};
+//! use std::rc::Rc;
+//!
+//! struct Node {
+//!     next: Deferred<Node>,
+//! }
+//!
+//! let recording = Recording::start();
+//! let a = Rc::new(Node { next: Deferred::new() });
+//! let b = Rc::new(Node { next: Deferred::new() });
+//! SetOnce::from(&a.next).try_set(&b).unwrap();
+//! SetOnce::from(&b.next).try_set(&a).unwrap();
+//! let journal = recording.finish();
+//!
+//! assert_eq!(journal.len(), 2);
+//! assert_eq!(journal.to(Rc::as_ptr(&b)).count(), 1);
+//! for entry in journal.entries() {
+//!     println!("{entry}");
+//! }
+//! ```
+
+use std::{
+    cell::RefCell,
+    fmt,
+    marker::PhantomData,
+    panic::Location,
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+type Sink = Arc<Mutex<Vec<Entry>>>;
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Sink>> = const { RefCell::new(None) };
+}
+
+/// One successful set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// The name of the cell, such as `Node.parent`, when set through a `#[deferred]`
+    /// accessor.
+    pub cell: Option<&'static str>,
+    /// The address of the cell.
+    pub cell_addr: usize,
+    /// The address of the target the cell was set to.
+    pub target: usize,
+    /// The name of the target's type.
+    pub target_type: &'static str,
+    /// When the set happened.
+    pub at: Instant,
+    /// The line that made the set, with the `debug-location` feature.
+    pub location: Option<&'static Location<'static>>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cell {
+            Some(name) => write!(f, "{name}")?,
+            None => write!(f, "{:#x}", self.cell_addr)?,
+        }
+        write!(f, " -> {:#x} ({})", self.target, self.target_type)?;
+        if let Some(location) = self.location {
+            write!(f, " at {location}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The sets recorded by a [`Recording`], in the order they happened.
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    entries: Vec<Entry>,
+}
+
+impl Journal {
+    #[must_use]
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.entries.len()
+    }
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Returns the sets of cells named `name`, such as `Node.parent`.
+    pub fn cell(&self, name: &str) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.cell == Some(name))
+    }
+    /// Returns the sets of the cell at `cell`.
+    pub fn at<T>(&self, cell: *const T) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.cell_addr == cell.addr())
+    }
+    /// Returns the sets that pointed a cell at `target`.
+    pub fn to<T>(&self, target: *const T) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.target == target.addr())
+    }
+}
+
+/// Records the sets made on the current thread, and on threads attached through its
+/// [`handle`](Self::handle), until finished or dropped.
+///
+/// Starting a recording while another is active pauses the outer one on the current
+/// thread until the inner one ends. Like [`Attached`], a recording is not `Send`: it
+/// must end on the thread that started it.
+#[derive(Debug)]
+#[must_use]
+pub struct Recording {
+    sink: Sink,
+    _attached: Attached,
+}
+
+impl Recording {
+    pub fn start() -> Self {
+        let sink = Sink::default();
+        Self {
+            _attached: Attached::new(Some(Arc::clone(&sink))),
+            sink,
+        }
+    }
+    /// Returns a handle that attaches other threads to this recording.
+    pub fn handle(&self) -> RecordingHandle {
+        RecordingHandle(Some(Arc::clone(&self.sink)))
+    }
+    /// Stops recording and returns every set recorded.
+    ///
+    /// Sets made afterwards on threads still attached through a handle are dropped.
+    #[must_use]
+    pub fn finish(self) -> Journal {
+        let mut entries = self.sink.lock().unwrap_or_else(PoisonError::into_inner);
+        Journal {
+            entries: std::mem::take(&mut *entries),
+        }
+    }
+}
+
+/// A handle to a [`Recording`], or to none, that can be sent to other threads.
+///
+/// ```
+/// use deferred_cell::{DeferredSync, SetOnceSync, journal::Recording};
+/// use std::{sync::Arc, thread};
+///
+/// let cell = DeferredSync::default();
+/// let recording = Recording::start();
+/// let handle = recording.handle();
+/// thread::scope(|s| {
+///     s.spawn(|| {
+///         let _attached = handle.attach();
+///         SetOnceSync::from(&cell).try_set(&Arc::new(1)).unwrap();
+///     });
+/// });
+/// assert_eq!(recording.finish().len(), 1);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RecordingHandle(Option<Sink>);
+
+impl RecordingHandle {
+    /// Returns a handle to the recording active on the current thread, if any.
+    pub fn current() -> Self {
+        Self(ACTIVE.with(|active| active.borrow().clone()))
+    }
+    /// Records the sets made on the current thread into this handle's recording until
+    /// the guard is dropped. A handle to no recording pauses recording instead.
+    pub fn attach(&self) -> Attached {
+        Attached::new(self.0.clone())
+    }
+}
+
+/// Keeps a thread attached to a recording. See [`RecordingHandle::attach`].
+///
+/// Not `Send`, since dropping it on another thread would detach the wrong thread:
+///
+/// ```compile_fail
+/// fn send<T: Send>(_: T) {}
+/// send(deferred_cell::journal::RecordingHandle::default().attach());
+/// ```
+#[derive(Debug)]
+#[must_use]
+pub struct Attached {
+    outer: Option<Sink>,
+    _thread: PhantomData<*const ()>,
+}
+
+impl Attached {
+    fn new(sink: Option<Sink>) -> Self {
+        let outer = ACTIVE.with(|active| active.replace(sink));
+        Self {
+            outer,
+            _thread: PhantomData,
+        }
+    }
+}
+
+impl Drop for Attached {
+    fn drop(&mut self) {
+        let outer = self.outer.take();
+        ACTIVE.with(|active| active.replace(outer));
+    }
+}
+
+/// Appends a set to the active recording, if any.
+#[cfg_attr(feature = "debug-location", track_caller)]
+pub(crate) fn record(cell: *const (), target: *const (), target_type: &'static str) {
+    ACTIVE.with(|active| {
+        if let Some(sink) = active.borrow().as_ref() {
+            let mut entries = sink.lock().unwrap_or_else(PoisonError::into_inner);
+            entries.push(Entry {
+                cell: crate::diagnostics::current_cell(),
+                cell_addr: cell.addr(),
+                target: target.addr(),
+                target_type,
+                at: Instant::now(),
+                #[cfg(feature = "debug-location")]
+                location: Some(Location::caller()),
+                #[cfg(not(feature = "debug-location"))]
+                location: None,
+            });
+        }
+    });
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use std::{rc::Rc, sync::Arc};
+
+    use super::*;
+    use crate::{Deferred, DeferredSync, SetOnce, SetOnceSync, diagnostics};
+
+    #[test]
+    fn sets_are_recorded_while_recording() {
+        let (a, b) = (Rc::new(1), Rc::new(2));
+        let cells = [Deferred::new(), Deferred::new(), Deferred::new()];
+        SetOnce::from(&cells[0]).try_set(&a).unwrap();
+
+        let outer = Recording::start();
+        {
+            let _cell = diagnostics::enter_cell("Node.parent");
+            SetOnce::from(&cells[1]).try_set(&b).unwrap();
+        }
+        let inner = Recording::start();
+        let shared = DeferredSync::default();
+        SetOnceSync::from(&shared).try_set(&Arc::new(3)).unwrap();
+        assert_eq!(inner.finish().len(), 1);
+        SetOnce::from(&cells[0]).try_set(&b).unwrap_err();
+        SetOnce::from(&cells[2]).try_set(&a).unwrap();
+        let journal = outer.finish();
+
+        let parent: Vec<_> = journal.cell("Node.parent").collect();
+        assert_eq!(parent.len(), 1);
+        assert_eq!(parent[0].target, Rc::as_ptr(&b).addr());
+        assert!(parent[0].to_string().starts_with("Node.parent -> "));
+        assert_eq!(journal.to(Rc::as_ptr(&a)).count(), 1);
+        assert_eq!(journal.at(&raw const cells[2]).count(), 1);
+        assert_eq!(journal.len(), 2);
+        assert!(journal.entries()[0].at <= journal.entries()[1].at);
+    }
+    #[test]
+    fn worker_threads_record_into_the_callers_recording() {
+        let targets: Vec<_> = (0..8).map(Arc::new).collect();
+        let cells: Vec<DeferredSync<i32>> = (0..9).map(|_| DeferredSync::default()).collect();
+        let assignments: Vec<_> = cells.iter().zip(&targets).collect();
+
+        let recording = Recording::start();
+        crate::wire_scoped(&assignments, 4, |(cell, target)| {
+            SetOnceSync::from(cell).try_set(target)
+        })
+        .unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| SetOnceSync::from(&cells[8]).try_set(&targets[0]).unwrap());
+        });
+        #[cfg(feature = "rayon")]
+        {
+            struct Link {
+                next: DeferredSync<Self>,
+            }
+            let nodes = crate::parallel::par_build(&[(); 4], |()| Link {
+                next: DeferredSync::default(),
+            });
+            crate::parallel::par_wire_sharded(&nodes, |i, node, nodes| {
+                SetOnceSync::from(&node.next).try_set(&nodes[(i + 1) % nodes.len()])
+            })
+            .unwrap();
+        }
+        let journal = recording.finish();
+
+        assert_eq!(journal.len(), if cfg!(feature = "rayon") { 12 } else { 8 });
+        assert_eq!(journal.at(&raw const cells[8]).count(), 0);
+        assert!((0..8).all(|i| journal.at(&raw const cells[i]).count() == 1));
+    }
+}
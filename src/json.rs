@@ -0,0 +1,311 @@
+//! Loading `serde_json` documents whose objects refer to one another.
+//!
+//! Services often send cyclic object graphs as JSON by giving objects an ID and
+//! replacing references with link objects such as `{"$ref": "b"}`. A [`JsonLoader`]
+//! turns every object of such a document into a node and every link into a wired
+//! [`Deferred`] edge. A link names its target by ID, or by JSON pointer when it starts
+//! with `#`, as in `{"$ref": "#/nodes/1"}`.
+//!
+//! Links are collected from the fields of each object, including inside arrays, in
+//! field order. Without `serde_json`'s `preserve_order` feature, that is alphabetical.
+//!
+//! ```
+//! use deferred_cell::{Deferred, json::JsonLoader};
+//! use serde_json::json;
+//!
+//! struct Person {
+//!     name: String,
+//!     links: Vec<Deferred<Person>>,
+//! }
+//!
+//! let doc = json!([
+//!     {"id": "ada", "name": "Ada", "friend": {"$ref": "alan"}},
+//!     {"id": "alan", "name": "Alan", "friend": {"$ref": "#/0"}},
+//! ]);
+//! let graph = JsonLoader::new()
+//!     .load(
+//!         &doc,
+//!         |object, links| Person {
+//!             name: object["name"].as_str().unwrap_or_default().to_owned(),
+//!             links: links.iter().map(|_| Deferred::new()).collect(),
+//!         },
+//!         |person, k| &person.links[k],
+//!     )
+//!     .unwrap();
+//!
+//! let ada = graph.by_id("ada").unwrap();
+//! assert_eq!(ada.links[0].get().links[0].get().name, "Ada");
+//! ```
+
+use std::{borrow::Cow, collections::HashMap, rc::Rc};
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::{Deferred, DeferredError, spec::GraphSpec};
+
+/// Errors returned by [`JsonLoader::load`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum LoadError {
+    #[error("Object {from} refers to unknown target {target:?}!")]
+    Unresolved { from: String, target: String },
+    #[error("Objects {first} and {second} share the id {id}!")]
+    DuplicateId {
+        id: String,
+        first: String,
+        second: String,
+    },
+    #[error(transparent)]
+    Wiring(#[from] DeferredError),
+}
+
+/// Wired nodes loaded from a JSON document.
+#[derive(Debug)]
+pub struct JsonGraph<T> {
+    nodes: Vec<Rc<T>>,
+    pointers: HashMap<String, usize>,
+    ids: HashMap<String, usize>,
+}
+
+impl<T> JsonGraph<T> {
+    /// Returns every node, in document order.
+    #[must_use]
+    pub fn nodes(&self) -> &[Rc<T>] {
+        &self.nodes
+    }
+    /// Returns the node of the object with the given ID.
+    #[must_use]
+    pub fn by_id(&self, id: &str) -> Option<&Rc<T>> {
+        self.ids.get(id).map(|&i| &self.nodes[i])
+    }
+    /// Returns the node of the object at a JSON pointer such as `/nodes/0`.
+    #[must_use]
+    pub fn by_pointer(&self, pointer: &str) -> Option<&Rc<T>> {
+        self.pointers.get(pointer).map(|&i| &self.nodes[i])
+    }
+    #[must_use]
+    pub fn into_nodes(self) -> Vec<Rc<T>> {
+        self.nodes
+    }
+}
+
+/// An object found while walking the document, with the fields of its links.
+struct Found<'d> {
+    object: &'d Map<String, Value>,
+    pointer: String,
+    links: Vec<(&'d str, String)>,
+}
+
+/// Loads JSON object graphs, with configurable ID and link fields.
+#[derive(Debug, Clone)]
+pub struct JsonLoader {
+    id_field: Cow<'static, str>,
+    ref_field: Cow<'static, str>,
+}
+
+impl Default for JsonLoader {
+    fn default() -> Self {
+        Self {
+            id_field: Cow::Borrowed("id"),
+            ref_field: Cow::Borrowed("$ref"),
+        }
+    }
+}
+
+impl JsonLoader {
+    /// Creates a loader reading IDs from `id` and links from `$ref`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Sets the field holding each object's ID.
+    #[must_use]
+    pub fn id_field(mut self, field: impl Into<Cow<'static, str>>) -> Self {
+        self.id_field = field.into();
+        self
+    }
+    /// Sets the only field of a link object.
+    #[must_use]
+    pub fn ref_field(mut self, field: impl Into<Cow<'static, str>>) -> Self {
+        self.ref_field = field.into();
+        self
+    }
+    /// Creates one node per object of `doc` and wires its links.
+    ///
+    /// Each node is made by `make_node(object, links)`, where `links` names the field of
+    /// each of the object's links, and link `k` is stored through `slot(node, k)`.
+    pub fn load<T>(
+        &self,
+        doc: &Value,
+        mut make_node: impl FnMut(&Map<String, Value>, &[&str]) -> T,
+        slot: impl Fn(&T, usize) -> &Deferred<T>,
+    ) -> Result<JsonGraph<T>, LoadError> {
+        let mut found = Vec::new();
+        self.collect(doc, String::new(), &mut found);
+
+        let pointers: HashMap<_, _> = found
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (f.pointer.clone(), i))
+            .collect();
+        let mut ids = HashMap::new();
+        for (i, f) in found.iter().enumerate() {
+            let id = match f.object.get(self.id_field.as_ref()) {
+                Some(Value::String(id)) => id.clone(),
+                Some(Value::Number(id)) => id.to_string(),
+                _ => continue,
+            };
+            if let Some(&first) = ids.get(&id) {
+                let first: &Found = &found[first];
+                return Err(LoadError::DuplicateId {
+                    id,
+                    first: first.pointer.clone(),
+                    second: f.pointer.clone(),
+                });
+            }
+            ids.insert(id, i);
+        }
+
+        let mut edges = Vec::new();
+        for (from, f) in found.iter().enumerate() {
+            for (_, target) in &f.links {
+                let to = target
+                    .strip_prefix('#')
+                    .map_or_else(|| ids.get(target), |pointer| pointers.get(pointer));
+                let Some(&to) = to else {
+                    return Err(LoadError::Unresolved {
+                        from: f.pointer.clone(),
+                        target: target.clone(),
+                    });
+                };
+                edges.push((from, to));
+            }
+        }
+
+        let nodes = GraphSpec::new(found.len(), edges).materialize(
+            |i, _| {
+                let fields: Vec<_> = found[i].links.iter().map(|&(field, _)| field).collect();
+                make_node(found[i].object, &fields)
+            },
+            slot,
+        )?;
+        Ok(JsonGraph {
+            nodes,
+            pointers,
+            ids,
+        })
+    }
+    /// Returns the target of `value` if it is a link object.
+    fn link(&self, value: &Value) -> Option<String> {
+        match value.as_object()? {
+            object if object.len() == 1 => match object.get(self.ref_field.as_ref())? {
+                Value::String(target) => Some(target.clone()),
+                Value::Number(target) => Some(target.to_string()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    fn collect<'d>(&self, value: &'d Value, pointer: String, found: &mut Vec<Found<'d>>) {
+        match value {
+            Value::Object(object) if self.link(value).is_none() => {
+                let mut links = Vec::new();
+                for (field, child) in object {
+                    let items = child
+                        .as_array()
+                        .map_or_else(|| std::slice::from_ref(child), Vec::as_slice);
+                    links.extend(
+                        items
+                            .iter()
+                            .filter_map(|v| Some((field.as_str(), self.link(v)?))),
+                    );
+                }
+                found.push(Found {
+                    object,
+                    pointer: pointer.clone(),
+                    links,
+                });
+                for (key, child) in object {
+                    self.collect(child, format!("{pointer}/{}", escape(key)), found);
+                }
+            }
+            Value::Array(items) => {
+                for (i, child) in items.iter().enumerate() {
+                    self.collect(child, format!("{pointer}/{i}"), found);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Escapes a key for use in a JSON pointer.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Node {
+        name: String,
+        fields: Vec<String>,
+        links: Vec<Deferred<Self>>,
+    }
+
+    fn load(loader: &JsonLoader, doc: &Value) -> Result<JsonGraph<Node>, LoadError> {
+        loader.load(
+            doc,
+            |object, links| Node {
+                name: object
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_owned(),
+                fields: links.iter().map(|&f| f.to_owned()).collect(),
+                links: links.iter().map(|_| Deferred::new()).collect(),
+            },
+            |node, k| &node.links[k],
+        )
+    }
+
+    #[test]
+    fn links_resolve_by_id_and_pointer() {
+        let doc = json!({
+            "a/b": {"key": 1, "name": "root", "kids": [{"@": 2}, {"@": "#/a~1b"}]},
+            "more": [{"key": 2, "name": "leaf", "up": {"@": "1"}}],
+        });
+        let loader = JsonLoader::new().id_field("key").ref_field("@");
+        let graph = load(&loader, &doc).unwrap();
+
+        // The document itself is an object too.
+        assert_eq!(graph.nodes().len(), 3);
+        let root = graph.by_pointer("/a~1b").unwrap();
+        assert_eq!(root.fields, ["kids", "kids"]);
+        assert_eq!(root.links[0].get().name, "leaf");
+        assert!(Rc::ptr_eq(&root.links[1].get(), root));
+        let leaf = graph.by_id("2").unwrap();
+        assert!(Rc::ptr_eq(&leaf.links[0].get(), root));
+    }
+    #[test]
+    fn unknown_and_duplicate_targets_are_rejected() {
+        let loader = JsonLoader::new();
+        let missing = json!([{"id": "a", "next": {"$ref": "b"}}]);
+        assert_eq!(
+            load(&loader, &missing).unwrap_err().to_string(),
+            r#"Object /0 refers to unknown target "b"!"#
+        );
+        let twice = json!([{"id": "a"}, {"id": "a"}]);
+        assert_eq!(
+            load(&loader, &twice).unwrap_err().to_string(),
+            "Objects /0 and /1 share the id a!"
+        );
+    }
+}
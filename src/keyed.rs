@@ -0,0 +1,126 @@
+//! Set-once cells holding `slotmap` keys instead of weak pointers.
+//!
+//! Some graphs keep their nodes in a `SlotMap` owned by one place and refer to each
+//! other by key rather than by `Rc`. A [`DeferredKey`] follows the same rules as
+//! [`Deferred`](crate::Deferred) for such graphs: it is set once through a
+//! [`SetOnceKey`], and [`DeferredKey::try_get`] looks its target up in the map, failing
+//! if the cell is unset or the target has since been removed.
+//!
+//! ```
+//! use deferred_cell::keyed::{DeferredKey, SetOnceKey};
+//! use slotmap::{SlotMap, new_key_type};
+//!
+//! new_key_type! { struct NodeKey; }
+//!
+//! struct Node {
+//!     name: &'static str,
+//!     next: DeferredKey<NodeKey>,
+//! }
+//!
+//! let mut nodes = SlotMap::with_key();
+//! let a = nodes.insert(Node { name: "a", next: DeferredKey::new() });
+//! let b = nodes.insert(Node { name: "b", next: DeferredKey::new() });
+//! SetOnceKey::from(&nodes[a].next).try_set(b).unwrap();
+//! SetOnceKey::from(&nodes[b].next).try_set(a).unwrap();
+//!
+//! let next = nodes[a].next.try_get(&nodes).unwrap();
+//! assert_eq!(next.name, "b");
+//! ```
+
+use std::cell::OnceCell;
+
+use slotmap::{Key, SlotMap};
+
+use crate::DeferredError;
+
+/// A write-once reference to a value stored in a `SlotMap`.
+#[derive(Debug, Clone)]
+pub struct DeferredKey<K: Key>(OnceCell<K>);
+
+impl<K: Key> Default for DeferredKey<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Key> From<K> for DeferredKey<K> {
+    fn from(key: K) -> Self {
+        Self(OnceCell::from(key))
+    }
+}
+
+impl<K: Key> DeferredKey<K> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(OnceCell::new())
+    }
+    /// Looks the target up in `map`.
+    ///
+    /// Fails if the cell is unset or its key no longer refers to a value in `map`.
+    pub fn try_get<'m, T>(&self, map: &'m SlotMap<K, T>) -> Result<&'m T, DeferredError> {
+        self.0
+            .get()
+            .and_then(|&key| map.get(key))
+            .ok_or(DeferredError::NotInitializedError())
+    }
+    /// Returns the key the cell was set to, without checking it against any map.
+    #[must_use]
+    pub fn key(&self) -> Option<K> {
+        self.0.get().copied()
+    }
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.0.get().is_some()
+    }
+}
+
+/// A helper for setting a [`DeferredKey`].
+#[derive(Debug, Clone)]
+pub struct SetOnceKey<'a, K: Key>(&'a DeferredKey<K>);
+
+impl<'a, K: Key> SetOnceKey<'a, K> {
+    pub const fn from(cell: &'a DeferredKey<K>) -> Self {
+        Self(cell)
+    }
+    pub fn try_set(&self, key: K) -> Result<(), DeferredError> {
+        self.0
+            .0
+            .set(key)
+            .map_err(|_| DeferredError::DuplicateInitialization())
+    }
+    #[must_use]
+    pub fn can_set(&self) -> bool {
+        self.0.0.get().is_none()
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use slotmap::DefaultKey;
+
+    use super::*;
+
+    #[test]
+    fn keys_are_set_once_and_checked_against_the_map() {
+        let mut map = SlotMap::new();
+        let (a, b) = (map.insert(1), map.insert(2));
+        let cell: DeferredKey<DefaultKey> = DeferredKey::new();
+        assert!(matches!(
+            cell.try_get(&map),
+            Err(DeferredError::NotInitializedError())
+        ));
+
+        SetOnceKey::from(&cell).try_set(a).unwrap();
+        assert!(matches!(
+            SetOnceKey::from(&cell).try_set(b),
+            Err(DeferredError::DuplicateInitialization())
+        ));
+        assert_eq!(*cell.try_get(&map).unwrap(), 1);
+
+        map.remove(a);
+        assert!(cell.try_get(&map).is_err());
+        assert_eq!(cell.key(), Some(a));
+    }
+}
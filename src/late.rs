@@ -0,0 +1,84 @@
+//! Cell assignments queued until their target is known.
+//!
+//! [`symbols`](crate::symbols), [`scope`](crate::scope) and [`plugin`](crate::plugin)
+//! all queue cells to be pointed at something that is only looked up later. Each queues
+//! a [`Link`] with a key saying what to look up, and resolves the [`LinkQueue`] with
+//! its own lookup.
+
+use std::{any::Any, rc::Rc};
+
+use crate::{Deferred, DeferredError, SetOnce};
+
+/// Sets a cell to its target, or returns `None` if the target has the wrong type.
+type SetCell<V> = Box<dyn FnOnce(&Rc<V>) -> Option<Result<(), DeferredError>>>;
+
+/// Sets one queued cell, once its target is known.
+pub struct Link<V: ?Sized>(SetCell<V>);
+
+impl<V: 'static> Link<V> {
+    /// Queues `slot(owner)`.
+    pub fn new<O: 'static>(owner: &Rc<O>, slot: for<'n> fn(&'n O) -> &'n Deferred<V>) -> Self {
+        let owner = Rc::clone(owner);
+        Self(Box::new(move |target| {
+            Some(SetOnce::from(slot(&owner)).try_set(target))
+        }))
+    }
+}
+
+impl Link<dyn Any> {
+    /// Queues `slot(owner)`, for a target only known as `dyn Any`.
+    pub fn downcast<O: 'static, S: 'static>(
+        owner: &Rc<O>,
+        slot: for<'n> fn(&'n O) -> &'n Deferred<S>,
+    ) -> Self {
+        let owner = Rc::clone(owner);
+        Self(Box::new(move |target| {
+            let target = Rc::clone(target).downcast::<S>().ok()?;
+            Some(SetOnce::from(slot(&owner)).try_set(&target))
+        }))
+    }
+}
+
+/// Why a queued [`Link`] could not be set.
+pub enum LinkError {
+    /// The lookup found nothing.
+    Unbound,
+    /// The target is not of the cell's type.
+    WrongType,
+    /// Setting the cell failed.
+    Wiring(DeferredError),
+}
+
+/// [`Link`]s waiting for their target, each with the key it is looked up by.
+pub struct LinkQueue<K, V: ?Sized>(Vec<(K, Link<V>)>);
+
+impl<K, V: ?Sized> Default for LinkQueue<K, V> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<K, V: ?Sized> LinkQueue<K, V> {
+    pub fn push(&mut self, key: K, link: Link<V>) {
+        self.0.push((key, link));
+    }
+    /// Sets every queued link to the target `lookup` finds for its key, and returns the
+    /// key of every link that could not be set, in the order they were queued. Wiring
+    /// errors are located at the caller.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn resolve(&mut self, mut lookup: impl FnMut(&K) -> Option<Rc<V>>) -> Vec<(K, LinkError)> {
+        let mut errors = Vec::new();
+        for (key, link) in self.0.drain(..) {
+            let Some(target) = lookup(&key) else {
+                errors.push((key, LinkError::Unbound));
+                continue;
+            };
+            match (link.0)(&target) {
+                Some(Ok(())) => {}
+                Some(Err(source)) => errors.push((key, LinkError::Wiring(source.relocated()))),
+                None => errors.push((key, LinkError::WrongType)),
+            }
+        }
+        errors
+    }
+}
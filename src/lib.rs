@@ -40,16 +40,181 @@
 //!     Ok(())
 //! }
 //! ```
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(clippy::unwrap_used, clippy::expect_used)]
 #![warn(clippy::all, clippy::nursery)]
 
-use std::{
-    cell::OnceCell,
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
     rc::{Rc, Weak},
 };
+#[cfg(feature = "alloc")]
+use core::{any::type_name, cell::OnceCell};
 
 use thiserror::Error;
 
+#[cfg(feature = "generational-arena")]
+pub mod arena;
+#[cfg(feature = "std")]
+pub mod audit;
+pub mod binary;
+#[cfg(feature = "std")]
+mod borrow;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod columnar;
+#[cfg(feature = "std")]
+pub mod compare;
+#[cfg(feature = "compress")]
+pub mod compress;
+#[cfg(feature = "std")]
+pub mod config_graph;
+#[cfg(feature = "std")]
+pub mod dag;
+#[cfg(feature = "alloc")]
+mod diagnostics;
+#[cfg(feature = "std")]
+pub mod dialogue;
+#[cfg(feature = "std")]
+pub mod dirty;
+pub mod failure;
+#[cfg(feature = "fault-injection")]
+pub mod faults;
+#[cfg(feature = "std")]
+mod fields;
+pub mod fixed;
+#[cfg(feature = "alloc")]
+pub mod frozen;
+#[cfg(feature = "serde")]
+pub mod graph_serde;
+#[cfg(feature = "std")]
+pub mod handle;
+#[cfg(feature = "std")]
+pub mod incremental;
+#[cfg(feature = "inspector")]
+pub mod inspector;
+#[cfg(feature = "std")]
+pub mod intern;
+#[cfg(feature = "journal")]
+pub mod journal;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "slotmap")]
+pub mod keyed;
+#[cfg(feature = "std")]
+mod late;
+#[cfg(feature = "alloc")]
+pub mod liveness;
+#[cfg(feature = "std")]
+mod macros;
+#[cfg(feature = "std")]
+pub mod merge;
+#[cfg(feature = "std")]
+pub mod meta;
+#[cfg(feature = "std")]
+pub mod namespace;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+#[cfg(feature = "std")]
+pub mod pin;
+#[cfg(feature = "std")]
+pub mod planner;
+#[cfg(feature = "std")]
+pub mod plugin;
+#[cfg(feature = "std")]
+mod primitives;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod query;
+#[cfg(feature = "std")]
+pub mod rebuild;
+#[cfg(feature = "bevy")]
+pub mod reflect;
+#[cfg(feature = "debug-registry")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod reload;
+#[cfg(feature = "std")]
+pub mod resolver;
+#[cfg(feature = "std")]
+pub mod sample;
+#[cfg(feature = "std")]
+pub mod scene;
+#[cfg(feature = "std")]
+pub mod scope;
+#[cfg(feature = "std")]
+pub mod script;
+#[cfg(feature = "std")]
+pub mod spec;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+#[cfg(feature = "std")]
+pub mod symbols;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "toml")]
+pub mod toml;
+#[cfg(feature = "std")]
+pub mod topology;
+#[cfg(feature = "std")]
+pub mod view;
+#[cfg(feature = "alloc")]
+pub mod visit;
+#[cfg(feature = "std")]
+pub mod weak_map;
+#[cfg(feature = "yaml")]
+pub mod yaml;
+
+#[cfg(feature = "std")]
+pub use borrow::{DeferredBorrowError, DeferredRef, DeferredRefCellExt, DeferredRefMut};
+#[cfg(feature = "derive")]
+pub use deferred_cell_derive::{DeferredBuilder, DeferredDefault, DeferredFields, Visit, deferred};
+#[cfg(all(feature = "derive", feature = "serde"))]
+pub use deferred_cell_derive::{GraphDeserialize, GraphSerialize};
+#[cfg(feature = "std")]
+pub use fields::{AnyDeferredCell, DeferredFields, UnsetCells};
+#[cfg(feature = "std")]
+pub use sync::{
+    DeferredLockError, DeferredMutexExt, DeferredRwLockExt, DeferredSync, DeferredSyncIteratorExt,
+    SetOnceSync, wire_scoped,
+};
+#[cfg(feature = "alloc")]
+pub use visit::HasNeighbors;
+
+/// Support items for code generated by `deferred-cell-derive`. Not public API.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::diagnostics::{CellGuard, enter_cell};
+    #[cfg(feature = "serde")]
+    pub use serde;
+
+    /// Builds a [`DuplicateInitialization`](crate::DeferredError::DuplicateInitialization)
+    /// error located at the macro's caller.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[must_use]
+    pub fn duplicate_initialization() -> crate::DeferredError {
+        crate::DeferredError::DuplicateInitialization().at_caller()
+    }
+
+    /// Reports a failed edge lookup to a serializer, naming the field it came from.
+    #[cfg(feature = "serde")]
+    pub fn edge_error<E: serde::ser::Error>(error: crate::DeferredError, field: &'static str) -> E {
+        E::custom(error.context(field))
+    }
+}
+
 /// Errors thrown by deferred-cell
 #[derive(Error, Debug)]
 #[non_exhaustive]
@@ -58,37 +223,548 @@ pub enum DeferredError {
     DuplicateInitialization(),
     #[error("Cannot use uninitialized value!")]
     NotInitializedError(),
+    /// A read of a set cell whose target has already been dropped.
+    #[error("Cannot use a value that was dropped!")]
+    TargetDropped(),
+    /// A cell whose target is not one of the nodes of the graph it was checked against.
+    #[error("Cell points outside the graph!")]
+    OutsideGraph(),
+    /// Another error, annotated with the cell it came from, such as `Node.parent`.
+    /// Requires the `alloc` feature.
+    #[cfg(feature = "alloc")]
+    #[error("{0}: {1}")]
+    Context(Cow<'static, str>, Box<Self>),
+    /// Another error, annotated with the call that caused it. Only produced with the
+    /// `debug-location` feature.
+    #[cfg(feature = "debug-location")]
+    #[error("{1} (at {0})")]
+    Located(&'static core::panic::Location<'static>, Box<Self>),
+}
+
+impl DeferredError {
+    /// Annotates the error with the cell it came from.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn context(self, context: impl Into<Cow<'static, str>>) -> Self {
+        Self::Context(context.into(), Box::new(self))
+    }
+    /// Returns the underlying error, skipping any [`Context`](Self::Context) layers.
+    #[cfg_attr(
+        not(feature = "alloc"),
+        allow(clippy::match_single_binding, clippy::missing_const_for_fn)
+    )]
+    #[must_use]
+    pub fn root_cause(&self) -> &Self {
+        match self {
+            #[cfg(feature = "alloc")]
+            Self::Context(_, inner) => inner.root_cause(),
+            #[cfg(feature = "debug-location")]
+            Self::Located(_, inner) => inner.root_cause(),
+            other => other,
+        }
+    }
+    /// Returns a stable code identifying the kind of error, for routing and counting
+    /// failures programmatically. Wrapper layers report the code of the error they wrap.
+    ///
+    /// | Code     | Error |
+    /// |----------|-------|
+    /// | `DC0001` | [`DuplicateInitialization`](Self::DuplicateInitialization) |
+    /// | `DC0002` | [`NotInitializedError`](Self::NotInitializedError) |
+    /// | `DC0003` | [`TargetDropped`](Self::TargetDropped) |
+    /// | `DC0004` | [`UnsetCells`] |
+    /// | `DC0005`–`DC0009` | [`ResolveError`](resolver::ResolveError) |
+    /// | `DC0010` | [`OutsideGraph`](Self::OutsideGraph) |
+    ///
+    /// Codes are never reused or reassigned.
+    ///
+    /// ```
+    /// use deferred_cell::{Deferred, DeferredResultExt};
+    ///
+    /// let cell: Deferred<u8> = Deferred::new();
+    /// let error = cell.try_get().cell_context("Node.parent").unwrap_err();
+    /// assert_eq!(error.code(), "DC0002");
+    /// ```
+    #[cfg_attr(not(feature = "alloc"), allow(clippy::missing_const_for_fn))]
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::DuplicateInitialization() => "DC0001",
+            Self::NotInitializedError() => "DC0002",
+            Self::TargetDropped() => "DC0003",
+            Self::OutsideGraph() => "DC0010",
+            #[cfg(feature = "alloc")]
+            Self::Context(_, inner) => inner.code(),
+            #[cfg(feature = "debug-location")]
+            Self::Located(_, inner) => inner.code(),
+        }
+    }
+    /// Returns the location of the `set` or `get` call that caused the error.
+    ///
+    /// Always `None` unless the `debug-location` feature is enabled.
+    #[cfg_attr(
+        not(feature = "alloc"),
+        allow(clippy::match_single_binding, clippy::missing_const_for_fn)
+    )]
+    #[must_use]
+    pub fn location(&self) -> Option<&'static core::panic::Location<'static>> {
+        match self {
+            #[cfg(feature = "alloc")]
+            Self::Context(_, inner) => inner.location(),
+            #[cfg(feature = "debug-location")]
+            Self::Located(location, _) => Some(location),
+            _ => None,
+        }
+    }
+    /// Annotates an error with the location of the caller of the enclosing
+    /// `#[track_caller]` function when the `debug-location` feature is enabled.
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[inline]
+    pub(crate) fn located<T>(result: Result<T, Self>) -> Result<T, Self> {
+        match result {
+            Ok(value) => Ok(value),
+            Err(error) => Err(error.at_caller()),
+        }
+    }
+    /// Builds the error of a failed read of a cell pointing to `target`. Shared by every
+    /// cell type and kept out of line, so accessors stay small enough to inline into
+    /// traversal loops.
+    #[cfg(feature = "alloc")]
+    #[cold]
+    #[inline(never)]
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub(crate) fn read_failed(
+        read: Result<(), Self>,
+        set: bool,
+        cell: *const (),
+        target: &'static str,
+    ) -> Self {
+        let error = match read {
+            Err(injected) => injected,
+            Ok(()) if !set => Self::NotInitializedError(),
+            Ok(()) => diagnostics::failed_upgrade(cell, target),
+        };
+        error.at_caller()
+    }
+    /// Like [`at_caller`](Self::at_caller), but replaces any location the error already
+    /// has. For errors returned by stored closures, whose own location is of no use.
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub(crate) fn relocated(self) -> Self {
+        #[cfg(feature = "debug-location")]
+        if let Self::Located(_, inner) = self {
+            return inner.at_caller();
+        }
+        self.at_caller()
+    }
+    /// Like [`located`](Self::located), for an error that has already occurred.
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[cfg_attr(not(feature = "debug-location"), allow(clippy::missing_const_for_fn))]
+    #[inline]
+    pub(crate) fn at_caller(self) -> Self {
+        #[cfg(feature = "debug-location")]
+        return Self::Located(core::panic::Location::caller(), Box::new(self));
+        #[cfg(not(feature = "debug-location"))]
+        self
+    }
+}
+
+/// Adds the stable [`code`](DeferredError::code) and a hint on how to fix each error.
+/// [`Context`](DeferredError::Context) layers report the code and help of the error they
+/// wrap.
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for DeferredError {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(self.code()))
+    }
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        let help = match self.root_cause() {
+            Self::DuplicateInitialization() => {
+                "a cell can only be set once; check SetOnce::can_set or remove the second assignment"
+            }
+            Self::NotInitializedError() => "call SetOnce::try_set before accessing this cell",
+            Self::TargetDropped() => {
+                "keep the cell's target alive, e.g. in an owning Vec<Rc<T>>, while the cell is in use"
+            }
+            Self::OutsideGraph() => {
+                "point the cell at one of the graph's nodes, or add its target to the graph"
+            }
+            _ => return None,
+        };
+        Some(Box::new(help))
+    }
+}
+
+/// Serializes as a map of the error's stable `code`, its `message`, the cells named by
+/// [`Context`](DeferredError::Context) layers from outermost to innermost, and the
+/// `location` of the call that caused it, or `null` without the `debug-location`
+/// feature.
+#[cfg(feature = "serde")]
+impl serde::Serialize for DeferredError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut context = Vec::new();
+        let mut layer = self;
+        loop {
+            layer = match layer {
+                Self::Context(cell, inner) => {
+                    context.push(cell);
+                    inner
+                }
+                #[cfg(feature = "debug-location")]
+                Self::Located(_, inner) => inner,
+                _ => break,
+            };
+        }
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+        map.serialize_entry("context", &context)?;
+        map.serialize_entry("location", &self.location().map(ToString::to_string))?;
+        map.end()
+    }
+}
+
+/// Every error collected by a batch operation, in the order they occurred.
+///
+/// Returned by [`wire!`] so a misconfigured graph can be fixed in one pass rather than
+/// one error at a time.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct InitErrors(Vec<DeferredError>);
+
+#[cfg(feature = "std")]
+impl InitErrors {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+    pub fn push(&mut self, error: DeferredError) {
+        self.0.push(error);
+    }
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.0.len()
+    }
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, DeferredError> {
+        self.0.iter()
+    }
+    /// Returns `Ok(())` if no errors were collected, or `Err(self)` otherwise.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for InitErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} initialization error(s)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n- {error}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InitErrors {}
+
+/// Lists each collected error as a related diagnostic.
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for InitErrors {
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn miette::Diagnostic> + 'a>> {
+        Some(Box::new(
+            self.0.iter().map(|e| e as &dyn miette::Diagnostic),
+        ))
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<DeferredError> for InitErrors {
+    fn from(error: DeferredError) -> Self {
+        Self(vec![error])
+    }
+}
+
+#[cfg(feature = "std")]
+impl FromIterator<DeferredError> for InitErrors {
+    fn from_iter<I: IntoIterator<Item = DeferredError>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Extend<DeferredError> for InitErrors {
+    fn extend<I: IntoIterator<Item = DeferredError>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+#[cfg(feature = "std")]
+impl IntoIterator for InitErrors {
+    type Item = DeferredError;
+    type IntoIter = std::vec::IntoIter<DeferredError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> IntoIterator for &'a InitErrors {
+    type Item = &'a DeferredError;
+    type IntoIter = std::slice::Iter<'a, DeferredError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Extension trait annotating results with the cell they came from.
+///
+/// ```
+/// use deferred_cell::{Deferred, DeferredResultExt};
+///
+/// let parent: Deferred<u32> = Deferred::new();
+/// let error = parent.try_get().cell_context("Node.parent").unwrap_err();
+/// assert!(error.to_string().starts_with("Node.parent: Cannot use uninitialized value!"));
+/// ```
+#[cfg(feature = "alloc")]
+pub trait DeferredResultExt<T> {
+    /// Wraps an error in a [`Context`](DeferredError::Context) naming `cell`.
+    fn cell_context(self, cell: impl Into<Cow<'static, str>>) -> Result<T, DeferredError>;
+    /// Like [`cell_context`](Self::cell_context), building the name only on error.
+    fn with_cell_context<C: Into<Cow<'static, str>>>(
+        self,
+        cell: impl FnOnce() -> C,
+    ) -> Result<T, DeferredError>;
+}
+
+#[cfg(feature = "alloc")]
+impl<T> DeferredResultExt<T> for Result<T, DeferredError> {
+    #[inline]
+    fn cell_context(self, cell: impl Into<Cow<'static, str>>) -> Self {
+        self.map_err(|e| e.context(cell))
+    }
+    #[inline]
+    fn with_cell_context<C: Into<Cow<'static, str>>>(self, cell: impl FnOnce() -> C) -> Self {
+        self.map_err(|e| e.context(cell()))
+    }
+}
+
+/// A duplicate-initialization error that hands back the rejected pointer.
+///
+/// Returned by [`SetOnce::try_set_owned`] and [`SetOnceSync::try_set_owned`], which take
+/// ownership of the value they store, so a caller can recover it or compare it with the
+/// cell's current target. Converts into [`DeferredError::DuplicateInitialization`].
+///
+/// ```
+/// use deferred_cell::{Deferred, SetOnce};
+/// use std::rc::Rc;
+///
+/// let cell = Deferred::new();
+/// let first = SetOnce::from(&cell).try_set_owned(Rc::new(1)).unwrap();
+/// let error = SetOnce::from(&cell).try_set_owned(Rc::new(2)).unwrap_err();
+/// assert_eq!(*error.rejected, 2);
+/// assert!(Rc::ptr_eq(&error.current.unwrap(), &first));
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Error, Debug)]
+#[error("Cannot initialize Deferred twice!")]
+pub struct AlreadySet<P> {
+    /// The value that was not stored.
+    pub rejected: P,
+    /// The cell's current target, or `None` if it was dropped.
+    pub current: Option<P>,
+}
+
+#[cfg(feature = "alloc")]
+impl<P> AlreadySet<P> {
+    /// Returns the value that was not stored.
+    pub fn into_rejected(self) -> P {
+        self.rejected
+    }
+    /// Returns this error's stable code, `DC0001`. See [`DeferredError::code`].
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        "DC0001"
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<P> From<AlreadySet<P>> for DeferredError {
+    fn from(_: AlreadySet<P>) -> Self {
+        Self::DuplicateInitialization()
+    }
+}
+
+/// The error of a chaining `with_*` setter generated by `#[deferred]`, handing back the
+/// node it consumed so the caller can recover it. Converts into the [`DeferredError`]
+/// it holds.
+#[cfg(feature = "alloc")]
+#[derive(Error)]
+#[error("{error}")]
+pub struct WithError<N> {
+    /// The node whose cell could not be set.
+    pub node: N,
+    /// Why the cell could not be set.
+    pub error: DeferredError,
+}
+
+#[cfg(feature = "alloc")]
+impl<N> WithError<N> {
+    /// Returns the node whose cell could not be set.
+    pub fn into_node(self) -> N {
+        self.node
+    }
+    /// Returns the stable code of the error. See [`DeferredError::code`].
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        self.error.code()
+    }
+}
+
+/// Shows only the error, so nodes need not implement `Debug` for `unwrap` to work.
+#[cfg(feature = "alloc")]
+impl<N> core::fmt::Debug for WithError<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("WithError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<N> From<WithError<N>> for DeferredError {
+    fn from(error: WithError<N>) -> Self {
+        error.error
+    }
 }
 
 /// A write-once, weak reference wrapper for late initialization.
 ///
-/// Use [`SetOnce`](crate::SetOnce) to assign a value exactly once,
-#[derive(Debug, Clone)]
-pub struct Deferred<T>(OnceCell<Weak<T>>);
+/// Use [`SetOnce`] to assign a value exactly once,
+///
+/// A cell is one pointer wide: `Weak` is never null, so the unset state is stored in
+/// its niche rather than in a separate flag. The `debug-registry` feature adds the
+/// `registry` bookkeeping next to it, which makes the cell several words wide.
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct Deferred<T>(
+    OnceCell<Weak<T>>,
+    #[cfg(feature = "debug-registry")] diagnostics::Counted,
+);
+
+#[cfg(all(feature = "alloc", not(feature = "debug-registry")))]
+const _: () = assert!(size_of::<Deferred<u8>>() == size_of::<usize>());
 
+#[cfg(feature = "alloc")]
 impl<T> Default for Deferred<T> {
     fn default() -> Self {
-        Self(OnceCell::new())
+        Self::counted(OnceCell::new())
     }
 }
 
+#[cfg(feature = "alloc")]
+impl<T> From<&Rc<T>> for Deferred<T> {
+    /// Creates a cell that is already set to `value`.
+    fn from(value: &Rc<T>) -> Self {
+        Self::counted(OnceCell::from(Rc::downgrade(value)))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Clone for Deferred<T> {
+    fn clone(&self) -> Self {
+        Self::counted(self.0.clone())
+    }
+}
+
+#[cfg(feature = "alloc")]
 impl<T> Deferred<T> {
+    /// Creates an unset cell in a const context.
+    ///
+    /// With the `debug-registry` feature, the cell is only counted once it is set.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(
+            OnceCell::new(),
+            #[cfg(feature = "debug-registry")]
+            diagnostics::Counted::uncounted(),
+        )
+    }
+    /// Wraps `cell` and counts it in the registry from now on.
+    fn counted(cell: OnceCell<Weak<T>>) -> Self {
+        let ready = cell.get().is_some();
+        diagnostics::created(ready, type_name::<Self>());
+        Self(
+            cell,
+            #[cfg(feature = "debug-registry")]
+            diagnostics::Counted::new(ready, type_name::<Self>()),
+        )
+    }
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[inline]
     pub fn try_get(&self) -> Result<Rc<T>, DeferredError> {
-        self.0
-            .get()
-            .ok_or(DeferredError::NotInitializedError())?
-            .upgrade()
-            .ok_or(DeferredError::NotInitializedError())
+        let read = diagnostics::get(type_name::<T>());
+        match self.0.get().and_then(Weak::upgrade) {
+            Some(target) if read.is_ok() => Ok(target),
+            _ => Err(self.read_error(read)),
+        }
     }
+    /// Returns the cell's target.
+    ///
+    /// # Panics
+    /// Panics if the cell is not set or its target was dropped, unless a
+    /// [`failure`] handler is installed.
+    #[cfg_attr(feature = "debug-location", track_caller)]
     #[must_use]
+    #[inline]
     pub fn get(&self) -> Rc<T> {
-        #[allow(clippy::expect_used)]
-        self.try_get().expect("Deferred value is not yet set!")
+        match self.try_get() {
+            Ok(target) => target,
+            Err(error) => failure::fail(&error),
+        }
+    }
+    /// Borrows the cell's target for as long as `live` guarantees it stays alive,
+    /// without changing its reference count.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[inline]
+    pub fn try_get_ref<'g>(&self, live: &liveness::Liveness<'g>) -> Result<&'g T, DeferredError> {
+        let _ = live;
+        let read = diagnostics::get(type_name::<T>());
+        match self.0.get() {
+            // SAFETY: the target is alive now, and the `Liveness` contract keeps it alive
+            // for `'g`.
+            Some(weak) if read.is_ok() && weak.strong_count() > 0 => Ok(unsafe { &*weak.as_ptr() }),
+            _ => Err(self.read_error(read)),
+        }
+    }
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[inline]
+    fn read_error(&self, read: Result<(), DeferredError>) -> DeferredError {
+        DeferredError::read_failed(
+            read,
+            self.0.get().is_some(),
+            core::ptr::from_ref(self).cast(),
+            type_name::<T>(),
+        )
     }
     #[inline]
     pub fn is_ready(&self) -> bool {
         self.0.get().is_some()
     }
+    #[cfg(feature = "std")]
+    pub(crate) fn is_dangling(&self) -> bool {
+        self.0.get().is_some_and(|weak| weak.strong_count() == 0)
+    }
 }
 
 /// A write-once assignment interface for [`Deferred<T>`].
@@ -107,18 +783,43 @@ impl<T> Deferred<T> {
 /// let value = Rc::new(42);
 /// SetOnce::from(&deferred).try_set(&value).unwrap();
 /// ```
+#[cfg(feature = "alloc")]
 #[derive(Debug, Clone)]
 pub struct SetOnce<'a, T>(&'a Deferred<T>);
 
+#[cfg(feature = "alloc")]
 impl<'a, T> SetOnce<'a, T> {
     pub const fn from(cell: &'a Deferred<T>) -> Self {
         Self(cell)
     }
+    #[cfg_attr(feature = "debug-location", track_caller)]
     pub fn try_set(&self, value: &Rc<T>) -> Result<(), DeferredError> {
-        self.0
-            .0
-            .set(Rc::downgrade(value))
-            .map_err(|_| DeferredError::DuplicateInitialization())
+        let cell = core::ptr::from_ref(self.0).cast();
+        DeferredError::located(
+            self.0
+                .0
+                .set(Rc::downgrade(value))
+                .map_err(|_| diagnostics::duplicate_set(cell, type_name::<T>())),
+        )?;
+        diagnostics::set(cell, Rc::as_ptr(value).cast(), type_name::<T>());
+        #[cfg(feature = "debug-registry")]
+        self.0.1.set(type_name::<Deferred<T>>());
+        Ok(())
+    }
+    /// Sets the cell to a weak reference to `value` and returns `value`, so a newly
+    /// created target can be stored and kept alive in one step.
+    ///
+    /// If the cell is already set, the error hands `value` back along with the current
+    /// target.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn try_set_owned(&self, value: Rc<T>) -> Result<Rc<T>, AlreadySet<Rc<T>>> {
+        match self.try_set(&value) {
+            Ok(()) => Ok(value),
+            Err(_) => Err(AlreadySet {
+                rejected: value,
+                current: self.0.0.get().and_then(Weak::upgrade),
+            }),
+        }
     }
     #[inline]
     pub fn can_set(&self) -> bool {
@@ -127,6 +828,7 @@ impl<'a, T> SetOnce<'a, T> {
 }
 
 /// Iterator extension trait to improve the ergonomics of `Deferred<T>` collections
+#[cfg(feature = "alloc")]
 pub trait DeferredIteratorExt<T>: Iterator<Item = Deferred<T>> + Sized {
     /// Returns an iterator of `Rc<T>` from an iterator of `Deferred<T>`.
     ///
@@ -141,18 +843,19 @@ pub trait DeferredIteratorExt<T>: Iterator<Item = Deferred<T>> + Sized {
     }
 }
 
+#[cfg(feature = "alloc")]
 impl<T, I> DeferredIteratorExt<T> for I where I: Iterator<Item = Deferred<T>> {}
 
 // Allowed in tests
 #[allow(clippy::unwrap_used)]
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
 
     #[derive(Debug, Clone)]
     struct Node {
         value: String,
-        neighbors: Vec<Deferred<Node>>,
+        neighbors: Vec<Deferred<Self>>,
     }
     impl Node {
         fn new(value: &str, n_neighbors: usize) -> Rc<Self> {
@@ -179,26 +882,25 @@ mod test {
         let south = Node::new("South", 3);
         let west = Node::new("West", 3);
 
-        SetOnce::from(&center.neighbors[0]).try_set(&north).unwrap();
-        SetOnce::from(&center.neighbors[1]).try_set(&west).unwrap();
-        SetOnce::from(&center.neighbors[2]).try_set(&south).unwrap();
-        SetOnce::from(&center.neighbors[3]).try_set(&east).unwrap();
-
-        SetOnce::from(&north.neighbors[0]).try_set(&west).unwrap();
-        SetOnce::from(&north.neighbors[1]).try_set(&center).unwrap();
-        SetOnce::from(&north.neighbors[2]).try_set(&east).unwrap();
-
-        SetOnce::from(&west.neighbors[0]).try_set(&north).unwrap();
-        SetOnce::from(&west.neighbors[1]).try_set(&south).unwrap();
-        SetOnce::from(&west.neighbors[2]).try_set(&center).unwrap();
-
-        SetOnce::from(&south.neighbors[0]).try_set(&center).unwrap();
-        SetOnce::from(&south.neighbors[1]).try_set(&west).unwrap();
-        SetOnce::from(&south.neighbors[2]).try_set(&east).unwrap();
-
-        SetOnce::from(&east.neighbors[0]).try_set(&north).unwrap();
-        SetOnce::from(&east.neighbors[1]).try_set(&center).unwrap();
-        SetOnce::from(&east.neighbors[2]).try_set(&south).unwrap();
+        crate::wire! {
+            center.neighbors[0] = north,
+            center.neighbors[1] = west,
+            center.neighbors[2] = south,
+            center.neighbors[3] = east,
+            north.neighbors[0] = west,
+            north.neighbors[1] = center,
+            north.neighbors[2] = east,
+            west.neighbors[0] = north,
+            west.neighbors[1] = south,
+            west.neighbors[2] = center,
+            south.neighbors[0] = center,
+            south.neighbors[1] = west,
+            south.neighbors[2] = east,
+            east.neighbors[0] = north,
+            east.neighbors[1] = center,
+            east.neighbors[2] = south,
+        }
+        .unwrap();
 
         vec![center, north, east, south, west]
     }
@@ -232,7 +934,10 @@ mod test {
         let duplicate_set = mutator.try_set(center);
 
         assert!(
-            matches!(duplicate_set, Err(DeferredError::DuplicateInitialization())),
+            matches!(
+                duplicate_set.as_ref().map_err(DeferredError::root_cause),
+                Err(DeferredError::DuplicateInitialization())
+            ),
             "Expected DuplicateInitialization error"
         );
     }
@@ -242,11 +947,23 @@ mod test {
         let result = uninitialized.try_get();
 
         assert!(
-            matches!(result, Err(DeferredError::NotInitializedError())),
+            matches!(
+                result.as_ref().map_err(DeferredError::root_cause),
+                Err(DeferredError::NotInitializedError())
+            ),
             "Expected NotInitializedError"
         );
     }
     #[test]
+    fn dropped_target_access_fails() {
+        let cell = Deferred::default();
+        SetOnce::from(&cell).try_set(&Rc::new(1)).unwrap();
+        let error = cell.try_get().unwrap_err();
+
+        assert!(matches!(error.root_cause(), DeferredError::TargetDropped()));
+        assert_eq!(error.code(), "DC0003");
+    }
+    #[test]
     fn iterator_extension_works() {
         let graph = make_cyclic_graph();
         let center = graph.first().unwrap();
@@ -262,6 +979,326 @@ mod test {
         assert_eq!(values, vec!["North", "West", "South", "East"]);
     }
     #[test]
+    fn graph_macro_builds_and_wires() {
+        struct Pair {
+            value: u8,
+            links: Vec<Deferred<Self>>,
+        }
+        let make = |value| Pair {
+            value,
+            links: vec![Deferred::new(), Deferred::new()],
+        };
+        let (first, second) = crate::graph! {
+            first: make(1);
+            second: make(2);
+            edges: first.links[0] -> second, first.links[1] -> first, second.links[0] -> first;
+        }
+        .unwrap();
+
+        assert_eq!(first.links[0].get().value, 2);
+        assert_eq!(first.links[1].get().value, 1);
+        assert_eq!(second.links[0].get().value, 1);
+        assert!(!second.links[1].is_ready());
+
+        let duplicate = crate::graph! {
+            only: make(3);
+            edges: only.links[0] -> only, only.links[0] -> only;
+        };
+        assert!(matches!(
+            duplicate.as_ref().map_err(DeferredError::root_cause),
+            Err(DeferredError::DuplicateInitialization())
+        ));
+    }
+    #[test]
+    fn link_macro_is_all_or_nothing() {
+        let graph = make_cyclic_graph();
+        let center = &graph[0];
+        let a = Node::new("A", 2);
+        let b = Node::new("B", 2);
+
+        crate::link!(a.neighbors[0] <-> b.neighbors[1]).unwrap();
+        assert_eq!(a.neighbors[0].get().value, "B");
+        assert_eq!(b.neighbors[1].get().value, "A");
+
+        let result = crate::link!(a.neighbors[1] <-> center.neighbors[0]);
+        assert!(matches!(
+            result.as_ref().map_err(DeferredError::root_cause),
+            Err(DeferredError::DuplicateInitialization())
+        ));
+        assert!(!a.neighbors[1].is_ready());
+
+        let result = crate::link!(a.neighbors[1] <-> a.neighbors[1]);
+        assert!(matches!(
+            result.as_ref().map_err(DeferredError::root_cause),
+            Err(DeferredError::DuplicateInitialization())
+        ));
+        assert!(!a.neighbors[1].is_ready());
+        #[cfg(feature = "debug-location")]
+        assert_eq!(result.unwrap_err().location().unwrap().file(), file!());
+    }
+    #[test]
+    fn link_macro_joins_different_node_types() {
+        struct Parent {
+            child: Deferred<Child>,
+        }
+        struct Child {
+            parent: Deferred<Parent>,
+        }
+        let parent = Rc::new(Parent {
+            child: Deferred::new(),
+        });
+        let child = Rc::new(Child {
+            parent: Deferred::new(),
+        });
+
+        crate::link!(parent.child <-> child.parent).unwrap();
+        assert!(Rc::ptr_eq(&parent.child.get(), &child));
+        assert!(Rc::ptr_eq(&child.parent.get(), &parent));
+    }
+    #[cfg(not(feature = "debug-location"))]
+    #[test]
+    fn wire_macro_reports_every_failure() {
+        let a = Node::new("A", 3);
+        let b = Node::new("B", 1);
+
+        let errors = crate::wire! {
+            a.neighbors[0] = b,
+            a.neighbors[0] = a,
+            a.neighbors[1] = a,
+            b.neighbors[0] = a,
+            b.neighbors[0] = b,
+        }
+        .unwrap_err();
+
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "a.neighbors[0]: Cannot initialize Deferred twice!",
+                "b.neighbors[0]: Cannot initialize Deferred twice!",
+            ]
+        );
+        assert!(
+            errors
+                .iter()
+                .all(|e| matches!(e.root_cause(), DeferredError::DuplicateInitialization()))
+        );
+        assert_eq!(a.neighbors[0].get().value, "B");
+        assert_eq!(a.neighbors[1].get().value, "A");
+        assert_eq!(b.neighbors[0].get().value, "A");
+        assert!(!a.neighbors[2].is_ready());
+    }
+    #[test]
+    fn init_errors_collect_and_display() {
+        let mut errors =
+            InitErrors::from(DeferredError::NotInitializedError().context("Node.parent"));
+        errors.push(DeferredError::DuplicateInitialization());
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors.to_string(),
+            "2 initialization error(s)\n- Node.parent: Cannot use uninitialized value!\n- Cannot initialize Deferred twice!"
+        );
+        assert!(InitErrors::new().into_result().is_ok());
+    }
+    #[test]
+    fn owned_set_hands_back_the_rejected_value() {
+        let cell = Deferred::new();
+        drop(
+            SetOnce::from(&cell)
+                .try_set_owned(Node::new("Gone", 0))
+                .unwrap(),
+        );
+        let error = SetOnce::from(&cell)
+            .try_set_owned(Node::new("Late", 0))
+            .unwrap_err();
+
+        assert_eq!(error.rejected.value, "Late");
+        assert!(error.current.is_none());
+        assert!(matches!(
+            DeferredError::from(error),
+            DeferredError::DuplicateInitialization()
+        ));
+    }
+    #[test]
+    fn deferred_from_rc_starts_set() {
+        let target = Node::new("Target", 0);
+        let deferred = Deferred::from(&target);
+
+        assert!(deferred.is_ready());
+        assert!(!SetOnce::from(&deferred).can_set());
+        assert_eq!(deferred.get().value, "Target");
+    }
+    #[test]
+    fn assert_wired_accepts_fully_wired_nodes() {
+        let graph = make_cyclic_graph();
+        let center = &graph[0];
+        crate::assert_wired!(
+            center,
+            neighbors[0],
+            neighbors[1],
+            neighbors[2],
+            neighbors[3]
+        );
+    }
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "node.neighbors[1] was never wired")]
+    fn assert_wired_reports_missing_fields() {
+        let node = Node::new("Partial", 2);
+        SetOnce::from(&node.neighbors[0]).try_set(&node).unwrap();
+        crate::assert_wired!(node, neighbors[0], neighbors[1]);
+    }
+    #[cfg(feature = "debug-location")]
+    #[test]
+    fn errors_record_the_calling_line() {
+        let cell: Deferred<Node> = Deferred::new();
+        let line = line!() + 1;
+        let error = cell.try_get().unwrap_err().context("Node.parent");
+
+        let location = error.location().unwrap();
+        assert_eq!((location.file(), location.line()), (file!(), line));
+        assert!(matches!(
+            error.root_cause(),
+            DeferredError::NotInitializedError()
+        ));
+        assert!(error.to_string().ends_with(&format!("(at {location})")));
+    }
+    #[test]
+    fn context_wraps_and_unwraps() {
+        let error = DeferredError::NotInitializedError()
+            .context("Node.parent")
+            .context("loading graph");
+
+        assert_eq!(
+            error.to_string(),
+            "loading graph: Node.parent: Cannot use uninitialized value!"
+        );
+        assert!(matches!(
+            error.root_cause(),
+            DeferredError::NotInitializedError()
+        ));
+    }
+    #[cfg(not(feature = "debug-location"))]
+    #[test]
+    fn result_context_names_the_cell() {
+        let node = Node::new("A", 1);
+        let error = node.neighbors[0]
+            .try_get()
+            .with_cell_context(|| format!("{}.neighbors[0]", node.value))
+            .cell_context("loading graph")
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "loading graph: A.neighbors[0]: Cannot use uninitialized value!"
+        );
+        assert!(node.neighbors[0].try_get().cell_context("unused").is_err());
+    }
+    #[cfg(feature = "miette")]
+    #[test]
+    fn diagnostics_see_through_context() {
+        use miette::Diagnostic;
+
+        let error = DeferredError::NotInitializedError().context("Node.parent");
+        assert_eq!(Diagnostic::code(&error).unwrap().to_string(), "DC0002");
+        assert_eq!(error.code(), "DC0002");
+        assert!(
+            error
+                .help()
+                .unwrap()
+                .to_string()
+                .starts_with("call SetOnce::try_set")
+        );
+    }
+    #[cfg(all(feature = "serde", feature = "json", not(feature = "debug-location")))]
+    #[test]
+    fn errors_serialize_as_structured_json() {
+        let mut errors = InitErrors::new();
+        errors.push(DeferredError::NotInitializedError().context("Node.parent"));
+        errors.push(DeferredError::DuplicateInitialization());
+        assert_eq!(
+            serde_json::to_value(&errors).unwrap(),
+            serde_json::json!([
+                {
+                    "code": "DC0002",
+                    "message": "Node.parent: Cannot use uninitialized value!",
+                    "context": ["Node.parent"],
+                    "location": null,
+                },
+                {
+                    "code": "DC0001",
+                    "message": "Cannot initialize Deferred twice!",
+                    "context": [],
+                    "location": null,
+                },
+            ])
+        );
+        let unresolved = resolver::ResolveError::Unresolved(vec![("a", "b")]);
+        let json = serde_json::to_value(&unresolved).unwrap();
+        assert_eq!(json["kind"], "unresolved");
+        assert_eq!(json["edges"], serde_json::json!([["a", "b"]]));
+    }
+    #[cfg(all(feature = "serde", feature = "json", not(feature = "debug-location")))]
+    #[test]
+    fn collected_errors_serialize_as_structured_json() {
+        let unset = visit::UnsetPath {
+            path: vec![visit::PathStep {
+                node: "root".to_string(),
+                field: "left".into(),
+            }],
+        };
+        assert_eq!(
+            serde_json::to_value(&unset).unwrap(),
+            serde_json::json!({"path": [{"node": "root", "field": "left"}]})
+        );
+        let unbound = scope::ScopeError::Unbound("x".to_string());
+        assert_eq!(
+            serde_json::to_value(&unbound).unwrap(),
+            serde_json::json!({
+                "kind": "unbound",
+                "message": unbound.to_string(),
+                "name": "x",
+            })
+        );
+        let wiring = merge::MergeError::Wiring {
+            key: 1,
+            source: DeferredError::DuplicateInitialization(),
+        };
+        let json = serde_json::to_value(&wiring).unwrap();
+        assert_eq!(json["kind"], "wiring");
+        assert_eq!(json["key"], 1);
+        assert_eq!(json["error"]["code"], "DC0001");
+        let duplicate = symbols::SymbolError::Duplicate {
+            name: "f".to_string(),
+            first: 1,
+            second: 2,
+        };
+        let json = serde_json::to_value(&duplicate).unwrap();
+        assert_eq!(json["kind"], "duplicate");
+        assert_eq!((&json["first"], &json["second"]), (&1.into(), &2.into()));
+        let missing = plugin::PluginError::Missing {
+            plugin: "render".to_string(),
+            service: "log".to_string(),
+        };
+        assert_eq!(serde_json::to_value(&missing).unwrap()["kind"], "missing");
+        let unknown = planner::PlanError::UnknownKey {
+            builder: "b".to_string(),
+            key: "k",
+        };
+        assert_eq!(
+            serde_json::to_value(&unknown).unwrap()["kind"],
+            "unknown_key"
+        );
+        let dangling = dialogue::Issue::Dangling {
+            line: "start".to_string(),
+            choice: "leave".to_string(),
+            target: "end".to_string(),
+        };
+        let json = serde_json::to_value(&dangling).unwrap();
+        assert_eq!(json["kind"], "dangling");
+        assert_eq!(json["target"], "end");
+    }
+    #[test]
     fn deferred_state_checking() {
         let graph = make_cyclic_graph();
         let center = graph.first().unwrap();
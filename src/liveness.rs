@@ -0,0 +1,95 @@
+//! Borrowing cell targets without touching their reference counts.
+//!
+//! [`Deferred::try_get`](crate::Deferred::try_get) upgrades the cell's weak reference,
+//! incrementing and later decrementing the target's strong count. In read-heavy
+//! traversals over a graph whose nodes are all owned by one collection, that churn is
+//! wasted: the collection already keeps every target alive. A [`Liveness`] guard
+//! borrowing that collection records the promise, and
+//! [`Deferred::try_get_ref`](crate::Deferred::try_get_ref) and
+//! [`DeferredSync::try_get_ref`](crate::DeferredSync::try_get_ref) then return plain
+//! references that live as long as the guard's borrow.
+//!
+//! ```
+//! use deferred_cell::{Deferred, SetOnce, liveness::Liveness};
+//! use std::rc::Rc;
+//!
+//! struct Node {
+//!     value: u32,
+//!     next: Deferred<Node>,
+//! }
+//!
+//! let nodes: Vec<_> = (0..3).map(|value| Rc::new(Node { value, next: Deferred::new() })).collect();
+//! for (i, node) in nodes.iter().enumerate() {
+//!     SetOnce::from(&node.next).try_set(&nodes[(i + 1) % 3]).unwrap();
+//! }
+//!
+//! // SAFETY: `nodes` owns every node the cells point to and is not modified while
+//! // `live` exists.
+//! let live = unsafe { Liveness::new(&nodes) };
+//! let mut node = &*nodes[0];
+//! let mut sum = 0;
+//! for _ in 0..6 {
+//!     sum += node.value;
+//!     node = node.next.try_get_ref(&live).unwrap();
+//! }
+//! assert_eq!(sum, 6);
+//! ```
+
+use core::marker::PhantomData;
+
+/// Proof that every target read through it stays alive for `'g`.
+#[derive(Debug, Clone, Copy)]
+pub struct Liveness<'g> {
+    _owner: PhantomData<&'g ()>,
+}
+
+impl<'g> Liveness<'g> {
+    /// Creates a guard borrowing `owner`, the collection that keeps the graph alive.
+    ///
+    /// # Safety
+    /// Every target read through the guard that is alive when it is read must stay
+    /// alive for `'g`. This holds when `owner` holds a strong reference to every such
+    /// target, since the shared borrow keeps `owner` from releasing them.
+    #[must_use]
+    pub const unsafe fn new<O: ?Sized>(owner: &'g O) -> Self {
+        let _ = owner;
+        Self {
+            _owner: PhantomData,
+        }
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+// loom's primitives panic outside `loom::model`.
+#[cfg(all(test, feature = "std", not(feature = "loom")))]
+mod test {
+    use std::{rc::Rc, sync::Arc};
+
+    use super::*;
+    use crate::{Deferred, DeferredError, DeferredSync, SetOnce, SetOnceSync};
+
+    #[test]
+    fn borrows_live_targets_and_rejects_dead_ones() {
+        let owned = vec![Rc::new(1)];
+        let shared = Arc::new(2);
+        let dropped = Rc::new(3);
+        let (local, sync, dead) = (Deferred::new(), DeferredSync::default(), Deferred::new());
+        SetOnce::from(&local).try_set(&owned[0]).unwrap();
+        SetOnceSync::from(&sync).try_set(&shared).unwrap();
+        SetOnce::from(&dead).try_set(&dropped).unwrap();
+        drop(dropped);
+
+        // SAFETY: `owned` and `shared` own every live target and outlive the guard.
+        let live = unsafe { Liveness::new(&owned) };
+        assert_eq!(*local.try_get_ref(&live).unwrap(), 1);
+        assert_eq!(*sync.try_get_ref(&live).unwrap(), 2);
+        assert!(matches!(
+            dead.try_get_ref(&live)
+                .as_ref()
+                .map_err(DeferredError::root_cause),
+            Err(DeferredError::TargetDropped())
+        ));
+        assert_eq!(Rc::strong_count(&owned[0]), 1);
+    }
+}
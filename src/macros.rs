@@ -0,0 +1,240 @@
+//! Declarative macros for building graphs.
+
+/// Builds a literal graph of `Rc` nodes and wires its edges.
+///
+/// Each `name: expr;` line creates a node with `Rc::new(expr)`. The `edges:` list wires
+/// the named cell of one node to another node, with `from.field -> to` or
+/// `from.field[index] -> to` for cells stored in an indexable collection.
+///
+/// Evaluates to `Result<(Rc<A>, Rc<B>, ...), DeferredError>`, with the nodes in
+/// declaration order.
+///
+/// The `edges:` section is required, even if it is empty:
+///
+/// ```compile_fail
+/// let (a,) = deferred_cell::graph! { a: 1; }?;
+/// # Ok::<(), deferred_cell::DeferredError>(())
+/// ```
+///
+/// ```
+/// use deferred_cell::{Deferred, DeferredError, graph};
+///
+/// struct Node {
+///     name: &'static str,
+///     next: Deferred<Node>,
+///     prev: Deferred<Node>,
+/// }
+///
+/// fn node(name: &'static str) -> Node {
+///     Node { name, next: Deferred::new(), prev: Deferred::new() }
+/// }
+///
+/// fn main() -> Result<(), DeferredError> {
+///     let (a, b) = graph! {
+///         a: node("A");
+///         b: node("B");
+///         edges: a.next -> b, b.prev -> a, b.next -> a;
+///     }?;
+///     assert_eq!(a.next.get().name, "B");
+///     assert_eq!(b.next.get().name, "A");
+///     assert!(!a.prev.is_ready());
+///     Ok(())
+/// }
+/// ```
+#[macro_export]
+macro_rules! graph {
+    (@nodes [$(($name:ident $node:expr))*]
+        edges: $($from:ident . $field:ident $([$index:expr])? -> $to:ident),* $(,)? $(;)?
+    ) => {
+        (|| -> ::core::result::Result<_, $crate::DeferredError> {
+            $(let $name = ::std::rc::Rc::new($node);)*
+            $($crate::SetOnce::from(&$from.$field $([$index])?).try_set(&$to)?;)*
+            ::core::result::Result::Ok(($($name,)*))
+        })()
+    };
+    (@nodes [$($nodes:tt)*] $name:ident : $node:expr; $($rest:tt)*) => {
+        $crate::graph!(@nodes [$($nodes)* ($name $node)] $($rest)*)
+    };
+    (@nodes [$($nodes:tt)*]) => {
+        ::core::compile_error!("graph! requires an `edges:` section")
+    };
+    ($($input:tt)*) => {
+        $crate::graph!(@nodes [] $($input)*)
+    };
+}
+
+/// Wires two `Rc` nodes to each other through one cell on each side.
+///
+/// `link!(a.next <-> b.prev)` sets `a.next` to `b` and `b.prev` to `a`. Both cells are
+/// checked first, so either both are set or, with a
+/// [`DuplicateInitialization`](crate::DeferredError::DuplicateInitialization) error,
+/// neither is. Naming the same cell on both sides, as in `link!(a.next <-> a.next)`,
+/// fails the same way, since it cannot be set twice.
+///
+/// ```
+/// use deferred_cell::{Deferred, DeferredError, link};
+/// use std::rc::Rc;
+///
+/// struct Node {
+///     value: u8,
+///     next: Deferred<Node>,
+///     prev: Deferred<Node>,
+/// }
+///
+/// fn main() -> Result<(), DeferredError> {
+///     let a = Rc::new(Node { value: 1, next: Deferred::new(), prev: Deferred::new() });
+///     let b = Rc::new(Node { value: 2, next: Deferred::new(), prev: Deferred::new() });
+///     link!(a.next <-> b.prev)?;
+///     assert_eq!(a.next.get().value, 2);
+///     assert_eq!(b.prev.get().value, 1);
+///     Ok(())
+/// }
+/// ```
+#[macro_export]
+macro_rules! link {
+    ($a:ident . $a_field:ident $([$a_index:expr])? <-> $b:ident . $b_field:ident $([$b_index:expr])?) => {{
+        let a_ref = &$a.$a_field $([$a_index])?;
+        let b_ref = &$b.$b_field $([$b_index])?;
+        let (a_cell, b_cell) = ($crate::SetOnce::from(a_ref), $crate::SetOnce::from(b_ref));
+        if !::core::ptr::addr_eq(a_ref, b_ref) && a_cell.can_set() && b_cell.can_set() {
+            a_cell
+                .try_set(&$b)
+                .and_then(|()| b_cell.try_set(&$a))
+        } else {
+            ::core::result::Result::Err($crate::__private::duplicate_initialization())
+        }
+    }};
+}
+
+/// Wires many cells in one statement.
+///
+/// Each `node.field = target` (or `node.field[index] = target`) assignment expands to a
+/// [`SetOnce::try_set`](crate::SetOnce::try_set) call. Every assignment is attempted,
+/// and the macro evaluates to `Ok(())` or an [`InitErrors`](crate::InitErrors) holding
+/// every failure, each annotated with its assignment, such as `a.neighbors[0]`.
+///
+/// ```
+/// use deferred_cell::{Deferred, InitErrors, wire};
+/// use std::rc::Rc;
+///
+/// struct Node {
+///     value: u8,
+///     neighbors: Vec<Deferred<Node>>,
+/// }
+///
+/// fn main() -> Result<(), InitErrors> {
+///     let node = |value| Rc::new(Node { value, neighbors: vec![Deferred::new(), Deferred::new()] });
+///     let (a, b, c) = (node(1), node(2), node(3));
+///     wire! {
+///         a.neighbors[0] = b,
+///         a.neighbors[1] = c,
+///         b.neighbors[0] = a,
+///     }?;
+///     assert_eq!(a.neighbors[1].get().value, 3);
+///     Ok(())
+/// }
+/// ```
+#[macro_export]
+macro_rules! wire {
+    ($($node:ident . $field:ident $([$index:expr])? = $target:expr),* $(,)?) => {{
+        let mut errors = $crate::InitErrors::new();
+        $(
+            if let ::core::result::Result::Err(error) =
+                $crate::SetOnce::from(&$node.$field $([$index])?).try_set(&$target)
+            {
+                errors.push(error.context(::core::concat!(
+                    ::core::stringify!($node),
+                    ".",
+                    ::core::stringify!($field),
+                    $("[", ::core::stringify!($index), "]",)?
+                )));
+            }
+        )*
+        errors.into_result()
+    }};
+}
+
+/// Queues symbolic edges on a [`Resolver`](crate::resolver::Resolver).
+///
+/// Each `"from".field -> "to"` (or `"from".field[index] -> "to"`) entry becomes a
+/// [`Resolver::edge`](crate::resolver::Resolver::edge) call; keys are literals or
+/// parenthesized expressions, converted with `Into`. Edges are wired as soon as both
+/// keys are registered. Evaluates to `Ok(())` or the first error encountered.
+///
+/// ```
+/// use deferred_cell::{Deferred, edges, resolver::Resolver};
+/// use std::rc::Rc;
+///
+/// struct Step {
+///     next: Deferred<Step>,
+/// }
+///
+/// let mut resolver = Resolver::<String, Step>::new();
+/// let last = String::from("c");
+/// edges! { resolver =>
+///     "a".next -> "b",
+///     "b".next -> (last.clone()),
+/// }
+/// .unwrap();
+/// for key in ["a", "b", "c"] {
+///     resolver.register(key.into(), Rc::new(Step { next: Deferred::new() })).unwrap();
+/// }
+/// let steps = resolver.finish().unwrap();
+/// assert!(steps["b"].next.is_ready());
+/// assert!(!steps["c"].next.is_ready());
+/// ```
+#[macro_export]
+macro_rules! edges {
+    ($resolver:expr => $($from:tt . $field:ident $([$index:expr])? -> $to:tt),* $(,)?) => {{
+        let resolver = &mut $resolver;
+        let mut result = ::core::result::Result::Ok(());
+        $(
+            let edge = resolver.edge(
+                ::core::convert::Into::into($from),
+                |node| &node.$field $([$index])?,
+                ::core::convert::Into::into($to),
+            );
+            if result.is_ok() {
+                result = edge;
+            }
+        )*
+        result
+    }};
+}
+
+/// Checks, in debug builds, that each listed deferred field of a node has been set.
+///
+/// Place it at the end of a construction function to catch fields that were added to a
+/// node type but never wired. Works with any cell that has an `is_ready` method, such as
+/// [`Deferred`](crate::Deferred) and [`DeferredSync`](crate::DeferredSync). Like
+/// [`debug_assert!`], the checks compile to nothing in release builds.
+///
+/// ```should_panic
+/// use deferred_cell::{Deferred, SetOnce, assert_wired};
+/// use std::rc::Rc;
+///
+/// struct Node {
+///     parent: Deferred<Node>,
+///     children: Vec<Deferred<Node>>,
+/// }
+///
+/// let root = Rc::new(Node { parent: Deferred::new(), children: vec![Deferred::new()] });
+/// SetOnce::from(&root.parent).try_set(&root).unwrap();
+///
+/// // Panics: "root.children[0] was never wired"
+/// assert_wired!(root, parent, children[0]);
+/// ```
+#[macro_export]
+macro_rules! assert_wired {
+    ($node:expr, $($field:ident $([$index:expr])?),+ $(,)?) => {{
+        $(
+            ::core::debug_assert!(
+                $node.$field $([$index])?.is_ready(),
+                "{}.{}{} was never wired",
+                ::core::stringify!($node),
+                ::core::stringify!($field),
+                ::core::concat!("" $(, "[", ::core::stringify!($index), "]")?),
+            );
+        )+
+    }};
+}
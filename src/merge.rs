@@ -0,0 +1,264 @@
+//! Merging graphs loaded from separate sources into one.
+//!
+//! [`merge`] builds the union of two graphs, in which every node of either graph with
+//! the same key becomes one node. The payloads of unified nodes are combined by a
+//! caller-supplied function, which may refuse with a conflict, and their
+//! [`HasNeighbors`] edges are combined, each target appearing once. All conflicts are
+//! reported together.
+//!
+//! ```
+//! use deferred_cell::{Deferred, HasNeighbors, SetOnce, merge::merge};
+//! use std::rc::Rc;
+//!
+//! struct Term {
+//!     name: &'static str,
+//!     see_also: Vec<Deferred<Term>>,
+//! }
+//!
+//! impl HasNeighbors for Term {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         self.see_also.iter()
+//!     }
+//! }
+//!
+//! fn term(name: &'static str, degree: usize) -> Rc<Term> {
+//!     Rc::new(Term { name, see_also: (0..degree).map(|_| Deferred::new()).collect() })
+//! }
+//!
+//! // Two glossaries, each linking "graph" to a different term.
+//! let a = [term("graph", 1), term("node", 0)];
+//! let b = [term("graph", 1), term("edge", 0)];
+//! SetOnce::from(&a[0].see_also[0]).try_set(&a[1]).unwrap();
+//! SetOnce::from(&b[0].see_also[0]).try_set(&b[1]).unwrap();
+//!
+//! let merged = merge(
+//!     &a,
+//!     &b,
+//!     |t| t.name,
+//!     |t, _, degree| {
+//!         let see_also = (0..degree).map(|_| Deferred::new()).collect();
+//!         Ok(Term { name: t.name, see_also })
+//!     },
+//!     |t, k| &t.see_also[k],
+//! )
+//! .unwrap();
+//! let related: Vec<_> = merged[0].see_also.iter().map(|t| t.get().name).collect();
+//! assert_eq!((merged.len(), related), (3, vec!["node", "edge"]));
+//! ```
+
+use std::{collections::HashMap, fmt::Debug, hash::Hash, rc::Rc};
+
+use thiserror::Error;
+
+use crate::{Deferred, DeferredError, HasNeighbors, SetOnce};
+
+/// A problem found by [`merge`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum MergeError<K> {
+    #[error("Nodes with key {key:?} conflict: {reason}!")]
+    Conflict { key: K, reason: String },
+    #[error("Edges of the node with key {key:?} could not be wired: {source}")]
+    Wiring { key: K, source: DeferredError },
+}
+
+/// Serializes as a map of the error's `kind`, its `message`, the `key`, and the
+/// conflict's `reason` or the wiring `error`.
+#[cfg(feature = "serde")]
+impl<K: Debug + serde::Serialize> serde::Serialize for MergeError<K> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            Self::Conflict { key, reason } => {
+                map.serialize_entry("kind", "conflict")?;
+                map.serialize_entry("message", &self.to_string())?;
+                map.serialize_entry("key", key)?;
+                map.serialize_entry("reason", reason)?;
+            }
+            Self::Wiring { key, source } => {
+                map.serialize_entry("kind", "wiring")?;
+                map.serialize_entry("message", &self.to_string())?;
+                map.serialize_entry("key", key)?;
+                map.serialize_entry("error", source)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// Builds the union of `a` and `b`, unifying nodes for which `key` is equal.
+///
+/// Each merged node is made by `merge_payload(first, None, degree)` from the first node
+/// with its key, then passed back as `merge_payload(merged, Some(other), degree)` with
+/// each other node sharing the key, in turn. It must have `degree` unset cells, which
+/// are wired through `slot(node, k)` to the targets of every edge of the nodes it
+/// unifies, without repeats, in the order they appear. Edges leading to nodes of
+/// neither graph are followed by key, and dropped if no node has that key.
+///
+/// Nodes are returned in the order their keys first appear, in `a` and then `b`.
+pub fn merge<T, K>(
+    a: &[Rc<T>],
+    b: &[Rc<T>],
+    key: impl Fn(&T) -> K,
+    mut merge_payload: impl FnMut(&T, Option<&T>, usize) -> Result<T, String>,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, Vec<MergeError<K>>>
+where
+    T: HasNeighbors,
+    K: Eq + Hash + Clone + Debug,
+{
+    let mut index = HashMap::new();
+    let mut groups: Vec<(K, Vec<&Rc<T>>)> = Vec::new();
+    for node in a.iter().chain(b) {
+        let key = key(node);
+        let group = *index.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[group].1.push(node);
+    }
+    let targets: Vec<Vec<usize>> = groups
+        .iter()
+        .map(|(_, members)| {
+            let mut targets = Vec::new();
+            let cells = members.iter().flat_map(|node| node.neighbors());
+            for target in cells.filter_map(|cell| cell.try_get().ok()) {
+                if let Some(&group) = index.get(&key(&target)) {
+                    if !targets.contains(&group) {
+                        targets.push(group);
+                    }
+                }
+            }
+            targets
+        })
+        .collect();
+
+    let mut errors = Vec::new();
+    let mut nodes = Vec::new();
+    for ((key, members), targets) in groups.iter().zip(&targets) {
+        let degree = targets.len();
+        let merged = merge_payload(members[0], None, degree).and_then(|first| {
+            members[1..].iter().try_fold(first, |merged, other| {
+                merge_payload(&merged, Some(other), degree)
+            })
+        });
+        match merged {
+            Ok(node) => nodes.push(Rc::new(node)),
+            Err(reason) => errors.push(MergeError::Conflict {
+                key: key.clone(),
+                reason,
+            }),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    for (from, targets) in targets.iter().enumerate() {
+        for (k, &to) in targets.iter().enumerate() {
+            if let Err(source) = SetOnce::from(slot(&nodes[from], k)).try_set(&nodes[to]) {
+                errors.push(MergeError::Wiring {
+                    key: groups[from].0.clone(),
+                    source,
+                });
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(nodes)
+    } else {
+        Err(errors)
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Node {
+        id: u8,
+        value: Option<u8>,
+        edges: Vec<Deferred<Self>>,
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.edges.iter()
+        }
+    }
+
+    fn graph(nodes: &[(u8, Option<u8>)], edges: &[(usize, usize)]) -> Vec<Rc<Node>> {
+        let degree = |i| edges.iter().filter(|&&(from, _)| from == i).count();
+        let nodes: Vec<_> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, &(id, value))| {
+                Rc::new(Node {
+                    id,
+                    value,
+                    edges: (0..degree(i)).map(|_| Deferred::new()).collect(),
+                })
+            })
+            .collect();
+        let mut next = vec![0; nodes.len()];
+        for &(from, to) in edges {
+            SetOnce::from(&nodes[from].edges[next[from]])
+                .try_set(&nodes[to])
+                .unwrap();
+            next[from] += 1;
+        }
+        nodes
+    }
+
+    /// Keeps whichever value is known, refusing two different ones.
+    fn merge_values(node: &Node, other: Option<&Node>, degree: usize) -> Result<Node, String> {
+        let value = match (node.value, other.and_then(|o| o.value)) {
+            (Some(a), Some(b)) if a != b => return Err(format!("{a} != {b}")),
+            (a, b) => a.or(b),
+        };
+        Ok(Node {
+            id: node.id,
+            value,
+            edges: (0..degree).map(|_| Deferred::new()).collect(),
+        })
+    }
+
+    fn slot(node: &Node, k: usize) -> &Deferred<Node> {
+        &node.edges[k]
+    }
+
+    #[test]
+    fn equal_keys_are_unified() {
+        let a = graph(&[(1, Some(10)), (2, None), (3, None)], &[(0, 1), (1, 0)]);
+        let b = graph(&[(2, Some(20)), (1, None), (4, None)], &[(0, 1), (1, 2)]);
+        let merged = merge(&a, &b, |n| n.id, merge_values, slot).unwrap();
+        let ids = |node: &Node| node.edges.iter().map(|e| e.get().id).collect::<Vec<_>>();
+        assert_eq!(
+            merged.iter().map(|n| n.id).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        assert_eq!((merged[1].value, ids(&merged[1])), (Some(20), vec![1]));
+        assert_eq!(ids(&merged[0]), [2, 4]);
+    }
+    #[test]
+    fn conflicting_nodes_are_reported() {
+        let a = graph(&[(1, Some(10)), (2, None), (3, None)], &[(0, 1), (1, 0)]);
+        let c = graph(&[(1, Some(11)), (3, Some(3)), (3, Some(4))], &[]);
+        let errors: Vec<_> = merge(&a, &c, |n| n.id, merge_values, slot)
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "Nodes with key 1 conflict: 10 != 11!",
+                "Nodes with key 3 conflict: 3 != 4!"
+            ]
+        );
+    }
+}
@@ -0,0 +1,34 @@
+//! Per-node data kept outside the nodes.
+//!
+//! Analyses often need scratch data per node, such as colors, distances, or visit
+//! marks, that has no place in the node struct. A [`NodeMetaMap`] attaches it by node
+//! identity instead, so nodes need no extra fields or interior mutability. It is a
+//! [`WeakKeyMap`], so entries whose node has been dropped are pruned automatically as
+//! the map grows, or on demand with [`prune`](WeakKeyMap::prune).
+//!
+//! ```
+//! use deferred_cell::{Deferred, meta::NodeMetaMap};
+//! use std::rc::Rc;
+//!
+//! struct Node {
+//!     next: Deferred<Node>,
+//! }
+//!
+//! let a = Rc::new(Node { next: Deferred::new() });
+//! let b = Rc::new(Node { next: Deferred::from(&a) });
+//!
+//! let mut distance = NodeMetaMap::new();
+//! distance.insert(&b, 0);
+//! let next = b.next.get();
+//! distance.insert(&next, distance.get(&b).unwrap() + 1);
+//! assert_eq!(distance.get(&a), Some(&1));
+//!
+//! drop((a, next));
+//! distance.prune();
+//! assert_eq!(distance.len(), 1);
+//! ```
+
+use crate::weak_map::WeakKeyMap;
+
+/// Maps nodes of type `T`, by identity, to metadata of type `M`.
+pub type NodeMetaMap<T, M> = WeakKeyMap<T, M>;
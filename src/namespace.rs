@@ -0,0 +1,404 @@
+//! Several independent graphs under one owner, kept apart by namespace.
+//!
+//! Applications that open several documents at once want one graph per document, with
+//! no edge silently leading from one into another. A [`GraphManager`] holds a
+//! [`HandleTable`] per named namespace and hands out [`NamespacedHandle`]s, which name
+//! both the namespace and the node. Wiring a cell to a node in another namespace fails
+//! with [`NamespaceError::CrossNamespace`] unless the two are joined with
+//! [`bridge`](GraphManager::bridge), which allows edges in one direction only.
+//!
+//! [`drop_namespace`](GraphManager::drop_namespace) drops every node of a namespace at
+//! once, such as when its document is closed. Handles to it are rejected from then on,
+//! even once its name is reused, and cells elsewhere that pointed into it fail to read
+//! like any cell whose target was dropped.
+//!
+//! ```
+//! use deferred_cell::{Deferred, HasNeighbors, namespace::{GraphManager, NamespaceError}};
+//! use std::rc::Rc;
+//!
+//! struct Section {
+//!     link: Deferred<Section>,
+//! }
+//!
+//! impl HasNeighbors for Section {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         std::iter::once(&self.link)
+//!     }
+//! }
+//!
+//! let section = || Rc::new(Section { link: Deferred::new() });
+//! let mut documents = GraphManager::new();
+//! let report = documents.create("report").unwrap();
+//! let notes = documents.create("notes").unwrap();
+//! let intro = documents.insert(report, section()).unwrap();
+//! let summary = documents.insert(report, section()).unwrap();
+//! let draft = documents.insert(notes, section()).unwrap();
+//!
+//! documents.try_set(intro, 0, summary).unwrap();
+//! assert!(matches!(
+//!     documents.try_set(draft, 0, summary),
+//!     Err(NamespaceError::CrossNamespace { .. })
+//! ));
+//! documents.bridge(notes, report).unwrap();
+//! documents.try_set(draft, 0, summary).unwrap();
+//! assert_eq!(documents.try_get(draft, 0).unwrap(), summary);
+//!
+//! assert!(documents.drop_namespace(report));
+//! assert!(documents.get(intro).is_err());
+//! assert!(documents.try_get(draft, 0).is_err());
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+use crate::{
+    DeferredError, HasNeighbors, SetOnce,
+    handle::{CellHandle, HandleTable, NodeHandle},
+};
+
+/// An opaque reference to a namespace of a [`GraphManager`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NamespaceId {
+    index: u32,
+    generation: u32,
+}
+
+impl fmt::Display for NamespaceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}v{}", self.index, self.generation)
+    }
+}
+
+/// A node of a [`GraphManager`], named by its namespace and its handle within it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NamespacedHandle {
+    namespace: NamespaceId,
+    node: NodeHandle,
+}
+
+impl NamespacedHandle {
+    #[must_use]
+    pub const fn namespace(&self) -> NamespaceId {
+        self.namespace
+    }
+    #[must_use]
+    pub const fn node(&self) -> NodeHandle {
+        self.node
+    }
+}
+
+impl fmt::Display for NamespacedHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.namespace, self.node)
+    }
+}
+
+/// Errors returned by [`GraphManager`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum NamespaceError {
+    #[error("Namespace {0:?} already exists!")]
+    DuplicateName(String),
+    #[error("Namespace {0} no longer exists!")]
+    Dropped(NamespaceId),
+    #[error("Cannot wire namespace {from:?} to namespace {to:?} without a bridge!")]
+    CrossNamespace { from: String, to: String },
+    #[error(transparent)]
+    Wiring(#[from] DeferredError),
+}
+
+struct Space<T> {
+    generation: u32,
+    /// The name and nodes of the namespace, or `None` once dropped.
+    live: Option<(String, HandleTable<T>)>,
+}
+
+/// Owns several named graphs and keeps edges from crossing between them.
+pub struct GraphManager<T> {
+    spaces: Vec<Space<T>>,
+    names: HashMap<String, NamespaceId>,
+    /// Pairs of namespaces whose nodes may point from the first into the second.
+    bridges: HashSet<(NamespaceId, NamespaceId)>,
+}
+
+impl<T> Default for GraphManager<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> GraphManager<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            spaces: Vec::new(),
+            names: HashMap::new(),
+            bridges: HashSet::new(),
+        }
+    }
+    /// Creates an empty namespace called `name`.
+    ///
+    /// # Panics
+    /// Panics if the manager would hold more than `u32::MAX` namespaces.
+    pub fn create(&mut self, name: impl Into<String>) -> Result<NamespaceId, NamespaceError> {
+        let name = name.into();
+        if self.names.contains_key(&name) {
+            return Err(NamespaceError::DuplicateName(name));
+        }
+        let index = self
+            .spaces
+            .iter()
+            .position(|space| space.live.is_none())
+            .unwrap_or_else(|| {
+                self.spaces.push(Space {
+                    generation: 0,
+                    live: None,
+                });
+                self.spaces.len() - 1
+            });
+        let space = &mut self.spaces[index];
+        let id = NamespaceId {
+            #[allow(clippy::expect_used)]
+            index: u32::try_from(index).expect("managers hold at most u32::MAX namespaces"),
+            generation: space.generation,
+        };
+        space.live = Some((name.clone(), HandleTable::new()));
+        self.names.insert(name, id);
+        Ok(id)
+    }
+    /// Returns the namespace called `name`.
+    #[must_use]
+    pub fn namespace(&self, name: &str) -> Option<NamespaceId> {
+        self.names.get(name).copied()
+    }
+    /// Returns the name of `namespace`, if it still exists.
+    #[must_use]
+    pub fn name(&self, namespace: NamespaceId) -> Option<&str> {
+        self.space(namespace).ok().map(|(name, _)| name.as_str())
+    }
+    /// Returns every namespace that still exists, in the order of their slots.
+    pub fn namespaces(&self) -> impl Iterator<Item = (NamespaceId, &str)> {
+        self.spaces.iter().zip(0..).filter_map(|(space, index)| {
+            let (name, _) = space.live.as_ref()?;
+            let id = NamespaceId {
+                index,
+                generation: space.generation,
+            };
+            Some((id, name.as_str()))
+        })
+    }
+    /// Returns the nodes of `namespace`.
+    pub fn table(&self, namespace: NamespaceId) -> Result<&HandleTable<T>, NamespaceError> {
+        self.space(namespace).map(|(_, table)| table)
+    }
+    /// Stores `node` in `namespace` and returns its handle.
+    pub fn insert(
+        &mut self,
+        namespace: NamespaceId,
+        node: Rc<T>,
+    ) -> Result<NamespacedHandle, NamespaceError> {
+        let (_, table) = self.space_mut(namespace)?;
+        Ok(NamespacedHandle {
+            namespace,
+            node: table.insert(node),
+        })
+    }
+    /// Returns the node `handle` refers to.
+    pub fn get(&self, handle: NamespacedHandle) -> Result<&Rc<T>, NamespaceError> {
+        Ok(self.table(handle.namespace)?.get(handle.node)?)
+    }
+    /// Allows nodes of `from` to point at nodes of `to`. Edges the other way still need
+    /// a bridge of their own.
+    pub fn bridge(&mut self, from: NamespaceId, to: NamespaceId) -> Result<(), NamespaceError> {
+        self.space(from)?;
+        self.space(to)?;
+        self.bridges.insert((from, to));
+        Ok(())
+    }
+    /// Returns whether nodes of `from` may point at nodes of `to`.
+    #[must_use]
+    pub fn is_bridged(&self, from: NamespaceId, to: NamespaceId) -> bool {
+        from == to || self.bridges.contains(&(from, to))
+    }
+    /// Drops every node of `namespace` and its bridges, and frees its name. Returns
+    /// whether it existed.
+    pub fn drop_namespace(&mut self, namespace: NamespaceId) -> bool {
+        if self.space(namespace).is_err() {
+            return false;
+        }
+        let space = &mut self.spaces[namespace.index as usize];
+        if let Some((name, _)) = space.live.take() {
+            self.names.remove(&name);
+        }
+        space.generation = space.generation.wrapping_add(1);
+        self.bridges
+            .retain(|&(from, to)| from != namespace && to != namespace);
+        true
+    }
+    fn space(&self, namespace: NamespaceId) -> Result<&(String, HandleTable<T>), NamespaceError> {
+        self.spaces
+            .get(namespace.index as usize)
+            .filter(|space| space.generation == namespace.generation)
+            .and_then(|space| space.live.as_ref())
+            .ok_or(NamespaceError::Dropped(namespace))
+    }
+    fn space_mut(
+        &mut self,
+        namespace: NamespaceId,
+    ) -> Result<&mut (String, HandleTable<T>), NamespaceError> {
+        self.spaces
+            .get_mut(namespace.index as usize)
+            .filter(|space| space.generation == namespace.generation)
+            .and_then(|space| space.live.as_mut())
+            .ok_or(NamespaceError::Dropped(namespace))
+    }
+}
+
+impl<T: HasNeighbors> GraphManager<T> {
+    /// Points the [`HasNeighbors`] cell at position `cell` of `from` at `to`.
+    ///
+    /// Fails if `to` is in another namespace that `from`'s is not bridged to.
+    pub fn try_set(
+        &self,
+        from: NamespacedHandle,
+        cell: u32,
+        to: NamespacedHandle,
+    ) -> Result<(), NamespaceError> {
+        let (from_name, source) = self.space(from.namespace)?;
+        let (to_name, targets) = self.space(to.namespace)?;
+        if !self.is_bridged(from.namespace, to.namespace) {
+            return Err(NamespaceError::CrossNamespace {
+                from: from_name.clone(),
+                to: to_name.clone(),
+            });
+        }
+        let target = targets.get(to.node)?;
+        let cell = CellHandle::new(from.node, cell);
+        Ok(source.with_cell(cell, |c| SetOnce::from(c).try_set(target))?)
+    }
+    /// Returns the handle of the node the cell at position `cell` of `from` points at.
+    ///
+    /// Fails if the cell is unset, or its target is no longer in `from`'s namespace or
+    /// one it is bridged to.
+    pub fn try_get(
+        &self,
+        from: NamespacedHandle,
+        cell: u32,
+    ) -> Result<NamespacedHandle, NamespaceError> {
+        let (_, source) = self.space(from.namespace)?;
+        let cell = CellHandle::new(from.node, cell);
+        let target = source.with_cell(cell, |c| c.try_get())?;
+        let reachable = std::iter::once(from.namespace).chain(
+            self.bridges
+                .iter()
+                .filter(|(bridged, _)| *bridged == from.namespace)
+                .map(|&(_, to)| to),
+        );
+        for namespace in reachable {
+            if let Some(node) = self.table(namespace)?.handle_of(&target) {
+                return Ok(NamespacedHandle { namespace, node });
+            }
+        }
+        Err(DeferredError::OutsideGraph()
+            .context(format!("{cell}: target is not in a reachable namespace"))
+            .into())
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Deferred;
+
+    struct Node {
+        links: [Deferred<Self>; 2],
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.links.iter()
+        }
+    }
+
+    fn node() -> Rc<Node> {
+        Rc::new(Node {
+            links: [Deferred::new(), Deferred::new()],
+        })
+    }
+
+    #[test]
+    fn namespace_names_are_unique() {
+        let mut manager = GraphManager::<Node>::new();
+        let a = manager.create("a").unwrap();
+        assert!(matches!(
+            manager.create("a"),
+            Err(NamespaceError::DuplicateName(name)) if name == "a"
+        ));
+        assert_eq!(manager.name(a), Some("a"));
+    }
+    #[test]
+    fn edges_between_namespaces_need_a_bridge() {
+        let mut manager = GraphManager::new();
+        let (a, b) = (manager.create("a").unwrap(), manager.create("b").unwrap());
+        let (a0, a1) = (
+            manager.insert(a, node()).unwrap(),
+            manager.insert(a, node()).unwrap(),
+        );
+        let b0 = manager.insert(b, node()).unwrap();
+        manager.try_set(a0, 0, a1).unwrap();
+        assert_eq!(
+            manager.try_set(a0, 1, b0).unwrap_err().to_string(),
+            r#"Cannot wire namespace "a" to namespace "b" without a bridge!"#
+        );
+        manager.bridge(a, b).unwrap();
+        manager.try_set(a0, 1, b0).unwrap();
+        assert_eq!(manager.try_get(a0, 1).unwrap(), b0);
+        assert!(manager.try_set(b0, 0, a0).is_err());
+        assert_eq!(manager.table(a).unwrap().len(), 2);
+    }
+    #[test]
+    fn dropped_namespaces_take_their_nodes_and_bridges() {
+        let mut manager = GraphManager::new();
+        let (a, b) = (manager.create("a").unwrap(), manager.create("b").unwrap());
+        let (a0, a1) = (
+            manager.insert(a, node()).unwrap(),
+            manager.insert(a, node()).unwrap(),
+        );
+        let b0 = manager.insert(b, node()).unwrap();
+        manager.bridge(a, b).unwrap();
+        manager.try_set(a0, 0, a1).unwrap();
+        manager.try_set(a0, 1, b0).unwrap();
+
+        assert!(manager.drop_namespace(b));
+        assert!(!manager.drop_namespace(b));
+        assert!(!manager.is_bridged(a, b));
+        assert!(matches!(
+            manager.insert(b, node()),
+            Err(NamespaceError::Dropped(_))
+        ));
+        assert!(manager.try_get(a0, 1).is_err());
+        assert_eq!(manager.try_get(a0, 0).unwrap(), a1);
+    }
+    #[test]
+    fn reused_namespaces_do_not_revive_old_handles() {
+        let mut manager = GraphManager::new();
+        manager.create("a").unwrap();
+        let b = manager.create("b").unwrap();
+        let b0 = manager.insert(b, node()).unwrap();
+        manager.drop_namespace(b);
+
+        let reused = manager.create("b").unwrap();
+        assert_eq!(reused.index, b.index);
+        assert_ne!(reused, b);
+        assert!(manager.get(b0).is_err());
+        let names: Vec<_> = manager.namespaces().map(|(_, name)| name).collect();
+        assert_eq!(names, ["a", "b"]);
+    }
+}
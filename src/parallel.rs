@@ -0,0 +1,225 @@
+//! Rayon-powered helpers for building large `Arc` based graphs.
+//!
+//! Construction is split in two phases: nodes are created in parallel from their
+//! source records with [`par_build`], then wired with either [`wire_sequential`] or
+//! [`par_wire_sharded`]. In both wiring modes every node wires only its own cells, so
+//! the resulting graph does not depend on thread scheduling. Both wire every node and
+//! return every failure in an [`InitErrors`], each error naming its node.
+//!
+//! ```
+//! use deferred_cell::{DeferredSync, SetOnceSync, parallel};
+//!
+//! struct Node {
+//!     id: usize,
+//!     next: DeferredSync<Self>,
+//! }
+//!
+//! let records: Vec<usize> = (0..1_000).collect();
+//! let nodes = parallel::par_build(&records, |&id| Node {
+//!     id,
+//!     next: DeferredSync::default(),
+//! });
+//! parallel::par_wire_sharded(&nodes, |i, node, nodes| {
+//!     SetOnceSync::from(&node.next).try_set(&nodes[(i + 1) % nodes.len()])
+//! })
+//! .unwrap();
+//! assert_eq!(nodes[999].next.get().id, 0);
+//! ```
+
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::{DeferredError, DeferredSync, InitErrors, diagnostics};
+
+/// Creates one `Arc` node per record, in parallel, preserving record order.
+pub fn par_build<R, T, F>(records: &[R], make_node: F) -> Vec<Arc<T>>
+where
+    R: Sync,
+    T: Send + Sync,
+    F: Fn(&R) -> T + Sync + Send,
+{
+    records.par_iter().map(|r| Arc::new(make_node(r))).collect()
+}
+
+/// Wires each node in index order.
+///
+/// `wire` receives the node index, the node, and the full node list. Every node is
+/// wired even after a failure, and the failures are returned in index order, each
+/// annotated with its node as `node {index}`.
+pub fn wire_sequential<T, F>(nodes: &[Arc<T>], mut wire: F) -> Result<(), InitErrors>
+where
+    F: FnMut(usize, &T, &[Arc<T>]) -> Result<(), DeferredError>,
+{
+    nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, node)| wire(i, node, nodes).err().map(|e| node_error(i, e)))
+        .collect::<InitErrors>()
+        .into_result()
+}
+
+/// Wires nodes in parallel shards.
+///
+/// `wire` must only set cells owned by the node it is given. Failures are returned as
+/// [`wire_sequential`] returns them, in index order whatever the thread scheduling.
+pub fn par_wire_sharded<T, F>(nodes: &[Arc<T>], wire: F) -> Result<(), InitErrors>
+where
+    T: Send + Sync,
+    F: Fn(usize, &T, &[Arc<T>]) -> Result<(), DeferredError> + Sync + Send,
+{
+    let context = diagnostics::WorkerContext::capture();
+    // `collect` keeps the order of an indexed parallel iterator.
+    nodes
+        .par_iter()
+        .enumerate()
+        .filter_map(|(i, node)| {
+            let _context = context.enter();
+            wire(i, node, nodes).err().map(|e| node_error(i, e))
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .collect::<InitErrors>()
+        .into_result()
+}
+
+fn node_error(index: usize, error: DeferredError) -> DeferredError {
+    error.context(format!("node {index}"))
+}
+
+/// Parallel iterator extension trait for collections of [`DeferredSync<T>`].
+pub trait ParallelDeferredIteratorExt<T>: ParallelIterator<Item = DeferredSync<T>>
+where
+    T: Send + Sync,
+{
+    /// Returns a parallel iterator of `Arc<T>` from a parallel iterator of `DeferredSync<T>`.
+    ///
+    /// # Panics
+    /// Panics if any `DeferredSync<T>` is not initialized.
+    fn get_deferred(self) -> impl ParallelIterator<Item = Arc<T>> {
+        self.map(|d| d.get())
+    }
+    /// Returns a parallel iterator of `Result<Arc<T>, DeferredError>`.
+    fn try_get_deferred(self) -> impl ParallelIterator<Item = Result<Arc<T>, DeferredError>> {
+        self.map(|d| d.try_get())
+    }
+}
+
+impl<T, I> ParallelDeferredIteratorExt<T> for I
+where
+    T: Send + Sync,
+    I: ParallelIterator<Item = DeferredSync<T>>,
+{
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SetOnceSync;
+
+    #[derive(Clone)]
+    struct Node {
+        id: usize,
+        next: DeferredSync<Self>,
+    }
+
+    fn build(n: usize) -> Vec<Arc<Node>> {
+        let records: Vec<usize> = (0..n).collect();
+        par_build(&records, |&id| Node {
+            id,
+            next: DeferredSync::default(),
+        })
+    }
+
+    #[test]
+    fn sharded_matches_sequential() {
+        let sequential = build(10_000);
+        let sharded = build(10_000);
+        wire_sequential(&sequential, |i, node, nodes| {
+            SetOnceSync::from(&node.next).try_set(&nodes[(i + 7) % nodes.len()])
+        })
+        .unwrap();
+        par_wire_sharded(&sharded, |i, node, nodes| {
+            SetOnceSync::from(&node.next).try_set(&nodes[(i + 7) % nodes.len()])
+        })
+        .unwrap();
+        for (a, b) in sequential.iter().zip(&sharded) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.next.get().id, b.next.get().id);
+        }
+    }
+    #[test]
+    fn wiring_errors_are_reported() {
+        let nodes = build(1_000);
+        SetOnceSync::from(&nodes[10].next)
+            .try_set(&nodes[0])
+            .unwrap();
+        SetOnceSync::from(&nodes[20].next)
+            .try_set(&nodes[0])
+            .unwrap();
+        let errors = wire_sequential(&nodes, |_, node, nodes| {
+            SetOnceSync::from(&node.next).try_set(&nodes[0])
+        })
+        .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors
+                .iter()
+                .all(|e| matches!(e.root_cause(), DeferredError::DuplicateInitialization()))
+        );
+        assert!(
+            errors
+                .iter()
+                .next()
+                .unwrap()
+                .to_string()
+                .starts_with("node 10: ")
+        );
+        assert!(nodes[11].next.is_ready());
+    }
+    #[test]
+    fn sharded_wiring_collects_every_error_in_index_order() {
+        let nodes = build(1_000);
+        for i in [900, 3, 450] {
+            SetOnceSync::from(&nodes[i].next)
+                .try_set(&nodes[0])
+                .unwrap();
+        }
+        let errors = par_wire_sharded(&nodes, |i, node, nodes| {
+            SetOnceSync::from(&node.next).try_set(&nodes[i])
+        })
+        .unwrap_err();
+        let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(messages.len(), 3);
+        for (message, i) in messages.iter().zip([3, 450, 900]) {
+            assert!(message.starts_with(&format!("node {i}: ")));
+        }
+        assert!(nodes[901].next.is_ready());
+    }
+    #[test]
+    fn parallel_iterator_extension_works() {
+        let nodes = build(100);
+        par_wire_sharded(&nodes, |i, node, nodes| {
+            SetOnceSync::from(&node.next).try_set(&nodes[(i + 1) % nodes.len()])
+        })
+        .unwrap();
+        let ids: Vec<_> = nodes
+            .iter()
+            .map(|n| n.next.clone())
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .get_deferred()
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(ids[0], 1);
+        assert_eq!(ids[99], 0);
+        assert!(
+            vec![DeferredSync::<Node>::default()]
+                .into_par_iter()
+                .try_get_deferred()
+                .all(|r| r.is_err())
+        );
+    }
+}
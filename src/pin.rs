@@ -0,0 +1,143 @@
+//! Cells whose targets stay pinned.
+//!
+//! Some nodes hold self-referential futures or buffers that must never move once they
+//! are wired. An `Rc` allocation never moves on its own, but anyone holding an `Rc<T>`
+//! can move the value out with [`Rc::try_unwrap`] or [`Rc::get_mut`], so a plain
+//! [`Deferred`] cannot promise that its target stays put. A [`PinnedDeferred`] is only
+//! set from a [`Pin<Rc<T>>`], such as one made by [`Rc::pin`], and only ever hands out
+//! [`Pin<Rc<T>>`]s, so a target wired through it stays pinned until it is dropped.
+//!
+//! Pinning is not structural for the cell itself: it holds a weak pointer, not the
+//! target, so a node may move its `PinnedDeferred` fields freely, and projecting a
+//! `Pin<&Node>` to `&PinnedDeferred<T>` is always sound. It is `Unpin` whatever `T` is.
+//!
+//! ```
+//! use deferred_cell::pin::PinnedDeferred;
+//! use std::{marker::PhantomPinned, pin::Pin, rc::Rc};
+//!
+//! struct Buffer {
+//!     data: [u8; 4],
+//!     reader: PinnedDeferred<Buffer>,
+//!     _pinned: PhantomPinned,
+//! }
+//!
+//! let buffer = Rc::pin(Buffer {
+//!     data: [1, 2, 3, 4],
+//!     reader: PinnedDeferred::new(),
+//!     _pinned: PhantomPinned,
+//! });
+//! buffer.reader.try_set(&buffer).unwrap();
+//!
+//! let target: Pin<Rc<Buffer>> = buffer.reader.get();
+//! assert_eq!(target.data.as_ptr(), buffer.data.as_ptr());
+//! ```
+
+use std::{pin::Pin, rc::Rc};
+
+use crate::{Deferred, DeferredError, SetOnce};
+
+/// A write-once, weak reference to a pinned target.
+#[derive(Debug, Clone)]
+pub struct PinnedDeferred<T>(Deferred<T>);
+
+impl<T> Default for PinnedDeferred<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<&Pin<Rc<T>>> for PinnedDeferred<T> {
+    /// Creates a cell that is already set to `value`.
+    fn from(value: &Pin<Rc<T>>) -> Self {
+        Self(Deferred::from(&unpinned(value)))
+    }
+}
+
+impl<T> PinnedDeferred<T> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(Deferred::new())
+    }
+    /// Sets the cell to `value`, or fails if it is already set.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn try_set(&self, value: &Pin<Rc<T>>) -> Result<(), DeferredError> {
+        SetOnce::from(&self.0).try_set(&unpinned(value))
+    }
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn try_get(&self) -> Result<Pin<Rc<T>>, DeferredError> {
+        self.0.try_get().map(pinned)
+    }
+    /// Returns the cell's target.
+    ///
+    /// # Panics
+    /// Panics if the cell is not set or its target was dropped, unless a
+    /// [`failure`](crate::failure) handler is installed.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[must_use]
+    pub fn get(&self) -> Pin<Rc<T>> {
+        pinned(self.0.get())
+    }
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.0.is_ready()
+    }
+}
+
+/// Unwraps a pinned `Rc` so it can be downgraded into the inner cell.
+fn unpinned<T>(value: &Pin<Rc<T>>) -> Rc<T> {
+    // SAFETY: the `Rc` is only downgraded to a `Weak`, which cannot move the value, and
+    // every `Rc` upgraded from it is pinned again by `pinned` before it is handed out.
+    unsafe { Pin::into_inner_unchecked(Pin::clone(value)) }
+}
+
+/// Pins an `Rc` upgraded from a cell.
+const fn pinned<T>(target: Rc<T>) -> Pin<Rc<T>> {
+    // SAFETY: the cell is only set from pinned `Rc`s, so its target was pinned already,
+    // and `Rc` allocations do not move.
+    unsafe { Pin::new_unchecked(target) }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use std::marker::PhantomPinned;
+
+    use super::*;
+
+    struct Node {
+        id: u8,
+        next: PinnedDeferred<Self>,
+        _pinned: PhantomPinned,
+    }
+
+    fn node(id: u8) -> Pin<Rc<Node>> {
+        Rc::pin(Node {
+            id,
+            next: PinnedDeferred::new(),
+            _pinned: PhantomPinned,
+        })
+    }
+
+    #[test]
+    fn targets_stay_pinned() {
+        let (a, b) = (node(1), node(2));
+        assert!(matches!(
+            a.next.try_get().err().unwrap().root_cause(),
+            DeferredError::NotInitializedError()
+        ));
+        a.next.try_set(&b).unwrap();
+        assert!(matches!(
+            a.next.try_set(&a).unwrap_err().root_cause(),
+            DeferredError::DuplicateInitialization()
+        ));
+        let target = a.next.get();
+        assert_eq!(target.id, 2);
+        assert!(std::ptr::eq(&*target, &*b));
+
+        let back = PinnedDeferred::from(&a);
+        assert!(back.is_ready());
+        drop((a, target));
+        assert!(back.try_get().is_err());
+    }
+}
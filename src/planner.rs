@@ -0,0 +1,335 @@
+//! Planning the order in which modular builders run.
+//!
+//! Large systems assemble their graph from many builders, each producing some keys and
+//! linking to keys produced by others. A [`Planner`] takes those declarations and
+//! computes a [`Plan`]: every builder after the builders it links to, with ties broken
+//! by declaration order so the result is deterministic. Builders that link to each
+//! other in a cycle cannot be ordered; they are grouped into a [`Step::TwoPhase`] step,
+//! whose builders must all create their nodes before any of them wires its links.
+//!
+//! ```
+//! use deferred_cell::planner::{Planner, Step};
+//!
+//! let plan = Planner::new()
+//!     .builder("routes", ["router"], ["db", "auth"])
+//!     .builder("auth", ["auth"], ["session"])
+//!     .builder("sessions", ["session"], ["auth"])
+//!     .builder("storage", ["db"], [])
+//!     .plan()
+//!     .unwrap();
+//!
+//! assert_eq!(
+//!     plan.steps,
+//!     [
+//!         Step::TwoPhase(vec!["auth".into(), "sessions".into()]),
+//!         Step::Build("storage".into()),
+//!         Step::Build("routes".into()),
+//!     ]
+//! );
+//! ```
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, hash_map::Entry},
+    hash::Hash,
+};
+
+use thiserror::Error;
+
+/// A problem found by [`Planner::plan`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PlanError<K> {
+    #[error("Key {key:?} is produced by both {first} and {second}!")]
+    DuplicateKey {
+        key: K,
+        first: String,
+        second: String,
+    },
+    #[error("Builder {builder} links to key {key:?}, which no builder produces!")]
+    UnknownKey { builder: String, key: K },
+}
+
+/// Serializes as a map of the error's `kind`, its `message`, the `key`, and the
+/// `first` and `second` builders producing it or the `builder` linking to it.
+#[cfg(feature = "serde")]
+impl<K: std::fmt::Debug + serde::Serialize> serde::Serialize for PlanError<K> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            Self::DuplicateKey { key, first, second } => {
+                map.serialize_entry("kind", "duplicate_key")?;
+                map.serialize_entry("message", &self.to_string())?;
+                map.serialize_entry("key", key)?;
+                map.serialize_entry("first", first)?;
+                map.serialize_entry("second", second)?;
+            }
+            Self::UnknownKey { builder, key } => {
+                map.serialize_entry("kind", "unknown_key")?;
+                map.serialize_entry("message", &self.to_string())?;
+                map.serialize_entry("key", key)?;
+                map.serialize_entry("builder", builder)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// One step of a [`Plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Runs one builder, whose links all lead to keys built in earlier steps.
+    Build(String),
+    /// Runs builders that link to each other in a cycle, in declaration order: each
+    /// creates its nodes first, then each wires its links.
+    TwoPhase(Vec<String>),
+}
+
+/// The order in which to run the builders of a [`Planner`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    /// Returns `true` if no step needs the two-phase approach.
+    #[must_use]
+    pub fn is_acyclic(&self) -> bool {
+        self.steps.iter().all(|step| matches!(step, Step::Build(_)))
+    }
+    /// Returns every builder, in the order they run.
+    pub fn order(&self) -> impl Iterator<Item = &str> {
+        self.steps
+            .iter()
+            .flat_map(|step| match step {
+                Step::Build(name) => std::slice::from_ref(name),
+                Step::TwoPhase(names) => names.as_slice(),
+            })
+            .map(String::as_str)
+    }
+}
+
+struct Builder<K> {
+    name: String,
+    produces: Vec<K>,
+    links: Vec<K>,
+}
+
+/// Collects the keys each builder produces and links to.
+pub struct Planner<K> {
+    builders: Vec<Builder<K>>,
+}
+
+impl<K> Default for Planner<K> {
+    fn default() -> Self {
+        Self {
+            builders: Vec::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone> Planner<K> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Declares the builder `name`, which produces the keys `produces` and links to the
+    /// keys `links`.
+    #[must_use]
+    pub fn builder(
+        mut self,
+        name: impl Into<String>,
+        produces: impl IntoIterator<Item = K>,
+        links: impl IntoIterator<Item = K>,
+    ) -> Self {
+        self.builders.push(Builder {
+            name: name.into(),
+            produces: produces.into_iter().collect(),
+            links: links.into_iter().collect(),
+        });
+        self
+    }
+    /// Computes the order in which to run the builders.
+    ///
+    /// Fails with every key produced twice, then every link to a key no builder
+    /// produces, in declaration order. A builder linking to its own keys is not a cycle.
+    pub fn plan(&self) -> Result<Plan, Vec<PlanError<K>>> {
+        let mut errors = Vec::new();
+        let mut producers = HashMap::new();
+        for (index, builder) in self.builders.iter().enumerate() {
+            for key in &builder.produces {
+                match producers.entry(key) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(index);
+                    }
+                    Entry::Occupied(entry) => errors.push(PlanError::DuplicateKey {
+                        key: key.clone(),
+                        first: self.builders[*entry.get()].name.clone(),
+                        second: builder.name.clone(),
+                    }),
+                }
+            }
+        }
+        // `after[a]` lists the builders that link to keys produced by builder `a`.
+        let mut after = vec![Vec::new(); self.builders.len()];
+        for (index, builder) in self.builders.iter().enumerate() {
+            for key in &builder.links {
+                match producers.get(key) {
+                    Some(&producer) if producer == index => {}
+                    Some(&producer) if !after[producer].contains(&index) => {
+                        after[producer].push(index);
+                    }
+                    Some(_) => {}
+                    None => errors.push(PlanError::UnknownKey {
+                        builder: builder.name.clone(),
+                        key: key.clone(),
+                    }),
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let components = components(&after);
+        let mut component_of = vec![0; self.builders.len()];
+        for (c, members) in components.iter().enumerate() {
+            members.iter().for_each(|&m| component_of[m] = c);
+        }
+        let mut waiting = vec![0; components.len()];
+        for (from, targets) in after.iter().enumerate() {
+            for &to in targets {
+                if component_of[from] != component_of[to] {
+                    waiting[component_of[to]] += 1;
+                }
+            }
+        }
+        // Components are ready in order of their first-declared builder.
+        let mut ready: BinaryHeap<_> = (0..components.len())
+            .filter(|&c| waiting[c] == 0)
+            .map(|c| Reverse((components[c][0], c)))
+            .collect();
+        let mut steps = Vec::new();
+        while let Some(Reverse((_, c))) = ready.pop() {
+            let mut names = components[c].iter().map(|&m| self.builders[m].name.clone());
+            steps.push(match components[c].len() {
+                1 => Step::Build(names.next().unwrap_or_default()),
+                _ => Step::TwoPhase(names.collect()),
+            });
+            for &from in &components[c] {
+                for &to in &after[from] {
+                    let target = component_of[to];
+                    if target != c {
+                        waiting[target] -= 1;
+                        if waiting[target] == 0 {
+                            ready.push(Reverse((components[target][0], target)));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Plan { steps })
+    }
+}
+
+/// Returns the strongly connected components of the graph, each sorted by index.
+fn components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = edges.len();
+    let mut finished = Vec::with_capacity(n);
+    let mut visited = vec![false; n];
+    for start in 0..n {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![(start, 0)];
+        while let Some((node, next)) = stack.last_mut() {
+            if let Some(&to) = edges[*node].get(*next) {
+                *next += 1;
+                if !visited[to] {
+                    visited[to] = true;
+                    stack.push((to, 0));
+                }
+            } else {
+                finished.push(*node);
+                stack.pop();
+            }
+        }
+    }
+    let mut reverse = vec![Vec::new(); n];
+    for (from, targets) in edges.iter().enumerate() {
+        targets.iter().for_each(|&to| reverse[to].push(from));
+    }
+    let mut assigned = vec![false; n];
+    let mut components = Vec::new();
+    for &start in finished.iter().rev() {
+        if assigned[start] {
+            continue;
+        }
+        assigned[start] = true;
+        let mut members = Vec::new();
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            members.push(node);
+            for &from in &reverse[node] {
+                if !assigned[from] {
+                    assigned[from] = true;
+                    stack.push(from);
+                }
+            }
+        }
+        members.sort_unstable();
+        components.push(members);
+    }
+    components
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plans_follow_links_and_group_cycles() {
+        let plan = Planner::new()
+            .builder("c", [3], [1, 4])
+            .builder("a", [1], [2, 1])
+            .builder("d", [4], [3])
+            .builder("b", [2], [])
+            .builder("e", [5], [])
+            .plan()
+            .unwrap();
+        assert_eq!(plan.order().collect::<Vec<_>>(), ["b", "a", "c", "d", "e"]);
+        assert_eq!(plan.steps[2], Step::TwoPhase(vec!["c".into(), "d".into()]));
+        assert!(!plan.is_acyclic());
+    }
+    #[test]
+    fn acyclic_plans_take_one_phase_per_builder() {
+        let plan = Planner::new()
+            .builder("b", [2], [1])
+            .builder("a", [1], [])
+            .plan()
+            .unwrap();
+        assert_eq!(plan.order().collect::<Vec<_>>(), ["a", "b"]);
+        assert!(plan.is_acyclic());
+    }
+    #[test]
+    fn conflicting_and_missing_keys_are_reported() {
+        let errors = Planner::new()
+            .builder("a", [1], [9])
+            .builder("b", [1], [])
+            .plan()
+            .unwrap_err();
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "Key 1 is produced by both a and b!",
+                "Builder a links to key 9, which no builder produces!",
+            ]
+        );
+    }
+}
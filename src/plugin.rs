@@ -0,0 +1,331 @@
+//! Wiring plugins to the services they depend on, at startup.
+//!
+//! In a plugin architecture, each plugin provides services and keeps [`Deferred`]
+//! handles to services provided by others, which may be registered in any order. A
+//! [`PluginRegistry`] collects both through one [`Registrar`] per plugin, with services
+//! of any type identified by name. [`PluginRegistry::finalize`] then wires every
+//! handle, or fails startup with every unsatisfied dependency at once, naming the plugin
+//! that needs it.
+//!
+//! ```
+//! use deferred_cell::{Deferred, plugin::PluginRegistry};
+//! use std::rc::Rc;
+//!
+//! struct Logger;
+//! struct Router {
+//!     logger: Deferred<Logger>,
+//! }
+//!
+//! let mut registry = PluginRegistry::new();
+//! let router = Rc::new(Router { logger: Deferred::new() });
+//! let mut http = registry.plugin("http");
+//! http.provide("router", &router);
+//! http.require(&router, |r| &r.logger, "logger");
+//! registry.plugin("logging").provide("logger", &Rc::new(Logger));
+//!
+//! let services = registry.finalize().unwrap();
+//! assert!(router.logger.is_ready());
+//! assert!(services.get::<Router>("router").is_some());
+//! ```
+
+use std::{
+    any::{Any, type_name},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+use crate::{
+    Deferred, DeferredError,
+    late::{Link, LinkError, LinkQueue},
+};
+
+/// A dependency problem found by [`PluginRegistry::finalize`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum PluginError {
+    #[error("Service {service:?} is provided by both {first} and {second}!")]
+    Duplicate {
+        service: String,
+        first: String,
+        second: String,
+    },
+    #[error("Plugin {plugin} requires service {service:?}, which no plugin provides!")]
+    Missing { plugin: String, service: String },
+    #[error("Plugin {plugin} requires service {service:?} as {expected}, but it is a {provided}!")]
+    WrongType {
+        plugin: String,
+        service: String,
+        expected: &'static str,
+        provided: &'static str,
+    },
+    #[error("Plugin {plugin} could not be given service {service:?}: {source}")]
+    Wiring {
+        plugin: String,
+        service: String,
+        source: DeferredError,
+    },
+}
+
+/// Serializes as a map of the error's `kind`, its `message`, the `service`, the
+/// `plugin` that requires it or the `first` and `second` providers of a duplicate, the
+/// `expected` and `provided` type names, and the wiring `error`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for PluginError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        let kind = match self {
+            Self::Duplicate { .. } => "duplicate",
+            Self::Missing { .. } => "missing",
+            Self::WrongType { .. } => "wrong_type",
+            Self::Wiring { .. } => "wiring",
+        };
+        map.serialize_entry("kind", kind)?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Self::Duplicate {
+                service,
+                first,
+                second,
+            } => {
+                map.serialize_entry("service", service)?;
+                map.serialize_entry("first", first)?;
+                map.serialize_entry("second", second)?;
+            }
+            Self::Missing { plugin, service } => {
+                map.serialize_entry("plugin", plugin)?;
+                map.serialize_entry("service", service)?;
+            }
+            Self::WrongType {
+                plugin,
+                service,
+                expected,
+                provided,
+            } => {
+                map.serialize_entry("plugin", plugin)?;
+                map.serialize_entry("service", service)?;
+                map.serialize_entry("expected", expected)?;
+                map.serialize_entry("provided", provided)?;
+            }
+            Self::Wiring {
+                plugin,
+                service,
+                source,
+            } => {
+                map.serialize_entry("plugin", plugin)?;
+                map.serialize_entry("service", service)?;
+                map.serialize_entry("error", source)?;
+            }
+        }
+        map.end()
+    }
+}
+
+struct Provided {
+    plugin: String,
+    type_name: &'static str,
+    service: Rc<dyn Any>,
+}
+
+/// A plugin's dependency on a service.
+struct Need {
+    plugin: String,
+    service: String,
+    expected: &'static str,
+}
+
+/// Collects the services and dependencies of every plugin until startup.
+#[derive(Default)]
+pub struct PluginRegistry {
+    services: HashMap<String, Provided>,
+    needs: LinkQueue<Need, dyn Any>,
+    problems: Vec<PluginError>,
+}
+
+impl PluginRegistry {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the registrar for the plugin `name`, through which it provides and
+    /// requires services.
+    pub fn plugin(&mut self, name: impl Into<String>) -> Registrar<'_> {
+        Registrar {
+            registry: self,
+            plugin: name.into(),
+        }
+    }
+    /// Wires every required handle to its service, returning all the services.
+    ///
+    /// Fails with every service provided twice, then every dependency that is missing,
+    /// has the wrong type, or cannot be wired, each in the order they were registered.
+    /// Dependencies that can be satisfied are wired either way.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn finalize(mut self) -> Result<Services, Vec<PluginError>> {
+        let mut problems = self.problems;
+        let services = &self.services;
+        let failed = self
+            .needs
+            .resolve(|need| Some(Rc::clone(&services.get(&need.service)?.service)));
+        problems.extend(failed.into_iter().map(|(need, error)| match error {
+            LinkError::Unbound => PluginError::Missing {
+                plugin: need.plugin,
+                service: need.service,
+            },
+            LinkError::WrongType => PluginError::WrongType {
+                provided: services[&need.service].type_name,
+                plugin: need.plugin,
+                service: need.service,
+                expected: need.expected,
+            },
+            LinkError::Wiring(source) => PluginError::Wiring {
+                plugin: need.plugin,
+                service: need.service,
+                source,
+            },
+        }));
+        if problems.is_empty() {
+            Ok(Services {
+                services: self.services,
+            })
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Registers the services and dependencies of one plugin.
+pub struct Registrar<'r> {
+    registry: &'r mut PluginRegistry,
+    plugin: String,
+}
+
+impl Registrar<'_> {
+    /// Provides `service` to other plugins under `name`.
+    pub fn provide<S: 'static>(&mut self, name: impl Into<String>, service: &Rc<S>) -> &mut Self {
+        let name = name.into();
+        if let Some(first) = self.registry.services.get(&name) {
+            self.registry.problems.push(PluginError::Duplicate {
+                service: name,
+                first: first.plugin.clone(),
+                second: self.plugin.clone(),
+            });
+        } else {
+            self.registry.services.insert(
+                name,
+                Provided {
+                    plugin: self.plugin.clone(),
+                    type_name: type_name::<S>(),
+                    service: Rc::clone(service) as Rc<dyn Any>,
+                },
+            );
+        }
+        self
+    }
+    /// Queues `slot(owner)` to be pointed at the service `name` when the registry is
+    /// finalized.
+    pub fn require<P: 'static, S: 'static>(
+        &mut self,
+        owner: &Rc<P>,
+        slot: for<'n> fn(&'n P) -> &'n Deferred<S>,
+        name: impl Into<String>,
+    ) -> &mut Self {
+        let need = Need {
+            plugin: self.plugin.clone(),
+            service: name.into(),
+            expected: type_name::<S>(),
+        };
+        self.registry.needs.push(need, Link::downcast(owner, slot));
+        self
+    }
+}
+
+/// The services provided by every plugin, after startup.
+pub struct Services {
+    services: HashMap<String, Provided>,
+}
+
+impl Services {
+    /// Returns the service `name`, if it was provided as an `S`.
+    #[must_use]
+    pub fn get<S: 'static>(&self, name: &str) -> Option<Rc<S>> {
+        let provided = self.services.get(name)?;
+        Rc::clone(&provided.service).downcast().ok()
+    }
+    /// Returns the plugin that provided the service `name`.
+    #[must_use]
+    pub fn provider(&self, name: &str) -> Option<&str> {
+        self.services.get(name).map(|p| p.plugin.as_str())
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SetOnce;
+
+    struct Db;
+
+    struct Cache {
+        db: Deferred<Db>,
+        clock: Deferred<u64>,
+    }
+
+    fn cache() -> Rc<Cache> {
+        Rc::new(Cache {
+            db: Deferred::new(),
+            clock: Deferred::new(),
+        })
+    }
+
+    #[test]
+    fn finalize_reports_every_unsatisfied_dependency() {
+        let mut registry = PluginRegistry::new();
+        let (good, bad) = (cache(), cache());
+        registry
+            .plugin("cache")
+            .provide("cache", &good)
+            .require(&good, |c| &c.db, "db")
+            .require(&bad, |c| &c.db, "clock")
+            .require(&bad, |c| &c.clock, "time");
+        registry
+            .plugin("storage")
+            .provide("db", &Rc::new(Db))
+            .provide("clock", &Rc::new(7_u64))
+            .provide("cache", &Rc::new(Db));
+
+        let problems: Vec<_> = registry
+            .finalize()
+            .err()
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                r#"Service "cache" is provided by both cache and storage!"#,
+                r#"Plugin cache requires service "clock" as deferred_cell::plugin::test::Db, but it is a u64!"#,
+                r#"Plugin cache requires service "time", which no plugin provides!"#,
+            ]
+        );
+        assert!(good.db.is_ready());
+    }
+    #[test]
+    fn handles_are_only_set_once() {
+        let good = cache();
+        SetOnce::from(&good.db).try_set(&Rc::new(Db)).unwrap();
+        let mut registry = PluginRegistry::new();
+        registry.plugin("storage").provide("db", &Rc::new(Db));
+        registry.plugin("cache").require(&good, |c| &c.db, "db");
+        assert!(matches!(
+            registry.finalize().err().unwrap()[..],
+            [PluginError::Wiring { .. }]
+        ));
+    }
+}
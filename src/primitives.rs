@@ -0,0 +1,38 @@
+//! Synchronization primitives used by [`DeferredSync`](crate::DeferredSync).
+//!
+//! With the `loom` feature enabled these are loom's model-checked versions, so code
+//! wiring `DeferredSync` cells can be explored with `loom::model`.
+
+#[cfg(feature = "loom")]
+pub use loom::{
+    cell::UnsafeCell,
+    sync::{
+        Condvar, Mutex,
+        atomic::{AtomicU8, Ordering},
+    },
+};
+#[cfg(not(feature = "loom"))]
+pub use std::sync::{
+    Condvar, Mutex,
+    atomic::{AtomicU8, Ordering},
+};
+
+/// `std::cell::UnsafeCell` with loom's closure based access API.
+#[cfg(not(feature = "loom"))]
+#[derive(Debug)]
+pub struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+#[cfg(not(feature = "loom"))]
+impl<T> UnsafeCell<T> {
+    pub const fn new(data: T) -> Self {
+        Self(std::cell::UnsafeCell::new(data))
+    }
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+    #[inline]
+    pub fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
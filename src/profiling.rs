@@ -0,0 +1,176 @@
+//! Per-type access counters, enabled by the `profiling` feature.
+//!
+//! Every set, get, and failed upgrade of a [`Deferred`](crate::Deferred) or
+//! [`DeferredSync`](crate::DeferredSync) cell is counted under the name of the cell's
+//! target type. Each type's counters are atomics, and each thread caches where they
+//! are, so counting is a thread-local lookup and a relaxed increment. A global lock is
+//! only taken the first time a thread sees a type, and by the reporting functions.
+//!
+//! ```
+//! use deferred_cell::{Deferred, SetOnce, profiling};
+//! use std::rc::Rc;
+//!
+//! struct Symbol;
+//!
+//! let cell = Deferred::new();
+//! let symbol = Rc::new(Symbol);
+//! SetOnce::from(&cell).try_set(&symbol).unwrap();
+//! let _ = cell.get();
+//!
+//! let stats = profiling::stats_for::<Symbol>();
+//! assert_eq!((stats.sets, stats.gets), (1, 1));
+//! ```
+
+use std::{
+    any::type_name,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Counters for cells pointing to one target type.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Successful sets.
+    pub sets: u64,
+    /// Calls to `get`, `try_get`, or `wait`, successful or not.
+    pub gets: u64,
+    /// Gets of set cells whose target was dropped.
+    pub failed_upgrades: u64,
+}
+
+/// The live counters of one target type, shared by every thread.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) sets: AtomicU64,
+    pub(crate) gets: AtomicU64,
+    pub(crate) failed_upgrades: AtomicU64,
+}
+
+impl Counters {
+    fn load(&self) -> Stats {
+        Stats {
+            sets: self.sets.load(Ordering::Relaxed),
+            gets: self.gets.load(Ordering::Relaxed),
+            failed_upgrades: self.failed_upgrades.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Every target type seen so far. Only locked the first time a thread sees a type, and
+/// when reporting.
+static REGISTRY: Mutex<BTreeMap<&'static str, &'static Counters>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// This thread's counters by the address and length of the type name.
+    static CACHE: RefCell<HashMap<(usize, usize), &'static Counters>> = RefCell::new(HashMap::new());
+}
+
+fn registered(target: &'static str) -> &'static Counters {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(target)
+        .or_insert_with(|| Box::leak(Box::default()))
+}
+
+fn counters(target: &'static str) -> &'static Counters {
+    let key = (target.as_ptr() as usize, target.len());
+    CACHE
+        .try_with(|cache| {
+            *cache
+                .borrow_mut()
+                .entry(key)
+                .or_insert_with(|| registered(target))
+        })
+        // The cache is gone while this thread shuts down.
+        .unwrap_or_else(|_| registered(target))
+}
+
+pub(crate) fn record(target: &'static str, counter: fn(&Counters) -> &AtomicU64) {
+    counter(counters(target)).fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the counters of every target type seen so far, keyed by type name.
+#[must_use]
+pub fn stats() -> BTreeMap<&'static str, Stats> {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|(&target, counters)| (target, counters.load()))
+        .collect()
+}
+
+/// Returns the counters of cells pointing to `T`.
+#[must_use]
+pub fn stats_for<T>() -> Stats {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(type_name::<T>())
+        .map_or_else(Stats::default, |counters| counters.load())
+}
+
+/// Clears every counter.
+pub fn reset() {
+    for counters in REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .values()
+    {
+        counters.sets.store(0, Ordering::Relaxed);
+        counters.gets.store(0, Ordering::Relaxed);
+        counters.failed_upgrades.store(0, Ordering::Relaxed);
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use std::{rc::Rc, sync::Arc};
+
+    use super::*;
+    use crate::{Deferred, DeferredSync, SetOnce, SetOnceSync};
+
+    #[test]
+    fn counts_sets_gets_and_failed_upgrades() {
+        #[derive(Debug)]
+        struct Local;
+        #[derive(Debug)]
+        struct Shared;
+
+        let cell = Deferred::new();
+        let target = Rc::new(Local);
+        cell.try_get().unwrap_err();
+        SetOnce::from(&cell).try_set(&target).unwrap();
+        SetOnce::from(&cell).try_set(&target).unwrap_err();
+        let _ = cell.get();
+        drop(target);
+        cell.try_get().unwrap_err();
+
+        let sync = DeferredSync::default();
+        SetOnceSync::from(&sync).try_set(&Arc::new(Shared)).unwrap();
+        sync.wait().unwrap_err();
+
+        assert_eq!(
+            stats_for::<Local>(),
+            Stats {
+                sets: 1,
+                gets: 3,
+                failed_upgrades: 1
+            }
+        );
+        assert_eq!(stats()[type_name::<Shared>()].failed_upgrades, 1);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let _ = sync.try_get();
+            });
+        });
+        assert_eq!(stats_for::<Shared>().gets, 2);
+    }
+}
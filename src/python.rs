@@ -0,0 +1,252 @@
+//! Python bindings for building and querying graphs.
+//!
+//! [`register`] adds two classes to a Python module. A `GraphBuilder` collects nodes,
+//! each a key with a `dict` of data, and edges between keys, which may be added before
+//! the nodes they name. `build()` checks every key and wires the nodes with
+//! [`GraphSpec::materialize`], returning a `Graph` that supports lookups by key, `bfs`,
+//! and unweighted `shortest_path`.
+//!
+//! To ship the bindings, call [`register`] from your extension module, built with
+//! `pyo3/extension-module` enabled:
+//!
+//! ```ignore
+//! #[pymodule]
+//! fn graphs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+//!     deferred_cell::python::register(m)
+//! }
+//! ```
+//!
+//! ```python
+//! builder = graphs.GraphBuilder()
+//! builder.add_edge("a", "b")
+//! for key in ["a", "b"]:
+//!     builder.add_node(key, {"label": key.upper()})
+//! graph = builder.build()
+//! assert graph.shortest_path("b", "a") is None
+//! assert graph["b"]["label"] == "B"
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+};
+
+use pyo3::{
+    exceptions::{PyKeyError, PyRuntimeError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+
+use crate::{Deferred, HasNeighbors, spec::GraphSpec, visit};
+
+struct Node {
+    key: String,
+    data: Py<PyDict>,
+    edges: Vec<Deferred<Self>>,
+}
+
+impl HasNeighbors for Node {
+    fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+        self.edges.iter()
+    }
+}
+
+/// Adds the `GraphBuilder` and `Graph` classes to `module`.
+pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<GraphBuilder>()?;
+    module.add_class::<Graph>()
+}
+
+/// Collects keyed nodes and edges for a [`Graph`].
+#[pyclass(module = "deferred_cell")]
+#[derive(Default)]
+pub struct GraphBuilder {
+    keys: Vec<String>,
+    index: HashMap<String, usize>,
+    data: Vec<Py<PyDict>>,
+    edges: Vec<(String, String)>,
+}
+
+#[pymethods]
+impl GraphBuilder {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a node under `key`, holding `data` or an empty dict.
+    #[pyo3(signature = (key, data = None))]
+    fn add_node(
+        &mut self,
+        py: Python<'_>,
+        key: String,
+        data: Option<Bound<'_, PyDict>>,
+    ) -> PyResult<()> {
+        if self.index.contains_key(&key) {
+            return Err(PyValueError::new_err(format!(
+                "Key {key:?} is already registered!"
+            )));
+        }
+        self.index.insert(key.clone(), self.keys.len());
+        self.keys.push(key);
+        self.data
+            .push(data.map_or_else(|| PyDict::new(py).unbind(), Bound::unbind));
+        Ok(())
+    }
+    /// Adds an edge from the node `from` to the node `to`.
+    fn add_edge(&mut self, from: String, to: String) {
+        self.edges.push((from, to));
+    }
+    /// Wires the nodes, failing with `KeyError` if an edge names an unknown key.
+    fn build(&self, py: Python<'_>) -> PyResult<Graph> {
+        let unresolved: Vec<_> = self
+            .edges
+            .iter()
+            .filter(|(from, to)| !(self.index.contains_key(from) && self.index.contains_key(to)))
+            .collect();
+        if !unresolved.is_empty() {
+            return Err(PyKeyError::new_err(format!(
+                "Unresolved references: {unresolved:?}"
+            )));
+        }
+        let edges = self
+            .edges
+            .iter()
+            .map(|(from, to)| (self.index[from], self.index[to]))
+            .collect();
+        let nodes = GraphSpec::new(self.keys.len(), edges)
+            .materialize(
+                |i, degree| Node {
+                    key: self.keys[i].clone(),
+                    data: self.data[i].clone_ref(py),
+                    edges: (0..degree).map(|_| Deferred::new()).collect(),
+                },
+                |node, k| &node.edges[k],
+            )
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(Graph {
+            nodes,
+            index: self.index.clone(),
+        })
+    }
+}
+
+/// A wired graph of keyed nodes.
+#[pyclass(module = "deferred_cell", unsendable)]
+pub struct Graph {
+    nodes: Vec<Rc<Node>>,
+    index: HashMap<String, usize>,
+}
+
+impl Graph {
+    fn node(&self, key: &str) -> PyResult<&Rc<Node>> {
+        self.index
+            .get(key)
+            .map(|&i| &self.nodes[i])
+            .ok_or_else(|| PyKeyError::new_err(key.to_owned()))
+    }
+}
+
+fn keys(nodes: impl IntoIterator<Item = Rc<Node>>) -> Vec<String> {
+    nodes.into_iter().map(|node| node.key.clone()).collect()
+}
+
+#[pymethods]
+impl Graph {
+    // pyo3 cannot export const fns
+    #[allow(clippy::missing_const_for_fn)]
+    fn __len__(&self) -> usize {
+        self.nodes.len()
+    }
+    fn __contains__(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+    fn __getitem__(&self, py: Python<'_>, key: &str) -> PyResult<Py<PyDict>> {
+        Ok(self.node(key)?.data.clone_ref(py))
+    }
+    /// Returns the keys `key` has edges to, in the order the edges were added.
+    fn neighbors(&self, key: &str) -> PyResult<Vec<String>> {
+        Ok(keys(
+            self.node(key)?
+                .neighbors()
+                .filter_map(|cell| cell.try_get().ok()),
+        ))
+    }
+    /// Returns the key of every node reachable from `start`, in breadth-first order.
+    fn bfs(&self, start: &str) -> PyResult<Vec<String>> {
+        Ok(keys(visit::bfs(self.node(start)?)))
+    }
+    /// Returns the keys along a path from `start` to `goal` with the fewest edges, or
+    /// `None` if `goal` cannot be reached.
+    fn shortest_path(&self, start: &str, goal: &str) -> PyResult<Option<Vec<String>>> {
+        let (start, goal) = (self.node(start)?, self.node(goal)?);
+        let mut parents: HashMap<*const Node, Rc<Node>> = HashMap::new();
+        let mut seen = HashSet::from([Rc::as_ptr(start)]);
+        let mut queue = VecDeque::from([Rc::clone(start)]);
+        while let Some(node) = queue.pop_front() {
+            if Rc::ptr_eq(&node, goal) {
+                let mut path = vec![node];
+                while let Some(parent) = parents.get(&Rc::as_ptr(&path[path.len() - 1])) {
+                    path.push(Rc::clone(parent));
+                }
+                path.reverse();
+                return Ok(Some(keys(path)));
+            }
+            for next in node.neighbors().filter_map(|cell| cell.try_get().ok()) {
+                if seen.insert(Rc::as_ptr(&next)) {
+                    parents.insert(Rc::as_ptr(&next), Rc::clone(&node));
+                    queue.push_back(next);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use pyo3::{ffi::c_str, types::IntoPyDict};
+
+    use super::*;
+
+    #[test]
+    fn python_builds_and_queries_graphs() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "deferred_cell").unwrap();
+            register(&module).unwrap();
+            let locals = [("dc", module)].into_py_dict(py).unwrap();
+            py.run(
+                c_str!(
+                    r#"
+builder = dc.GraphBuilder()
+for a, b in [("a", "b"), ("b", "c"), ("a", "d"), ("d", "c"), ("c", "a")]:
+    builder.add_edge(a, b)
+for key in "abcd":
+    builder.add_node(key, {"name": key.upper()})
+builder.add_node("e")
+graph = builder.build()
+
+assert len(graph) == 5 and "e" in graph
+assert graph["b"] == {"name": "B"}
+assert graph.neighbors("a") == ["b", "d"]
+assert graph.bfs("b") == ["b", "c", "a", "d"]
+assert graph.shortest_path("b", "d") == ["b", "c", "a", "d"]
+assert graph.shortest_path("a", "e") is None
+
+builder.add_edge("e", "missing")
+try:
+    builder.build()
+    raise AssertionError("unresolved edge was accepted")
+except KeyError:
+    pass
+"#
+                ),
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
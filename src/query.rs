@@ -0,0 +1,443 @@
+//! Path expressions for finding nodes without writing a traversal.
+//!
+//! A [`Query`] follows named edges from a root node and keeps the nodes at the end of
+//! the path, optionally filtered by comparing one of their fields to a value:
+//!
+//! - `name` follows every cell of the edge field `name`, and `name[*]` says so
+//!   explicitly;
+//! - `name[2]` follows only its third cell;
+//! - `**` stands for any number of edges, including none, in breadth-first order;
+//! - a trailing `.field == 'value'` or `.field != 'value'` keeps the nodes whose field
+//!   has that value, or a different one. Values may be quoted with `'` or `"`, or bare.
+//!
+//! Nodes describe their fields by implementing [`Queryable`]. Each step visits a node at
+//! most once, so cycles are safe, and unset or dangling cells are skipped. Matches are
+//! returned in the order they were reached.
+//!
+//! ```
+//! use deferred_cell::{Deferred, HasNeighbors, SetOnce, query::{self, Queryable}};
+//! use std::{borrow::Cow, rc::Rc};
+//!
+//! struct Doc {
+//!     name: &'static str,
+//!     children: Vec<Deferred<Doc>>,
+//! }
+//!
+//! impl HasNeighbors for Doc {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         self.children.iter()
+//!     }
+//! }
+//!
+//! impl Queryable for Doc {
+//!     fn edges(&self, name: &str) -> Vec<&Deferred<Self>> {
+//!         match name {
+//!             "children" => self.children.iter().collect(),
+//!             _ => Vec::new(),
+//!         }
+//!     }
+//!     fn value(&self, name: &str) -> Option<Cow<'_, str>> {
+//!         (name == "name").then_some(Cow::Borrowed(self.name))
+//!     }
+//! }
+//!
+//! let doc = |name, children: usize| Rc::new(Doc {
+//!     name,
+//!     children: (0..children).map(|_| Deferred::new()).collect(),
+//! });
+//! let (root, intro, outro) = (doc("root", 2), doc("X", 1), doc("outro", 0));
+//! SetOnce::from(&root.children[0]).try_set(&intro).unwrap();
+//! SetOnce::from(&root.children[1]).try_set(&outro).unwrap();
+//! SetOnce::from(&intro.children[0]).try_set(&root).unwrap();
+//!
+//! let found = query::query(&root, "children[*].name == 'X'").unwrap();
+//! assert!(Rc::ptr_eq(&found[0], &intro));
+//! assert_eq!(query::query(&root, "** . name != root").unwrap().len(), 2);
+//! ```
+
+use std::{borrow::Cow, collections::HashSet, fmt, rc::Rc, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{Deferred, HasNeighbors, visit};
+
+/// A node whose edges and values can be named in a [`Query`].
+pub trait Queryable: HasNeighbors {
+    /// Returns the cells of the edge field `name`, or none if there is no such field.
+    fn edges(&self, name: &str) -> Vec<&Deferred<Self>>;
+    /// Returns the value of the field `name` as text, or `None` if there is no such
+    /// field.
+    fn value(&self, name: &str) -> Option<Cow<'_, str>>;
+}
+
+/// A query expression that could not be parsed.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid query at column {column}: {reason}!")]
+pub struct QueryError {
+    /// The 1-based column of the problem.
+    pub column: usize,
+    pub reason: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    /// Follows the cells of an edge field, or only the one at an index.
+    Edge { name: String, index: Option<usize> },
+    /// Follows any number of edges.
+    Descendants,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Filter {
+    field: String,
+    value: String,
+    equal: bool,
+}
+
+/// A parsed or built path expression.
+///
+/// Parse one with [`str::parse`], or build the same thing with methods:
+///
+/// ```
+/// use deferred_cell::query::Query;
+///
+/// let built = Query::new().edge("children").descendants().where_eq("name", "X");
+/// assert_eq!(built, "children[*].**.name == 'X'".parse().unwrap());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    steps: Vec<Step>,
+    filter: Option<Filter>,
+}
+
+impl Query {
+    /// Creates a query matching only the root.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Follows every cell of the edge field `name`.
+    #[must_use]
+    pub fn edge(mut self, name: impl Into<String>) -> Self {
+        self.steps.push(Step::Edge {
+            name: name.into(),
+            index: None,
+        });
+        self
+    }
+    /// Follows the cell at `index` of the edge field `name`.
+    #[must_use]
+    pub fn edge_at(mut self, name: impl Into<String>, index: usize) -> Self {
+        self.steps.push(Step::Edge {
+            name: name.into(),
+            index: Some(index),
+        });
+        self
+    }
+    /// Follows any number of edges, including none.
+    #[must_use]
+    pub fn descendants(mut self) -> Self {
+        self.steps.push(Step::Descendants);
+        self
+    }
+    /// Keeps the nodes whose `field` is `value`.
+    #[must_use]
+    pub fn where_eq(self, field: impl Into<String>, value: impl Into<String>) -> Self {
+        self.filter(field.into(), value.into(), true)
+    }
+    /// Keeps the nodes that have a `field` other than `value`.
+    #[must_use]
+    pub fn where_ne(self, field: impl Into<String>, value: impl Into<String>) -> Self {
+        self.filter(field.into(), value.into(), false)
+    }
+    fn filter(mut self, field: String, value: String, equal: bool) -> Self {
+        self.filter = Some(Filter {
+            field,
+            value,
+            equal,
+        });
+        self
+    }
+    /// Returns every node matching the query from `root`.
+    pub fn run<T: Queryable>(&self, root: &Rc<T>) -> Vec<Rc<T>> {
+        let mut nodes = vec![Rc::clone(root)];
+        for step in &self.steps {
+            let next = nodes.iter().flat_map(|node| -> Vec<Rc<T>> {
+                match step {
+                    Step::Edge { name, index } => {
+                        let cells = node.edges(name);
+                        let cells = match index {
+                            Some(i) => cells.get(*i..=*i).unwrap_or_default(),
+                            None => &cells,
+                        };
+                        cells.iter().filter_map(|c| c.try_get().ok()).collect()
+                    }
+                    Step::Descendants => visit::bfs(node).collect(),
+                }
+            });
+            nodes = distinct(next);
+        }
+        if let Some(filter) = &self.filter {
+            nodes.retain(|node| {
+                node.value(&filter.field)
+                    .is_some_and(|value| (value == filter.value) == filter.equal)
+            });
+        }
+        nodes
+    }
+}
+
+/// Drops every node after its first occurrence.
+fn distinct<T>(nodes: impl Iterator<Item = Rc<T>>) -> Vec<Rc<T>> {
+    let mut seen = HashSet::new();
+    nodes.filter(|node| seen.insert(Rc::as_ptr(node))).collect()
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps = self.steps.iter().map(|step| match step {
+            Step::Edge { name, index: None } => format!("{name}[*]"),
+            Step::Edge {
+                name,
+                index: Some(i),
+            } => format!("{name}[{i}]"),
+            Step::Descendants => "**".to_owned(),
+        });
+        let filter = self.filter.iter().map(|filter| {
+            let op = if filter.equal { "==" } else { "!=" };
+            let quote = if filter.value.contains('\'') {
+                '"'
+            } else {
+                '\''
+            };
+            format!("{} {op} {quote}{}{quote}", filter.field, filter.value)
+        });
+        write!(f, "{}", steps.chain(filter).collect::<Vec<_>>().join("."))
+    }
+}
+
+/// Reads an expression one token at a time.
+struct Parser<'e> {
+    expr: &'e str,
+    at: usize,
+}
+
+impl<'e> Parser<'e> {
+    fn rest(&self) -> &'e str {
+        &self.expr[self.at..]
+    }
+    const fn error(&self, reason: &'static str) -> QueryError {
+        QueryError {
+            column: self.at + 1,
+            reason,
+        }
+    }
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.at += rest.len() - rest.trim_start().len();
+    }
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.at += token.len();
+        }
+        found
+    }
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> &'e str {
+        let rest = self.rest();
+        let len = rest.find(|c| !keep(c)).unwrap_or(rest.len());
+        self.at += len;
+        &rest[..len]
+    }
+    fn name(&mut self) -> Result<&'e str, QueryError> {
+        self.skip_space();
+        match self.take_while(|c| c.is_alphanumeric() || c == '_') {
+            "" => Err(self.error("expected a field name or `**`")),
+            name => Ok(name),
+        }
+    }
+    fn index(&mut self) -> Result<Option<usize>, QueryError> {
+        let index = if self.eat("*") {
+            None
+        } else {
+            let Ok(index) = self.take_while(|c| c.is_ascii_digit()).parse() else {
+                return Err(self.error("expected `*` or an index"));
+            };
+            Some(index)
+        };
+        if self.eat("]") {
+            Ok(index)
+        } else {
+            Err(self.error("expected `]`"))
+        }
+    }
+    fn value(&mut self) -> Result<String, QueryError> {
+        self.skip_space();
+        for quote in ['\'', '"'] {
+            if self.eat(&quote.to_string()) {
+                let value = self.take_while(|c| c != quote).to_owned();
+                if !self.eat(&quote.to_string()) {
+                    return Err(self.error("unterminated value"));
+                }
+                return Ok(value);
+            }
+        }
+        match self.take_while(|c| !c.is_whitespace()) {
+            "" => Err(self.error("expected a value")),
+            value => Ok(value.to_owned()),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { expr, at: 0 };
+        let mut query = Self::new();
+        // The last field named, which a comparison turns into the filtered field.
+        let mut last = None;
+        loop {
+            if let Some(name) = last.take() {
+                query = query.edge(name);
+            }
+            if parser.eat("**") {
+                query = query.descendants();
+            } else {
+                let name = parser.name()?.to_owned();
+                if parser.eat("[") {
+                    query = match parser.index()? {
+                        Some(index) => query.edge_at(name, index),
+                        None => query.edge(name),
+                    };
+                } else {
+                    last = Some(name);
+                }
+            }
+            if parser.eat(".") {
+                continue;
+            }
+            parser.skip_space();
+            let column = parser.at;
+            let equal = if parser.eat("==") {
+                true
+            } else if parser.eat("!=") {
+                false
+            } else {
+                break;
+            };
+            let Some(field) = last.take() else {
+                return Err(QueryError {
+                    column: column + 1,
+                    reason: "only a plain field name can be compared",
+                });
+            };
+            query = query.filter(field, parser.value()?, equal);
+            break;
+        }
+        if let Some(name) = last {
+            query = query.edge(name);
+        }
+        parser.skip_space();
+        if parser.rest().is_empty() {
+            Ok(query)
+        } else {
+            Err(parser.error("expected `.`, `==`, or `!=`"))
+        }
+    }
+}
+
+/// Parses `expr` and returns every node matching it from `root`.
+pub fn query<T: Queryable>(root: &Rc<T>, expr: &str) -> Result<Vec<Rc<T>>, QueryError> {
+    Ok(expr.parse::<Query>()?.run(root))
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SetOnce;
+
+    struct Node {
+        name: String,
+        target: Deferred<Self>,
+        children: Vec<Deferred<Self>>,
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            std::iter::once(&self.target).chain(&self.children)
+        }
+    }
+
+    impl Queryable for Node {
+        fn edges(&self, name: &str) -> Vec<&Deferred<Self>> {
+            match name {
+                "target" => vec![&self.target],
+                "children" => self.children.iter().collect(),
+                _ => Vec::new(),
+            }
+        }
+        fn value(&self, name: &str) -> Option<Cow<'_, str>> {
+            (name == "name").then_some(Cow::Borrowed(&self.name))
+        }
+    }
+
+    fn node(name: &str, children: usize) -> Rc<Node> {
+        Rc::new(Node {
+            name: name.to_owned(),
+            target: Deferred::new(),
+            children: (0..children).map(|_| Deferred::new()).collect(),
+        })
+    }
+
+    fn names(nodes: &[Rc<Node>]) -> Vec<&str> {
+        nodes.iter().map(|n| n.name.as_str()).collect()
+    }
+
+    #[test]
+    fn queries_follow_edges_through_cycles() {
+        let root = node("root", 3);
+        let (a, b, x) = (node("a", 0), node("b", 1), node("X", 0));
+        for (cell, target) in root.children.iter().zip([&a, &b, &a]) {
+            SetOnce::from(cell).try_set(target).unwrap();
+        }
+        SetOnce::from(&a.target).try_set(&x).unwrap();
+        SetOnce::from(&b.target).try_set(&x).unwrap();
+        SetOnce::from(&b.children[0]).try_set(&root).unwrap();
+        SetOnce::from(&x.target).try_set(&root).unwrap();
+
+        let run = |expr| names(&query(&root, expr).unwrap()).join(" ");
+        assert_eq!(run("children"), "a b");
+        assert_eq!(run("children[*].target.name == 'X'"), "X");
+        assert_eq!(run("children[1] . children[0].target"), "");
+        assert_eq!(run("children[9]"), "");
+        assert_eq!(run("**"), "root a b X");
+        assert_eq!(run("children.** . name != \"X\""), "a root b");
+        assert_eq!(run("target.name == X"), "");
+    }
+    #[test]
+    fn malformed_queries_are_rejected() {
+        let error = |expr: &str| expr.parse::<Query>().unwrap_err().to_string();
+        assert_eq!(
+            error("children[x]"),
+            "Invalid query at column 10: expected `*` or an index!"
+        );
+        assert_eq!(
+            error("children[0] == a"),
+            "Invalid query at column 13: only a plain field name can be compared!"
+        );
+        assert_eq!(
+            error("name == 'a"),
+            "Invalid query at column 11: unterminated value!"
+        );
+        assert_eq!(
+            error("a b"),
+            "Invalid query at column 3: expected `.`, `==`, or `!=`!"
+        );
+        let query: Query = "a[*] . b[2].**.name != ' x '".parse().unwrap();
+        assert_eq!(query.to_string(), "a[*].b[2].**.name != ' x '");
+    }
+}
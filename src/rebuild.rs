@@ -0,0 +1,248 @@
+//! Partial rebuilds of graphs built from source records, for hot reload.
+//!
+//! Cells are set once, so a node cannot be pointed at a replacement of its target; it
+//! must be rebuilt too. When one record changes, [`RecordGraph::update`] rebuilds the
+//! nodes reachable from it, then copies every node that could reach those, directly or
+//! not, so that no edge is left pointing into the old graph. Every other node is shared
+//! with the old graph, and rebuilt nodes linking to them are wired to the shared nodes.
+//! The new graph is returned with a [`Changes`] report saying which nodes were touched.
+//!
+//! Pair this with [`GraphSlot::reload`](crate::reload::GraphSlot::reload) to publish the
+//! updated graph while readers finish with the old one.
+//!
+//! ```
+//! use deferred_cell::{Deferred, rebuild::RecordGraph};
+//!
+//! struct Node {
+//!     name: &'static str,
+//!     edges: Vec<Deferred<Node>>,
+//! }
+//!
+//! fn make(name: &&'static str, degree: usize) -> Node {
+//!     Node { name: *name, edges: (0..degree).map(|_| Deferred::new()).collect() }
+//! }
+//!
+//! fn slot(node: &Node, k: usize) -> &Deferred<Node> {
+//!     &node.edges[k]
+//! }
+//!
+//! // app -> db -> disk, and an unrelated log -> disk.
+//! let old = RecordGraph::build(
+//!     vec![("app", vec![1]), ("db", vec![2]), ("disk", vec![]), ("log", vec![2])],
+//!     make,
+//!     slot,
+//! )
+//! .unwrap();
+//! let (new, changes) = old.update(1, "db2", vec![2], make, slot).unwrap();
+//!
+//! assert_eq!(new.node(0).edges[0].get().name, "db2");
+//! assert_eq!((changes.rebuilt, changes.rewired), (vec![1, 2], vec![0, 3]));
+//! ```
+
+use std::rc::Rc;
+
+use crate::{Deferred, DeferredError, SetOnce};
+
+/// The nodes [`RecordGraph::update`] replaced, by index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// The changed node and every node reachable from it, rebuilt from their records.
+    pub rebuilt: Vec<usize>,
+    /// Nodes copied from their records only because they could reach a rebuilt node.
+    pub rewired: Vec<usize>,
+    /// Nodes carried over from the old graph as they were.
+    pub shared: Vec<usize>,
+}
+
+/// A wired graph that remembers the record and targets each node was built from.
+#[derive(Debug)]
+pub struct RecordGraph<R, T> {
+    records: Vec<(R, Vec<usize>)>,
+    nodes: Vec<Rc<T>>,
+}
+
+impl<R, T> RecordGraph<R, T> {
+    /// Builds node `i` from `make_node(record, targets.len())` for the `i`-th
+    /// `(record, targets)` pair, and wires its `k`-th target through `slot(node, k)`.
+    ///
+    /// # Panics
+    /// Panics if a target is not the index of a record.
+    pub fn build(
+        records: Vec<(R, Vec<usize>)>,
+        mut make_node: impl FnMut(&R, usize) -> T,
+        slot: impl Fn(&T, usize) -> &Deferred<T>,
+    ) -> Result<Self, DeferredError> {
+        let all = vec![true; records.len()];
+        Self::assemble(records, &all, &[], &mut make_node, &slot)
+    }
+    /// Returns a new graph in which node `index` has `record` and `targets`, and a report
+    /// of which nodes had to be replaced.
+    ///
+    /// # Panics
+    /// Panics if `index` or a target is not the index of a record.
+    pub fn update(
+        &self,
+        index: usize,
+        record: R,
+        targets: Vec<usize>,
+        mut make_node: impl FnMut(&R, usize) -> T,
+        slot: impl Fn(&T, usize) -> &Deferred<T>,
+    ) -> Result<(Self, Changes), DeferredError>
+    where
+        R: Clone,
+    {
+        let mut records = self.records.clone();
+        records[index] = (record, targets);
+        let reached = reach(records.len(), [index], |i| &records[i].1);
+        let mut sources = vec![Vec::new(); records.len()];
+        for (from, (_, targets)) in records.iter().enumerate() {
+            for &to in targets {
+                sources[to].push(from);
+            }
+        }
+        let rebuilt = (0..records.len()).filter(|&i| reached[i]);
+        let replaced = reach(records.len(), rebuilt, |i| &sources[i]);
+
+        let mut changes = Changes::default();
+        for i in 0..records.len() {
+            match (reached[i], replaced[i]) {
+                (true, _) => changes.rebuilt.push(i),
+                (false, true) => changes.rewired.push(i),
+                (false, false) => changes.shared.push(i),
+            }
+        }
+        let graph = Self::assemble(records, &replaced, &self.nodes, &mut make_node, &slot)?;
+        Ok((graph, changes))
+    }
+    /// Makes the nodes marked in `fresh` and takes the rest from `old`, then wires every
+    /// fresh node.
+    fn assemble(
+        records: Vec<(R, Vec<usize>)>,
+        fresh: &[bool],
+        old: &[Rc<T>],
+        make_node: &mut impl FnMut(&R, usize) -> T,
+        slot: &impl Fn(&T, usize) -> &Deferred<T>,
+    ) -> Result<Self, DeferredError> {
+        assert!(
+            records
+                .iter()
+                .flat_map(|(_, t)| t)
+                .all(|&to| to < records.len()),
+            "edge target out of range for {} records",
+            records.len()
+        );
+        let nodes: Vec<_> = records
+            .iter()
+            .enumerate()
+            .map(|(i, (record, targets))| {
+                if fresh[i] {
+                    Rc::new(make_node(record, targets.len()))
+                } else {
+                    Rc::clone(&old[i])
+                }
+            })
+            .collect();
+        for (from, (_, targets)) in records.iter().enumerate().filter(|&(i, _)| fresh[i]) {
+            for (k, &to) in targets.iter().enumerate() {
+                SetOnce::from(slot(&nodes[from], k)).try_set(&nodes[to])?;
+            }
+        }
+        Ok(Self { records, nodes })
+    }
+    /// Returns node `index`.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of range.
+    #[must_use]
+    pub fn node(&self, index: usize) -> &Rc<T> {
+        &self.nodes[index]
+    }
+    /// Returns every node, by index.
+    #[must_use]
+    pub fn nodes(&self) -> &[Rc<T>] {
+        &self.nodes
+    }
+    /// Returns the record node `index` was built from.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of range.
+    #[must_use]
+    pub fn record(&self, index: usize) -> &R {
+        &self.records[index].0
+    }
+}
+
+/// Marks every index reachable from `starts`, themselves included, following `next`.
+fn reach<'a>(
+    len: usize,
+    starts: impl IntoIterator<Item = usize>,
+    next: impl Fn(usize) -> &'a [usize],
+) -> Vec<bool> {
+    let mut seen = vec![false; len];
+    let mut stack: Vec<_> = starts.into_iter().collect();
+    stack.iter().for_each(|&i| seen[i] = true);
+    while let Some(i) = stack.pop() {
+        for &to in next(i) {
+            if !seen[to] {
+                seen[to] = true;
+                stack.push(to);
+            }
+        }
+    }
+    seen
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Node {
+        value: u8,
+        edges: Vec<Deferred<Self>>,
+    }
+
+    fn make(value: &u8, degree: usize) -> Node {
+        Node {
+            value: *value,
+            edges: (0..degree).map(|_| Deferred::new()).collect(),
+        }
+    }
+
+    fn slot(node: &Node, k: usize) -> &Deferred<Node> {
+        &node.edges[k]
+    }
+
+    #[test]
+    fn only_affected_nodes_are_replaced() {
+        // 0 -> 1 <-> 2 -> 3, with 4 -> 3 and 5 alone.
+        let records = vec![
+            (0, vec![1]),
+            (1, vec![2]),
+            (2, vec![1, 3]),
+            (3, vec![]),
+            (4, vec![3]),
+            (5, vec![]),
+        ];
+        let old = RecordGraph::build(records, make, slot).unwrap();
+        let (new, changes) = old.update(2, 20, vec![1, 5], make, slot).unwrap();
+        assert_eq!(
+            changes,
+            Changes {
+                rebuilt: vec![1, 2, 5],
+                rewired: vec![0],
+                shared: vec![3, 4],
+            }
+        );
+        assert!(Rc::ptr_eq(new.node(3), old.node(3)));
+        assert!(!Rc::ptr_eq(new.node(0), old.node(0)));
+        assert_eq!(*new.record(2), 20);
+
+        drop(old);
+        let two = new.node(0).edges[0].get().edges[0].get();
+        assert_eq!(two.value, 20);
+        assert_eq!(two.edges[1].get().value, 5);
+        assert_eq!(new.node(4).edges[0].get().value, 3);
+    }
+}
@@ -0,0 +1,96 @@
+//! `bevy_reflect` support for [`DeferredSync`].
+//!
+//! Cells reflect as opaque values, so scenes and editors can carry them around, clone
+//! them, and print them. Their state is inspected through the [`DeferredInspect`] type
+//! data registered with them, which reports whether a cell is set and which target it
+//! points at. [`Deferred`](crate::Deferred) is not `Send`, which `Reflect` requires, so
+//! only the thread-safe cells are reflected.
+//!
+//! ```
+//! use bevy_reflect::{Reflect, TypeRegistry};
+//! use deferred_cell::{DeferredSync, SetOnceSync, reflect::ReflectDeferredInspect};
+//! use std::sync::Arc;
+//!
+//! let mut registry = TypeRegistry::new();
+//! registry.register::<DeferredSync<u32>>();
+//!
+//! let target = Arc::new(7_u32);
+//! let cell = DeferredSync::default();
+//! SetOnceSync::from(&cell).try_set(&target).unwrap();
+//!
+//! let reflected: &dyn Reflect = &cell;
+//! let inspect = registry
+//!     .get_type_data::<ReflectDeferredInspect>(std::any::TypeId::of::<DeferredSync<u32>>())
+//!     .unwrap();
+//! let cell = inspect.get(reflected).unwrap();
+//! assert!(cell.is_ready());
+//! assert_eq!(cell.target_id(), Some(Arc::as_ptr(&target).addr()));
+//! ```
+
+use std::sync::Arc;
+
+use bevy_reflect::{impl_reflect_opaque, prelude::ReflectDefault, reflect_trait};
+
+use crate::DeferredSync;
+
+/// Read-only access to a reflected cell's state.
+#[reflect_trait]
+pub trait DeferredInspect {
+    fn is_ready(&self) -> bool;
+    /// Returns the address of the live target, which identifies it among the nodes of
+    /// a graph, or `None` if the cell is unset or its target was dropped.
+    fn target_id(&self) -> Option<usize>;
+}
+
+impl<T> DeferredInspect for DeferredSync<T> {
+    fn is_ready(&self) -> bool {
+        Self::is_ready(self)
+    }
+    fn target_id(&self) -> Option<usize> {
+        self.try_get()
+            .ok()
+            .map(|target| Arc::as_ptr(&target).addr())
+    }
+}
+
+impl_reflect_opaque!((in deferred_cell) DeferredSync<T: Send + Sync>(
+    Clone,
+    Debug,
+    Default,
+    DeferredInspect
+));
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use bevy_reflect::{PartialReflect, Reflect, TypePath};
+
+    use super::*;
+    use crate::SetOnceSync;
+
+    #[test]
+    fn reflected_cells_report_their_target() {
+        let target = Arc::new(1_u8);
+        let cell = DeferredSync::default();
+        let reflected: Box<dyn Reflect> = Box::new(cell.clone());
+        assert!(
+            !reflected
+                .downcast_ref::<DeferredSync<u8>>()
+                .unwrap()
+                .is_ready()
+        );
+
+        SetOnceSync::from(&cell).try_set(&target).unwrap();
+        let copy = cell.reflect_clone().unwrap();
+        let copy = copy.downcast_ref::<DeferredSync<u8>>().unwrap();
+        assert_eq!(
+            DeferredInspect::target_id(copy),
+            Some(Arc::as_ptr(&target).addr())
+        );
+        assert_eq!(
+            DeferredSync::<u8>::type_path(),
+            format!("deferred_cell::DeferredSync<{}>", u8::type_path())
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Live cell counts per cell type, enabled by the `debug-registry` feature.
+//!
+//! [`Deferred`](crate::Deferred) and [`DeferredSync`](crate::DeferredSync) cells are
+//! counted until dropped under the name of their own type, such as
+//! `deferred_cell::Deferred<app::Symbol>`, so a debugging session can ask how many
+//! cells of a kind exist and how many are still unset. Cells created through
+//! `default`, `from`, or `clone` are counted from creation. Cells created by the const
+//! `new` are counted from their first set, so they never show up as unset.
+//!
+//! The bookkeeping is stored in each cell, so with this feature a cell is several words
+//! wide instead of one. It does not change which programs compile: a cell may still be
+//! dropped after the data its target borrows.
+//!
+//! ```
+//! use deferred_cell::{Deferred, SetOnce, registry};
+//! use std::rc::Rc;
+//!
+//! struct Symbol;
+//!
+//! let cells: Vec<Deferred<Symbol>> = (0..3).map(|_| Deferred::default()).collect();
+//! let symbol = Rc::new(Symbol);
+//! SetOnce::from(&cells[0]).try_set(&symbol).unwrap();
+//!
+//! let counts = registry::counts_for::<Deferred<Symbol>>();
+//! assert_eq!(counts, registry::CellCounts { live: 3, unset: 2 });
+//! drop(cells);
+//! assert_eq!(registry::counts_for::<Deferred<Symbol>>().live, 0);
+//! ```
+
+use std::{
+    any::type_name,
+    collections::BTreeMap,
+    sync::{Mutex, PoisonError},
+};
+
+/// Counts of the cells of one cell type.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CellCounts {
+    /// Cells counted and not yet dropped.
+    pub live: usize,
+    /// Live cells that have not been set.
+    pub unset: usize,
+}
+
+static COUNTS: Mutex<BTreeMap<&'static str, CellCounts>> = Mutex::new(BTreeMap::new());
+
+pub(crate) fn record(cell: &'static str, update: impl FnOnce(&mut CellCounts)) {
+    let mut counts = COUNTS.lock().unwrap_or_else(PoisonError::into_inner);
+    update(counts.entry(cell).or_default());
+}
+
+/// Returns the counts of every cell type seen so far, keyed by type name.
+#[must_use]
+pub fn counts() -> BTreeMap<&'static str, CellCounts> {
+    COUNTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Returns the counts of cells of type `C`, such as `Deferred<Symbol>`.
+#[must_use]
+pub fn counts_for<C>() -> CellCounts {
+    COUNTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(type_name::<C>())
+        .copied()
+        .unwrap_or_default()
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use std::{rc::Rc, sync::Arc};
+
+    use super::*;
+    use crate::{Deferred, DeferredSync, SetOnce, SetOnceSync};
+
+    #[test]
+    fn tracks_every_way_of_creating_cells() {
+        struct Local;
+        struct Shared;
+
+        let local = Rc::new(Local);
+        let unset = Deferred::<Local>::default();
+        let set = Deferred::from(&local);
+        let copies = [unset.clone(), set.clone()];
+        assert_eq!(
+            counts_for::<Deferred<Local>>(),
+            CellCounts { live: 4, unset: 2 }
+        );
+        drop(copies);
+        assert_eq!(
+            counts_for::<Deferred<Local>>(),
+            CellCounts { live: 2, unset: 1 }
+        );
+        assert!(!unset.is_ready() && set.is_ready());
+
+        let shared = Arc::new(Shared);
+        let sync = DeferredSync::default();
+        let copy = sync.clone();
+        SetOnceSync::from(&sync).try_set(&shared).unwrap();
+        assert_eq!(
+            counts()[type_name::<DeferredSync<Shared>>()],
+            CellCounts { live: 2, unset: 1 }
+        );
+        drop((sync, copy));
+        assert_eq!(counts_for::<DeferredSync<Shared>>(), CellCounts::default());
+    }
+    #[test]
+    fn counts_const_cells_from_their_first_set() {
+        struct Target;
+
+        let target = Rc::new(Target);
+        let never_set = Deferred::<Target>::new();
+        let set_later = Deferred::new();
+        assert_eq!(counts_for::<Deferred<Target>>(), CellCounts::default());
+        SetOnce::from(&set_later).try_set(&target).unwrap();
+        assert_eq!(
+            counts_for::<Deferred<Target>>(),
+            CellCounts { live: 1, unset: 0 }
+        );
+        drop((never_set, set_later));
+        assert_eq!(counts_for::<Deferred<Target>>(), CellCounts::default());
+    }
+    #[test]
+    #[allow(clippy::needless_late_init)]
+    fn cells_may_outlive_what_their_targets_borrow() {
+        // Declared first, so it is dropped after `name`.
+        let cell: Deferred<&String>;
+        let name = String::from("a");
+        let target = Rc::new(&name);
+        cell = Deferred::from(&target);
+        assert!(cell.is_ready());
+    }
+    #[test]
+    fn keeps_cell_kinds_apart() {
+        struct Target;
+
+        let local = Deferred::<Target>::default();
+        let shared = DeferredSync::<Target>::default();
+        let single = CellCounts { live: 1, unset: 1 };
+        assert_eq!(counts_for::<Deferred<Target>>(), single);
+        assert_eq!(counts_for::<DeferredSync<Target>>(), single);
+        drop((local, shared));
+    }
+}
@@ -0,0 +1,197 @@
+//! Double-buffered graphs for hot reloading.
+//!
+//! Nodes wired through [`Deferred`](crate::Deferred) cells cannot be rewired once set,
+//! so reloading configuration means building a whole new graph. A [`GraphSlot`] holds
+//! the current graph behind a cheap [`GraphHandle`], and swaps a newly built graph in
+//! at once: readers that loaded the old handle keep the old graph alive, and fully
+//! wired, until they drop it, while new readers see the new one.
+//!
+//! ```
+//! use deferred_cell::{Deferred, SetOnce, reload::GraphSlot};
+//! use std::rc::Rc;
+//!
+//! struct Route {
+//!     port: u16,
+//!     fallback: Deferred<Route>,
+//! }
+//!
+//! fn build(port: u16) -> Vec<Rc<Route>> {
+//!     let routes: Vec<_> = [port, port + 1]
+//!         .map(|port| Rc::new(Route { port, fallback: Deferred::new() }))
+//!         .into();
+//!     SetOnce::from(&routes[0].fallback).try_set(&routes[1]).unwrap();
+//!     routes
+//! }
+//!
+//! let slot = GraphSlot::new(build(80));
+//! let reader = slot.load();
+//! slot.swap(build(8080));
+//!
+//! assert_eq!(reader[0].fallback.get().port, 81);
+//! assert_eq!(slot.load()[0].fallback.get().port, 8081);
+//! assert_eq!((reader.version(), slot.version()), (0, 1));
+//! ```
+
+use std::{
+    ops::Deref,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// A version of the graph in a [`GraphSlot`], kept alive while the handle is held.
+#[derive(Debug)]
+pub struct GraphHandle<T> {
+    inner: Arc<(u64, T)>,
+}
+
+impl<T> Clone for GraphHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> GraphHandle<T> {
+    /// Returns how many times the slot had been swapped when this graph was stored.
+    #[must_use]
+    pub fn version(&self) -> u64 {
+        self.inner.0
+    }
+}
+
+impl<T> Deref for GraphHandle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner.1
+    }
+}
+
+/// Holds the current version of a graph, replacing it as a whole.
+#[derive(Debug)]
+pub struct GraphSlot<T> {
+    current: Mutex<GraphHandle<T>>,
+    /// Held by writers for the whole of a swap or reload, so one reload cannot replace
+    /// a graph stored while it was building.
+    writer: Mutex<()>,
+}
+
+impl<T> GraphSlot<T> {
+    /// Stores `graph` as version `0`.
+    pub fn new(graph: T) -> Self {
+        Self {
+            current: Mutex::new(GraphHandle {
+                inner: Arc::new((0, graph)),
+            }),
+            writer: Mutex::new(()),
+        }
+    }
+    /// Returns a handle to the current graph.
+    pub fn load(&self) -> GraphHandle<T> {
+        self.lock().clone()
+    }
+    /// Returns the version of the current graph.
+    pub fn version(&self) -> u64 {
+        self.lock().version()
+    }
+    /// Replaces the current graph with `graph`, returning a handle to the one replaced.
+    pub fn swap(&self, graph: T) -> GraphHandle<T> {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut current = self.lock();
+        let next = GraphHandle {
+            inner: Arc::new((current.version() + 1, graph)),
+        };
+        std::mem::replace(&mut *current, next)
+    }
+    /// Builds a graph from the current one and swaps it in, returning a handle to the
+    /// new graph. If `build` fails, the current graph is kept.
+    ///
+    /// Readers keep loading the current graph while `build` runs. Swaps and other
+    /// reloads wait for it, so each reload builds from the graph it replaces.
+    pub fn reload<E>(&self, build: impl FnOnce(&T) -> Result<T, E>) -> Result<GraphHandle<T>, E> {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let current = self.load();
+        let next = GraphHandle {
+            inner: Arc::new((current.version() + 1, build(&current)?)),
+        };
+        *self.lock() = next.clone();
+        Ok(next)
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, GraphHandle<T>> {
+        self.current.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Deferred, SetOnce};
+    use std::rc::Rc;
+
+    struct Node {
+        value: u8,
+        next: Deferred<Self>,
+    }
+
+    fn cycle(value: u8) -> Vec<Rc<Node>> {
+        let nodes: Vec<_> = (value..value + 2)
+            .map(|value| {
+                Rc::new(Node {
+                    value,
+                    next: Deferred::new(),
+                })
+            })
+            .collect();
+        SetOnce::from(&nodes[0].next).try_set(&nodes[1]).unwrap();
+        SetOnce::from(&nodes[1].next).try_set(&nodes[0]).unwrap();
+        nodes
+    }
+
+    #[test]
+    fn old_versions_live_until_released() {
+        let slot = GraphSlot::new(cycle(1));
+        let old = slot.load();
+        let replaced = slot.swap(cycle(10));
+        assert_eq!(replaced.version(), old.version());
+        drop(replaced);
+        assert_eq!(old[1].next.get().value, 1);
+
+        let weak = Rc::downgrade(&old[0]);
+        drop(old);
+        assert!(weak.upgrade().is_none());
+
+        assert!(slot.reload(|_| Err("invalid")).is_err());
+        let reloaded = slot
+            .reload(|nodes| Ok::<_, ()>(cycle(nodes[0].value + 1)))
+            .unwrap();
+        assert_eq!(reloaded[0].next.get().value, 12);
+        assert_eq!(slot.version(), 2);
+    }
+    #[test]
+    fn readers_load_while_a_reload_builds() {
+        let slot = GraphSlot::new(cycle(1));
+        let reloaded = slot
+            .reload(|nodes| {
+                assert_eq!(slot.load().version(), 0);
+                Ok::<_, ()>(cycle(nodes[0].value + 1))
+            })
+            .unwrap();
+        assert_eq!(reloaded.version(), 1);
+    }
+    #[test]
+    fn concurrent_reloads_keep_every_update() {
+        let slot = GraphSlot::new(0u32);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..50 {
+                        slot.reload(|count| Ok::<_, ()>(count + 1)).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!((*slot.load(), slot.version()), (200, 200));
+    }
+}
@@ -0,0 +1,461 @@
+//! Key-based wiring for graphs assembled from data.
+//!
+//! A [`Resolver`] maps keys to nodes and queues symbolic edges between keys. Each edge
+//! is wired as soon as both of its endpoints are registered, so edges may be declared
+//! before or after the nodes they refer to.
+//!
+//! ```
+//! use deferred_cell::{Deferred, resolver::Resolver};
+//! use std::rc::Rc;
+//!
+//! struct Scene {
+//!     name: &'static str,
+//!     next: Deferred<Scene>,
+//! }
+//!
+//! let scene = |name| Rc::new(Scene { name, next: Deferred::new() });
+//! let mut resolver = Resolver::<&str, Scene>::new();
+//! resolver.edge("intro", |s| &s.next, "ending").unwrap();
+//! resolver.register("intro", scene("Intro")).unwrap();
+//! resolver.register("ending", scene("Ending")).unwrap();
+//!
+//! let scenes = resolver.finish().unwrap();
+//! assert_eq!(scenes["intro"].next.get().name, "Ending");
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    hash::Hash,
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+use crate::{Deferred, DeferredError, SetOnce};
+
+/// Selects the cell of a node that an edge is stored in.
+pub type Slot<T> = for<'n> fn(&'n T) -> &'n Deferred<T>;
+
+/// Selects the `k`th of several cells of a node that edges are stored in, such as an
+/// entry of a list.
+pub type IndexedSlot<T> = for<'n> fn(&'n T, usize) -> &'n Deferred<T>;
+
+/// Errors returned by [`Resolver`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ResolveError<K> {
+    #[error("Key {0:?} is already registered!")]
+    DuplicateKey(K),
+    #[error("Unresolved references: {0:?}")]
+    Unresolved(Vec<(K, K)>),
+    #[error("Key {0:?} refers to a removed arena entry!")]
+    Stale(K),
+    #[error("Key {0:?} is already wired or shared, so it can no longer be mutated!")]
+    Frozen(K),
+    #[error("Cannot hold more than {0} unresolved references!")]
+    PendingLimit(usize),
+    #[error(transparent)]
+    Wiring(#[from] DeferredError),
+}
+
+impl<K> ResolveError<K> {
+    /// Returns a stable code identifying the kind of error, continuing the codes of
+    /// [`DeferredError::code`]. Wiring errors report the code of the underlying error.
+    ///
+    /// | Code     | Error |
+    /// |----------|-------|
+    /// | `DC0005` | [`DuplicateKey`](Self::DuplicateKey) |
+    /// | `DC0006` | [`Unresolved`](Self::Unresolved) |
+    /// | `DC0007` | [`Stale`](Self::Stale) |
+    /// | `DC0008` | [`Frozen`](Self::Frozen) |
+    /// | `DC0009` | [`PendingLimit`](Self::PendingLimit) |
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::DuplicateKey(_) => "DC0005",
+            Self::Unresolved(_) => "DC0006",
+            Self::Stale(_) => "DC0007",
+            Self::Frozen(_) => "DC0008",
+            Self::PendingLimit(_) => "DC0009",
+            Self::Wiring(error) => error.code(),
+        }
+    }
+}
+
+/// Adds the stable [`code`](ResolveError::code) and a hint on how to fix each error.
+/// Wiring errors report the diagnostic of the underlying [`DeferredError`].
+#[cfg(feature = "miette")]
+impl<K: Debug> miette::Diagnostic for ResolveError<K> {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(self.code()))
+    }
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        match self {
+            Self::DuplicateKey(_) => Some(Box::new("register each key only once")),
+            Self::Unresolved(_) => Some(Box::new(
+                "register a node for every key these edges refer to, or remove the edges",
+            )),
+            Self::Stale(_) => Some(Box::new(
+                "register the index of a live entry, or remove the entry after wiring",
+            )),
+            Self::Frozen(_) => Some(Box::new(
+                "mutate nodes before declaring their edges, and before cloning their `Rc`",
+            )),
+            Self::PendingLimit(_) => Some(Box::new(
+                "register the nodes that pending edges refer to before declaring more",
+            )),
+            Self::Wiring(error) => error.help(),
+        }
+    }
+}
+
+/// Serializes as a map of the error's `kind`, its `message`, and the offending `key`,
+/// the unresolved `edges` as `[from, to]` pairs, the `limit`, or the wiring `error`.
+#[cfg(feature = "serde")]
+impl<K: Debug + serde::Serialize> serde::Serialize for ResolveError<K> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(3))?;
+        let kind = match self {
+            Self::DuplicateKey(_) => "duplicate_key",
+            Self::Unresolved(_) => "unresolved",
+            Self::Stale(_) => "stale",
+            Self::Frozen(_) => "frozen",
+            Self::PendingLimit(_) => "pending_limit",
+            Self::Wiring(_) => "wiring",
+        };
+        map.serialize_entry("kind", kind)?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Self::DuplicateKey(key) | Self::Stale(key) | Self::Frozen(key) => {
+                map.serialize_entry("key", key)?;
+            }
+            Self::Unresolved(edges) => map.serialize_entry("edges", edges)?,
+            Self::PendingLimit(limit) => map.serialize_entry("limit", limit)?,
+            Self::Wiring(error) => map.serialize_entry("error", error)?,
+        }
+        map.end()
+    }
+}
+
+/// The cell of its source node that an edge is stored in.
+enum Cell<T> {
+    Field(Slot<T>),
+    Indexed(IndexedSlot<T>, usize),
+}
+
+impl<T> Cell<T> {
+    fn of<'n>(&self, node: &'n T) -> &'n Deferred<T> {
+        match *self {
+            Self::Field(slot) => slot(node),
+            Self::Indexed(slot, k) => slot(node, k),
+        }
+    }
+}
+
+struct Edge<K, T> {
+    from: K,
+    cell: Cell<T>,
+    to: K,
+    /// How many edges were declared before this one.
+    order: usize,
+}
+
+/// Registered nodes, kept in registration order when [`Resolver::finish_indexed`] is
+/// available.
+#[cfg(feature = "indexmap")]
+type Nodes<K, T> = indexmap::IndexMap<K, Rc<T>>;
+#[cfg(not(feature = "indexmap"))]
+type Nodes<K, T> = HashMap<K, Rc<T>>;
+
+/// Registers nodes by key and wires edges between keys once both ends exist.
+pub struct Resolver<K, T> {
+    nodes: Nodes<K, T>,
+    /// Registered keys, in registration order, which `nodes` only keeps with the
+    /// `indexmap` feature.
+    #[cfg(not(feature = "indexmap"))]
+    registered: Vec<K>,
+    /// Pending edges, indexed by the endpoint they are waiting for.
+    waiting: HashMap<K, Vec<Edge<K, T>>>,
+    /// Keys of nodes that at least one edge has been wired from or to.
+    wired: HashSet<K>,
+    declared: usize,
+}
+
+impl<K, T> Default for Resolver<K, T> {
+    fn default() -> Self {
+        Self {
+            nodes: Nodes::default(),
+            #[cfg(not(feature = "indexmap"))]
+            registered: Vec::new(),
+            waiting: HashMap::new(),
+            wired: HashSet::new(),
+            declared: 0,
+        }
+    }
+}
+
+impl<K, T> Resolver<K, T>
+where
+    K: Eq + Hash + Clone + Debug,
+{
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers `node` under `key` and wires every pending edge it completes.
+    pub fn register(&mut self, key: K, node: Rc<T>) -> Result<(), ResolveError<K>> {
+        if self.nodes.contains_key(&key) {
+            return Err(ResolveError::DuplicateKey(key));
+        }
+        self.nodes.insert(key.clone(), node);
+        #[cfg(not(feature = "indexmap"))]
+        self.registered.push(key.clone());
+        let mut result = Ok(());
+        for edge in self.waiting.remove(&key).unwrap_or_default() {
+            let wired = self.add(edge);
+            if result.is_ok() {
+                result = wired;
+            }
+        }
+        result
+    }
+    /// Wires `slot(from)` to `to`, now if both keys are registered or later otherwise.
+    pub fn edge(&mut self, from: K, slot: Slot<T>, to: K) -> Result<(), ResolveError<K>> {
+        let order = self.declared;
+        self.declared += 1;
+        self.add(Edge {
+            from,
+            cell: Cell::Field(slot),
+            to,
+            order,
+        })
+    }
+    /// Like [`edge`](Self::edge), for the `k`th cell selected by `slot`, so that nodes
+    /// with a list of edges can be wired without a function per entry.
+    pub fn edge_at(
+        &mut self,
+        from: K,
+        slot: IndexedSlot<T>,
+        k: usize,
+        to: K,
+    ) -> Result<(), ResolveError<K>> {
+        let order = self.declared;
+        self.declared += 1;
+        self.add(Edge {
+            from,
+            cell: Cell::Indexed(slot, k),
+            to,
+            order,
+        })
+    }
+    /// Returns the node registered under `key`.
+    pub fn get(&self, key: &K) -> Option<&Rc<T>> {
+        self.nodes.get(key)
+    }
+    /// Returns the node registered under `key` for mutation, while it is still being
+    /// built.
+    ///
+    /// Fails once an edge has been wired from or to the node, since replacing its cells
+    /// would silently drop that edge, or while its `Rc` is shared outside the resolver.
+    pub fn get_mut(&mut self, key: &K) -> Result<Option<&mut T>, ResolveError<K>> {
+        if self.wired.contains(key) {
+            return Err(ResolveError::Frozen(key.clone()));
+        }
+        let Some(node) = self.nodes.get_mut(key) else {
+            return Ok(None);
+        };
+        Rc::get_mut(node)
+            .map(Some)
+            .ok_or_else(|| ResolveError::Frozen(key.clone()))
+    }
+    /// Returns the `(from, to)` keys of every edge still waiting for an endpoint, in the
+    /// order the edges were declared.
+    pub fn pending(&self) -> impl Iterator<Item = (&K, &K)> {
+        let mut edges: Vec<_> = self.waiting.values().flatten().collect();
+        edges.sort_unstable_by_key(|edge| edge.order);
+        edges.into_iter().map(|edge| (&edge.from, &edge.to))
+    }
+    /// Returns the number of edges still waiting for an endpoint.
+    pub fn pending_len(&self) -> usize {
+        self.waiting.values().map(Vec::len).sum()
+    }
+    /// Returns the registered nodes, or every edge that could not be resolved, in the
+    /// order the edges were declared.
+    pub fn finish(self) -> Result<HashMap<K, Rc<T>>, ResolveError<K>> {
+        Ok(self.resolved()?.into_iter().collect())
+    }
+    /// Like [`finish`](Self::finish), but returns the nodes in registration order.
+    #[cfg(feature = "indexmap")]
+    pub fn finish_ordered(self) -> Result<Vec<(K, Rc<T>)>, ResolveError<K>> {
+        Ok(self.resolved()?.into_iter().collect())
+    }
+    /// Like [`finish`](Self::finish), but returns the nodes in registration order.
+    #[cfg(not(feature = "indexmap"))]
+    pub fn finish_ordered(mut self) -> Result<Vec<(K, Rc<T>)>, ResolveError<K>> {
+        let registered = std::mem::take(&mut self.registered);
+        let mut nodes = self.resolved()?;
+        Ok(registered
+            .into_iter()
+            .filter_map(|key| {
+                let node = nodes.remove(&key)?;
+                Some((key, node))
+            })
+            .collect())
+    }
+    /// Like [`finish`](Self::finish), but returns an `IndexMap` that iterates in
+    /// registration order, so output built from it follows the order of the source
+    /// data. Requires the `indexmap` feature.
+    #[cfg(feature = "indexmap")]
+    pub fn finish_indexed(self) -> Result<indexmap::IndexMap<K, Rc<T>>, ResolveError<K>> {
+        self.resolved()
+    }
+    /// Returns the registered nodes, or every edge that could not be resolved, in the
+    /// order the edges were declared.
+    fn resolved(self) -> Result<Nodes<K, T>, ResolveError<K>> {
+        let mut edges: Vec<_> = self.waiting.into_values().flatten().collect();
+        edges.sort_unstable_by_key(|edge| edge.order);
+        let unresolved: Vec<_> = edges.into_iter().map(|edge| (edge.from, edge.to)).collect();
+        if unresolved.is_empty() {
+            Ok(self.nodes)
+        } else {
+            Err(ResolveError::Unresolved(unresolved))
+        }
+    }
+    fn add(&mut self, edge: Edge<K, T>) -> Result<(), ResolveError<K>> {
+        let Some(from) = self.nodes.get(&edge.from) else {
+            self.waiting
+                .entry(edge.from.clone())
+                .or_default()
+                .push(edge);
+            return Ok(());
+        };
+        let Some(to) = self.nodes.get(&edge.to) else {
+            self.waiting.entry(edge.to.clone()).or_default().push(edge);
+            return Ok(());
+        };
+        SetOnce::from(edge.cell.of(from)).try_set(to)?;
+        self.wired.insert(edge.from);
+        self.wired.insert(edge.to);
+        Ok(())
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Node {
+        name: &'static str,
+        links: Vec<Deferred<Self>>,
+    }
+
+    fn node(name: &'static str) -> Rc<Node> {
+        Rc::new(Node {
+            name,
+            links: vec![Deferred::new(), Deferred::new()],
+        })
+    }
+
+    #[test]
+    fn edges_resolve_in_any_order() {
+        let mut resolver = Resolver::new();
+        resolver.register("a", node("A")).unwrap();
+        resolver.edge("a", |n| &n.links[0], "b").unwrap();
+        resolver.edge("c", |n| &n.links[0], "a").unwrap();
+        resolver.edge("b", |n| &n.links[1], "c").unwrap();
+        assert_eq!(
+            resolver.pending().collect::<Vec<_>>(),
+            [(&"a", &"b"), (&"c", &"a"), (&"b", &"c")]
+        );
+
+        resolver.register("b", node("B")).unwrap();
+        resolver.register("c", node("C")).unwrap();
+        assert_eq!(resolver.pending().count(), 0);
+
+        let nodes = resolver.finish().unwrap();
+        assert_eq!(nodes["a"].links[0].get().name, "B");
+        assert_eq!(nodes["b"].links[1].get().name, "C");
+        assert_eq!(nodes["c"].links[0].get().name, "A");
+    }
+    #[test]
+    fn indexed_edges_pick_their_cell() {
+        let mut resolver = Resolver::<_, Node>::new();
+        for k in 0..2 {
+            resolver
+                .edge_at("a", |n, k| &n.links[k], k, ["b", "a"][k])
+                .unwrap();
+        }
+        resolver.register("a", node("A")).unwrap();
+        assert_eq!(resolver.pending().collect::<Vec<_>>(), [(&"a", &"b")]);
+        resolver.register("b", node("B")).unwrap();
+
+        let nodes = resolver.finish().unwrap();
+        assert_eq!(nodes["a"].links[0].get().name, "B");
+        assert_eq!(nodes["a"].links[1].get().name, "A");
+    }
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn indexed_finish_keeps_registration_order() {
+        let mut resolver = Resolver::new();
+        for key in ["c", "a", "b"] {
+            resolver.register(key, node(key)).unwrap();
+        }
+        resolver.edge("c", |n| &n.links[0], "a").unwrap();
+        let nodes = resolver.finish_indexed().unwrap();
+        assert_eq!(nodes.keys().copied().collect::<Vec<_>>(), ["c", "a", "b"]);
+        assert_eq!(nodes["c"].links[0].get().name, "a");
+    }
+    #[test]
+    fn nodes_are_mutable_until_wired() {
+        let mut resolver = Resolver::new();
+        resolver.register("a", node("A")).unwrap();
+        resolver.edge("a", |n| &n.links[0], "b").unwrap();
+        assert!(resolver.get_mut(&"b").unwrap().is_none());
+        resolver.get_mut(&"a").unwrap().unwrap().name = "A2";
+
+        let b = node("B");
+        resolver.register("b", Rc::clone(&b)).unwrap();
+        assert!(matches!(
+            resolver.get_mut(&"a"),
+            Err(ResolveError::Frozen("a"))
+        ));
+        resolver.register("c", b).unwrap();
+        assert!(matches!(
+            resolver.get_mut(&"c"),
+            Err(ResolveError::Frozen("c"))
+        ));
+        let nodes = resolver.finish_ordered().unwrap();
+        let keys: Vec<_> = nodes.iter().map(|(key, _)| *key).collect();
+        assert_eq!((keys, nodes[0].1.name), (vec!["a", "b", "c"], "A2"));
+    }
+    #[test]
+    fn resolver_reports_problems() {
+        let mut resolver = Resolver::new();
+        resolver.register("a", node("A")).unwrap();
+        assert!(matches!(
+            resolver.register("a", node("A2")),
+            Err(ResolveError::DuplicateKey("a"))
+        ));
+        resolver.edge("a", |n| &n.links[0], "a").unwrap();
+        assert!(matches!(
+            resolver.edge("a", |n| &n.links[0], "a"),
+            Err(ResolveError::Wiring(error))
+                if matches!(error.root_cause(), DeferredError::DuplicateInitialization())
+        ));
+        resolver.edge("z", |n| &n.links[0], "a").unwrap();
+        resolver.edge("a", |n| &n.links[1], "missing").unwrap();
+        assert!(matches!(
+            resolver.get_mut(&"a"),
+            Err(ResolveError::Frozen("a"))
+        ));
+        assert!(matches!(
+            resolver.finish(),
+            Err(ResolveError::Unresolved(edges)) if edges == [("z", "a"), ("a", "missing")]
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+//! Random connected slices of large graphs, for load tests.
+//!
+//! [`sample_subgraph`] grows a sample outward from a set of roots, taking one random
+//! node from the edge of the sample at a time, until it holds the requested number of
+//! nodes or runs out of reachable ones. Every node it takes was reached from one taken
+//! before it, so the sample is connected to the roots. Edges between sampled nodes are
+//! kept by index in [`Sample::edges`]; edges leaving the sample are listed separately
+//! in [`Sample::boundary`], so a test can tell a node's degree in the full graph from
+//! its degree in the slice. [`Sample::spec`] describes the slice as a [`GraphSpec`] to
+//! materialize an independent copy of it.
+//!
+//! The random source is any `FnMut() -> u64`, so the crate needs no `rand`
+//! dependency and a seeded generator gives the same sample on every run.
+//!
+//! ```
+//! use deferred_cell::{Deferred, sample, spec::GraphSpec};
+//!
+//! struct Node {
+//!     id: usize,
+//!     edges: Vec<Deferred<Node>>,
+//! }
+//!
+//! let ring: Vec<_> = (0..100).map(|i| (i, (i + 1) % 100)).collect();
+//! let nodes = GraphSpec::new(100, ring)
+//!     .materialize(
+//!         |id, degree| Node { id, edges: (0..degree).map(|_| Deferred::new()).collect() },
+//!         |node, k| &node.edges[k],
+//!     )
+//!     .unwrap();
+//!
+//! let mut state = 0x2545_f491_4f6c_dd1d_u64;
+//! let xorshift = move || {
+//!     state ^= state << 13;
+//!     state ^= state >> 7;
+//!     state ^= state << 17;
+//!     state
+//! };
+//! let targets = |n: &Node| n.edges.iter().map(Deferred::get).collect::<Vec<_>>();
+//! let sample = sample::sample_subgraph([&nodes[0]], targets, 10, xorshift);
+//! let ids: Vec<_> = sample.nodes.iter().map(|n| n.id).collect();
+//! assert_eq!(ids, (0..10).collect::<Vec<_>>());
+//! assert_eq!(sample.edges.len(), 9);
+//! assert_eq!(sample.boundary.len(), 1);
+//! assert_eq!(sample.boundary[0].1.id, 10);
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::spec::GraphSpec;
+
+/// A connected subgraph returned by [`sample_subgraph`].
+#[derive(Debug)]
+pub struct Sample<T> {
+    /// The sampled nodes, roots first, then in the order they were taken.
+    pub nodes: Vec<Rc<T>>,
+    /// Edges between sampled nodes, as indices into `nodes`.
+    pub edges: Vec<(usize, usize)>,
+    /// Edges leaving the sample, as the index of their source in `nodes` and their
+    /// target.
+    pub boundary: Vec<(usize, Rc<T>)>,
+}
+
+impl<T> Sample<T> {
+    /// Describes the sample without its boundary edges.
+    #[must_use]
+    pub fn spec(&self) -> GraphSpec {
+        GraphSpec::new(self.nodes.len(), self.edges.clone())
+    }
+}
+
+/// Samples up to `target_size` nodes connected to `roots`, following the edges
+/// `neighbors` returns and choosing among candidates with `rng`.
+///
+/// The roots are always taken first, and the sample is smaller than `target_size` only
+/// if fewer nodes are reachable.
+pub fn sample_subgraph<'r, T, I>(
+    roots: impl IntoIterator<Item = &'r Rc<T>>,
+    mut neighbors: impl FnMut(&T) -> I,
+    target_size: usize,
+    mut rng: impl FnMut() -> u64,
+) -> Sample<T>
+where
+    T: 'r,
+    I: IntoIterator<Item = Rc<T>>,
+{
+    let mut nodes: Vec<Rc<T>> = Vec::new();
+    let mut index = HashMap::new();
+    // The targets of each sampled node, in the order `neighbors` returned them.
+    let mut targets: Vec<Vec<Rc<T>>> = Vec::new();
+    let mut frontier: Vec<Rc<T>> = Vec::new();
+    let mut queued = HashSet::new();
+    let mut roots = roots.into_iter().map(Rc::clone);
+    while nodes.len() < target_size {
+        let node = match roots.next() {
+            Some(root) => root,
+            None if frontier.is_empty() => break,
+            None => frontier.swap_remove((rng() % frontier.len() as u64) as usize),
+        };
+        if index.contains_key(&Rc::as_ptr(&node)) {
+            continue;
+        }
+        let out: Vec<_> = neighbors(&node).into_iter().collect();
+        for target in &out {
+            if queued.insert(Rc::as_ptr(target)) {
+                frontier.push(Rc::clone(target));
+            }
+        }
+        index.insert(Rc::as_ptr(&node), nodes.len());
+        nodes.push(node);
+        targets.push(out);
+    }
+    let mut sample = Sample {
+        nodes,
+        edges: Vec::new(),
+        boundary: Vec::new(),
+    };
+    for (from, out) in targets.into_iter().enumerate() {
+        for target in out {
+            match index.get(&Rc::as_ptr(&target)) {
+                Some(&to) => sample.edges.push((from, to)),
+                None => sample.boundary.push((from, target)),
+            }
+        }
+    }
+    sample
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Deferred;
+
+    struct Node {
+        edges: Vec<Deferred<Self>>,
+    }
+
+    /// Returns 200 nodes with two edges each.
+    fn nodes() -> Vec<Rc<Node>> {
+        let edges: Vec<_> = (0..200)
+            .flat_map(|i| [(i, (i * 7 + 1) % 200), (i, (i * 13 + 5) % 200)])
+            .collect();
+        GraphSpec::new(200, edges)
+            .materialize(
+                |_, degree| Node {
+                    edges: (0..degree).map(|_| Deferred::new()).collect(),
+                },
+                |node, k| &node.edges[k],
+            )
+            .unwrap()
+    }
+
+    /// Samples up to `size` nodes from node 3, with a fixed seed.
+    fn sample(nodes: &[Rc<Node>], size: usize) -> Sample<Node> {
+        let mut state = 1_u64;
+        let rng = || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1);
+            state >> 33
+        };
+        let targets = |n: &Node| n.edges.iter().map(Deferred::get).collect::<Vec<_>>();
+        sample_subgraph([&nodes[3], &nodes[3]], targets, size, rng)
+    }
+
+    #[test]
+    fn samples_are_connected_and_account_for_every_edge() {
+        let nodes = nodes();
+        let slice = sample(&nodes, 50);
+        assert_eq!(slice.nodes.len(), 50);
+        assert!(Rc::ptr_eq(&slice.nodes[0], &nodes[3]));
+        assert_eq!(slice.edges.len() + slice.boundary.len(), 100);
+        assert!(
+            slice
+                .boundary
+                .iter()
+                .all(|(_, target)| { !slice.nodes.iter().any(|node| Rc::ptr_eq(node, target)) })
+        );
+        for taken in 1..50 {
+            assert!(
+                slice
+                    .edges
+                    .iter()
+                    .any(|&(from, to)| to == taken && from < taken)
+            );
+        }
+        assert_eq!(slice.spec().edges, slice.edges);
+    }
+    #[test]
+    fn large_samples_take_every_reachable_node() {
+        let all = sample(&nodes(), 1000);
+        assert_eq!(all.nodes.len(), 200);
+        assert!(all.boundary.is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+//! Helpers for trees linked by [`Deferred`] parent pointers, such as scene graphs and
+//! widget trees.
+//!
+//! [`ancestors`] walks from a node up its chain of parents. [`accumulate_from_root`]
+//! folds a value down that chain from the root to the node, which is how a world
+//! transform is composed from local ones, and [`accumulate_to_root`] folds it up from
+//! the node instead. Both folds fail with [`ParentCycle`] rather than looping forever if
+//! a corrupted graph's parent chain loops back on itself.
+//!
+//! [`dispatch`] propagates an event the way GUI toolkits do: down from the root to the
+//! target, then back up, until a handler returns [`Propagation::Stop`].
+//!
+//! ```
+//! use deferred_cell::{Deferred, SetOnce, scene};
+//! use std::rc::Rc;
+//!
+//! struct Entity {
+//!     offset: (i32, i32),
+//!     parent: Deferred<Entity>,
+//! }
+//!
+//! let entity = |offset| Rc::new(Entity { offset, parent: Deferred::new() });
+//! let (world, ship, turret) = (entity((0, 0)), entity((10, 5)), entity((1, -2)));
+//! SetOnce::from(&ship.parent).try_set(&world).unwrap();
+//! SetOnce::from(&turret.parent).try_set(&ship).unwrap();
+//!
+//! let position = scene::accumulate_from_root(&turret, |e| &e.parent, (0, 0), |(x, y), e| {
+//!     (x + e.offset.0, y + e.offset.1)
+//! });
+//! assert_eq!(position.unwrap(), (11, 3));
+//! ```
+
+use std::{collections::HashSet, rc::Rc};
+
+use thiserror::Error;
+
+use crate::Deferred;
+
+/// A parent chain that loops back to a node already on it.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Parent chain loops back on itself after {len} nodes!")]
+pub struct ParentCycle {
+    /// How many distinct nodes the chain holds, counting the starting node.
+    pub len: usize,
+}
+
+/// Iterator returned by [`ancestors`].
+#[derive(Debug)]
+pub struct Ancestors<T, F> {
+    next: Option<Rc<T>>,
+    parent: F,
+    seen: HashSet<*const T>,
+    cyclic: bool,
+}
+
+impl<T, F> Ancestors<T, F> {
+    /// Returns `true` if the walk stopped because the chain loops back on itself.
+    #[must_use]
+    pub const fn is_cyclic(&self) -> bool {
+        self.cyclic
+    }
+}
+
+impl<T, F: Fn(&T) -> &Deferred<T>> Iterator for Ancestors<T, F> {
+    type Item = Rc<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        let parent = (self.parent)(&node).try_get().ok()?;
+        if !self.seen.insert(Rc::as_ptr(&parent)) {
+            self.cyclic = true;
+            return None;
+        }
+        self.next = Some(Rc::clone(&parent));
+        Some(parent)
+    }
+}
+
+/// Visits the parent of `node`, then its parent, and so on up to the root.
+///
+/// The walk ends at the first unset or dangling parent cell, or before revisiting a
+/// node, which [`Ancestors::is_cyclic`] reports.
+pub fn ancestors<T, F>(node: &Rc<T>, parent: F) -> Ancestors<T, F>
+where
+    F: Fn(&T) -> &Deferred<T>,
+{
+    Ancestors {
+        next: Some(Rc::clone(node)),
+        parent,
+        seen: HashSet::from([Rc::as_ptr(node)]),
+        cyclic: false,
+    }
+}
+
+/// Returns `node` followed by all of its ancestors, or the cycle they form.
+fn chain<T>(node: &Rc<T>, parent: impl Fn(&T) -> &Deferred<T>) -> Result<Vec<Rc<T>>, ParentCycle> {
+    let mut walk = ancestors(node, parent);
+    let chain: Vec<_> = std::iter::once(Rc::clone(node)).chain(&mut walk).collect();
+    if walk.is_cyclic() {
+        Err(ParentCycle { len: chain.len() })
+    } else {
+        Ok(chain)
+    }
+}
+
+/// Folds `init` through every node from the root down to `node`, both included.
+pub fn accumulate_from_root<T, A>(
+    node: &Rc<T>,
+    parent: impl Fn(&T) -> &Deferred<T>,
+    init: A,
+    mut fold: impl FnMut(A, &T) -> A,
+) -> Result<A, ParentCycle> {
+    Ok(chain(node, parent)?
+        .iter()
+        .rev()
+        .fold(init, |acc, node| fold(acc, node)))
+}
+
+/// Folds `init` through every node from `node` up to the root, both included.
+pub fn accumulate_to_root<T, A>(
+    node: &Rc<T>,
+    parent: impl Fn(&T) -> &Deferred<T>,
+    init: A,
+    mut fold: impl FnMut(A, &T) -> A,
+) -> Result<A, ParentCycle> {
+    Ok(chain(node, parent)?
+        .iter()
+        .fold(init, |acc, node| fold(acc, node)))
+}
+
+/// Whether an event should keep propagating after a handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    Continue,
+    Stop,
+}
+
+/// The phase of [`dispatch`] a handler is called in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// On an ancestor of the target, going down from the root.
+    Capture,
+    /// On the target itself.
+    Target,
+    /// On an ancestor of the target, going back up to the root.
+    Bubble,
+}
+
+/// Calls `handler` on each ancestor of `target` from the root down, then on `target`,
+/// then on each ancestor again from the parent up, until it returns
+/// [`Propagation::Stop`].
+///
+/// Returns [`Propagation::Stop`] if a handler stopped the event. The ancestors are
+/// collected before any handler runs.
+///
+/// ```
+/// use deferred_cell::{Deferred, SetOnce, scene::{self, Phase, Propagation}};
+/// use std::rc::Rc;
+///
+/// struct Widget {
+///     name: &'static str,
+///     parent: Deferred<Widget>,
+/// }
+///
+/// let widget = |name| Rc::new(Widget { name, parent: Deferred::new() });
+/// let (window, form, button) = (widget("window"), widget("form"), widget("button"));
+/// SetOnce::from(&form.parent).try_set(&window).unwrap();
+/// SetOnce::from(&button.parent).try_set(&form).unwrap();
+///
+/// let mut log = Vec::new();
+/// let result = scene::dispatch(&button, |w| &w.parent, |phase, w| {
+///     log.push(format!("{phase:?} {}", w.name));
+///     if phase == Phase::Bubble && w.name == "form" {
+///         Propagation::Stop
+///     } else {
+///         Propagation::Continue
+///     }
+/// });
+/// assert_eq!(result.unwrap(), Propagation::Stop);
+/// assert_eq!(log, ["Capture window", "Capture form", "Target button", "Bubble form"]);
+/// ```
+pub fn dispatch<T>(
+    target: &Rc<T>,
+    parent: impl Fn(&T) -> &Deferred<T>,
+    mut handler: impl FnMut(Phase, &T) -> Propagation,
+) -> Result<Propagation, ParentCycle> {
+    let chain = chain(target, parent)?;
+    let ancestors = &chain[1..];
+    let calls = ancestors
+        .iter()
+        .rev()
+        .map(|node| (Phase::Capture, node))
+        .chain([(Phase::Target, target)])
+        .chain(ancestors.iter().map(|node| (Phase::Bubble, node)));
+    for (phase, node) in calls {
+        if handler(phase, node) == Propagation::Stop {
+            return Ok(Propagation::Stop);
+        }
+    }
+    Ok(Propagation::Continue)
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SetOnce;
+
+    struct Node {
+        name: &'static str,
+        parent: Deferred<Self>,
+    }
+
+    fn line(names: &[&'static str]) -> Vec<Rc<Node>> {
+        let nodes: Vec<_> = names
+            .iter()
+            .map(|&name| {
+                Rc::new(Node {
+                    name,
+                    parent: Deferred::new(),
+                })
+            })
+            .collect();
+        for pair in nodes.windows(2) {
+            SetOnce::from(&pair[1].parent).try_set(&pair[0]).unwrap();
+        }
+        nodes
+    }
+
+    fn parent(node: &Node) -> &Deferred<Node> {
+        &node.parent
+    }
+
+    fn path(acc: String, node: &Node) -> String {
+        format!("{acc}/{}", node.name)
+    }
+
+    #[test]
+    fn folds_walk_parent_chains_and_detect_cycles() {
+        let nodes = line(&["root", "arm", "hand"]);
+        let names: Vec<_> = ancestors(&nodes[2], parent).map(|n| n.name).collect();
+        assert_eq!(names, ["arm", "root"]);
+        assert_eq!(
+            accumulate_from_root(&nodes[2], parent, String::new(), path).unwrap(),
+            "/root/arm/hand"
+        );
+        assert_eq!(
+            accumulate_to_root(&nodes[1], parent, String::new(), path).unwrap(),
+            "/arm/root"
+        );
+
+        let looped = line(&["a", "b", "c"]);
+        SetOnce::from(&looped[0].parent)
+            .try_set(&looped[2])
+            .unwrap();
+        let mut walk = ancestors(&looped[1], parent);
+        assert_eq!(walk.by_ref().count(), 2);
+        assert!(walk.is_cyclic());
+        assert_eq!(
+            accumulate_from_root(&looped[1], parent, String::new(), path),
+            Err(ParentCycle { len: 3 })
+        );
+    }
+    #[test]
+    fn dispatch_captures_then_bubbles() {
+        let nodes = line(&["root", "panel", "button"]);
+        let mut calls = Vec::new();
+        let result = dispatch(&nodes[2], parent, |phase, node| {
+            calls.push((phase, node.name));
+            Propagation::Continue
+        });
+        assert_eq!(result.unwrap(), Propagation::Continue);
+        assert_eq!(
+            calls,
+            [
+                (Phase::Capture, "root"),
+                (Phase::Capture, "panel"),
+                (Phase::Target, "button"),
+                (Phase::Bubble, "panel"),
+                (Phase::Bubble, "root"),
+            ]
+        );
+
+        let mut calls = 0;
+        let result = dispatch(&nodes[2], parent, |_, _| {
+            calls += 1;
+            Propagation::Stop
+        });
+        assert_eq!((result.unwrap(), calls), (Propagation::Stop, 1));
+    }
+}
@@ -0,0 +1,255 @@
+//! Lexical scope chains for interpreters.
+//!
+//! An interpreter's environment is a chain of scopes, each linked to its parent by a
+//! [`Deferred`] cell, so [`Scope::lookup`] finds a name in the nearest scope defining
+//! it. Code may also refer to functions defined further down the file. Queue such a
+//! reference with [`ScopeChain::late_bind`] while parsing, and
+//! [`ScopeChain::resolve`] looks every one up once the whole file has been seen.
+//!
+//! The [`ScopeChain`] owns every scope, since children only hold weak links to their
+//! parents.
+//!
+//! ```
+//! use deferred_cell::{Deferred, scope::ScopeChain};
+//! use std::rc::Rc;
+//!
+//! struct Function {
+//!     name: &'static str,
+//! }
+//!
+//! struct Call {
+//!     callee: Deferred<Function>,
+//! }
+//!
+//! let mut chain = ScopeChain::new();
+//! let global = chain.global();
+//! let body = chain.push(&global);
+//!
+//! // `main` calls `helper`, which is only defined afterwards.
+//! let call = Rc::new(Call { callee: Deferred::new() });
+//! chain.late_bind(&body, "helper", &call, |c| &c.callee);
+//! global.define("helper", Rc::new(Function { name: "helper" }));
+//!
+//! chain.resolve().unwrap();
+//! assert_eq!(call.callee.get().name, "helper");
+//! assert!(body.lookup("helper").is_some());
+//! ```
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use thiserror::Error;
+
+use crate::{
+    Deferred, DeferredError,
+    late::{Link, LinkError, LinkQueue},
+};
+
+/// Errors returned by [`ScopeChain::resolve`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ScopeError {
+    #[error("Name {0:?} is not defined in any enclosing scope!")]
+    Unbound(String),
+    #[error("Name {name:?} could not be bound: {source}")]
+    Wiring { name: String, source: DeferredError },
+}
+
+/// Serializes as a map of the error's `kind`, its `message`, the `name`, and the
+/// wiring `error`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for ScopeError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        let (kind, name) = match self {
+            Self::Unbound(name) => ("unbound", name),
+            Self::Wiring { name, .. } => ("wiring", name),
+        };
+        map.serialize_entry("kind", kind)?;
+        map.serialize_entry("message", &self.to_string())?;
+        map.serialize_entry("name", name)?;
+        if let Self::Wiring { source, .. } = self {
+            map.serialize_entry("error", source)?;
+        }
+        map.end()
+    }
+}
+
+/// One scope of a [`ScopeChain`], binding names to values of type `V`.
+pub struct Scope<V> {
+    parent: Deferred<Self>,
+    bindings: RefCell<HashMap<String, Rc<V>>>,
+}
+
+impl<V> Scope<V> {
+    /// Binds `name` to `value` in this scope, returning the value it replaces.
+    pub fn define(&self, name: impl Into<String>, value: Rc<V>) -> Option<Rc<V>> {
+        self.bindings.borrow_mut().insert(name.into(), value)
+    }
+    /// Returns the value `name` is bound to here or in the nearest enclosing scope.
+    pub fn lookup(&self, name: &str) -> Option<Rc<V>> {
+        if let Some(value) = self.bindings.borrow().get(name) {
+            return Some(Rc::clone(value));
+        }
+        self.parent.try_get().ok()?.lookup(name)
+    }
+    /// Returns the enclosing scope, or `None` for the global scope.
+    pub fn parent(&self) -> Option<Rc<Self>> {
+        self.parent.try_get().ok()
+    }
+}
+
+/// Owns a tree of [`Scope`]s and the references waiting to be bound in them.
+pub struct ScopeChain<V> {
+    scopes: Vec<Rc<Scope<V>>>,
+    /// Queued references, by the scope and name they are looked up in.
+    late: LinkQueue<(Rc<Scope<V>>, String), V>,
+}
+
+impl<V> Default for ScopeChain<V> {
+    fn default() -> Self {
+        Self {
+            scopes: vec![Rc::new(Scope {
+                parent: Deferred::new(),
+                bindings: RefCell::default(),
+            })],
+            late: LinkQueue::default(),
+        }
+    }
+}
+
+impl<V> ScopeChain<V> {
+    /// Creates a chain holding only the global scope.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the global scope.
+    #[must_use]
+    pub fn global(&self) -> Rc<Scope<V>> {
+        Rc::clone(&self.scopes[0])
+    }
+    /// Creates a scope nested in `parent`.
+    pub fn push(&mut self, parent: &Rc<Scope<V>>) -> Rc<Scope<V>> {
+        let scope = Rc::new(Scope {
+            parent: Deferred::from(parent),
+            bindings: RefCell::default(),
+        });
+        self.scopes.push(Rc::clone(&scope));
+        scope
+    }
+    /// Queues `slot(owner)` to be bound to whatever `name` means in `scope` once
+    /// [`resolve`](Self::resolve) is called.
+    pub fn late_bind<O: 'static>(
+        &mut self,
+        scope: &Rc<Scope<V>>,
+        name: impl Into<String>,
+        owner: &Rc<O>,
+        slot: for<'n> fn(&'n O) -> &'n Deferred<V>,
+    ) where
+        V: 'static,
+    {
+        self.late
+            .push((Rc::clone(scope), name.into()), Link::new(owner, slot));
+    }
+    /// Binds every queued reference, reporting each one that cannot be bound.
+    ///
+    /// References that can be bound are bound either way.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn resolve(&mut self) -> Result<(), Vec<ScopeError>> {
+        let errors: Vec<_> = self
+            .late
+            .resolve(|(scope, name)| scope.lookup(name))
+            .into_iter()
+            .map(|((_, name), error)| match error {
+                LinkError::Wiring(source) => ScopeError::Wiring { name, source },
+                // Links made by `Link::new` never have the wrong type.
+                LinkError::Unbound | LinkError::WrongType => ScopeError::Unbound(name),
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Use {
+        value: Deferred<u8>,
+    }
+
+    fn reference() -> Rc<Use> {
+        Rc::new(Use {
+            value: Deferred::new(),
+        })
+    }
+
+    #[test]
+    fn lookups_walk_parents() {
+        let mut chain = ScopeChain::new();
+        let global = chain.global();
+        let outer = chain.push(&global);
+        let inner = chain.push(&outer);
+        global.define("x", Rc::new(1));
+        outer.define("y", Rc::new(2));
+        assert_eq!(inner.lookup("x").as_deref(), Some(&1));
+        assert!(global.lookup("y").is_none());
+        assert!(Rc::ptr_eq(&inner.parent().unwrap(), &outer));
+    }
+    #[test]
+    fn late_bindings_see_later_definitions() {
+        let mut chain = ScopeChain::new();
+        let global = chain.global();
+        let outer = chain.push(&global);
+        let inner = chain.push(&outer);
+        global.define("x", Rc::new(1));
+
+        let (shadowed, later) = (reference(), reference());
+        chain.late_bind(&inner, "x", &shadowed, |u| &u.value);
+        chain.late_bind(&outer, "z", &later, |u| &u.value);
+        inner.define("x", Rc::new(3));
+        global.define("z", Rc::new(4));
+
+        chain.resolve().unwrap();
+        assert_eq!(*shadowed.value.get(), 3);
+        assert_eq!(*later.value.get(), 4);
+    }
+    #[test]
+    fn unbound_and_unwirable_names_are_reported() {
+        let mut chain = ScopeChain::new();
+        let global = chain.global();
+        global.define("z", Rc::new(4));
+        let bound = reference();
+        chain.late_bind(&global, "z", &bound, |u| &u.value);
+        chain.late_bind(&global, "z", &bound, |u| &u.value);
+        chain.late_bind(&global, "nope", &reference(), |u| &u.value);
+
+        #[cfg(feature = "debug-location")]
+        let line = line!() + 1;
+        let errors = chain.resolve().unwrap_err();
+        assert_eq!(*bound.value.get(), 4);
+        let [
+            ScopeError::Wiring { name, source },
+            ScopeError::Unbound(unbound),
+        ] = &errors[..]
+        else {
+            panic!("expected a wiring error and an unbound name, got {errors:?}");
+        };
+        assert_eq!((name.as_str(), unbound.as_str()), ("z", "nope"));
+        assert!(matches!(
+            source.root_cause(),
+            DeferredError::DuplicateInitialization()
+        ));
+        #[cfg(feature = "debug-location")]
+        assert_eq!(source.location().unwrap().line(), line);
+        assert!(chain.resolve().is_ok());
+    }
+}
@@ -0,0 +1,344 @@
+//! Recording graph construction as a script that can be replayed.
+//!
+//! A [`Recorder`] wires nodes by key like a [`Resolver`], and also writes down each
+//! node and edge as a [`Script`] step. The script prints as plain text, one step per
+//! line, and parses back, so it can be attached to a bug report. [`Script::replay`]
+//! feeds the steps through a fresh recorder with the same slots to build an identical
+//! graph.
+//!
+//! Nodes are recorded with a string of data from which `make_node` can rebuild them
+//! on replay, and edges name their slot, registered with [`Recorder::slot`]. Each step
+//! is recorded before it is taken, so a step that fails is recorded too, and replaying
+//! the script stops at that step with the same error.
+//!
+//! ```
+//! use deferred_cell::{Deferred, script::{Recorder, Script}};
+//! use std::rc::Rc;
+//!
+//! struct Room {
+//!     name: String,
+//!     exit: Deferred<Room>,
+//! }
+//!
+//! fn room(_: &str, data: &str) -> Room {
+//!     Room { name: data.to_owned(), exit: Deferred::new() }
+//! }
+//!
+//! let recorder = || Recorder::new().slot("exit", |r: &Room| &r.exit);
+//! let mut building = recorder();
+//! for (key, name) in [("hall", "Great Hall"), ("vault", "Vault")] {
+//!     building.node(key, name, Rc::new(room(key, name))).unwrap();
+//! }
+//! building.edge("hall", "exit", "vault").unwrap();
+//! building.edge("vault", "exit", "hall").unwrap();
+//! let text = building.script().to_string();
+//!
+//! let script: Script = text.parse().unwrap();
+//! let (rooms, replayed) = script.replay(recorder(), room).unwrap();
+//! assert_eq!(rooms["hall"].exit.get().name, "Vault");
+//! assert_eq!(replayed.to_string(), text);
+//! ```
+
+use std::{collections::HashMap, fmt, rc::Rc, str::FromStr};
+
+use thiserror::Error;
+
+use crate::resolver::{ResolveError, Resolver, Slot};
+
+/// A problem recording, parsing, or replaying a [`Script`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ScriptError {
+    #[error("Slot {0:?} is not registered!")]
+    UnknownSlot(String),
+    #[error("Line {line} of the script is invalid: {reason}!")]
+    Parse { line: usize, reason: String },
+    #[error(transparent)]
+    Resolve(#[from] ResolveError<String>),
+}
+
+/// One step of a [`Script`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptStep {
+    /// Creates the node `key` from `data`.
+    Node { key: String, data: String },
+    /// Wires the cell `slot` of the node `from` to the node `to`.
+    Edge {
+        from: String,
+        slot: String,
+        to: String,
+    },
+}
+
+/// The steps that built a graph, in the order they were taken.
+///
+/// Prints one step per line, as `node`, key, and data, or `edge`, from, slot, and to,
+/// separated by tabs. Tabs, line breaks, and backslashes within fields are escaped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    pub steps: Vec<ScriptStep>,
+}
+
+impl Script {
+    /// Rebuilds the graph by taking every step with `recorder`, making each node with
+    /// `make_node(key, data)`.
+    ///
+    /// Returns the nodes by key, and the script recorded along the way, which equals
+    /// this one.
+    pub fn replay<T>(
+        &self,
+        mut recorder: Recorder<T>,
+        mut make_node: impl FnMut(&str, &str) -> T,
+    ) -> Result<(HashMap<String, Rc<T>>, Self), ScriptError> {
+        for step in &self.steps {
+            match step {
+                ScriptStep::Node { key, data } => {
+                    let node = Rc::new(make_node(key, data));
+                    recorder.node(key, data, node)?;
+                }
+                ScriptStep::Edge { from, slot, to } => recorder.edge(from, slot, to)?,
+            }
+        }
+        recorder.finish()
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            let fields = match step {
+                ScriptStep::Node { key, data } => vec!["node", key, data],
+                ScriptStep::Edge { from, slot, to } => vec!["edge", from, slot, to],
+            };
+            let fields: Vec<_> = fields.into_iter().map(escape).collect();
+            writeln!(f, "{}", fields.join("\t"))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |reason: &str| ScriptError::Parse {
+                line: index + 1,
+                reason: reason.to_owned(),
+            };
+            if line.is_empty() {
+                continue;
+            }
+            let fields = line
+                .split('\t')
+                .map(unescape)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| invalid("a field has an invalid escape"))?;
+            steps.push(match fields.as_slice() {
+                [kind, key, data] if kind == "node" => ScriptStep::Node {
+                    key: key.clone(),
+                    data: data.clone(),
+                },
+                [kind, from, slot, to] if kind == "edge" => ScriptStep::Edge {
+                    from: from.clone(),
+                    slot: slot.clone(),
+                    to: to.clone(),
+                },
+                _ => return Err(invalid("expected a node with 3 fields or an edge with 4")),
+            });
+        }
+        Ok(Self { steps })
+    }
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(field: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(unescaped)
+}
+
+/// Wires nodes by key while recording every step as a [`Script`].
+pub struct Recorder<T> {
+    resolver: Resolver<String, T>,
+    slots: Vec<(String, Slot<T>)>,
+    script: Script,
+}
+
+impl<T> Default for Recorder<T> {
+    fn default() -> Self {
+        Self {
+            resolver: Resolver::default(),
+            slots: Vec::new(),
+            script: Script::default(),
+        }
+    }
+}
+
+impl<T> Recorder<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers `slot` under `name`, for edges to refer to.
+    #[must_use]
+    pub fn slot(mut self, name: impl Into<String>, slot: Slot<T>) -> Self {
+        self.slots.push((name.into(), slot));
+        self
+    }
+    /// Records the step, then registers `node` under `key`, with `data` to rebuild it
+    /// from on replay.
+    pub fn node(
+        &mut self,
+        key: impl Into<String>,
+        data: impl Into<String>,
+        node: Rc<T>,
+    ) -> Result<(), ScriptError> {
+        let key = key.into();
+        self.script.steps.push(ScriptStep::Node {
+            key: key.clone(),
+            data: data.into(),
+        });
+        self.resolver.register(key, node)?;
+        Ok(())
+    }
+    /// Records the step, then wires the cell registered as `slot` of the node `from` to
+    /// the node `to`, once both are registered.
+    pub fn edge(
+        &mut self,
+        from: impl Into<String>,
+        slot: impl Into<String>,
+        to: impl Into<String>,
+    ) -> Result<(), ScriptError> {
+        let (from, slot, to) = (from.into(), slot.into(), to.into());
+        self.script.steps.push(ScriptStep::Edge {
+            from: from.clone(),
+            slot: slot.clone(),
+            to: to.clone(),
+        });
+        let Some(&(_, select)) = self.slots.iter().find(|(name, _)| *name == slot) else {
+            return Err(ScriptError::UnknownSlot(slot));
+        };
+        self.resolver.edge(from, select, to)?;
+        Ok(())
+    }
+    /// Returns the steps recorded so far.
+    #[must_use]
+    pub const fn script(&self) -> &Script {
+        &self.script
+    }
+    /// Returns every registered node by key, and the script that built them, or every
+    /// edge that is still pending.
+    pub fn finish(self) -> Result<(HashMap<String, Rc<T>>, Script), ScriptError> {
+        Ok((self.resolver.finish()?, self.script))
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Deferred;
+
+    struct Node {
+        label: String,
+        left: Deferred<Self>,
+        right: Deferred<Self>,
+    }
+
+    fn node(_: &str, label: &str) -> Node {
+        Node {
+            label: label.to_owned(),
+            left: Deferred::new(),
+            right: Deferred::new(),
+        }
+    }
+
+    fn recorder() -> Recorder<Node> {
+        Recorder::<Node>::new()
+            .slot("left", |n| &n.left)
+            .slot("right", |n| &n.right)
+    }
+
+    #[test]
+    fn scripts_round_trip_and_rebuild_the_graph() {
+        let mut original = recorder();
+        original.edge("a", "left", "b\tc").unwrap();
+        for (key, label) in [("a", "A\\1"), ("b\tc", "multi\nline\r")] {
+            original
+                .node(key, label, Rc::new(node(key, label)))
+                .unwrap();
+        }
+        original.edge("b\tc", "right", "a").unwrap();
+        let (_, script) = original.finish().unwrap();
+        let text = script.to_string();
+        assert_eq!(text.lines().count(), 4);
+
+        let parsed: Script = text.parse().unwrap();
+        assert_eq!(parsed, script);
+        let (nodes, replayed) = parsed.replay(recorder(), node).unwrap();
+        assert_eq!(replayed, script);
+        assert_eq!(nodes["a"].left.get().label, "multi\nline\r");
+        assert_eq!(nodes["b\tc"].right.get().label, "A\\1");
+    }
+    #[test]
+    fn trailing_carriage_returns_round_trip() {
+        let script = Script {
+            steps: vec![ScriptStep::Node {
+                key: "a\r".into(),
+                data: "\r".into(),
+            }],
+        };
+        assert_eq!(script.to_string(), "node\ta\\r\t\\r\n");
+        assert_eq!(script.to_string().parse::<Script>().unwrap(), script);
+    }
+    #[test]
+    fn failing_steps_are_recorded() {
+        let mut original = recorder();
+        original.node("a", "A", Rc::new(node("a", "A"))).unwrap();
+        assert!(matches!(
+            original.edge("a", "up", "a"),
+            Err(ScriptError::UnknownSlot(slot)) if slot == "up"
+        ));
+        assert!(matches!(
+            original.node("a", "A2", Rc::new(node("a", "A2"))),
+            Err(ScriptError::Resolve(ResolveError::DuplicateKey(key))) if key == "a"
+        ));
+        let script = original.script().clone();
+        assert_eq!(script.steps.len(), 3);
+        assert!(matches!(
+            script.replay(recorder(), node),
+            Err(ScriptError::UnknownSlot(slot)) if slot == "up"
+        ));
+    }
+    #[test]
+    fn invalid_lines_are_reported() {
+        let error = "node\ta\tA\nedge\ta\tleft".parse::<Script>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Line 2 of the script is invalid: expected a node with 3 fields or an edge with 4!"
+        );
+        assert!("node\ta\\x\tA".parse::<Script>().is_err());
+    }
+}
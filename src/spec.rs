@@ -0,0 +1,256 @@
+//! Plain-data graph descriptions.
+//!
+//! A [`GraphSpec`] lists a node count and directed edges by index. It is easy to
+//! generate, inspect, and store, and [`GraphSpec::materialize`] turns it into wired
+//! `Rc` nodes. [`GraphSpec::minimize`] shrinks a failing spec down to a small repro.
+//!
+//! ```
+//! use deferred_cell::{Deferred, spec::GraphSpec};
+//!
+//! struct Node {
+//!     id: usize,
+//!     edges: Vec<Deferred<Node>>,
+//! }
+//!
+//! let spec = GraphSpec::new(3, vec![(0, 1), (0, 2), (2, 0)]);
+//! let nodes = spec
+//!     .materialize(
+//!         |id, degree| Node { id, edges: (0..degree).map(|_| Deferred::new()).collect() },
+//!         |node, k| &node.edges[k],
+//!     )
+//!     .unwrap();
+//! assert_eq!(nodes[0].edges[1].get().id, 2);
+//! assert_eq!(nodes[2].edges[0].get().id, 0);
+//! ```
+
+use std::rc::Rc;
+
+use crate::{Deferred, DeferredError, SetOnce};
+
+/// A graph of `nodes` nodes and directed `(from, to)` edges between node indices.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct GraphSpec {
+    pub nodes: usize,
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl GraphSpec {
+    /// Creates a spec.
+    ///
+    /// # Panics
+    /// Panics if an edge refers to a node index outside `0..nodes`.
+    #[must_use]
+    pub fn new(nodes: usize, edges: Vec<(usize, usize)>) -> Self {
+        assert!(
+            edges.iter().all(|&(from, to)| from < nodes && to < nodes),
+            "edge endpoint out of range for {nodes} nodes"
+        );
+        Self { nodes, edges }
+    }
+    /// Returns the number of edges leaving each node.
+    #[must_use]
+    pub fn out_degrees(&self) -> Vec<usize> {
+        let mut degrees = vec![0; self.nodes];
+        for &(from, _) in &self.edges {
+            degrees[from] += 1;
+        }
+        degrees
+    }
+    /// Returns `true` if the edges contain a directed cycle, including self-loops.
+    #[must_use]
+    pub fn is_cyclic(&self) -> bool {
+        let mut remaining = vec![0usize; self.nodes];
+        for &(_, to) in &self.edges {
+            remaining[to] += 1;
+        }
+        let mut ready: Vec<_> = (0..self.nodes).filter(|&n| remaining[n] == 0).collect();
+        let mut visited = 0;
+        while let Some(node) = ready.pop() {
+            visited += 1;
+            for &(_, to) in self.edges.iter().filter(|&&(from, _)| from == node) {
+                remaining[to] -= 1;
+                if remaining[to] == 0 {
+                    ready.push(to);
+                }
+            }
+        }
+        visited < self.nodes
+    }
+    /// Creates every node with `make_node(index, out_degree)` and wires the edges.
+    ///
+    /// A node's outgoing edges are stored through `slot(node, k)` for `k` in
+    /// `0..out_degree`, in the order they appear in `edges`.
+    pub fn materialize<T>(
+        &self,
+        mut make_node: impl FnMut(usize, usize) -> T,
+        slot: impl Fn(&T, usize) -> &Deferred<T>,
+    ) -> Result<Vec<Rc<T>>, DeferredError> {
+        let nodes: Vec<_> = self
+            .out_degrees()
+            .into_iter()
+            .enumerate()
+            .map(|(index, degree)| Rc::new(make_node(index, degree)))
+            .collect();
+        let mut next_slot = vec![0; self.nodes];
+        for &(from, to) in &self.edges {
+            SetOnce::from(slot(&nodes[from], next_slot[from])).try_set(&nodes[to])?;
+            next_slot[from] += 1;
+        }
+        Ok(nodes)
+    }
+    /// Returns a copy without node `index` and its edges; later nodes shift down by one.
+    #[must_use]
+    pub fn without_node(&self, index: usize) -> Self {
+        let shift = |n: usize| if n > index { n - 1 } else { n };
+        Self {
+            nodes: self.nodes - 1,
+            edges: self
+                .edges
+                .iter()
+                .filter(|&&(from, to)| from != index && to != index)
+                .map(|&(from, to)| (shift(from), shift(to)))
+                .collect(),
+        }
+    }
+    /// Returns smaller valid specs, largest reductions first: the first half of the
+    /// nodes, each single node removed, half of the edges, and each single edge removed.
+    pub fn shrink(&self) -> impl Iterator<Item = Self> + '_ {
+        let half_nodes = (self.nodes > 1).then(|| {
+            let keep = self.nodes / 2;
+            (keep..self.nodes)
+                .rev()
+                .fold(self.clone(), |spec, index| spec.without_node(index))
+        });
+        let half_edges = (self.edges.len() > 1).then(|| {
+            let mid = self.edges.len() / 2;
+            [&self.edges[..mid], &self.edges[mid..]].map(|edges| Self {
+                nodes: self.nodes,
+                edges: edges.to_vec(),
+            })
+        });
+        let without_edge = (0..self.edges.len()).map(|i| {
+            let mut smaller = self.clone();
+            smaller.edges.remove(i);
+            smaller
+        });
+        half_nodes
+            .into_iter()
+            .chain((0..self.nodes).map(|index| self.without_node(index)))
+            .chain(half_edges.into_iter().flatten())
+            .chain(without_edge)
+    }
+    /// Greedily shrinks the spec while `fails` keeps returning `true`, returning a spec
+    /// none of whose [`shrink`](Self::shrink) candidates still fails.
+    #[must_use]
+    pub fn minimize(self, mut fails: impl FnMut(&Self) -> bool) -> Self {
+        let mut current = self;
+        loop {
+            let Some(smaller) = current.shrink().find(|candidate| fails(candidate)) else {
+                return current;
+            };
+            current = smaller;
+        }
+    }
+}
+
+/// Generates a spec of up to 255 nodes whose edges always refer to existing nodes.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for GraphSpec {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let nodes = usize::from(u.arbitrary::<u8>()?);
+        if nodes == 0 {
+            return Ok(Self::default());
+        }
+        let edges = u
+            .arbitrary_iter::<(u8, u8)>()?
+            .map(|pair| pair.map(|(from, to)| (usize::from(from) % nodes, usize::from(to) % nodes)))
+            .collect::<arbitrary::Result<_>>()?;
+        Ok(Self { nodes, edges })
+    }
+}
+
+/// Generates specs of up to `g.size()` nodes, shrinking with [`GraphSpec::shrink`].
+#[cfg(feature = "quickcheck")]
+impl quickcheck::Arbitrary for GraphSpec {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        let nodes = usize::arbitrary(g) % (g.size() + 1);
+        if nodes == 0 {
+            return Self::default();
+        }
+        let edges = (0..usize::arbitrary(g) % (nodes * 2 + 1))
+            .map(|_| (usize::arbitrary(g) % nodes, usize::arbitrary(g) % nodes))
+            .collect();
+        Self { nodes, edges }
+    }
+    // The boxed iterator must be `'static`, so the borrowing candidates are collected first.
+    #[allow(clippy::needless_collect)]
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let candidates: Vec<_> = Self::shrink(self).collect();
+        Box::new(candidates.into_iter())
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cycles_are_detected() {
+        assert!(!GraphSpec::new(3, vec![(0, 1), (1, 2), (0, 2)]).is_cyclic());
+        assert!(GraphSpec::new(3, vec![(0, 1), (1, 2), (2, 1)]).is_cyclic());
+        assert!(GraphSpec::new(1, vec![(0, 0)]).is_cyclic());
+    }
+    #[test]
+    fn shrinking_keeps_specs_valid() {
+        let spec = GraphSpec::new(4, vec![(0, 1), (1, 2), (2, 3), (3, 0)]);
+        assert_eq!(
+            spec.without_node(1),
+            GraphSpec::new(3, vec![(1, 2), (2, 0)])
+        );
+        for smaller in spec.shrink() {
+            assert_eq!(
+                GraphSpec::new(smaller.nodes, smaller.edges.clone()),
+                smaller
+            );
+        }
+    }
+    #[test]
+    fn minimize_finds_a_minimal_cycle() {
+        let mut edges: Vec<_> = (0..50).map(|i| (i, i + 1)).collect();
+        edges.push((30, 20));
+        let minimal = GraphSpec::new(51, edges).minimize(GraphSpec::is_cyclic);
+        assert!(minimal.is_cyclic());
+        assert_eq!(minimal.nodes, 11);
+        assert_eq!(minimal.edges.len(), 11);
+    }
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_specs_are_valid() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let spec = GraphSpec::arbitrary(&mut Unstructured::new(&[5; 64])).unwrap();
+        assert!(spec.nodes > 0 && !spec.edges.is_empty());
+        assert_eq!(GraphSpec::new(spec.nodes, spec.edges.clone()), spec);
+    }
+    #[cfg(feature = "quickcheck")]
+    #[test]
+    fn quickcheck_specs_shrink_to_valid_specs() {
+        use quickcheck::{Arbitrary, Gen};
+
+        let spec = GraphSpec::arbitrary(&mut Gen::new(20));
+        for smaller in spec.shrink() {
+            assert_eq!(
+                GraphSpec::new(smaller.nodes, smaller.edges.clone()),
+                smaller
+            );
+            assert!(smaller.nodes + smaller.edges.len() < spec.nodes + spec.edges.len());
+        }
+    }
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn out_of_range_edges_are_rejected() {
+        let _ = GraphSpec::new(2, vec![(0, 2)]);
+    }
+}
@@ -0,0 +1,134 @@
+//! [`proptest`](mod@proptest) strategies for random graphs.
+//!
+//! [`graph_spec`] generates [`GraphSpec`]s, which shrink toward fewer nodes and edges;
+//! [`graph`] materializes them into wired nodes of your own type.
+//!
+//! ```
+//! use deferred_cell::{Deferred, strategy::{GraphParams, graph}};
+//! use proptest::prelude::*;
+//!
+//! #[derive(Debug)]
+//! struct Node {
+//!     id: usize,
+//!     edges: Vec<Deferred<Node>>,
+//! }
+//!
+//! proptest!(|(nodes in graph(
+//!     GraphParams { nodes: 1..20, density: 0.3, cyclic: false },
+//!     |id, degree| Node { id, edges: (0..degree).map(|_| Deferred::new()).collect() },
+//!     |node, k| &node.edges[k],
+//! ))| {
+//!     for node in &nodes {
+//!         for edge in &node.edges {
+//!             prop_assert!(edge.get().id > node.id);
+//!         }
+//!     }
+//! });
+//! ```
+
+use std::{fmt::Debug, ops::Range, rc::Rc};
+
+use proptest::prelude::*;
+
+use crate::{Deferred, spec::GraphSpec};
+
+/// Shape of the graphs generated by [`graph_spec`] and [`graph`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphParams {
+    /// Range of node counts.
+    pub nodes: Range<usize>,
+    /// Probability that any given ordered pair of nodes is joined by an edge.
+    pub density: f64,
+    /// Whether edges may form cycles. When `false`, edges only run from lower to
+    /// higher node indices.
+    pub cyclic: bool,
+}
+
+impl Default for GraphParams {
+    fn default() -> Self {
+        Self {
+            nodes: 0..32,
+            density: 0.2,
+            cyclic: true,
+        }
+    }
+}
+
+/// Generates random [`GraphSpec`]s without self-loops.
+pub fn graph_spec(params: GraphParams) -> impl Strategy<Value = GraphSpec> {
+    let GraphParams {
+        nodes,
+        density,
+        cyclic,
+    } = params;
+    nodes.prop_flat_map(move |n| {
+        let pairs: Vec<_> = (0..n)
+            .flat_map(|from| (0..n).map(move |to| (from, to)))
+            .filter(|&(from, to)| if cyclic { from != to } else { from < to })
+            .collect();
+        prop::collection::vec(prop::bool::weighted(density), pairs.len()).prop_map(move |chosen| {
+            let edges = pairs
+                .iter()
+                .zip(chosen)
+                .filter_map(|(&pair, keep)| keep.then_some(pair))
+                .collect();
+            GraphSpec::new(n, edges)
+        })
+    })
+}
+
+/// Generates random graphs of `T`, fully wired and returned in index order.
+///
+/// Nodes are created with `make_node(index, out_degree)` and edges are stored through
+/// `slot(node, k)`, as in [`GraphSpec::materialize`].
+///
+/// # Panics
+/// Generation panics if `slot` returns the same cell for two edges of one node.
+pub fn graph<T, M, S>(
+    params: GraphParams,
+    make_node: M,
+    slot: S,
+) -> impl Strategy<Value = Vec<Rc<T>>>
+where
+    T: Debug,
+    M: Fn(usize, usize) -> T,
+    S: Fn(&T, usize) -> &Deferred<T>,
+{
+    graph_spec(params).prop_map(move |spec| match spec.materialize(&make_node, &slot) {
+        Ok(nodes) => nodes,
+        Err(error) => panic!("`slot` must return a distinct cell per edge: {error}"),
+    })
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Node(Vec<Deferred<Self>>);
+
+    proptest! {
+        #[test]
+        fn acyclic_specs_have_no_cycles(spec in graph_spec(GraphParams {
+            cyclic: false,
+            ..GraphParams::default()
+        })) {
+            prop_assert!(!spec.is_cyclic());
+        }
+        #[test]
+        fn generated_graphs_match_their_spec(spec in graph_spec(GraphParams::default())) {
+            let nodes = spec
+                .materialize(
+                    |_, degree| Node((0..degree).map(|_| Deferred::new()).collect()),
+                    |node, k| &node.0[k],
+                )
+                .unwrap();
+            let wired: usize = nodes
+                .iter()
+                .map(|node| node.0.iter().filter(|cell| cell.is_ready()).count())
+                .sum();
+            prop_assert_eq!(wired, spec.edges.len());
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Two-pass symbol resolution for syntax trees.
+//!
+//! Identifiers in a syntax tree usually need to point at their declarations, which may
+//! come later in the source or in an enclosing scope. While walking the tree, register
+//! each declaration with [`SymbolTable::declare`] and queue each use with
+//! [`SymbolTable::refer`], entering and leaving scopes as blocks open and close. Then
+//! [`SymbolTable::resolve`] finds each use's declaration in its own scope or the nearest
+//! enclosing one, wires its [`Deferred`] cell, and reports every undefined or duplicate
+//! symbol with its span. Declarations are registered in a [`Resolver`] keyed by scope
+//! and name.
+//!
+//! ```
+//! use deferred_cell::{Deferred, symbols::SymbolTable};
+//! use std::rc::Rc;
+//!
+//! struct Let {
+//!     name: &'static str,
+//! }
+//!
+//! struct Ident {
+//!     decl: Deferred<Let>,
+//! }
+//!
+//! // let x; { use x; let y; } use y;
+//! let mut table = SymbolTable::new();
+//! let (x, y) = (Rc::new(Let { name: "x" }), Rc::new(Let { name: "y" }));
+//! let ident = || Rc::new(Ident { decl: Deferred::new() });
+//! let (inner, outer) = (ident(), ident());
+//!
+//! table.declare("x", 4..5, &x);
+//! table.enter();
+//! table.refer("x", 13..14, &inner, |i| &i.decl);
+//! table.declare("y", 20..21, &y);
+//! table.leave();
+//! table.refer("y", 29..30, &outer, |i| &i.decl);
+//!
+//! let errors = table.resolve().unwrap_err();
+//! assert_eq!(inner.decl.get().name, "x");
+//! assert_eq!(errors[0].to_string(), "Undefined symbol `y` at 29..30!");
+//! ```
+
+use std::{collections::HashMap, fmt::Debug, iter, rc::Rc};
+
+use thiserror::Error;
+
+use crate::{
+    Deferred, DeferredError,
+    late::{Link, LinkError, LinkQueue},
+    resolver::{ResolveError, Resolver},
+};
+
+/// Problems found by [`SymbolTable::resolve`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum SymbolError<S> {
+    #[error("Undefined symbol `{name}` at {span:?}!")]
+    Undefined { name: String, span: S },
+    #[error("Symbol `{name}` at {second:?} is already declared at {first:?}!")]
+    Duplicate { name: String, first: S, second: S },
+    #[error("Symbol `{name}` at {span:?} could not be wired: {source}")]
+    Wiring {
+        name: String,
+        span: S,
+        source: DeferredError,
+    },
+}
+
+/// Serializes as a map of the error's `kind`, its `message`, the `name`, its `span`
+/// or the `first` and `second` spans of a duplicate, and the wiring `error`.
+#[cfg(feature = "serde")]
+impl<S: Debug + serde::Serialize> serde::Serialize for SymbolError<S> {
+    fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        let kind = match self {
+            Self::Undefined { .. } => "undefined",
+            Self::Duplicate { .. } => "duplicate",
+            Self::Wiring { .. } => "wiring",
+        };
+        map.serialize_entry("kind", kind)?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Self::Undefined { name, span } => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("span", span)?;
+            }
+            Self::Duplicate {
+                name,
+                first,
+                second,
+            } => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("first", first)?;
+                map.serialize_entry("second", second)?;
+            }
+            Self::Wiring { name, span, source } => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("span", span)?;
+                map.serialize_entry("error", source)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// A use waiting for its declaration.
+struct Use<S> {
+    scope: usize,
+    name: Rc<str>,
+    span: S,
+}
+
+/// Collects scoped declarations of type `D`, and the uses that should point at them,
+/// with spans of type `S`.
+pub struct SymbolTable<S, D> {
+    /// The parent of each scope, with the outermost scope first.
+    parents: Vec<Option<usize>>,
+    current: usize,
+    /// The first declaration of each name in each scope.
+    declarations: Resolver<(usize, Rc<str>), D>,
+    /// The span of each registered declaration.
+    spans: HashMap<(usize, Rc<str>), S>,
+    /// Duplicate declarations, in the order they were made.
+    duplicates: Vec<SymbolError<S>>,
+    uses: LinkQueue<Use<S>, D>,
+}
+
+impl<S, D> Default for SymbolTable<S, D> {
+    fn default() -> Self {
+        Self {
+            parents: vec![None],
+            current: 0,
+            declarations: Resolver::new(),
+            spans: HashMap::new(),
+            duplicates: Vec::new(),
+            uses: LinkQueue::default(),
+        }
+    }
+}
+
+impl<S: Clone + Debug, D> SymbolTable<S, D> {
+    /// Creates a table whose current scope is the outermost one.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Opens a scope nested in the current one.
+    pub fn enter(&mut self) {
+        self.parents.push(Some(self.current));
+        self.current = self.parents.len() - 1;
+    }
+    /// Closes the current scope, returning to its parent. Does nothing in the
+    /// outermost scope.
+    pub fn leave(&mut self) {
+        self.current = self.parents[self.current].unwrap_or(self.current);
+    }
+    /// Declares `node` as `name` in the current scope.
+    pub fn declare(&mut self, name: impl Into<String>, span: S, node: &Rc<D>) {
+        let key = (self.current, Rc::from(name.into()));
+        match self.declarations.register(key.clone(), Rc::clone(node)) {
+            Ok(()) => {
+                self.spans.insert(key, span);
+            }
+            Err(ResolveError::DuplicateKey(key)) => {
+                self.duplicates.push(SymbolError::Duplicate {
+                    first: self.spans[&key].clone(),
+                    name: key.1.to_string(),
+                    second: span,
+                });
+            }
+            Err(error) => unreachable!("declarations are registered without edges: {error}"),
+        }
+    }
+    /// Queues `slot(owner)` to point at the declaration `name` is bound to in the
+    /// current scope, which may be declared later.
+    pub fn refer<U: 'static>(
+        &mut self,
+        name: impl Into<String>,
+        span: S,
+        owner: &Rc<U>,
+        slot: for<'n> fn(&'n U) -> &'n Deferred<D>,
+    ) where
+        D: 'static,
+    {
+        let reference = Use {
+            scope: self.current,
+            name: Rc::from(name.into()),
+            span,
+        };
+        self.uses.push(reference, Link::new(owner, slot));
+    }
+    /// Wires every use to its declaration.
+    ///
+    /// A use is bound to the declaration in its own scope, or else in the nearest
+    /// enclosing scope that has one. Every symbol declared twice in one scope is
+    /// reported, then every use that is undefined or cannot be wired, each in the order
+    /// they were added. All other uses are still wired, and a duplicate's uses are bound
+    /// to its first declaration.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn resolve(mut self) -> Result<(), Vec<SymbolError<S>>> {
+        let mut errors = self.duplicates;
+        let (parents, declarations) = (&self.parents, &self.declarations);
+        let failed = self.uses.resolve(|reference| {
+            iter::successors(Some(reference.scope), |&at| parents[at])
+                .find_map(|at| declarations.get(&(at, Rc::clone(&reference.name))))
+                .cloned()
+        });
+        errors.extend(failed.into_iter().map(|(reference, error)| {
+            let (name, span) = (reference.name.to_string(), reference.span);
+            match error {
+                LinkError::Wiring(source) => SymbolError::Wiring { name, span, source },
+                // Links made by `Link::new` never have the wrong type.
+                LinkError::Unbound | LinkError::WrongType => SymbolError::Undefined { name, span },
+            }
+        }));
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use std::ops::Range;
+
+    use super::*;
+
+    struct Decl {
+        id: u8,
+    }
+
+    struct Ident {
+        decl: Deferred<Decl>,
+    }
+
+    fn ident() -> Rc<Ident> {
+        Rc::new(Ident {
+            decl: Deferred::new(),
+        })
+    }
+
+    fn decls(count: u8) -> Vec<Rc<Decl>> {
+        (0..count).map(|id| Rc::new(Decl { id })).collect()
+    }
+
+    #[test]
+    fn uses_bind_to_the_nearest_declaration() {
+        let decls = decls(4);
+        let uses: Vec<_> = (0..4).map(|_| ident()).collect();
+        let mut table: SymbolTable<Range<usize>, Decl> = SymbolTable::new();
+        table.refer("f", 0..1, &uses[0], |i| &i.decl);
+        table.declare("f", 1..2, &decls[0]);
+        table.enter();
+        table.declare("x", 2..3, &decls[1]);
+        table.enter();
+        table.declare("x", 3..4, &decls[2]);
+        table.refer("x", 4..5, &uses[1], |i| &i.decl);
+        table.refer("f", 5..6, &uses[2], |i| &i.decl);
+        table.leave();
+        table.refer("x", 6..7, &uses[3], |i| &i.decl);
+        table.leave();
+        table.leave();
+
+        table.resolve().unwrap();
+        let ids: Vec<_> = uses.iter().map(|u| u.decl.get().id).collect();
+        assert_eq!(ids, [0, 2, 0, 1]);
+    }
+    #[test]
+    fn duplicates_are_reported_before_undefined_uses() {
+        let decls = decls(2);
+        let (inner, outer) = (ident(), ident());
+        let mut table: SymbolTable<Range<usize>, Decl> = SymbolTable::new();
+        table.enter();
+        table.declare("x", 0..1, &decls[0]);
+        table.refer("x", 1..2, &inner, |i| &i.decl);
+        table.declare("x", 2..3, &decls[1]);
+        table.leave();
+        table.refer("x", 3..4, &outer, |i| &i.decl);
+
+        let errors: Vec<_> = table
+            .resolve()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "Symbol `x` at 2..3 is already declared at 0..1!",
+                "Undefined symbol `x` at 3..4!",
+            ]
+        );
+        assert_eq!(inner.decl.get().id, 0);
+        assert!(!outer.decl.is_ready());
+    }
+    #[test]
+    fn wiring_errors_name_the_use() {
+        let decls = decls(1);
+        let ident = ident();
+        let mut table: SymbolTable<Range<usize>, Decl> = SymbolTable::new();
+        table.declare("f", 0..1, &decls[0]);
+        table.refer("f", 1..2, &ident, |i| &i.decl);
+        table.refer("f", 2..3, &ident, |i| &i.decl);
+
+        #[cfg(feature = "debug-location")]
+        let line = line!() + 1;
+        let errors = table.resolve().unwrap_err();
+        let [SymbolError::Wiring { name, span, source }] = &errors[..] else {
+            panic!("expected one wiring error, got {errors:?}");
+        };
+        assert_eq!((name.as_str(), span), ("f", &(2..3)));
+        assert!(matches!(
+            source.root_cause(),
+            DeferredError::DuplicateInitialization()
+        ));
+        #[cfg(feature = "debug-location")]
+        assert_eq!(source.location().unwrap().line(), line);
+        assert_eq!(ident.decl.get().id, 0);
+    }
+}
@@ -0,0 +1,646 @@
+//! Thread-safe counterparts of [`Deferred`](crate::Deferred) and [`SetOnce`](crate::SetOnce).
+//!
+//! `DeferredSync<T>` stores a [`std::sync::Weak`] in a set-once slot, so graphs of
+//! `Arc` nodes can be shared across threads and wired from any of them.
+//!
+//! With the `loom` feature enabled, the slot is built on loom's primitives so wiring
+//! code can be model-checked. See `tests/loom.rs` for examples.
+
+use std::{
+    any::type_name,
+    fmt,
+    sync::{Arc, PoisonError, Weak},
+    thread,
+};
+
+use thiserror::Error;
+
+use crate::{
+    AlreadySet, DeferredError, InitErrors, diagnostics, failure,
+    liveness::Liveness,
+    primitives::{AtomicU8, Condvar, Mutex, Ordering, UnsafeCell},
+};
+
+const EMPTY: u8 = 0;
+const WRITING: u8 = 1;
+const READY: u8 = 2;
+
+/// The state machine of a [`OnceSlot`], kept free of the value type so it is compiled
+/// once rather than for every target type.
+struct SlotState {
+    state: AtomicU8,
+    lock: Mutex<()>,
+    ready: Condvar,
+}
+
+impl SlotState {
+    #[cfg(not(feature = "loom"))]
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(EMPTY),
+            lock: Mutex::new(()),
+            ready: Condvar::new(),
+        }
+    }
+    // loom's primitives cannot be constructed in const contexts
+    #[cfg(feature = "loom")]
+    fn new() -> Self {
+        Self {
+            state: AtomicU8::new(EMPTY),
+            lock: Mutex::new(()),
+            ready: Condvar::new(),
+        }
+    }
+    #[inline]
+    fn is_ready(&self) -> bool {
+        self.state.load(Ordering::Acquire) == READY
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.state.load(Ordering::Acquire) == EMPTY
+    }
+    /// Moves from EMPTY to WRITING, returning `false` if another set got there first.
+    fn claim(&self) -> bool {
+        self.state
+            .compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+    /// Publishes a claimed slot's value and wakes every waiter.
+    fn publish(&self) {
+        {
+            let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+            self.state.store(READY, Ordering::Release);
+        }
+        self.ready.notify_all();
+    }
+    fn wait(&self) {
+        let mut guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        while self.state.load(Ordering::Acquire) != READY {
+            guard = self
+                .ready
+                .wait(guard)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        drop(guard);
+    }
+}
+
+/// A set-once slot that readers can block on.
+struct OnceSlot<V> {
+    core: SlotState,
+    value: UnsafeCell<Option<V>>,
+}
+
+// SAFETY: `value` is written once, by the thread that moved `state` from EMPTY to
+// WRITING, and is only read after observing READY with acquire ordering.
+unsafe impl<V: Send + Sync> Sync for OnceSlot<V> {}
+
+impl<V> OnceSlot<V> {
+    #[cfg(not(feature = "loom"))]
+    const fn new() -> Self {
+        Self {
+            core: SlotState::new(),
+            value: UnsafeCell::new(None),
+        }
+    }
+    #[cfg(feature = "loom")]
+    fn new() -> Self {
+        Self {
+            core: SlotState::new(),
+            value: UnsafeCell::new(None),
+        }
+    }
+    #[inline]
+    fn get(&self) -> Option<&V> {
+        if !self.core.is_ready() {
+            return None;
+        }
+        // SAFETY: READY is only stored after the value is written, and the value is
+        // never written again.
+        self.value.with(|v| unsafe { (*v).as_ref() })
+    }
+    fn set(&self, value: V) -> Result<(), V> {
+        if !self.core.claim() {
+            return Err(value);
+        }
+        // SAFETY: winning the EMPTY -> WRITING exchange grants exclusive write access,
+        // and readers ignore the value until READY is published below.
+        self.value.with_mut(|v| unsafe { *v = Some(value) });
+        self.core.publish();
+        Ok(())
+    }
+    fn wait(&self) -> &V {
+        loop {
+            if let Some(v) = self.get() {
+                return v;
+            }
+            self.core.wait();
+        }
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.core.is_empty()
+    }
+}
+
+/// A write-once, weak reference wrapper for `Arc` based graphs.
+///
+/// Use [`SetOnceSync`] to assign a value exactly once.
+pub struct DeferredSync<T>(
+    OnceSlot<Weak<T>>,
+    #[cfg(feature = "debug-registry")] diagnostics::Counted,
+);
+
+impl<T> Default for DeferredSync<T> {
+    fn default() -> Self {
+        Self::counted(OnceSlot::new())
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+impl<T> DeferredSync<T> {
+    /// Creates an unset cell in a const context, allowing graph roots in `static`s.
+    ///
+    /// ```
+    /// use deferred_cell::{DeferredSync, SetOnceSync};
+    /// use std::sync::Arc;
+    ///
+    /// static ROOT: DeferredSync<u32> = DeferredSync::new();
+    ///
+    /// let root = Arc::new(7);
+    /// SetOnceSync::from(&ROOT).try_set(&root).unwrap();
+    /// assert_eq!(*ROOT.get(), 7);
+    /// ```
+    ///
+    /// Not available with the `loom` feature, as loom's primitives are not const. With
+    /// the `debug-registry` feature, the cell is only counted once it is set.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(
+            OnceSlot::new(),
+            #[cfg(feature = "debug-registry")]
+            diagnostics::Counted::uncounted(),
+        )
+    }
+}
+
+impl<T> DeferredSync<T> {
+    /// Wraps `cell` and counts it in the registry from now on.
+    fn counted(cell: OnceSlot<Weak<T>>) -> Self {
+        let ready = cell.get().is_some();
+        diagnostics::created(ready, type_name::<Self>());
+        Self(
+            cell,
+            #[cfg(feature = "debug-registry")]
+            diagnostics::Counted::new(ready, type_name::<Self>()),
+        )
+    }
+}
+
+impl<T> From<&Arc<T>> for DeferredSync<T> {
+    /// Creates a cell that is already set to `value`.
+    fn from(value: &Arc<T>) -> Self {
+        let cell = OnceSlot::new();
+        cell.set(Arc::downgrade(value)).ok();
+        Self::counted(cell)
+    }
+}
+
+impl<T> Clone for DeferredSync<T> {
+    fn clone(&self) -> Self {
+        let clone = OnceSlot::new();
+        if let Some(weak) = self.0.get() {
+            clone.set(weak.clone()).ok();
+        }
+        Self::counted(clone)
+    }
+}
+
+impl<T> fmt::Debug for DeferredSync<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DeferredSync").field(&self.0.get()).finish()
+    }
+}
+
+impl<T> DeferredSync<T> {
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[inline]
+    pub fn try_get(&self) -> Result<Arc<T>, DeferredError> {
+        let read = diagnostics::get(type_name::<T>());
+        match self.0.get().and_then(Weak::upgrade) {
+            Some(target) if read.is_ok() => Ok(target),
+            _ => Err(self.read_error(read)),
+        }
+    }
+    /// Borrows the cell's target for as long as `live` guarantees it stays alive,
+    /// without changing its reference count.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[inline]
+    pub fn try_get_ref<'g>(&self, live: &Liveness<'g>) -> Result<&'g T, DeferredError> {
+        let _ = live;
+        let read = diagnostics::get(type_name::<T>());
+        match self.0.get() {
+            // SAFETY: the target is alive now, and the `Liveness` contract keeps it alive
+            // for `'g`.
+            Some(weak) if read.is_ok() && weak.strong_count() > 0 => Ok(unsafe { &*weak.as_ptr() }),
+            _ => Err(self.read_error(read)),
+        }
+    }
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[inline]
+    fn read_error(&self, read: Result<(), DeferredError>) -> DeferredError {
+        DeferredError::read_failed(
+            read,
+            self.0.get().is_some(),
+            std::ptr::from_ref(self).cast(),
+            type_name::<T>(),
+        )
+    }
+    /// Returns the cell's target.
+    ///
+    /// # Panics
+    /// Panics if the cell is not set or its target was dropped, unless a
+    /// [`failure`] handler is installed.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    #[must_use]
+    #[inline]
+    pub fn get(&self) -> Arc<T> {
+        match self.try_get() {
+            Ok(target) => target,
+            Err(error) => failure::fail(&error),
+        }
+    }
+    /// Blocks the current thread until the cell is set, then upgrades it.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn wait(&self) -> Result<Arc<T>, DeferredError> {
+        DeferredError::located(diagnostics::get(type_name::<T>()).and_then(|()| {
+            self.0.wait().upgrade().ok_or_else(|| {
+                diagnostics::failed_upgrade(std::ptr::from_ref(self).cast(), type_name::<T>())
+            })
+        }))
+    }
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        self.0.get().is_some()
+    }
+    pub(crate) fn is_dangling(&self) -> bool {
+        self.0.get().is_some_and(|weak| weak.strong_count() == 0)
+    }
+}
+
+/// A write-once assignment interface for [`DeferredSync<T>`].
+///
+/// # Example
+/// ```
+/// # // loom's primitives panic outside `loom::model`.
+/// # #[cfg(not(feature = "loom"))] {
+/// use deferred_cell::{DeferredSync, SetOnceSync};
+/// use std::sync::Arc;
+///
+/// let deferred = DeferredSync::default();
+/// let value = Arc::new(42);
+/// SetOnceSync::from(&deferred).try_set(&value).unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SetOnceSync<'a, T>(&'a DeferredSync<T>);
+
+impl<'a, T> SetOnceSync<'a, T> {
+    pub const fn from(cell: &'a DeferredSync<T>) -> Self {
+        Self(cell)
+    }
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn try_set(&self, value: &Arc<T>) -> Result<(), DeferredError> {
+        let cell = std::ptr::from_ref(self.0).cast();
+        DeferredError::located(
+            self.0
+                .0
+                .set(Arc::downgrade(value))
+                .map_err(|_| diagnostics::duplicate_set(cell, type_name::<T>())),
+        )?;
+        diagnostics::set(cell, Arc::as_ptr(value).cast(), type_name::<T>());
+        #[cfg(feature = "debug-registry")]
+        self.0.1.set(type_name::<DeferredSync<T>>());
+        Ok(())
+    }
+    /// Sets the cell to a weak reference to `value` and returns `value`, so a newly
+    /// created target can be stored and kept alive in one step.
+    ///
+    /// If the cell is already set, the error hands `value` back along with the current
+    /// target.
+    #[cfg_attr(feature = "debug-location", track_caller)]
+    pub fn try_set_owned(&self, value: Arc<T>) -> Result<Arc<T>, AlreadySet<Arc<T>>> {
+        match self.try_set(&value) {
+            Ok(()) => Ok(value),
+            // A concurrent writer may still be publishing its value, so wait for it.
+            Err(_) => Err(AlreadySet {
+                rejected: value,
+                current: self.0.0.wait().upgrade(),
+            }),
+        }
+    }
+    #[inline]
+    pub fn can_set(&self) -> bool {
+        self.0.0.is_empty()
+    }
+}
+
+/// Applies `wire` to every assignment across `workers` scoped threads.
+///
+/// Assignments are split into contiguous chunks, one per worker. Every assignment is
+/// attempted, and the failures are returned in index order, each annotated with its
+/// assignment as `assignment {index}`.
+///
+/// ```
+/// # // loom's primitives panic outside `loom::model`.
+/// # #[cfg(not(feature = "loom"))] {
+/// use deferred_cell::{DeferredSync, SetOnceSync, wire_scoped};
+/// use std::sync::Arc;
+///
+/// let targets: Vec<_> = (0..100).map(Arc::new).collect();
+/// let cells: Vec<DeferredSync<i32>> = (0..100).map(|_| DeferredSync::new()).collect();
+/// let assignments: Vec<_> = cells.iter().zip(&targets).collect();
+///
+/// wire_scoped(&assignments, 4, |(cell, target)| {
+///     SetOnceSync::from(cell).try_set(target)
+/// })
+/// .unwrap();
+/// assert_eq!(*cells[42].get(), 42);
+/// # }
+/// ```
+pub fn wire_scoped<A, F>(assignments: &[A], workers: usize, wire: F) -> Result<(), InitErrors>
+where
+    A: Sync,
+    F: Fn(&A) -> Result<(), DeferredError> + Sync,
+{
+    let chunk_size = assignments.len().div_ceil(workers.max(1)).max(1);
+    let (wire, context) = (&wire, &diagnostics::WorkerContext::capture());
+    let errors: Vec<_> = thread::scope(|s| {
+        let handles: Vec<_> = assignments
+            .chunks(chunk_size)
+            .enumerate()
+            .map(|(chunk, items)| {
+                s.spawn(move || {
+                    let _context = context.enter();
+                    items
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, a)| {
+                            let index = chunk * chunk_size + i;
+                            wire(a)
+                                .err()
+                                .map(|e| e.context(format!("assignment {index}")))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|p| std::panic::resume_unwind(p)))
+            .collect()
+    });
+    // Chunks are joined in order, so the errors already are in index order.
+    errors.into_iter().collect::<InitErrors>().into_result()
+}
+
+/// Iterator extension trait for collections of [`DeferredSync<T>`].
+///
+/// The returned adaptors are `Send` whenever the source iterator is, so they can be
+/// moved into spawned threads or rayon tasks.
+pub trait DeferredSyncIteratorExt<T>: Iterator<Item = DeferredSync<T>> + Sized {
+    /// Returns an iterator of `Arc<T>` from an iterator of `DeferredSync<T>`.
+    ///
+    /// # Panics
+    /// Panics if any `DeferredSync<T>` is not initialized.
+    fn get_deferred(self) -> impl Iterator<Item = Arc<T>> + Send
+    where
+        Self: Send,
+    {
+        self.map(|d| d.get())
+    }
+    /// Returns an iterator of `Result<Arc<T>, DeferredError>` from an iterator of `DeferredSync<T>`.
+    fn try_get_deferred(self) -> impl Iterator<Item = Result<Arc<T>, DeferredError>> + Send
+    where
+        Self: Send,
+    {
+        self.map(|d| d.try_get())
+    }
+}
+
+impl<T, I> DeferredSyncIteratorExt<T> for I where I: Iterator<Item = DeferredSync<T>> {}
+
+/// The error returned when locking the target of a [`DeferredSync`] cell fails.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum DeferredLockError {
+    #[error(transparent)]
+    Deferred(#[from] DeferredError),
+    #[error("Cannot lock a value that was poisoned by a panic!")]
+    Poisoned,
+}
+
+/// Locks the [`Mutex`](std::sync::Mutex) behind a [`DeferredSync`] cell in one step.
+///
+/// ```
+/// # // loom's primitives panic outside `loom::model`.
+/// # #[cfg(not(feature = "loom"))] {
+/// use deferred_cell::{DeferredMutexExt, DeferredSync};
+/// use std::sync::{Arc, Mutex};
+///
+/// let hits = Arc::new(Mutex::new(0));
+/// let cell = DeferredSync::from(&hits);
+/// cell.lock_deferred(|hits| *hits += 1).unwrap();
+/// assert_eq!(*hits.lock().unwrap(), 1);
+///
+/// drop(hits);
+/// assert!(cell.lock_deferred(|hits| *hits += 1).is_err());
+/// # }
+/// ```
+pub trait DeferredMutexExt<T> {
+    /// Upgrades the cell, locks its target, and calls `f` with the locked value.
+    ///
+    /// The target is kept alive until `f` returns.
+    fn lock_deferred<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, DeferredLockError>;
+}
+
+impl<T> DeferredMutexExt<T> for DeferredSync<std::sync::Mutex<T>> {
+    fn lock_deferred<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, DeferredLockError> {
+        let target = self.try_get()?;
+        let mut guard = target.lock().map_err(|_| DeferredLockError::Poisoned)?;
+        Ok(f(&mut guard))
+    }
+}
+
+/// Locks the [`RwLock`](std::sync::RwLock) behind a [`DeferredSync`] cell in one step.
+pub trait DeferredRwLockExt<T> {
+    /// Upgrades the cell, takes a read lock on its target, and calls `f` with the value.
+    fn read_deferred<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, DeferredLockError>;
+    /// Upgrades the cell, takes a write lock on its target, and calls `f` with the
+    /// value.
+    fn write_deferred<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, DeferredLockError>;
+}
+
+impl<T> DeferredRwLockExt<T> for DeferredSync<std::sync::RwLock<T>> {
+    fn read_deferred<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R, DeferredLockError> {
+        let target = self.try_get()?;
+        let guard = target.read().map_err(|_| DeferredLockError::Poisoned)?;
+        Ok(f(&guard))
+    }
+    fn write_deferred<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, DeferredLockError> {
+        let target = self.try_get()?;
+        let mut guard = target.write().map_err(|_| DeferredLockError::Poisoned)?;
+        Ok(f(&mut guard))
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+// loom's primitives panic outside `loom::model`; see `tests/loom.rs`.
+#[cfg(all(test, not(feature = "loom")))]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[derive(Debug)]
+    struct Node {
+        value: u32,
+        next: DeferredSync<Self>,
+    }
+
+    #[test]
+    fn set_from_another_thread() {
+        let a = Arc::new(Node {
+            value: 1,
+            next: DeferredSync::default(),
+        });
+        let b = Arc::new(Node {
+            value: 2,
+            next: DeferredSync::default(),
+        });
+        thread::scope(|s| {
+            s.spawn(|| SetOnceSync::from(&a.next).try_set(&b).unwrap());
+            assert_eq!(a.next.wait().unwrap().value, 2);
+        });
+        assert!(!SetOnceSync::from(&a.next).can_set());
+        assert!(matches!(
+            SetOnceSync::from(&a.next)
+                .try_set(&a)
+                .unwrap_err()
+                .root_cause(),
+            DeferredError::DuplicateInitialization()
+        ));
+        let error = SetOnceSync::from(&a.next)
+            .try_set_owned(Arc::clone(&a))
+            .unwrap_err();
+        assert!(Arc::ptr_eq(&error.rejected, &a));
+        assert_eq!(error.current.unwrap().value, 2);
+        assert!(!b.next.is_ready());
+        assert_eq!(b.value, 2);
+    }
+    #[cfg(not(feature = "loom"))]
+    #[test]
+    fn static_root_is_shared_between_threads() {
+        static ROOT: DeferredSync<Node> = DeferredSync::new();
+        let root = Arc::new(Node {
+            value: 9,
+            next: DeferredSync::new(),
+        });
+        thread::scope(|s| {
+            let mut waiters = Vec::new();
+            for _ in 0..4 {
+                waiters.push(s.spawn(|| ROOT.wait().unwrap().value));
+            }
+            SetOnceSync::from(&ROOT).try_set(&root).unwrap();
+            let readers: Vec<_> = (0..4).map(|_| s.spawn(|| ROOT.get().value)).collect();
+            for handle in waiters.into_iter().chain(readers) {
+                assert_eq!(handle.join().unwrap(), 9);
+            }
+        });
+    }
+    #[test]
+    fn scoped_wiring_collects_every_duplicate() {
+        let target = Arc::new(Node {
+            value: 1,
+            next: DeferredSync::default(),
+        });
+        let cells: Vec<DeferredSync<Node>> = (0..10).map(|_| DeferredSync::default()).collect();
+        SetOnceSync::from(&cells[3]).try_set(&target).unwrap();
+        SetOnceSync::from(&cells[7]).try_set(&target).unwrap();
+        let assignments: Vec<_> = cells.iter().collect();
+
+        let errors = wire_scoped(&assignments, 3, |cell| {
+            SetOnceSync::from(cell).try_set(&target)
+        })
+        .unwrap_err();
+
+        let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("assignment 3: "));
+        assert!(messages[1].starts_with("assignment 7: "));
+        assert!(
+            errors
+                .iter()
+                .all(|e| matches!(e.root_cause(), DeferredError::DuplicateInitialization()))
+        );
+        assert!(cells.iter().all(DeferredSync::is_ready));
+    }
+    #[test]
+    fn locks_are_taken_through_cells() {
+        let counter = Arc::new(std::sync::Mutex::new(0));
+        let config = Arc::new(std::sync::RwLock::new("a"));
+        let (counter_cell, config_cell) =
+            (DeferredSync::from(&counter), DeferredSync::from(&config));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| counter_cell.lock_deferred(|n| *n += 1).unwrap());
+            }
+        });
+        config_cell.write_deferred(|c| *c = "b").unwrap();
+        assert_eq!(counter_cell.lock_deferred(|n| *n).unwrap(), 4);
+        assert_eq!(config_cell.read_deferred(|c| *c).unwrap(), "b");
+
+        let poisoned = Arc::clone(&config);
+        thread::spawn(move || {
+            let _guard = poisoned.write().unwrap();
+            panic!("poison the lock");
+        })
+        .join()
+        .unwrap_err();
+        assert!(matches!(
+            config_cell.read_deferred(|c| *c),
+            Err(DeferredLockError::Poisoned)
+        ));
+        drop(counter);
+        assert!(matches!(
+            counter_cell.lock_deferred(|n| *n),
+            Err(DeferredLockError::Deferred(_))
+        ));
+    }
+    #[test]
+    fn iterator_extension_is_send() {
+        let nodes: Vec<_> = (0..4)
+            .map(|value| {
+                Arc::new(Node {
+                    value,
+                    next: DeferredSync::default(),
+                })
+            })
+            .collect();
+        let cells: Vec<_> = nodes
+            .iter()
+            .map(|n| {
+                let cell = DeferredSync::default();
+                SetOnceSync::from(&cell).try_set(n).unwrap();
+                cell
+            })
+            .collect();
+        let values = thread::scope(|s| {
+            let iter = cells.into_iter().get_deferred();
+            s.spawn(move || iter.map(|n| n.value).collect::<Vec<_>>())
+                .join()
+                .unwrap()
+        });
+        assert_eq!(values, vec![0, 1, 2, 3]);
+    }
+}
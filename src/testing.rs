@@ -0,0 +1,306 @@
+//! Ready-made graphs for tests.
+//!
+//! Each fixture creates its nodes with `make_node(index, out_degree)`, where
+//! `out_degree` is the number of outgoing edges the node will hold, and wires them
+//! through `slot(node, k)` for `k` in `0..out_degree`. Nodes are returned in index
+//! order, and the caller keeps them alive.
+//!
+//! ```
+//! use deferred_cell::{Deferred, testing};
+//!
+//! struct Node {
+//!     name: &'static str,
+//!     edges: Vec<Deferred<Node>>,
+//! }
+//!
+//! let nodes = testing::compass_rose(
+//!     |i, degree| Node {
+//!         name: testing::COMPASS[i],
+//!         edges: (0..degree).map(|_| Deferred::new()).collect(),
+//!     },
+//!     |node, k| &node.edges[k],
+//! )
+//! .unwrap();
+//! assert_eq!(nodes[0].edges[0].get().name, "North");
+//! ```
+//!
+//! For one-off graphs, [`Fixture`] names nodes and edges fluently:
+//!
+//! ```
+//! # use deferred_cell::{Deferred, testing::Fixture};
+//! # struct Node { name: &'static str, edges: Vec<Deferred<Node>> }
+//! let graph = Fixture::new(
+//!     |name, degree| Node { name, edges: (0..degree).map(|_| Deferred::new()).collect() },
+//!     |node, k| &node.edges[k],
+//! )
+//! .node("a", "A")
+//! .node("b", "B")
+//! .edge("a", "b")
+//! .build()
+//! .unwrap();
+//! assert_eq!(graph["a"].edges[0].get().name, "B");
+//! ```
+
+use std::{collections::BTreeMap, marker::PhantomData, ops::Index, rc::Rc};
+
+use crate::{Deferred, DeferredError, SetOnce, spec::GraphSpec, topology};
+
+/// Node names of [`compass_rose`], in index order.
+pub const COMPASS: [&str; 5] = ["Center", "North", "East", "South", "West"];
+
+/// Builds the five-node compass rose used throughout this crate's documentation.
+///
+/// ```text
+///            North
+///         /    |     \
+///     East - Center - West
+///         \    |     /
+///            South
+/// ```
+///
+/// The center links to north, west, south, and east; each outer node links to its two
+/// outer neighbors and the center.
+pub fn compass_rose<T>(
+    make_node: impl FnMut(usize, usize) -> T,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, DeferredError> {
+    let [center, north, east, south, west] = [0, 1, 2, 3, 4];
+    GraphSpec::new(
+        COMPASS.len(),
+        vec![
+            (center, north),
+            (center, west),
+            (center, south),
+            (center, east),
+            (north, west),
+            (north, center),
+            (north, east),
+            (east, north),
+            (east, center),
+            (east, south),
+            (south, center),
+            (south, west),
+            (south, east),
+            (west, north),
+            (west, south),
+            (west, center),
+        ],
+    )
+    .materialize(make_node, slot)
+}
+
+/// Builds a complete binary tree of `depth` levels in breadth-first order.
+///
+/// Node `i` links to its children `2i + 1` and `2i + 2` through slots `0` and `1`, and
+/// every node but the root links back through `parent(node)`. Leaves have an
+/// `out_degree` of `0`.
+pub fn binary_tree<T>(
+    depth: u32,
+    make_node: impl FnMut(usize, usize) -> T,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+    parent: impl Fn(&T) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, DeferredError> {
+    let len = 2usize.pow(depth) - 1;
+    let inner = len / 2;
+    let nodes = GraphSpec::new(
+        len,
+        (0..inner)
+            .flat_map(|i| [(i, 2 * i + 1), (i, 2 * i + 2)])
+            .collect(),
+    )
+    .materialize(make_node, slot)?;
+    for child in 1..len {
+        SetOnce::from(parent(&nodes[child])).try_set(&nodes[(child - 1) / 2])?;
+    }
+    Ok(nodes)
+}
+
+/// Builds a directed cycle of `n` nodes, each with an `out_degree` of `1`.
+/// See [`topology::ring`].
+pub fn ring<T>(
+    n: usize,
+    mut make_node: impl FnMut(usize, usize) -> T,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, DeferredError> {
+    topology::ring(n, |i| make_node(i, 1), slot)
+}
+
+/// Builds a complete graph of `n` nodes, each with an `out_degree` of `n - 1`.
+/// See [`topology::complete`].
+pub fn complete<T>(
+    n: usize,
+    mut make_node: impl FnMut(usize, usize) -> T,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, DeferredError> {
+    topology::complete(n, |i| make_node(i, n.saturating_sub(1)), slot)
+}
+
+/// A fluent builder for small named graphs.
+///
+/// Each node is created from its payload with `make_node(payload, out_degree)` and its
+/// outgoing edges are stored through `slot(node, k)` in the order they were added.
+pub struct Fixture<P, T, M, S> {
+    make_node: M,
+    slot: S,
+    nodes: Vec<(&'static str, P)>,
+    edges: Vec<(&'static str, &'static str)>,
+    _node: PhantomData<fn() -> T>,
+}
+
+impl<P, T, M, S> Fixture<P, T, M, S>
+where
+    M: FnMut(P, usize) -> T,
+    S: Fn(&T, usize) -> &Deferred<T>,
+{
+    #[must_use]
+    pub const fn new(make_node: M, slot: S) -> Self {
+        Self {
+            make_node,
+            slot,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            _node: PhantomData,
+        }
+    }
+    /// Adds a node named `name`.
+    #[must_use]
+    pub fn node(mut self, name: &'static str, payload: P) -> Self {
+        self.nodes.push((name, payload));
+        self
+    }
+    /// Adds an edge between two named nodes, which may be added before or after it.
+    #[must_use]
+    pub fn edge(mut self, from: &'static str, to: &'static str) -> Self {
+        self.edges.push((from, to));
+        self
+    }
+    /// Creates and wires the nodes.
+    ///
+    /// # Panics
+    /// Panics if two nodes share a name or an edge refers to an unknown name.
+    pub fn build(self) -> Result<FixtureGraph<T>, DeferredError> {
+        let mut indices = BTreeMap::new();
+        for (index, (name, _)) in self.nodes.iter().enumerate() {
+            assert!(
+                indices.insert(*name, index).is_none(),
+                "duplicate fixture node `{name}`"
+            );
+        }
+        let index = |name| match indices.get(name) {
+            Some(&index) => index,
+            None => panic!("unknown fixture node `{name}`"),
+        };
+        let spec = GraphSpec::new(
+            self.nodes.len(),
+            self.edges
+                .iter()
+                .map(|(from, to)| (index(from), index(to)))
+                .collect(),
+        );
+        let mut payloads = self.nodes.into_iter().map(|(_, payload)| payload);
+        let mut make_node = self.make_node;
+        let nodes = spec.materialize(
+            |_, degree| {
+                #[allow(clippy::expect_used)]
+                let payload = payloads.next().expect("one payload per node");
+                make_node(payload, degree)
+            },
+            self.slot,
+        )?;
+        let by_name = indices
+            .into_iter()
+            .map(|(name, index)| (name, Rc::clone(&nodes[index])))
+            .collect();
+        Ok(FixtureGraph { nodes, by_name })
+    }
+}
+
+/// The nodes built by a [`Fixture`], in the order they were added and by name.
+#[derive(Debug)]
+pub struct FixtureGraph<T> {
+    pub nodes: Vec<Rc<T>>,
+    /// The same nodes, in name order.
+    pub by_name: BTreeMap<&'static str, Rc<T>>,
+}
+
+impl<T> Index<&str> for FixtureGraph<T> {
+    type Output = Rc<T>;
+
+    /// # Panics
+    /// Panics if no node is named `name`.
+    fn index(&self, name: &str) -> &Rc<T> {
+        &self.by_name[name]
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Node {
+        id: usize,
+        edges: Vec<Deferred<Self>>,
+        parent: Deferred<Self>,
+    }
+
+    fn node(id: usize, degree: usize) -> Node {
+        Node {
+            id,
+            edges: (0..degree).map(|_| Deferred::new()).collect(),
+            parent: Deferred::new(),
+        }
+    }
+
+    fn targets(node: &Node) -> Vec<usize> {
+        node.edges.iter().map(|e| e.get().id).collect()
+    }
+
+    #[test]
+    fn compass_rose_matches_the_diagram() {
+        let nodes = compass_rose(node, |n, k| &n.edges[k]).unwrap();
+        assert_eq!(targets(&nodes[0]), vec![1, 4, 3, 2]);
+        assert_eq!(targets(&nodes[3]), vec![0, 4, 2]);
+    }
+    #[test]
+    fn binary_tree_links_children_and_parents() {
+        let nodes = binary_tree(3, node, |n, k| &n.edges[k], |n| &n.parent).unwrap();
+        assert_eq!(nodes.len(), 7);
+        assert_eq!(targets(&nodes[2]), vec![5, 6]);
+        assert!(nodes[6].edges.is_empty());
+        assert_eq!(nodes[6].parent.get().id, 2);
+        assert!(!nodes[0].parent.is_ready());
+    }
+    #[test]
+    fn ring_and_complete_size_their_nodes() {
+        let ring = ring(3, node, |n, k| &n.edges[k]).unwrap();
+        assert_eq!(targets(&ring[2]), vec![0]);
+        let complete = complete(3, node, |n, k| &n.edges[k]).unwrap();
+        assert_eq!(targets(&complete[1]), vec![0, 2]);
+    }
+    #[test]
+    fn fixture_wires_named_nodes() {
+        let graph = Fixture::new(node, |n, k| &n.edges[k])
+            .edge("root", "leaf")
+            .node("root", 10)
+            .node("leaf", 20)
+            .edge("leaf", "root")
+            .edge("root", "root")
+            .build()
+            .unwrap();
+        assert_eq!(targets(&graph["root"]), vec![20, 10]);
+        assert_eq!(targets(&graph.nodes[1]), vec![10]);
+        assert!(Rc::ptr_eq(&graph["leaf"], &graph.nodes[1]));
+        let names: Vec<_> = graph.by_name.keys().copied().collect();
+        assert_eq!(names, ["leaf", "root"]);
+    }
+    #[test]
+    #[should_panic(expected = "unknown fixture node `missing`")]
+    fn fixture_rejects_unknown_names() {
+        let _ = Fixture::new(node, |n, k| &n.edges[k])
+            .node("a", 0)
+            .edge("a", "missing")
+            .build();
+    }
+}
@@ -0,0 +1,244 @@
+//! Loading TOML documents whose sections refer to one another by name.
+//!
+//! Configuration files often describe graphs as one table per node, with fields naming
+//! other tables, such as `next_scene = "ending"`. A [`TomlLoader`] knows which fields
+//! are links and which cell each one fills. [`TomlLoader::load`] makes one node per
+//! top-level table and wires the links with a [`Resolver`]. A link naming no table is
+//! reported with its line and column.
+//!
+//! ```
+//! use deferred_cell::{Deferred, toml::TomlLoader};
+//!
+//! struct Scene {
+//!     title: String,
+//!     next: Deferred<Scene>,
+//! }
+//!
+//! let source = r#"
+//! [intro]
+//! title = "Intro"
+//! next_scene = "ending"
+//!
+//! [ending]
+//! title = "Ending"
+//! next_scene = "intro"
+//! "#;
+//! let scenes = TomlLoader::new()
+//!     .link("next_scene", |s: &Scene| &s.next)
+//!     .load(source, |_, table| Scene {
+//!         title: table.get("title").and_then(|t| t.as_str()).unwrap_or_default().into(),
+//!         next: Deferred::new(),
+//!     })
+//!     .unwrap();
+//! assert_eq!(scenes["intro"].next.get().title, "Ending");
+//! ```
+
+use std::{borrow::Cow, collections::HashMap, fmt, rc::Rc};
+
+use thiserror::Error;
+use toml_edit::{ImDocument, Item, Table};
+
+use crate::resolver::{ResolveError, Resolver, Slot};
+
+/// A field whose value is not the name of another table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadLink {
+    /// The table the field belongs to.
+    pub section: String,
+    pub field: String,
+    /// The value, as written in the source.
+    pub value: String,
+    /// The 1-based line of the value.
+    pub line: usize,
+    /// The 1-based column of the value.
+    pub column: usize,
+}
+
+impl fmt::Display for BadLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} = {} at line {}, column {}",
+            self.section, self.field, self.value, self.line, self.column
+        )
+    }
+}
+
+/// Errors returned by [`TomlLoader::load`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum TomlError {
+    #[error(transparent)]
+    Parse(#[from] toml_edit::TomlError),
+    #[error("Unresolved names: {}", list(.0))]
+    Unresolved(Vec<BadLink>),
+    #[error(transparent)]
+    Wiring(#[from] ResolveError<String>),
+}
+
+fn list(links: &[BadLink]) -> String {
+    links
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Loads TOML graphs, with one node per top-level table.
+pub struct TomlLoader<T> {
+    links: Vec<(Cow<'static, str>, Slot<T>)>,
+}
+
+impl<T> Default for TomlLoader<T> {
+    fn default() -> Self {
+        Self { links: Vec::new() }
+    }
+}
+
+impl<T> TomlLoader<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Treats `field` as a link, stored in the cell selected by `slot`.
+    #[must_use]
+    pub fn link(mut self, field: impl Into<Cow<'static, str>>, slot: Slot<T>) -> Self {
+        self.links.push((field.into(), slot));
+        self
+    }
+    /// Parses `source`, makes each node with `make_node(name, table)`, and wires every
+    /// link field.
+    ///
+    /// Every link naming no table, or holding something other than a string, is
+    /// reported together.
+    pub fn load(
+        &self,
+        source: &str,
+        make_node: impl FnMut(&str, &Table) -> T,
+    ) -> Result<HashMap<String, Rc<T>>, TomlError> {
+        Ok(self.resolve(source, make_node)?.finish()?)
+    }
+    /// Like [`load`](Self::load), but returns an `IndexMap` that iterates in document
+    /// order. Requires the `indexmap` feature.
+    #[cfg(feature = "indexmap")]
+    pub fn load_indexed(
+        &self,
+        source: &str,
+        make_node: impl FnMut(&str, &Table) -> T,
+    ) -> Result<indexmap::IndexMap<String, Rc<T>>, TomlError> {
+        Ok(self.resolve(source, make_node)?.finish_indexed()?)
+    }
+    fn resolve(
+        &self,
+        source: &str,
+        mut make_node: impl FnMut(&str, &Table) -> T,
+    ) -> Result<Resolver<String, T>, TomlError> {
+        let doc = ImDocument::parse(source)?;
+        let sections: Vec<_> = doc
+            .iter()
+            .filter_map(|(name, item)| Some((name, item.as_table()?)))
+            .collect();
+
+        let mut bad = Vec::new();
+        let mut resolver = Resolver::new();
+        for &(name, table) in &sections {
+            for (field, slot) in &self.links {
+                let Some(item) = table.get(field) else {
+                    continue;
+                };
+                match item.as_str() {
+                    Some(target) if doc.get(target).is_some_and(Item::is_table) => {
+                        resolver.edge(name.to_owned(), *slot, target.to_owned())?;
+                    }
+                    _ => bad.push(bad_link(source, name, field, item)),
+                }
+            }
+        }
+        if !bad.is_empty() {
+            return Err(TomlError::Unresolved(bad));
+        }
+        for (name, table) in sections {
+            resolver.register(name.to_owned(), Rc::new(make_node(name, table)))?;
+        }
+        Ok(resolver)
+    }
+}
+
+fn bad_link(source: &str, section: &str, field: &str, item: &Item) -> BadLink {
+    let start = item.span().map_or(0, |span| span.start);
+    let before = &source[..start];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    BadLink {
+        section: section.to_owned(),
+        field: field.to_owned(),
+        value: item.span().map_or_else(
+            || item.type_name().to_owned(),
+            |span| source[span].to_owned(),
+        ),
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Deferred;
+
+    #[derive(Debug)]
+    struct Room {
+        name: String,
+        north: Deferred<Self>,
+        south: Deferred<Self>,
+    }
+
+    fn loader() -> TomlLoader<Room> {
+        TomlLoader::new()
+            .link("north", |r: &Room| &r.north)
+            .link("south", |r: &Room| &r.south)
+    }
+
+    fn room(name: &str, _: &Table) -> Room {
+        Room {
+            name: name.to_owned(),
+            north: Deferred::new(),
+            south: Deferred::new(),
+        }
+    }
+
+    #[test]
+    fn sections_link_by_name() {
+        let rooms = loader()
+            .load(
+                "[hall]\nnorth = \"attic\"\n\n[attic]\nsouth = \"hall\"\n",
+                room,
+            )
+            .unwrap();
+        assert_eq!(rooms["hall"].north.get().name, "attic");
+        assert_eq!(rooms["attic"].south.get().name, "hall");
+        assert!(!rooms["hall"].south.is_ready());
+    }
+    #[test]
+    fn unresolved_names_report_their_location() {
+        let source = "[hall]\nnorth =  \"atic\"\nsouth = 3\n\n[attic]\n";
+        let error = loader().load(source, room).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unresolved names: hall.north = \"atic\" at line 2, column 10; \
+             hall.south = 3 at line 3, column 9"
+        );
+    }
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn indexed_loads_keep_document_order() {
+        let source = "[hall]\nnorth = \"attic\"\n\n[attic]\n\n[cellar]\n";
+        let rooms = loader().load_indexed(source, room).unwrap();
+        assert_eq!(
+            rooms.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["hall", "attic", "cellar"]
+        );
+        assert_eq!(rooms["hall"].north.get().name, "attic");
+    }
+}
@@ -0,0 +1,258 @@
+//! Constructors for common graph topologies.
+//!
+//! Each constructor creates its nodes with `make_node(index)` and wires every edge
+//! through `slot(node, k)`, which returns the cell holding the node's `k`-th outgoing
+//! edge. Nodes are returned in index order.
+//!
+//! Tile maps can use [`build_grid`] instead, whose [`Tile`]s have named links to their
+//! neighbors and may wrap around the edges.
+//!
+//! ```
+//! use deferred_cell::{Deferred, topology};
+//!
+//! struct Node {
+//!     id: usize,
+//!     edges: Vec<Deferred<Node>>,
+//! }
+//!
+//! let nodes = topology::complete(
+//!     4,
+//!     |id| Node { id, edges: (0..3).map(|_| Deferred::new()).collect() },
+//!     |node, k| &node.edges[k],
+//! )
+//! .unwrap();
+//! let ids: Vec<_> = nodes[2].edges.iter().map(|e| e.get().id).collect();
+//! assert_eq!(ids, vec![0, 1, 3]);
+//! ```
+
+use std::rc::Rc;
+
+use crate::{Deferred, DeferredError, HasNeighbors, SetOnce};
+
+fn build<T>(n: usize, make_node: impl FnMut(usize) -> T) -> Vec<Rc<T>> {
+    (0..n).map(make_node).map(Rc::new).collect()
+}
+
+fn link<T>(
+    nodes: &[Rc<T>],
+    slot: &impl Fn(&T, usize) -> &Deferred<T>,
+    from: usize,
+    k: usize,
+    to: usize,
+) -> Result<(), DeferredError> {
+    SetOnce::from(slot(&nodes[from], k)).try_set(&nodes[to])
+}
+
+/// Builds a directed cycle: node `i` links to node `(i + 1) % n` through slot `0`.
+pub fn ring<T>(
+    n: usize,
+    make_node: impl FnMut(usize) -> T,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, DeferredError> {
+    let nodes = build(n, make_node);
+    for i in 0..n {
+        link(&nodes, &slot, i, 0, (i + 1) % n)?;
+    }
+    Ok(nodes)
+}
+
+/// Builds a star around node `0`.
+///
+/// The hub links to leaf `i` through slot `i - 1`; every leaf links back to the hub
+/// through slot `0`.
+pub fn star<T>(
+    n: usize,
+    make_node: impl FnMut(usize) -> T,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, DeferredError> {
+    let nodes = build(n, make_node);
+    for leaf in 1..n {
+        link(&nodes, &slot, 0, leaf - 1, leaf)?;
+        link(&nodes, &slot, leaf, 0, 0)?;
+    }
+    Ok(nodes)
+}
+
+/// Builds a complete graph: every node links to every other node, in index order,
+/// through slots `0..n - 1`.
+pub fn complete<T>(
+    n: usize,
+    make_node: impl FnMut(usize) -> T,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, DeferredError> {
+    let nodes = build(n, make_node);
+    for from in 0..n {
+        for (k, to) in (0..n).filter(|&to| to != from).enumerate() {
+            link(&nodes, &slot, from, k, to)?;
+        }
+    }
+    Ok(nodes)
+}
+
+/// Builds a `width` by `height` grid in row-major order.
+///
+/// Slots `0..4` hold the north, east, south, and west neighbors. Slots of cells on the
+/// border that have no neighbor in that direction are left unset.
+pub fn grid<T>(
+    width: usize,
+    height: usize,
+    make_node: impl FnMut(usize) -> T,
+    slot: impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<Vec<Rc<T>>, DeferredError> {
+    let nodes = build(width * height, make_node);
+    wire_grid(&nodes, width, height, false, &slot)?;
+    Ok(nodes)
+}
+
+fn wire_grid<T>(
+    nodes: &[Rc<T>],
+    width: usize,
+    height: usize,
+    wrap: bool,
+    slot: &impl Fn(&T, usize) -> &Deferred<T>,
+) -> Result<(), DeferredError> {
+    // Steps to the previous or next index below `len`, wrapping around if allowed.
+    let back = |i: usize, len: usize| match i {
+        0 => wrap.then(|| len - 1),
+        i => Some(i - 1),
+    };
+    let ahead = |i: usize, len: usize| match i + 1 {
+        next if next < len => Some(next),
+        _ => wrap.then_some(0),
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let neighbors = [
+                back(y, height).map(|y| (x, y)),
+                ahead(x, width).map(|x| (x, y)),
+                ahead(y, height).map(|y| (x, y)),
+                back(x, width).map(|x| (x, y)),
+            ];
+            for (k, to) in neighbors.into_iter().enumerate() {
+                if let Some((to_x, to_y)) = to {
+                    link(nodes, slot, y * width + x, k, to_y * width + to_x)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A cell of a tile map built by [`build_grid`], linked to its four neighbors.
+#[derive(Debug)]
+pub struct Tile<V> {
+    pub x: usize,
+    pub y: usize,
+    pub value: V,
+    pub north: Deferred<Self>,
+    pub east: Deferred<Self>,
+    pub south: Deferred<Self>,
+    pub west: Deferred<Self>,
+}
+
+impl<V> Tile<V> {
+    /// Returns the link in direction `k`, counting clockwise from north.
+    const fn link(&self, k: usize) -> &Deferred<Self> {
+        match k {
+            0 => &self.north,
+            1 => &self.east,
+            2 => &self.south,
+            _ => &self.west,
+        }
+    }
+}
+
+impl<V> HasNeighbors for Tile<V> {
+    fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+        [&self.north, &self.east, &self.south, &self.west].into_iter()
+    }
+}
+
+/// Builds a `width` by `height` tile map in row-major order, holding
+/// `make_node(x, y)` in each tile.
+///
+/// With `wrap`, the map is a torus: tiles on one border link to those on the opposite
+/// border. Otherwise links leading off the map are left unset.
+pub fn build_grid<V>(
+    width: usize,
+    height: usize,
+    mut make_node: impl FnMut(usize, usize) -> V,
+    wrap: bool,
+) -> Result<Vec<Rc<Tile<V>>>, DeferredError> {
+    let nodes = build(width * height, |i| {
+        let (x, y) = (i % width, i / width);
+        Tile {
+            x,
+            y,
+            value: make_node(x, y),
+            north: Deferred::new(),
+            east: Deferred::new(),
+            south: Deferred::new(),
+            west: Deferred::new(),
+        }
+    });
+    wire_grid(&nodes, width, height, wrap, &Tile::link)?;
+    Ok(nodes)
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Node {
+        id: usize,
+        edges: Vec<Deferred<Self>>,
+    }
+
+    fn node(slots: usize) -> impl Fn(usize) -> Node {
+        move |id| Node {
+            id,
+            edges: (0..slots).map(|_| Deferred::new()).collect(),
+        }
+    }
+
+    fn targets(node: &Node) -> Vec<Option<usize>> {
+        node.edges
+            .iter()
+            .map(|e| e.try_get().ok().map(|n| n.id))
+            .collect()
+    }
+
+    #[test]
+    fn ring_and_star_are_wired() {
+        let ring = ring(3, node(1), |n, k| &n.edges[k]).unwrap();
+        assert_eq!(targets(&ring[2]), vec![Some(0)]);
+
+        let star = star(4, node(3), |n, k| &n.edges[k]).unwrap();
+        assert_eq!(targets(&star[0]), vec![Some(1), Some(2), Some(3)]);
+        assert_eq!(targets(&star[3]), vec![Some(0), None, None]);
+    }
+    #[test]
+    fn grid_leaves_borders_unset() {
+        let grid = grid(3, 2, node(4), |n, k| &n.edges[k]).unwrap();
+        assert_eq!(targets(&grid[0]), vec![None, Some(1), Some(3), None]);
+        assert_eq!(targets(&grid[4]), vec![Some(1), Some(5), None, Some(3)]);
+    }
+    #[test]
+    fn tile_maps_wrap_when_asked() {
+        let at = |tile: &Deferred<Tile<char>>| tile.get().value;
+        let open = build_grid(3, 2, |x, y| (b'a' + (y * 3 + x) as u8) as char, false).unwrap();
+        assert_eq!((at(&open[1].east), at(&open[1].south)), ('c', 'e'));
+        assert!(!open[0].north.is_ready() && !open[5].east.is_ready());
+
+        let torus = build_grid(3, 2, |x, y| (b'a' + (y * 3 + x) as u8) as char, true).unwrap();
+        assert_eq!((torus[5].x, torus[5].y), (2, 1));
+        let around: Vec<_> = torus[0].neighbors().map(at).collect();
+        assert_eq!(around, ['d', 'b', 'd', 'c']);
+    }
+    #[test]
+    fn complete_rejects_shared_slots() {
+        let result = complete(3, node(1), |n, _| &n.edges[0]);
+        assert!(matches!(
+            result.as_ref().map_err(DeferredError::root_cause),
+            Err(DeferredError::DuplicateInitialization())
+        ));
+    }
+}
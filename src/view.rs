@@ -0,0 +1,216 @@
+//! Separate handles for wiring a graph and for reading it.
+//!
+//! A [`GraphWiring`] owns the nodes while the graph is assembled, and is the only
+//! handle that gives out [`SetOnce`] setters for their cells. Once every edge is wired,
+//! [`seal`](GraphWiring::seal) checks that no [`HasNeighbors`] cell was left unset and
+//! turns it into a [`GraphView`]: a cheap, cloneable handle that can look nodes up and
+//! traverse them, but has no way to wire anything. Handing subsystems a `GraphView`
+//! keeps construction and consumption apart in the type system, and since every cell
+//! of a sealed graph is set, a setter made from one of its cells would only fail.
+//!
+//! ```
+//! use deferred_cell::{Deferred, HasNeighbors, view::GraphWiring};
+//!
+//! struct Stop {
+//!     name: &'static str,
+//!     next: Deferred<Stop>,
+//! }
+//!
+//! impl HasNeighbors for Stop {
+//!     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+//!         std::iter::once(&self.next)
+//!     }
+//! }
+//!
+//! let mut wiring = GraphWiring::new();
+//! let depot = wiring.add(Stop { name: "Depot", next: Deferred::new() });
+//! let market = wiring.add(Stop { name: "Market", next: Deferred::new() });
+//! wiring.link(depot, |s| &s.next, market).unwrap();
+//! wiring.link(market, |s| &s.next, depot).unwrap();
+//!
+//! let view = wiring.seal().unwrap();
+//! let route: Vec<_> = view.bfs(depot).unwrap().map(|s| s.name).collect();
+//! assert_eq!(route, ["Depot", "Market"]);
+//! ```
+
+use std::rc::Rc;
+
+use crate::{
+    DeferredError, HasNeighbors, SetOnce,
+    resolver::Slot,
+    visit::{self, Bfs},
+};
+
+/// The handle that owns a graph's nodes while they are wired.
+#[derive(Debug)]
+pub struct GraphWiring<T> {
+    nodes: Vec<Rc<T>>,
+}
+
+impl<T> Default for GraphWiring<T> {
+    fn default() -> Self {
+        Self { nodes: Vec::new() }
+    }
+}
+
+impl<T> GraphWiring<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds `node` to the graph and returns its index.
+    pub fn add(&mut self, node: T) -> usize {
+        self.nodes.push(Rc::new(node));
+        self.nodes.len() - 1
+    }
+    /// Returns the node at `index`.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&Rc<T>> {
+        self.nodes.get(index)
+    }
+    /// Returns a setter for the cell `slot` selects in the node at `index`.
+    #[must_use]
+    pub fn setter(&self, index: usize, slot: Slot<T>) -> Option<SetOnce<'_, T>> {
+        self.nodes.get(index).map(|node| SetOnce::from(slot(node)))
+    }
+    /// Wires the cell `slot` selects in the node at `from` to the node at `to`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `from` or `to` is out of bounds.
+    pub fn link(&self, from: usize, slot: Slot<T>, to: usize) -> Result<(), DeferredError> {
+        SetOnce::from(slot(&self.nodes[from])).try_set(&self.nodes[to])
+    }
+}
+
+impl<T: HasNeighbors> GraphWiring<T> {
+    /// Ends wiring, checking that every [`HasNeighbors`] cell of every node is set.
+    /// The error names the first unset cell, as `node {index} neighbor {k}`.
+    pub fn seal(self) -> Result<GraphView<T>, DeferredError> {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if let Some(k) = node.neighbors().position(|cell| !cell.is_ready()) {
+                return Err(DeferredError::NotInitializedError()
+                    .context(format!("node {index} neighbor {k}")));
+            }
+        }
+        Ok(GraphView {
+            nodes: self.nodes.into(),
+        })
+    }
+}
+
+/// A read-only handle to a sealed graph, cheap to clone and share.
+#[derive(Debug)]
+pub struct GraphView<T> {
+    nodes: Rc<[Rc<T>]>,
+}
+
+impl<T> Clone for GraphView<T> {
+    fn clone(&self) -> Self {
+        Self {
+            nodes: Rc::clone(&self.nodes),
+        }
+    }
+}
+
+impl<T> GraphView<T> {
+    /// Returns the node at `index`.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.nodes.get(index).map(Rc::as_ref)
+    }
+    /// Returns every node, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.nodes.iter().map(Rc::as_ref)
+    }
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl<T: HasNeighbors> GraphView<T> {
+    /// Returns the targets of the node at `index`, in the order of its cells.
+    #[must_use]
+    pub fn neighbors(&self, index: usize) -> Vec<Rc<T>> {
+        self.nodes
+            .get(index)
+            .map_or_else(Vec::new, |node| visit::neighbors_of(node.as_ref()))
+    }
+    /// Visits the nodes reachable from the node at `index`, breadth first.
+    #[must_use]
+    pub fn bfs(&self, index: usize) -> Option<Bfs<T>> {
+        self.nodes.get(index).map(visit::bfs)
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Deferred;
+
+    struct Node {
+        value: u8,
+        links: [Deferred<Self>; 2],
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.links.iter()
+        }
+    }
+
+    fn wiring(values: &[u8]) -> GraphWiring<Node> {
+        let mut wiring = GraphWiring::new();
+        for &value in values {
+            wiring.add(Node {
+                value,
+                links: [Deferred::new(), Deferred::new()],
+            });
+        }
+        wiring
+    }
+
+    #[test]
+    fn only_fully_wired_graphs_are_sealed() {
+        let graph = wiring(&[1, 2]);
+        graph.link(0, |n| &n.links[0], 1).unwrap();
+        graph.link(0, |n| &n.links[1], 0).unwrap();
+        graph
+            .setter(1, |n| &n.links[0])
+            .unwrap()
+            .try_set(graph.get(0).unwrap())
+            .unwrap();
+        assert!(graph.link(0, |n| &n.links[0], 0).is_err());
+        assert!(graph.setter(2, |n| &n.links[0]).is_none());
+        let Err(error) = graph.seal() else {
+            panic!("a graph with an unset cell was sealed");
+        };
+        assert!(matches!(
+            error.root_cause(),
+            DeferredError::NotInitializedError()
+        ));
+        assert!(error.to_string().contains("node 1 neighbor 1"));
+    }
+    #[test]
+    fn sealed_views_are_shared_read_only() {
+        let graph = wiring(&[1, 2]);
+        for (from, to) in [(0, 1), (1, 0)] {
+            graph.link(from, |n| &n.links[0], to).unwrap();
+            graph.link(from, |n| &n.links[1], from).unwrap();
+        }
+        let view = graph.seal().unwrap();
+        let shared = view.clone();
+        let values: Vec<_> = view.neighbors(1).iter().map(|n| n.value).collect();
+        assert_eq!(values, [1, 2]);
+        assert_eq!(shared.iter().map(|n| n.value).sum::<u8>(), 3);
+        assert_eq!(shared.bfs(1).unwrap().count(), 2);
+        assert!(view.get(2).is_none());
+    }
+}
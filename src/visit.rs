@@ -0,0 +1,501 @@
+//! Generic traversal over graphs of `Rc` nodes linked by [`Deferred`] cells.
+//!
+//! Node types describe their outgoing edges by implementing [`HasNeighbors`], by hand or
+//! with `#[derive(Visit)]` (requires the `derive` feature). Traversals visit each node
+//! once, so cyclic graphs are safe, and skip cells that are unset or whose target was
+//! dropped. Each node's neighbors are followed in the order
+//! [`neighbors`](HasNeighbors::neighbors) yields them, so a traversal's order depends
+//! only on the graph and is the same on every run.
+//!
+//! [`find_nodes`] searches any edges for nodes matching a predicate, within
+//! [`SearchLimits`].
+//!
+//! [`validate`] walks the same way to find unset cells, naming the path to each one.
+
+#[cfg(feature = "std")]
+use std::{
+    borrow::Cow,
+    collections::{HashSet, VecDeque},
+    fmt,
+    rc::Rc,
+};
+
+#[cfg(feature = "std")]
+use thiserror::Error;
+
+use crate::Deferred;
+#[cfg(feature = "std")]
+use crate::DeferredFields;
+
+/// A node whose outgoing edges are `Deferred` cells pointing at the same node type.
+///
+/// ```
+/// use deferred_cell::{Deferred, HasNeighbors};
+///
+/// struct Node {
+///     parent: Deferred<Node>,
+///     children: Vec<Deferred<Node>>,
+/// }
+///
+/// impl HasNeighbors for Node {
+///     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+///         std::iter::once(&self.parent).chain(&self.children)
+///     }
+/// }
+/// ```
+pub trait HasNeighbors: Sized {
+    /// Returns every cell holding an edge out of this node.
+    fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>>;
+}
+
+/// Breadth-first iterator returned by [`bfs`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Bfs<T> {
+    queue: VecDeque<Rc<T>>,
+    seen: HashSet<*const T>,
+}
+
+#[cfg(feature = "std")]
+impl<T: HasNeighbors> Iterator for Bfs<T> {
+    type Item = Rc<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.pop_front()?;
+        for next in node.neighbors().filter_map(|d| d.try_get().ok()) {
+            if self.seen.insert(Rc::as_ptr(&next)) {
+                self.queue.push_back(next);
+            }
+        }
+        Some(node)
+    }
+}
+
+/// Depth-first (preorder) iterator returned by [`dfs`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Dfs<T> {
+    stack: Vec<Rc<T>>,
+    seen: HashSet<*const T>,
+}
+
+#[cfg(feature = "std")]
+impl<T: HasNeighbors> Iterator for Dfs<T> {
+    type Item = Rc<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            if !self.seen.insert(Rc::as_ptr(&node)) {
+                continue;
+            }
+            let mut neighbors: Vec<_> = node
+                .neighbors()
+                .filter_map(|d| d.try_get().ok())
+                .filter(|n| !self.seen.contains(&Rc::as_ptr(n)))
+                .collect();
+            neighbors.reverse();
+            self.stack.extend(neighbors);
+            return Some(node);
+        }
+        None
+    }
+}
+
+/// Visits every node reachable from `root` in breadth-first order, starting with `root`.
+#[cfg(feature = "std")]
+pub fn bfs<T: HasNeighbors>(root: &Rc<T>) -> Bfs<T> {
+    Bfs {
+        queue: VecDeque::from([root.clone()]),
+        seen: HashSet::from([Rc::as_ptr(root)]),
+    }
+}
+
+/// Visits every node reachable from `root` in depth-first preorder, starting with `root`.
+#[cfg(feature = "std")]
+pub fn dfs<T: HasNeighbors>(root: &Rc<T>) -> Dfs<T> {
+    Dfs {
+        stack: vec![root.clone()],
+        seen: HashSet::new(),
+    }
+}
+
+/// Limits on how much of a graph [`find_nodes`] searches. `None` means unlimited.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// The most edges followed from a root.
+    pub max_depth: Option<usize>,
+    /// The most nodes visited, counting the roots.
+    pub max_visited: Option<usize>,
+}
+
+/// The nodes found by [`find_nodes`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Found<T> {
+    /// Every matching node, in breadth-first order.
+    pub matches: Vec<Rc<T>>,
+    /// The nodes from a root to the first match, both included.
+    pub first_path: Option<Vec<Rc<T>>>,
+    /// Whether a limit stopped the search before every reachable node was visited.
+    pub truncated: bool,
+}
+
+/// Returns the targets of every set [`HasNeighbors`] cell of `node`, for use as the
+/// `neighbors` of [`find_nodes`].
+#[cfg(feature = "std")]
+pub fn neighbors_of<T: HasNeighbors>(node: &T) -> Vec<Rc<T>> {
+    node.neighbors().filter_map(|d| d.try_get().ok()).collect()
+}
+
+/// Searches breadth-first from `roots` for the nodes matching `predicate`.
+///
+/// Edges are followed through `neighbors`, so any edges can be searched, not only
+/// those of [`HasNeighbors`]. Each node is visited once, so cycles are safe.
+///
+/// ```
+/// use deferred_cell::{Deferred, SetOnce, visit::{self, SearchLimits}};
+/// use std::rc::Rc;
+///
+/// struct Task {
+///     name: &'static str,
+///     blocked_by: Deferred<Task>,
+/// }
+///
+/// let task = |name| Rc::new(Task { name, blocked_by: Deferred::new() });
+/// let (build, test, ship) = (task("build"), task("test"), task("ship"));
+/// SetOnce::from(&ship.blocked_by).try_set(&test).unwrap();
+/// SetOnce::from(&test.blocked_by).try_set(&build).unwrap();
+/// SetOnce::from(&build.blocked_by).try_set(&ship).unwrap();
+///
+/// let found = visit::find_nodes(
+///     [&ship],
+///     |t| t.blocked_by.try_get().ok(),
+///     |t| t.name.starts_with('b'),
+///     SearchLimits::default(),
+/// );
+/// let path: Vec<_> = found.first_path.unwrap().iter().map(|t| t.name).collect();
+/// assert_eq!(path, ["ship", "test", "build"]);
+/// ```
+#[cfg(feature = "std")]
+pub fn find_nodes<'r, T, I>(
+    roots: impl IntoIterator<Item = &'r Rc<T>>,
+    mut neighbors: impl FnMut(&T) -> I,
+    mut predicate: impl FnMut(&T) -> bool,
+    limits: SearchLimits,
+) -> Found<T>
+where
+    T: 'r,
+    I: IntoIterator<Item = Rc<T>>,
+{
+    let max_visited = limits.max_visited.unwrap_or(usize::MAX);
+    // Each visited node with its depth and the index of the node it was reached from.
+    let mut visited: Vec<(Rc<T>, usize, Option<usize>)> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut truncated = false;
+    for root in roots {
+        if seen.insert(Rc::as_ptr(root)) {
+            if visited.len() == max_visited {
+                truncated = true;
+                break;
+            }
+            visited.push((Rc::clone(root), 0, None));
+        }
+    }
+    let mut matches = Vec::new();
+    let mut next = 0;
+    while let Some((node, depth, _)) = visited.get(next) {
+        let (node, depth) = (Rc::clone(node), *depth);
+        if predicate(&node) {
+            matches.push(next);
+        }
+        for target in neighbors(&node) {
+            if seen.contains(&Rc::as_ptr(&target)) {
+                continue;
+            }
+            if limits.max_depth.is_some_and(|max| depth >= max) || visited.len() == max_visited {
+                truncated = true;
+                break;
+            }
+            seen.insert(Rc::as_ptr(&target));
+            visited.push((target, depth + 1, Some(next)));
+        }
+        next += 1;
+    }
+    let first_path = matches.first().map(|&first| {
+        let mut path = vec![Rc::clone(&visited[first].0)];
+        let mut at = first;
+        while let Some(parent) = visited[at].2 {
+            path.push(Rc::clone(&visited[parent].0));
+            at = parent;
+        }
+        path.reverse();
+        path
+    });
+    Found {
+        matches: matches.iter().map(|&i| Rc::clone(&visited[i].0)).collect(),
+        first_path,
+        truncated,
+    }
+}
+
+/// One step of an [`UnsetPath`]: a node and the field followed out of it.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PathStep {
+    /// The node's label.
+    pub node: String,
+    /// The field's name, or `neighbors[k]` for the `k`th neighbor if it is not a
+    /// [`DeferredFields`] field, such as an element of a `Vec`.
+    pub field: Cow<'static, str>,
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for PathStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.node, self.field)
+    }
+}
+
+/// An unset cell found by [`validate`], with the shortest path to it from the root.
+///
+/// The last step names the unset cell, e.g. `root.left -> mid.parent not initialized`.
+#[cfg(feature = "std")]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[error("{} not initialized", path.iter().map(ToString::to_string).collect::<Vec<_>>().join(" -> "))]
+pub struct UnsetPath {
+    pub path: Vec<PathStep>,
+}
+
+/// The index of the visited node an edge leaves, and the field it follows.
+#[cfg(feature = "std")]
+type Edge = (usize, Cow<'static, str>);
+
+/// Returns the path to every unset cell reachable from `root`, nearest first.
+///
+/// Nodes are named by `label`. Unset cells are those listed by [`DeferredFields`] plus
+/// any unset [`HasNeighbors`] edge.
+#[cfg(feature = "std")]
+pub fn unset_paths<T, L>(root: &Rc<T>, label: impl Fn(&T) -> L) -> Vec<UnsetPath>
+where
+    T: HasNeighbors + DeferredFields,
+    L: fmt::Display,
+{
+    // Each visited node with the edge it was reached by.
+    let mut visited: Vec<(Rc<T>, Option<Edge>)> = vec![(root.clone(), None)];
+    let mut seen = HashSet::from([Rc::as_ptr(root)]);
+    let mut found = Vec::new();
+    let mut next = 0;
+    while let Some((node, _)) = visited.get(next) {
+        let node = Rc::clone(node);
+        let path_to = |field: Cow<'static, str>| {
+            let mut path = vec![PathStep {
+                node: label(&node).to_string(),
+                field,
+            }];
+            let mut at = next;
+            while let Some((parent, field)) = &visited[at].1 {
+                path.push(PathStep {
+                    node: label(&visited[*parent].0).to_string(),
+                    field: field.clone(),
+                });
+                at = *parent;
+            }
+            path.reverse();
+            UnsetPath { path }
+        };
+        for (field, cell) in node.deferred_fields() {
+            if !cell.is_ready() {
+                found.push(path_to(field.into()));
+            }
+        }
+        let mut edges = Vec::new();
+        for (k, cell) in node.neighbors().enumerate() {
+            let named = node
+                .deferred_fields()
+                .find(|(_, field)| std::ptr::addr_eq(*field, cell))
+                .map(|(name, _)| name);
+            let field = named.map_or_else(|| format!("neighbors[{k}]").into(), Cow::from);
+            match cell.try_get() {
+                Ok(target) => edges.push((target, field)),
+                Err(_) if named.is_none() && !cell.is_ready() => found.push(path_to(field)),
+                Err(_) => {}
+            }
+        }
+        for (target, field) in edges {
+            if seen.insert(Rc::as_ptr(&target)) {
+                visited.push((target, Some((next, field))));
+            }
+        }
+        next += 1;
+    }
+    found
+}
+
+/// Checks that no cell reachable from `root` is unset, returning the nearest one if any.
+///
+/// ```
+/// use deferred_cell::{AnyDeferredCell, Deferred, DeferredFields, HasNeighbors, SetOnce, visit};
+/// use std::rc::Rc;
+///
+/// struct Node {
+///     name: &'static str,
+///     parent: Deferred<Node>,
+///     children: Vec<Deferred<Node>>,
+/// }
+///
+/// impl HasNeighbors for Node {
+///     fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+///         std::iter::once(&self.parent).chain(&self.children)
+///     }
+/// }
+///
+/// impl DeferredFields for Node {
+///     fn deferred_fields(&self) -> impl Iterator<Item = (&'static str, &dyn AnyDeferredCell)> {
+///         [("parent", &self.parent as &dyn AnyDeferredCell)].into_iter()
+///     }
+/// }
+///
+/// let node = |name, children| {
+///     Rc::new(Node {
+///         name,
+///         parent: Deferred::new(),
+///         children: (0..children).map(|_| Deferred::new()).collect(),
+///     })
+/// };
+/// let (root, leaf) = (node("root", 1), node("leaf", 0));
+/// SetOnce::from(&root.parent).try_set(&root).unwrap();
+/// SetOnce::from(&root.children[0]).try_set(&leaf).unwrap();
+///
+/// let error = visit::validate(&root, |n| n.name).unwrap_err();
+/// assert_eq!(
+///     error.to_string(),
+///     "root.neighbors[1] -> leaf.parent not initialized"
+/// );
+/// ```
+#[cfg(feature = "std")]
+pub fn validate<T, L>(root: &Rc<T>, label: impl Fn(&T) -> L) -> Result<(), UnsetPath>
+where
+    T: HasNeighbors + DeferredFields,
+    L: fmt::Display,
+{
+    unset_paths(root, label)
+        .into_iter()
+        .next()
+        .map_or(Ok(()), Err)
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::SetOnce;
+
+    struct Node {
+        value: u8,
+        edges: Vec<Deferred<Self>>,
+    }
+
+    impl HasNeighbors for Node {
+        fn neighbors(&self) -> impl Iterator<Item = &Deferred<Self>> {
+            self.edges.iter()
+        }
+    }
+
+    impl DeferredFields for Node {
+        fn deferred_fields(
+            &self,
+        ) -> impl Iterator<Item = (&'static str, &dyn crate::AnyDeferredCell)> {
+            std::iter::empty()
+        }
+    }
+
+    fn diamond() -> Vec<Rc<Node>> {
+        /*
+            0 -> 1 -> 3 -> 0
+             \-> 2 -/
+        */
+        let nodes: Vec<_> = (0..4)
+            .map(|value| {
+                Rc::new(Node {
+                    value,
+                    edges: vec![Deferred::new(), Deferred::new()],
+                })
+            })
+            .collect();
+        for (from, slot, to) in [(0, 0, 1), (0, 1, 2), (1, 0, 3), (2, 0, 3), (3, 0, 0)] {
+            SetOnce::from(&nodes[from].edges[slot])
+                .try_set(&nodes[to])
+                .unwrap();
+        }
+        nodes
+    }
+
+    #[test]
+    fn bfs_visits_each_node_once() {
+        let nodes = diamond();
+        let order: Vec<_> = bfs(&nodes[0]).map(|n| n.value).collect();
+        assert_eq!(order, vec![0, 1, 2, 3]);
+    }
+    #[test]
+    fn dfs_visits_in_preorder() {
+        let nodes = diamond();
+        let order: Vec<_> = dfs(&nodes[0]).map(|n| n.value).collect();
+        assert_eq!(order, vec![0, 1, 3, 2]);
+    }
+    #[test]
+    fn find_nodes_respects_limits() {
+        let nodes = diamond();
+        let values =
+            |found: &Found<Node>| -> Vec<u8> { found.matches.iter().map(|n| n.value).collect() };
+        let found = find_nodes(
+            [&nodes[3], &nodes[0]],
+            neighbors_of,
+            |n| n.value > 0,
+            SearchLimits::default(),
+        );
+        assert_eq!(values(&found), [3, 1, 2]);
+        assert!(!found.truncated);
+        let path: Vec<_> = found.first_path.unwrap().iter().map(|n| n.value).collect();
+        assert_eq!(path, [3]);
+
+        let shallow = SearchLimits {
+            max_depth: Some(1),
+            ..SearchLimits::default()
+        };
+        let found = find_nodes([&nodes[0]], neighbors_of, |n| n.value == 3, shallow);
+        assert!(found.matches.is_empty() && found.first_path.is_none() && found.truncated);
+        let small = SearchLimits {
+            max_visited: Some(3),
+            ..SearchLimits::default()
+        };
+        let found = find_nodes([&nodes[0]], neighbors_of, |_| true, small);
+        assert_eq!(values(&found), [0, 1, 2]);
+        assert!(found.truncated);
+    }
+    #[test]
+    fn unset_paths_lead_from_the_root() {
+        let nodes = diamond();
+        let paths: Vec<_> = unset_paths(&nodes[0], |n| n.value)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "0.neighbors[0] -> 1.neighbors[1] not initialized",
+                "0.neighbors[1] -> 2.neighbors[1] not initialized",
+                "0.neighbors[0] -> 1.neighbors[0] -> 3.neighbors[1] not initialized",
+            ]
+        );
+
+        let leaf = Rc::new(Node {
+            value: 9,
+            edges: Vec::new(),
+        });
+        assert!(validate(&leaf, |n| n.value).is_ok());
+    }
+}
@@ -0,0 +1,247 @@
+//! Maps and sets keyed weakly by node identity.
+//!
+//! A [`WeakKeyMap`] associates values with nodes without keeping the nodes alive: each
+//! entry holds a [`Weak`] to its key, and entries whose key has been dropped are skipped
+//! by [`iter`](WeakKeyMap::iter) and pruned automatically as the map grows, or on
+//! demand with [`prune`](WeakKeyMap::prune). This suits caches keyed by node and, with
+//! [`WeakKeySet`], observer lists that should not outlive what they observe. Both
+//! iterate in insertion order, so output built from them is the same on every run.
+//!
+//! ```
+//! use deferred_cell::weak_map::WeakKeySet;
+//! use std::rc::Rc;
+//!
+//! struct Listener(&'static str);
+//!
+//! let (kept, dropped) = (Rc::new(Listener("kept")), Rc::new(Listener("dropped")));
+//! let mut listeners = WeakKeySet::new();
+//! listeners.insert(&kept);
+//! listeners.insert(&dropped);
+//! drop(dropped);
+//!
+//! let names: Vec<_> = listeners.iter().map(|l| l.0).collect();
+//! assert_eq!(names, ["kept"]);
+//! listeners.prune();
+//! assert_eq!(listeners.len(), 1);
+//! ```
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    ptr,
+    rc::{Rc, Weak},
+};
+
+/// Entries are pruned once the map holds this many, at least.
+const MIN_PRUNE_AT: usize = 16;
+
+/// Maps keys of type `K`, by identity, to values of type `V`.
+#[derive(Debug)]
+pub struct WeakKeyMap<K, V> {
+    // The weak reference keeps the node's allocation, and so its address, from being
+    // reused while the entry exists. The `u64` numbers entries in insertion order.
+    entries: HashMap<*const K, (Weak<K>, V, u64)>,
+    inserted: u64,
+    prune_at: usize,
+}
+
+impl<K, V> Default for WeakKeyMap<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            inserted: 0,
+            prune_at: MIN_PRUNE_AT,
+        }
+    }
+}
+
+impl<K, V> WeakKeyMap<K, V> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Maps `key` to `value`, returning the value it replaces.
+    ///
+    /// Replacing a value keeps the entry's place in the iteration order.
+    pub fn insert(&mut self, key: &Rc<K>, value: V) -> Option<V> {
+        self.grow();
+        match self.entries.entry(Rc::as_ptr(key)) {
+            Entry::Occupied(mut entry) => Some(std::mem::replace(&mut entry.get_mut().1, value)),
+            Entry::Vacant(entry) => {
+                entry.insert((Rc::downgrade(key), value, self.inserted));
+                self.inserted += 1;
+                None
+            }
+        }
+    }
+    /// Returns the value of `key`, inserting `default()` if there is none.
+    pub fn get_or_insert_with(&mut self, key: &Rc<K>, default: impl FnOnce() -> V) -> &mut V {
+        self.grow();
+        let inserted = &mut self.inserted;
+        let (_, value, _) = self.entries.entry(Rc::as_ptr(key)).or_insert_with(|| {
+            *inserted += 1;
+            (Rc::downgrade(key), default(), *inserted - 1)
+        });
+        value
+    }
+    /// Prunes before an insertion once the map has doubled since the last prune.
+    fn grow(&mut self) {
+        if self.entries.len() >= self.prune_at {
+            self.prune();
+            self.prune_at = (self.entries.len() * 2).max(MIN_PRUNE_AT);
+        }
+    }
+    #[must_use]
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries
+            .get(&ptr::from_ref(key))
+            .map(|(_, value, _)| value)
+    }
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.entries
+            .get_mut(&ptr::from_ref(key))
+            .map(|(_, value, _)| value)
+    }
+    #[must_use]
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries.contains_key(&ptr::from_ref(key))
+    }
+    /// Removes and returns the value of `key`.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries
+            .remove(&ptr::from_ref(key))
+            .map(|(_, value, _)| value)
+    }
+    /// Drops the entries of keys that no longer exist.
+    pub fn prune(&mut self) {
+        self.entries.retain(|_, (key, _, _)| key.strong_count() > 0);
+    }
+    /// Keeps only the entries whose key still exists and for which `keep` returns `true`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Rc<K>, &mut V) -> bool) {
+        self.entries
+            .retain(|_, (key, value, _)| key.upgrade().is_some_and(|key| keep(&key, value)));
+    }
+    /// Returns every key that still exists with its value, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (Rc<K>, &V)> {
+        let mut entries: Vec<_> = self.entries.values().collect();
+        entries.sort_unstable_by_key(|(_, _, inserted)| *inserted);
+        entries
+            .into_iter()
+            .filter_map(|(key, value, _)| Some((key.upgrade()?, value)))
+    }
+    /// Returns the number of entries, including those of dropped keys not yet pruned.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Removes every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// A set of keys of type `K`, by identity. See [`WeakKeyMap`].
+#[derive(Debug)]
+pub struct WeakKeySet<K> {
+    map: WeakKeyMap<K, ()>,
+}
+
+impl<K> Default for WeakKeySet<K> {
+    fn default() -> Self {
+        Self {
+            map: WeakKeyMap::default(),
+        }
+    }
+}
+
+impl<K> WeakKeySet<K> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds `key`, returning `false` if it was already present.
+    pub fn insert(&mut self, key: &Rc<K>) -> bool {
+        self.map.insert(key, ()).is_none()
+    }
+    #[must_use]
+    pub fn contains(&self, key: &K) -> bool {
+        self.map.contains(key)
+    }
+    /// Removes `key`, returning `false` if it was not present.
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.map.remove(key).is_some()
+    }
+    /// Drops the keys that no longer exist.
+    pub fn prune(&mut self) {
+        self.map.prune();
+    }
+    /// Keeps only the keys that still exist and for which `keep` returns `true`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Rc<K>) -> bool) {
+        self.map.retain(|key, ()| keep(key));
+    }
+    /// Returns every key that still exists, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = Rc<K>> {
+        self.map.iter().map(|(key, ())| key)
+    }
+    /// Returns the number of keys, including dropped keys not yet pruned.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dead_entries_are_pruned_as_the_map_grows() {
+        let mut marks = WeakKeyMap::new();
+        let kept = Rc::new(0);
+        *marks.get_or_insert_with(&kept, || 1) += 1;
+        assert_eq!(marks.insert(&kept, 5), Some(2));
+
+        for i in 1..MIN_PRUNE_AT {
+            marks.insert(&Rc::new(i), 0);
+        }
+        assert_eq!(marks.len(), MIN_PRUNE_AT);
+        marks.insert(&Rc::new(0), 0);
+        assert_eq!(marks.len(), 2);
+        assert_eq!(marks.iter().count(), 1);
+
+        *marks.get_mut(&kept).unwrap() += 1;
+        assert_eq!(marks.remove(&kept), Some(6));
+        assert!(!marks.contains(&kept));
+    }
+    #[test]
+    fn sets_skip_dropped_keys() {
+        let (a, b) = (Rc::new(1), Rc::new(2));
+        let mut set = WeakKeySet::new();
+        assert!(set.insert(&a) && set.insert(&b) && !set.insert(&a));
+        drop(b);
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.iter().collect::<Vec<_>>(), [Rc::clone(&a)]);
+        set.retain(|key| **key != 1);
+        assert!(set.is_empty() && !set.contains(&a));
+
+        let keys: Vec<_> = (0..8).map(Rc::new).collect();
+        keys.iter().rev().for_each(|key| {
+            set.insert(key);
+        });
+        set.insert(&keys[3]);
+        let order: Vec<_> = set.iter().map(|key| *key).collect();
+        assert_eq!(order, [7, 6, 5, 4, 3, 2, 1, 0]);
+    }
+}
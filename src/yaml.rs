@@ -0,0 +1,284 @@
+//! Loading YAML where aliases share nodes instead of copying them.
+//!
+//! YAML can already describe shared and cyclic structures with anchors (`&name`) and
+//! aliases (`*name`), but most loaders expand each alias into a deep copy, and refuse
+//! cycles. [`YamlLoader`] makes every mapping of a document a node instead. A field
+//! whose value is a mapping, or an alias to one, becomes a [`Deferred`] link to that
+//! node, so an alias links to the anchored node itself. Fields holding sequences link
+//! to each mapping in them.
+//!
+//! Other fields are handed to the node constructor as scalars. Aliases to scalars are
+//! copied, as usual.
+//!
+//! ```
+//! use deferred_cell::{Deferred, yaml::YamlLoader};
+//!
+//! struct Person {
+//!     name: String,
+//!     links: Vec<Deferred<Person>>,
+//! }
+//!
+//! let source = "
+//! name: Ada
+//! friend: &alan
+//!   name: Alan
+//!   friend: { name: Grace, friend: *alan }
+//! ";
+//! let people = YamlLoader::new()
+//!     .load(
+//!         source,
+//!         |scalars, links| Person {
+//!             name: scalars.iter().find(|(k, _)| k == "name").map(|(_, v)| v.clone()).unwrap_or_default(),
+//!             links: links.iter().map(|_| Deferred::new()).collect(),
+//!         },
+//!         |person, k| &person.links[k],
+//!     )
+//!     .unwrap();
+//!
+//! let alan = people[0].links[0].get();
+//! let grace = alan.links[0].get();
+//! assert!(std::rc::Rc::ptr_eq(&grace.links[0].get(), &alan));
+//! ```
+
+use std::{collections::HashMap, rc::Rc};
+
+use thiserror::Error;
+use yaml_rust2::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::{Marker, ScanError},
+};
+
+use crate::{Deferred, DeferredError, spec::GraphSpec};
+
+/// Errors returned by [`YamlLoader::load`].
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum YamlError {
+    #[error(transparent)]
+    Parse(#[from] ScanError),
+    #[error("Mapping keys must be scalars, at line {line}, column {column}!")]
+    ComplexKey { line: usize, column: usize },
+    #[error("Aliases to sequences are not supported, at line {line}, column {column}!")]
+    SequenceAlias { line: usize, column: usize },
+    #[error(transparent)]
+    Wiring(#[from] DeferredError),
+}
+
+/// The fields of one mapping.
+#[derive(Default)]
+struct Found {
+    scalars: Vec<(String, String)>,
+    links: Vec<(String, usize)>,
+}
+
+enum Anchored {
+    Node(usize),
+    Scalar(String),
+    Sequence,
+}
+
+enum Frame {
+    /// A mapping node, and the key whose value comes next.
+    Mapping { node: usize, key: Option<String> },
+    /// A sequence, and the node and field it is the value of.
+    Sequence { field: Option<(usize, String)> },
+}
+
+#[derive(Default)]
+struct Receiver {
+    found: Vec<Found>,
+    anchors: HashMap<usize, Anchored>,
+    stack: Vec<Frame>,
+    error: Option<YamlError>,
+}
+
+impl Receiver {
+    /// Returns the node and field the next value belongs to, consuming a pending key.
+    /// Fails if the next value is a mapping key instead.
+    fn field(&mut self, mark: Marker) -> Result<Option<(usize, String)>, YamlError> {
+        match self.stack.last_mut() {
+            Some(Frame::Mapping { key: None, .. }) => Err(YamlError::ComplexKey {
+                line: mark.line(),
+                column: mark.col() + 1,
+            }),
+            Some(Frame::Mapping { node, key }) => Ok(key.take().map(|key| (*node, key))),
+            Some(Frame::Sequence { field }) => Ok(field.clone()),
+            None => Ok(None),
+        }
+    }
+    fn link(&mut self, to: usize, mark: Marker) -> Result<(), YamlError> {
+        if let Some((from, field)) = self.field(mark)? {
+            self.found[from].links.push((field, to));
+        }
+        Ok(())
+    }
+    fn scalar(&mut self, value: String, mark: Marker) -> Result<(), YamlError> {
+        if let Some(Frame::Mapping {
+            key: key @ None, ..
+        }) = self.stack.last_mut()
+        {
+            *key = Some(value);
+            return Ok(());
+        }
+        if let Some((node, field)) = self.field(mark)? {
+            self.found[node].scalars.push((field, value));
+        }
+        Ok(())
+    }
+    fn handle(&mut self, event: Event, mark: Marker) -> Result<(), YamlError> {
+        match event {
+            Event::MappingStart(anchor, _) => {
+                let node = self.found.len();
+                self.link(node, mark)?;
+                self.found.push(Found::default());
+                if anchor > 0 {
+                    self.anchors.insert(anchor, Anchored::Node(node));
+                }
+                self.stack.push(Frame::Mapping { node, key: None });
+            }
+            Event::SequenceStart(anchor, _) => {
+                let field = self.field(mark)?;
+                if anchor > 0 {
+                    self.anchors.insert(anchor, Anchored::Sequence);
+                }
+                self.stack.push(Frame::Sequence { field });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            }
+            Event::Scalar(value, _, anchor, _) => {
+                if anchor > 0 {
+                    self.anchors.insert(anchor, Anchored::Scalar(value.clone()));
+                }
+                self.scalar(value, mark)?;
+            }
+            Event::Alias(anchor) => match self.anchors.get(&anchor) {
+                Some(&Anchored::Node(node)) => self.link(node, mark)?,
+                Some(Anchored::Scalar(value)) => self.scalar(value.clone(), mark)?,
+                Some(Anchored::Sequence) | None => {
+                    return Err(YamlError::SequenceAlias {
+                        line: mark.line(),
+                        column: mark.col() + 1,
+                    });
+                }
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl MarkedEventReceiver for Receiver {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        if self.error.is_none() {
+            self.error = self.handle(event, mark).err();
+        }
+    }
+}
+
+/// Loads YAML documents, with one node per mapping.
+#[derive(Debug, Clone, Default)]
+pub struct YamlLoader;
+
+impl YamlLoader {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self
+    }
+    /// Creates one node per mapping of `source`, in document order, and wires its links.
+    ///
+    /// Each node is made by `make_node(scalars, links)`, where `scalars` holds its
+    /// scalar fields, one pair per item for sequences, and `links` names the field of
+    /// each of its links. Link `k` is stored through `slot(node, k)`.
+    pub fn load<T>(
+        &self,
+        source: &str,
+        mut make_node: impl FnMut(&[(String, String)], &[&str]) -> T,
+        slot: impl Fn(&T, usize) -> &Deferred<T>,
+    ) -> Result<Vec<Rc<T>>, YamlError> {
+        let mut receiver = Receiver::default();
+        Parser::new_from_str(source).load(&mut receiver, true)?;
+        if let Some(error) = receiver.error {
+            return Err(error);
+        }
+        let found = receiver.found;
+        let edges = found
+            .iter()
+            .enumerate()
+            .flat_map(|(from, f)| f.links.iter().map(move |&(_, to)| (from, to)))
+            .collect();
+        Ok(GraphSpec::new(found.len(), edges).materialize(
+            |i, _| {
+                let fields: Vec<_> = found[i].links.iter().map(|(f, _)| f.as_str()).collect();
+                make_node(&found[i].scalars, &fields)
+            },
+            slot,
+        )?)
+    }
+}
+
+// Allowed in tests
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Node {
+        scalars: Vec<(String, String)>,
+        fields: Vec<String>,
+        links: Vec<Deferred<Self>>,
+    }
+
+    fn load(source: &str) -> Result<Vec<Rc<Node>>, YamlError> {
+        YamlLoader::new().load(
+            source,
+            |scalars, links| Node {
+                scalars: scalars.to_vec(),
+                fields: links.iter().map(|&f| f.to_owned()).collect(),
+                links: links.iter().map(|_| Deferred::new()).collect(),
+            },
+            |node, k| &node.links[k],
+        )
+    }
+
+    #[test]
+    fn aliases_share_the_anchored_node() {
+        let source = "
+root: &root
+  name: &n root
+  tags: [a, b]
+  label: *n
+  kids:
+    - &leaf { up: *root }
+    - *leaf
+    - *root
+";
+        let nodes = load(source).unwrap();
+        assert_eq!(nodes.len(), 3);
+        let root = &nodes[1];
+        let pairs = |node: &Node| -> Vec<_> {
+            node.scalars
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect()
+        };
+        assert_eq!(pairs(root), ["name=root", "tags=a", "tags=b", "label=root"]);
+        assert_eq!(root.fields, ["kids", "kids", "kids"]);
+        assert!(Rc::ptr_eq(&root.links[0].get(), &root.links[1].get()));
+        assert!(Rc::ptr_eq(&root.links[2].get(), root));
+        assert!(Rc::ptr_eq(&nodes[2].links[0].get(), root));
+        assert_eq!(nodes[0].fields, ["root"]);
+    }
+    #[test]
+    fn unsupported_shapes_are_rejected() {
+        assert_eq!(
+            load("? {a: 1}\n: b\n").unwrap_err().to_string(),
+            "Mapping keys must be scalars, at line 1, column 3!"
+        );
+        assert!(matches!(
+            load("a: &s [1]\nb: *s\n"),
+            Err(YamlError::SequenceAlias { line: 2, .. })
+        ));
+    }
+}
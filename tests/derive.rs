@@ -0,0 +1,324 @@
+// Allowed in tests
+#![allow(clippy::unwrap_used)]
+
+use std::{rc::Rc, sync::Arc};
+
+use deferred_cell::{
+    Deferred, DeferredBuilder, DeferredDefault, DeferredError, DeferredFields, DeferredSync,
+    SetOnce, Visit, deferred, visit,
+};
+
+#[derive(DeferredFields)]
+struct Node {
+    name: String,
+    parent: Deferred<Self>,
+    sibling: deferred_cell::Deferred<Self>,
+    children: Vec<Rc<Self>>,
+}
+
+impl Node {
+    fn new(name: &str) -> Rc<Self> {
+        Rc::new(Self {
+            name: name.into(),
+            parent: Deferred::default(),
+            sibling: Deferred::default(),
+            children: Vec::new(),
+        })
+    }
+}
+
+#[derive(DeferredFields)]
+struct Shared<T: Send + Sync> {
+    target: DeferredSync<T>,
+}
+
+#[test]
+fn deferred_fields_lists_cells_in_order() {
+    let root = Node::new("root");
+    let child = Node::new("child");
+    SetOnce::from(&child.parent).try_set(&root).unwrap();
+
+    let fields: Vec<_> = child
+        .deferred_fields()
+        .map(|(name, cell)| (name, cell.is_ready()))
+        .collect();
+
+    assert_eq!(fields, vec![("parent", true), ("sibling", false)]);
+    assert!(root.children.is_empty());
+    assert_eq!(child.parent.get().name, "root");
+}
+
+#[test]
+fn deferred_fields_supports_generics_and_sync_cells() {
+    let shared = Shared::<u8> {
+        target: DeferredSync::default(),
+    };
+    let (name, cell) = shared.deferred_fields().next().unwrap();
+
+    assert_eq!(name, "target");
+    assert_eq!(cell.target_type_name(), "u8");
+    assert!(!cell.is_ready());
+}
+
+#[test]
+fn validate_reports_every_unset_field() {
+    let root = Node::new("root");
+    assert_eq!(
+        root.validate().unwrap_err().to_string(),
+        "Node: parent, sibling not initialized"
+    );
+    SetOnce::from(&root.parent).try_set(&root).unwrap();
+    SetOnce::from(&root.sibling).try_set(&root).unwrap();
+    assert!(root.validate().is_ok());
+}
+
+#[deferred]
+struct Link {
+    value: u32,
+    next: Deferred<Self>,
+    prev: Deferred<Self>,
+}
+
+#[deferred]
+struct SyncLink {
+    target: DeferredSync<u32>,
+}
+
+#[test]
+fn deferred_attribute_generates_setters() {
+    let a = Rc::new(Link {
+        value: 1,
+        next: Deferred::new(),
+        prev: Deferred::new(),
+    });
+    let b = Rc::new(
+        Link {
+            value: 2,
+            next: Deferred::new(),
+            prev: Deferred::new(),
+        }
+        .with_prev(&a)
+        .unwrap(),
+    );
+    a.set_next(&b).unwrap();
+
+    assert_eq!(a.next().value, 2);
+    assert_eq!(b.prev().value, 1);
+    assert!(matches!(
+        a.set_next(&a).unwrap_err().root_cause(),
+        DeferredError::DuplicateInitialization()
+    ));
+    let set = Link {
+        value: 3,
+        next: Deferred::from(&a),
+        prev: Deferred::new(),
+    };
+    let Err(rejected) = set.with_next(&b) else {
+        panic!("with_next set a cell twice");
+    };
+    assert_eq!(rejected.code(), "DC0001");
+    assert_eq!(rejected.into_node().next().value, 1);
+
+    let target = Arc::new(5);
+    let link = SyncLink {
+        target: DeferredSync::default(),
+    };
+    link.set_target(&target).unwrap();
+    assert_eq!(*link.target(), 5);
+}
+
+#[test]
+fn deferred_attribute_errors_name_the_field() {
+    let a = Link {
+        value: 1,
+        next: Deferred::new(),
+        prev: Deferred::new(),
+    };
+    #[cfg(feature = "debug-location")]
+    let line = line!() + 1;
+    let error = a.try_prev().err().unwrap();
+
+    #[cfg(not(feature = "debug-location"))]
+    assert_eq!(
+        error.to_string(),
+        "Link.prev: Cannot use uninitialized value!"
+    );
+    #[cfg(feature = "debug-location")]
+    assert_eq!(error.location().unwrap().line(), line);
+    assert!(matches!(
+        error.root_cause(),
+        DeferredError::NotInitializedError()
+    ));
+}
+
+#[derive(Visit)]
+struct Tree {
+    label: char,
+    parent: Deferred<Self>,
+    children: Vec<Deferred<Tree>>,
+    payload: Deferred<u32>,
+}
+
+#[test]
+fn visit_derive_walks_cells_and_cell_vectors() {
+    let tree = |label, children| {
+        Rc::new(Tree {
+            label,
+            parent: Deferred::new(),
+            children: (0..children).map(|_| Deferred::new()).collect(),
+            payload: Deferred::new(),
+        })
+    };
+    let root = tree('a', 2);
+    let left = tree('b', 0);
+    let right = tree('c', 0);
+    SetOnce::from(&root.children[0]).try_set(&left).unwrap();
+    SetOnce::from(&root.children[1]).try_set(&right).unwrap();
+    SetOnce::from(&left.parent).try_set(&root).unwrap();
+    SetOnce::from(&right.parent).try_set(&root).unwrap();
+
+    let labels: String = visit::bfs(&right).map(|n| n.label).collect();
+
+    assert_eq!(labels, "cab");
+    assert!(!root.payload.is_ready());
+}
+
+#[derive(DeferredBuilder)]
+struct Employee<'a, T: Clone> {
+    name: &'a str,
+    level: T,
+    office: DeferredSync<String>,
+    #[builder(optional)]
+    manager: Deferred<Self>,
+}
+
+#[test]
+fn builder_sets_supplied_links() {
+    let office = Arc::new(String::from("HQ"));
+    let boss = Rc::new(Employee::builder("boss", 2).office(&office).build());
+    let worker = Employee::builder("worker", 1)
+        .manager(&boss)
+        .office(&office)
+        .build();
+
+    assert_eq!(worker.manager.get().name, "boss");
+    assert_eq!(*worker.office.get(), "HQ");
+    assert_eq!(worker.level, 1);
+    assert!(!boss.manager.is_ready());
+}
+
+#[derive(DeferredDefault)]
+struct Symbol {
+    name: String,
+    arity: usize,
+    declaration: Deferred<Self>,
+    uses: Vec<Deferred<Self>>,
+    module: DeferredSync<String>,
+}
+
+#[derive(DeferredDefault)]
+struct Edge {
+    from: Deferred<Self>,
+    to: Deferred<Self>,
+}
+
+#[test]
+fn deferred_default_takes_payload_and_leaves_cells_unset() {
+    let symbol = Symbol::new("main".into(), 0);
+
+    assert_eq!(symbol.name, "main");
+    assert_eq!(symbol.arity, 0);
+    assert!(!symbol.declaration.is_ready());
+    assert!(symbol.uses.is_empty());
+    assert!(!symbol.module.is_ready());
+
+    let edge = Edge::default();
+    assert!(!edge.from.is_ready() && !edge.to.is_ready());
+}
+
+#[cfg(all(feature = "serde", feature = "json"))]
+mod graph_serde {
+    use std::rc::Rc;
+
+    use deferred_cell::{
+        Deferred, DeferredError, GraphDeserialize, GraphSerialize, SetOnce,
+        graph_serde::{DeserializeGraph, SerializeGraph},
+    };
+
+    #[derive(Debug, GraphSerialize, GraphDeserialize)]
+    struct Task {
+        #[serde(rename = "title")]
+        name: String,
+        #[serde(default)]
+        weight: u32,
+        after: Deferred<Self>,
+        blocks: Vec<Deferred<Task>>,
+    }
+
+    fn task(name: &str) -> Rc<Task> {
+        Rc::new(Task {
+            name: name.into(),
+            weight: 1,
+            after: Deferred::new(),
+            blocks: vec![Deferred::new(), Deferred::new()],
+        })
+    }
+
+    #[test]
+    fn edges_round_trip_as_ids() {
+        let tasks = vec![task("plan"), task("build")];
+        SetOnce::from(&tasks[1].after).try_set(&tasks[0]).unwrap();
+        SetOnce::from(&tasks[0].blocks[0])
+            .try_set(&tasks[1])
+            .unwrap();
+        SetOnce::from(&tasks[0].blocks[1])
+            .try_set(&tasks[0])
+            .unwrap();
+
+        let json = serde_json::to_value(SerializeGraph(&tasks)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"title": "plan", "weight": 1, "after": null, "blocks": [1, 0]},
+                {"title": "build", "weight": 1, "after": 0, "blocks": [null, null]},
+            ])
+        );
+
+        let DeserializeGraph(copy) =
+            serde_json::from_value::<DeserializeGraph<Task>>(json).unwrap();
+        assert_eq!(copy[1].after.get().name, "plan");
+        assert_eq!(copy[0].blocks[0].get().name, "build");
+        assert!(Rc::ptr_eq(&copy[0].blocks[1].get(), &copy[0]));
+        assert!(!copy[0].after.is_ready() && !copy[1].blocks[1].is_ready());
+    }
+
+    #[test]
+    fn serde_attributes_apply_to_payload_fields() {
+        let json = r#"[{"title": "solo", "after": null, "blocks": []}]"#;
+        let DeserializeGraph(tasks) = serde_json::from_str::<DeserializeGraph<Task>>(json).unwrap();
+        assert_eq!(tasks[0].name, "solo");
+        assert_eq!(tasks[0].weight, 0);
+    }
+
+    #[test]
+    fn edges_must_stay_inside_the_graph() {
+        let (inside, outside) = (task("inside"), task("outside"));
+        SetOnce::from(&inside.after).try_set(&outside).unwrap();
+        let error = serde_json::to_string(&SerializeGraph(&[inside])).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Task.after: Cell points outside the graph!")
+        );
+
+        let json = r#"[{"title": "lost", "after": 3, "blocks": []}]"#;
+        let error = serde_json::from_str::<DeserializeGraph<Task>>(json).unwrap_err();
+        assert!(
+            error.to_string().starts_with(
+                &DeferredError::OutsideGraph()
+                    .context("node 0 edge 0")
+                    .to_string()
+            )
+        );
+    }
+}
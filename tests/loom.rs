@@ -0,0 +1,80 @@
+//! Model-checks concurrent wiring of `DeferredSync` cells.
+//!
+//! Run with `cargo test --release --features loom --test loom`.
+
+use deferred_cell::{DeferredError, DeferredSync, SetOnceSync};
+use loom::thread;
+use std::sync::Arc;
+
+struct Node {
+    value: u32,
+    next: DeferredSync<Self>,
+}
+
+fn node(value: u32) -> Arc<Node> {
+    Arc::new(Node {
+        value,
+        next: DeferredSync::default(),
+    })
+}
+
+#[test]
+fn racing_setters_only_one_wins() {
+    loom::model(|| {
+        let target_a = node(1);
+        let target_b = node(2);
+        let source = node(0);
+
+        let handles: Vec<_> = [target_a.clone(), target_b.clone()]
+            .into_iter()
+            .map(|target| {
+                let source = source.clone();
+                thread::spawn(move || SetOnceSync::from(&source.next).try_set(&target).is_ok())
+            })
+            .collect();
+        let winners = handles
+            .into_iter()
+            .map(|h| h.join().unwrap_or(false))
+            .filter(|won| *won)
+            .count();
+
+        assert_eq!(winners, 1);
+        assert!(matches!(source.next.get().value, 1 | 2));
+        assert!(matches!(
+            SetOnceSync::from(&source.next).try_set(&target_a),
+            Err(DeferredError::DuplicateInitialization())
+        ));
+    });
+}
+
+#[test]
+fn wait_observes_concurrent_set() {
+    loom::model(|| {
+        let source = node(0);
+        let target = node(7);
+
+        let waiter = {
+            let source = source.clone();
+            thread::spawn(move || source.next.wait().map(|n| n.value).ok())
+        };
+        SetOnceSync::from(&source.next).try_set(&target).ok();
+
+        assert_eq!(waiter.join().ok().flatten(), Some(7));
+    });
+}
+
+#[test]
+fn try_get_never_sees_partial_writes() {
+    loom::model(|| {
+        let source = node(0);
+        let target = node(3);
+
+        let reader = {
+            let source = source.clone();
+            thread::spawn(move || source.next.try_get().map(|n| n.value).ok())
+        };
+        SetOnceSync::from(&source.next).try_set(&target).ok();
+
+        assert!(matches!(reader.join().ok().flatten(), None | Some(3)));
+    });
+}